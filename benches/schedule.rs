@@ -0,0 +1,165 @@
+//! Benchmarks of a [Schedule] used by many tasks at once, comparing a single
+//! shard to the default number of them, and of taking due items from
+//! a [Schedule] and a [WheelSchedule] with many distinct intervals.
+//!
+//! Run with `cargo bench --bench schedule`.
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicI64, Ordering};
+
+use criterion::{BenchmarkId, Criterion, criterion_group, criterion_main};
+use limon_core::schedule::wheel::WheelSchedule;
+use limon_core::schedule::{Schedulable, Schedule};
+use tokio::runtime::Runtime;
+use tokio::task::JoinSet;
+
+/// The number of items in the benchmarked schedules.
+const ITEMS: i64 = 10_000;
+
+/// The number of tasks using a schedule at the same time.
+const TASKS: i64 = 8;
+
+struct Check {
+  id: i64,
+  interval: i64,
+}
+
+impl Schedulable for Check {
+  type Id = i64;
+  type Interval = i64;
+
+  fn get_id(&self) -> Self::Id {
+    self.id
+  }
+
+  fn get_interval(&self) -> Self::Interval {
+    self.interval
+  }
+}
+
+fn check(id: i64) -> Check {
+  Check {
+    id,
+    interval: 30 * (id % 10 + 1),
+  }
+}
+
+async fn schedule(shards: usize) -> Arc<Schedule<Check>> {
+  let schedule = Schedule::with_jitter(30).shards(shards);
+  schedule.insert_many((0..ITEMS).map(check)).await.unwrap();
+
+  Arc::new(schedule)
+}
+
+/// Every task gets and replaces items, while one more task takes due items,
+/// as a runner would.
+async fn concurrent_use(schedule: &Arc<Schedule<Check>>) {
+  let mut tasks = JoinSet::new();
+
+  for task in 0..TASKS {
+    let schedule = Arc::clone(schedule);
+
+    tasks.spawn(async move {
+      for id in (task..ITEMS).step_by(TASKS as usize * 10) {
+        std::hint::black_box(schedule.get(id).await);
+        schedule.insert(check(id)).await.unwrap();
+      }
+    });
+  }
+
+  let schedule = Arc::clone(schedule);
+  tasks.spawn(async move {
+    std::hint::black_box(schedule.get_due(1, 30).await);
+  });
+
+  tasks.join_all().await;
+}
+
+fn bench_concurrent_use(c: &mut Criterion) {
+  let runtime = Runtime::new().expect("runtime");
+  let mut group = c.benchmark_group("concurrent_use");
+
+  for shards in [1, 16] {
+    let schedule = runtime.block_on(schedule(shards));
+
+    group.bench_with_input(
+      BenchmarkId::from_parameter(shards),
+      &schedule,
+      |b, schedule| {
+        b.to_async(&runtime).iter(|| concurrent_use(schedule));
+      },
+    );
+  }
+
+  group.finish();
+}
+
+fn bench_get_due(c: &mut Criterion) {
+  let runtime = Runtime::new().expect("runtime");
+  let mut group = c.benchmark_group("get_due");
+
+  for shards in [1, 16] {
+    let schedule = runtime.block_on(schedule(shards));
+
+    group.bench_with_input(
+      BenchmarkId::from_parameter(shards),
+      &schedule,
+      |b, schedule| {
+        b.to_async(&runtime).iter(|| schedule.get_due(1, 30));
+      },
+    );
+  }
+
+  group.finish();
+}
+
+/// Every item has its own interval, and due items are taken every second.
+fn bench_distinct_intervals(c: &mut Criterion) {
+  let runtime = Runtime::new().expect("runtime");
+  let mut group = c.benchmark_group("distinct_intervals");
+
+  let distinct = |id| Check {
+    id,
+    interval: 60 + id,
+  };
+
+  let schedule = Schedule::new();
+  let wheel = WheelSchedule::new();
+
+  runtime.block_on(async {
+    schedule
+      .insert_many((0..ITEMS).map(distinct))
+      .await
+      .unwrap();
+
+    for id in 0..ITEMS {
+      wheel.insert(distinct(id)).await.unwrap();
+    }
+  });
+
+  let now = AtomicI64::new(1_700_000_000);
+  group.bench_function("schedule", |b| {
+    b.to_async(&runtime).iter(|| {
+      let now = now.fetch_add(1, Ordering::Relaxed);
+      schedule.get_due(now, now)
+    });
+  });
+
+  let now = AtomicI64::new(1_700_000_000);
+  group.bench_function("wheel", |b| {
+    b.to_async(&runtime).iter(|| {
+      let now = now.fetch_add(1, Ordering::Relaxed);
+      wheel.get_due(now, now)
+    });
+  });
+
+  group.finish();
+}
+
+criterion_group!(
+  benches,
+  bench_concurrent_use,
+  bench_get_due,
+  bench_distinct_intervals
+);
+criterion_main!(benches);
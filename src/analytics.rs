@@ -0,0 +1,290 @@
+//! A module aggregating measurements of monitors into the numbers shown on
+//! status pages.
+//!
+//! An [UptimeCalculator] keeps the results of a monitor's measurements and
+//! computes its [Uptime] over any window of time: the share of time it was
+//! up, how often it failed and how long it took to recover. Recent latency
+//! percentiles and failure rates are kept by
+//! [RollingStats](rolling::RollingStats), and unusual latencies are flagged
+//! by an [AnomalyDetector](anomaly::AnomalyDetector). Latency distributions
+//! of several agents can be aggregated as
+//! [LatencyHistogram](histogram::LatencyHistogram)s.
+//!
+//! # Example
+//!
+//! ```rust
+//! use limon_core::analytics::UptimeCalculator;
+//! use limon_core::monitor::models::Measurement;
+//! use time::{Duration, OffsetDateTime};
+//!
+//! fn daily_uptime(measurements: &[Measurement]) -> Option<f64> {
+//!   let mut calculator = UptimeCalculator::new();
+//!   measurements
+//!     .iter()
+//!     .for_each(|measurement| calculator.record(measurement));
+//!
+//!   let now = OffsetDateTime::now_utc();
+//!
+//!   calculator
+//!     .window(now - Duration::days(1), now)
+//!     .map(|uptime| uptime.percentage)
+//! }
+//! ```
+
+use std::time::Duration;
+
+use time::OffsetDateTime;
+
+use crate::monitor::models::{Measurement, Status};
+
+pub mod anomaly;
+pub mod errors;
+pub mod histogram;
+pub mod rolling;
+
+/// Collects the results of the measurements of a single monitor.
+///
+/// A monitor is considered to be in the state of its last measurement until
+/// the next one, so the uptime is weighted by time rather than by the number
+/// of measurements. Degraded measurements count as up, and the ones with
+/// neither data nor an error are ignored.
+#[derive(Debug, Default, Clone)]
+pub struct UptimeCalculator {
+  /// Samples ordered by the moment they were taken.
+  samples: Vec<Sample>,
+}
+
+/// The result of a measurement.
+#[derive(Debug, Clone, Copy)]
+struct Sample {
+  /// Unix timestamp, in milliseconds.
+  at: i64,
+  up: bool,
+  latency: Option<f32>,
+}
+
+/// Availability of a monitor over a window of time, computed by
+/// [UptimeCalculator::window].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Uptime {
+  /// Percentage of the known time the monitor was up.
+  pub percentage: f64,
+
+  /// Time the monitor was up.
+  pub uptime: Duration,
+
+  /// Time the monitor was down.
+  pub downtime: Duration,
+
+  /// Number of times the monitor went down during the window.
+  pub failures: usize,
+
+  /// Mean time to recovery: the average duration of the periods the monitor
+  /// was down, if there were any.
+  pub mttr: Option<Duration>,
+
+  /// Mean time between failures: the time the monitor was up divided by the
+  /// number of failures, if there were any.
+  pub mtbf: Option<Duration>,
+
+  /// Average latency in milliseconds of the successful measurements taken
+  /// during the window.
+  pub average_latency: Option<f32>,
+}
+
+impl UptimeCalculator {
+  /// Creates a calculator without measurements.
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// Records the result of a measurement. Measurements can be recorded in
+  /// any order.
+  pub fn record(&mut self, measurement: &Measurement) {
+    let up = match measurement.status() {
+      Status::Up | Status::Degraded => true,
+      Status::Down => false,
+      Status::Unknown => return,
+    };
+
+    let sample = Sample {
+      at: unix_millis(measurement.timestamp),
+      up,
+      latency: measurement.data.as_ref().map(|data| data.latency()),
+    };
+
+    let index = self.samples.partition_point(|other| other.at <= sample.at);
+    self.samples.insert(index, sample);
+  }
+
+  /// Returns the number of recorded measurements.
+  pub fn len(&self) -> usize {
+    self.samples.len()
+  }
+
+  /// Returns `true` if no measurements are recorded.
+  pub fn is_empty(&self) -> bool {
+    self.samples.is_empty()
+  }
+
+  /// Forgets the measurements taken before `before`, except for the last
+  /// one, which gives the state at that moment.
+  pub fn prune(&mut self, before: OffsetDateTime) {
+    let index = self
+      .samples
+      .partition_point(|sample| sample.at <= unix_millis(before));
+
+    self.samples.drain(..index.saturating_sub(1));
+  }
+
+  /// Computes the uptime between `from` and `to`. The time before the first
+  /// measurement isn't known, so it isn't counted. Returns `None` if none of
+  /// the window is known.
+  pub fn window(&self, from: OffsetDateTime, to: OffsetDateTime) -> Option<Uptime> {
+    let (from, to) = (unix_millis(from), unix_millis(to));
+    let start = self.samples.partition_point(|sample| sample.at < from);
+
+    let mut state = start.checked_sub(1).map(|index| self.samples[index].up);
+    let mut cursor = from;
+    let (mut up, mut down) = (0, 0);
+    let mut failures = 0;
+    let mut down_periods = usize::from(state == Some(false));
+    let (mut latency, mut latencies) = (0.0, 0);
+
+    let mut advance = |state: Option<bool>, until: i64| {
+      match state {
+        Some(true) => up += until - cursor,
+        Some(false) => down += until - cursor,
+        None => {}
+      }
+      cursor = until;
+    };
+
+    for sample in self.samples[start..]
+      .iter()
+      .take_while(|sample| sample.at < to)
+    {
+      advance(state, sample.at);
+
+      if !sample.up && state != Some(false) {
+        failures += 1;
+        down_periods += 1;
+      }
+
+      if let Some(value) = sample.latency {
+        latency += value;
+        latencies += 1;
+      }
+
+      state = Some(sample.up);
+    }
+
+    advance(state, to);
+
+    if up + down <= 0 {
+      return None;
+    }
+
+    let uptime = Duration::from_millis(up as u64);
+    let downtime = Duration::from_millis(down as u64);
+
+    Some(Uptime {
+      percentage: up as f64 * 100.0 / (up + down) as f64,
+      uptime,
+      downtime,
+      failures,
+      mttr: (down_periods > 0).then(|| downtime / down_periods as u32),
+      mtbf: (failures > 0).then(|| uptime / failures as u32),
+      average_latency: (latencies > 0).then(|| latency / latencies as f32),
+    })
+  }
+}
+
+/// Returns the unix timestamp of the moment, in milliseconds.
+fn unix_millis(moment: OffsetDateTime) -> i64 {
+  (moment.unix_timestamp_nanos() / 1_000_000) as i64
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn at(seconds: i64) -> OffsetDateTime {
+    OffsetDateTime::from_unix_timestamp(seconds).unwrap()
+  }
+
+  fn calculator(results: &[(i64, bool)]) -> UptimeCalculator {
+    let mut calculator = UptimeCalculator::new();
+
+    for (seconds, up) in results {
+      calculator.record(
+        &Measurement::fixture(1)
+          .at(*seconds)
+          .latency(*seconds as f32)
+          .up(*up),
+      );
+    }
+
+    calculator
+  }
+
+  #[test]
+  fn uptime() {
+    let calculator = calculator(&[
+      (0, true),
+      (60, false),
+      (90, true),
+      (300, false),
+      (330, false),
+      (360, true),
+    ]);
+
+    let uptime = calculator.window(at(0), at(600)).unwrap();
+
+    assert_eq!(uptime.percentage, 85.0);
+    assert_eq!(uptime.downtime, Duration::from_secs(90));
+    assert_eq!(uptime.failures, 2);
+    assert_eq!(uptime.mttr, Some(Duration::from_secs(45)));
+    assert_eq!(uptime.mtbf, Some(Duration::from_secs(255)));
+    assert_eq!(uptime.average_latency, Some(150.0));
+  }
+
+  #[test]
+  fn partial_windows() {
+    let calculator = calculator(&[(100, true), (200, false), (300, true)]);
+
+    assert!(
+      calculator.window(at(0), at(100)).is_none(),
+      "time before the first measurement isn't known"
+    );
+
+    let uptime = calculator.window(at(250), at(350)).unwrap();
+
+    assert_eq!(uptime.percentage, 50.0, "state at the start of the window");
+    assert_eq!(uptime.failures, 0, "failure began before the window");
+    assert_eq!(uptime.mttr, Some(Duration::from_secs(50)));
+    assert_eq!(uptime.mtbf, None);
+
+    let uptime = calculator.window(at(0), at(200)).unwrap();
+    assert_eq!(uptime.percentage, 100.0);
+  }
+
+  #[test]
+  fn unordered_and_pruned() {
+    let mut calculator = calculator(&[(300, true), (100, true), (200, false)]);
+
+    assert_eq!(
+      calculator.window(at(100), at(400)).unwrap().percentage,
+      200.0 / 3.0
+    );
+
+    calculator.prune(at(250));
+
+    assert_eq!(calculator.len(), 2);
+    assert_eq!(
+      calculator.window(at(250), at(400)).unwrap().downtime,
+      Duration::from_secs(50),
+      "state at the pruning moment is kept"
+    );
+  }
+}
@@ -0,0 +1,256 @@
+//! Latency anomalies against a learned baseline.
+//!
+//! An [AnomalyDetector] learns the usual latency of every monitor as an
+//! exponentially weighted moving average and variance, and flags the
+//! successful measurements whose latency deviates from it by more than a
+//! number of standard deviations. Flagged measurements can be marked as
+//! degraded, so a monitor that slows down unusually is reported even when its
+//! checks succeed within the configured thresholds.
+//!
+//! # Example
+//!
+//! ```rust
+//! use limon_core::analytics::anomaly::AnomalyDetector;
+//! use limon_core::monitor::models::Measurement;
+//!
+//! fn inspect(detector: &mut AnomalyDetector, mut measurement: Measurement) -> Measurement {
+//!   if let Some(anomaly) = detector.apply(&mut measurement) {
+//!     println!("monitor {} is {:.1} sigma off", anomaly.monitor_id, anomaly.sigmas);
+//!   }
+//!
+//!   measurement
+//! }
+//!
+//! let mut detector = AnomalyDetector::new().threshold(4.0);
+//! ```
+
+use std::collections::HashMap;
+
+use crate::monitor::models::{Degradation, Measurement};
+
+/// The default weight of a new latency in the baseline.
+const DEFAULT_ALPHA: f64 = 0.1;
+
+/// The default number of standard deviations a latency is anomalous beyond.
+const DEFAULT_THRESHOLD: f64 = 3.0;
+
+/// The default number of latencies learned before anomalies are flagged.
+const DEFAULT_WARMUP: u32 = 20;
+
+/// The default lowest standard deviation, in milliseconds.
+const DEFAULT_MIN_DEVIATION: f64 = 1.0;
+
+/// Detects latency anomalies of any number of monitors.
+#[derive(Debug, Clone)]
+pub struct AnomalyDetector {
+  alpha: f64,
+  threshold: f64,
+  warmup: u32,
+  min_deviation: f64,
+
+  /// Baselines by monitor id.
+  baselines: HashMap<i64, Baseline>,
+}
+
+/// The usual latency of a monitor.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Baseline {
+  /// Moving average of the latency, in milliseconds.
+  pub mean: f64,
+
+  /// Moving variance of the latency, in square milliseconds.
+  pub variance: f64,
+
+  /// Number of latencies learned.
+  pub samples: u32,
+}
+
+/// A measurement whose latency deviates from the baseline of its monitor.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Anomaly {
+  /// Identifier of the monitor.
+  pub monitor_id: i64,
+
+  /// Latency of the measurement, in milliseconds.
+  pub latency: f32,
+
+  /// Usual latency of the monitor, in milliseconds.
+  pub expected: f64,
+
+  /// Number of standard deviations the latency is above the usual one.
+  pub sigmas: f64,
+}
+
+impl AnomalyDetector {
+  /// Creates a detector flagging latencies beyond 3 standard deviations, once
+  /// 20 of them are learned.
+  pub fn new() -> Self {
+    Self {
+      alpha: DEFAULT_ALPHA,
+      threshold: DEFAULT_THRESHOLD,
+      warmup: DEFAULT_WARMUP,
+      min_deviation: DEFAULT_MIN_DEVIATION,
+      baselines: HashMap::new(),
+    }
+  }
+
+  /// Sets the weight of a new latency in the baseline, between 0 and 1, 0.1
+  /// by default. Higher weights adapt faster to lasting changes.
+  pub fn alpha(mut self, alpha: f64) -> Self {
+    self.alpha = alpha.clamp(f64::EPSILON, 1.0);
+    self
+  }
+
+  /// Sets the number of standard deviations a latency is anomalous beyond.
+  pub fn threshold(mut self, threshold: f64) -> Self {
+    self.threshold = threshold;
+    self
+  }
+
+  /// Sets the number of latencies of a monitor learned before its anomalies
+  /// are flagged.
+  pub fn warmup(mut self, warmup: u32) -> Self {
+    self.warmup = warmup;
+    self
+  }
+
+  /// Sets the lowest standard deviation, in milliseconds, so a monitor with
+  /// a steady latency isn't flagged for a jitter of a millisecond.
+  pub fn min_deviation(mut self, min_deviation: f64) -> Self {
+    self.min_deviation = min_deviation.max(0.0);
+    self
+  }
+
+  /// Returns the baseline of a monitor, if any of its latencies is learned.
+  pub fn baseline(&self, monitor_id: i64) -> Option<&Baseline> {
+    self.baselines.get(&monitor_id)
+  }
+
+  /// Forgets the baseline of a monitor, e.g. after its config changed.
+  pub fn reset(&mut self, monitor_id: i64) {
+    self.baselines.remove(&monitor_id);
+  }
+
+  /// Compares the latency of a successful measurement with the baseline of
+  /// its monitor, then learns it. Only latencies higher than usual are
+  /// flagged. Failed measurements are ignored.
+  pub fn observe(&mut self, measurement: &Measurement) -> Option<Anomaly> {
+    if measurement.error.is_some() {
+      return None;
+    }
+
+    let latency = measurement.data.as_ref()?.latency();
+    let value = f64::from(latency);
+
+    let Some(baseline) = self.baselines.get_mut(&measurement.monitor_id) else {
+      self.baselines.insert(measurement.monitor_id, Baseline {
+        mean: value,
+        variance: 0.0,
+        samples: 1,
+      });
+
+      return None;
+    };
+
+    let deviation = baseline.variance.sqrt().max(self.min_deviation);
+    let sigmas = if deviation > 0.0 {
+      (value - baseline.mean) / deviation
+    } else {
+      0.0
+    };
+
+    let anomaly = (baseline.samples >= self.warmup && sigmas > self.threshold).then_some(Anomaly {
+      monitor_id: measurement.monitor_id,
+      latency,
+      expected: baseline.mean,
+      sigmas,
+    });
+
+    let difference = value - baseline.mean;
+    baseline.mean += self.alpha * difference;
+    baseline.variance =
+      (1.0 - self.alpha) * (baseline.variance + self.alpha * difference * difference);
+    baseline.samples = baseline.samples.saturating_add(1);
+
+    anomaly
+  }
+
+  /// Observes the measurement and, if it's anomalous, marks it as degraded
+  /// with a warning, unless it already is.
+  pub fn apply(&mut self, measurement: &mut Measurement) -> Option<Anomaly> {
+    let anomaly = self.observe(measurement)?;
+    measurement.degradation.get_or_insert(Degradation::Warning);
+
+    Some(anomaly)
+  }
+}
+
+impl Default for AnomalyDetector {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::monitor::models::Status;
+
+  #[test]
+  fn flags_deviations() {
+    let mut detector = AnomalyDetector::new().warmup(10);
+
+    for index in 0..30 {
+      let latency = if index % 2 == 0 { 95.0 } else { 105.0 };
+      assert_eq!(
+        detector.observe(&Measurement::fixture(1).latency(latency)),
+        None
+      );
+    }
+
+    let baseline = *detector.baseline(1).unwrap();
+    assert!((baseline.mean - 100.0).abs() < 1.0);
+    assert_eq!(baseline.samples, 30);
+
+    assert_eq!(
+      detector.observe(&Measurement::fixture(1).latency(110.0)),
+      None,
+      "within the threshold"
+    );
+    assert_eq!(
+      detector.observe(&Measurement::fixture(1).latency(20.0)),
+      None,
+      "faster than usual"
+    );
+
+    let mut slow = Measurement::fixture(1).latency(400.0);
+    let anomaly = detector.apply(&mut slow).unwrap();
+
+    assert!(anomaly.sigmas > 3.0);
+    assert!((anomaly.expected - 100.0).abs() < 10.0);
+    assert_eq!(slow.status(), Status::Degraded);
+
+    assert_eq!(
+      detector.observe(&Measurement::fixture(2).latency(400.0)),
+      None,
+      "a baseline per monitor"
+    );
+  }
+
+  #[test]
+  fn warmup() {
+    let mut detector = AnomalyDetector::new().warmup(5);
+
+    for _ in 0..4 {
+      detector.observe(&Measurement::fixture(1).latency(50.0));
+    }
+
+    assert_eq!(
+      detector.observe(&Measurement::fixture(1).latency(500.0)),
+      None
+    );
+
+    detector.reset(1);
+    assert!(detector.baseline(1).is_none());
+  }
+}
@@ -0,0 +1,16 @@
+//! A module describing analytics errors.
+
+use thiserror::Error;
+
+/// Errors that can occur when aggregating measurements.
+#[derive(Error, Debug)]
+pub enum AnalyticsError {
+  /// Histograms with different buckets can't be merged.
+  #[error("Histogram buckets don't match")]
+  BucketsMismatch,
+
+  /// A deserialized histogram has unordered bounds, or a count per bucket
+  /// missing.
+  #[error("Invalid histogram: bounds must ascend and have a count each")]
+  InvalidHistogram,
+}
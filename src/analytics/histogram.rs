@@ -0,0 +1,287 @@
+//! Latency distributions that can be merged.
+//!
+//! A [LatencyHistogram] counts the latencies of successful measurements in
+//! buckets with fixed upper bounds. Histograms with the same buckets can be
+//! [merged](LatencyHistogram::merge), e.g. the ones of several agents
+//! measuring the same monitor, and they're serializable, so agents can send
+//! them to where they're aggregated.
+//!
+//! # Example
+//!
+//! ```rust
+//! use limon_core::analytics::histogram::LatencyHistogram;
+//! use limon_core::monitor::models::Measurement;
+//!
+//! fn p95(agents: &[Vec<Measurement>]) -> Option<f64> {
+//!   let mut total = LatencyHistogram::new();
+//!
+//!   for measurements in agents {
+//!     let mut histogram = LatencyHistogram::new();
+//!     measurements.iter().for_each(|measurement| histogram.record(measurement));
+//!
+//!     total.merge(&histogram).unwrap();
+//!   }
+//!
+//!   total.quantile(0.95)
+//! }
+//! ```
+
+use super::errors::AnalyticsError;
+use crate::monitor::models::{Measurement, Status};
+
+/// Default upper bounds, in milliseconds, of the buckets.
+const DEFAULT_BUCKETS: [f64; 11] = [
+  5.0, 10.0, 25.0, 50.0, 100.0, 250.0, 500.0, 1000.0, 2500.0, 5000.0, 10000.0,
+];
+
+/// A histogram of latencies, in milliseconds.
+///
+/// Besides a bucket per upper bound, there's an unbounded one for the
+/// latencies above the highest bound.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(try_from = "SerializedHistogram")]
+pub struct LatencyHistogram {
+  /// Upper bounds of the buckets, ascending.
+  bounds: Vec<f64>,
+
+  /// Number of latencies in each bucket, not cumulative.
+  counts: Vec<u64>,
+
+  sum: f64,
+  min: Option<f32>,
+  max: Option<f32>,
+}
+
+/// A [LatencyHistogram] as it's deserialized, before it's validated.
+#[derive(serde::Deserialize)]
+struct SerializedHistogram {
+  bounds: Vec<f64>,
+  counts: Vec<u64>,
+  sum: f64,
+  min: Option<f32>,
+  max: Option<f32>,
+}
+
+impl TryFrom<SerializedHistogram> for LatencyHistogram {
+  type Error = AnalyticsError;
+
+  fn try_from(histogram: SerializedHistogram) -> Result<Self, Self::Error> {
+    let sorted = histogram.bounds.windows(2).all(|pair| pair[0] < pair[1]);
+
+    if !sorted || histogram.counts.len() != histogram.bounds.len() + 1 {
+      return Err(AnalyticsError::InvalidHistogram);
+    }
+
+    Ok(Self {
+      bounds: histogram.bounds,
+      counts: histogram.counts,
+      sum: histogram.sum,
+      min: histogram.min,
+      max: histogram.max,
+    })
+  }
+}
+
+impl LatencyHistogram {
+  /// Creates an empty histogram with buckets from 5 milliseconds to 10
+  /// seconds.
+  pub fn new() -> Self {
+    Self::with_buckets(DEFAULT_BUCKETS)
+  }
+
+  /// Creates an empty histogram with the given upper bounds of the buckets,
+  /// in milliseconds.
+  pub fn with_buckets(bounds: impl IntoIterator<Item = f64>) -> Self {
+    let mut bounds: Vec<f64> = bounds
+      .into_iter()
+      .filter(|bound| bound.is_finite())
+      .collect();
+    bounds.sort_by(f64::total_cmp);
+    bounds.dedup();
+
+    Self {
+      counts: vec![0; bounds.len() + 1],
+      bounds,
+      sum: 0.0,
+      min: None,
+      max: None,
+    }
+  }
+
+  /// Adds the latency of a successful measurement, possibly degraded.
+  pub fn record(&mut self, measurement: &Measurement) {
+    if matches!(measurement.status(), Status::Up | Status::Degraded)
+      && let Some(data) = &measurement.data
+    {
+      self.observe(data.latency());
+    }
+  }
+
+  /// Adds a latency, in milliseconds.
+  pub fn observe(&mut self, latency: f32) {
+    let value = f64::from(latency);
+    let bucket = self.bounds.partition_point(|bound| *bound < value);
+
+    self.counts[bucket] += 1;
+    self.sum += value;
+    self.min = Some(self.min.map_or(latency, |min| min.min(latency)));
+    self.max = Some(self.max.map_or(latency, |max| max.max(latency)));
+  }
+
+  /// Adds the latencies of another histogram with the same buckets.
+  pub fn merge(&mut self, other: &LatencyHistogram) -> Result<(), AnalyticsError> {
+    if self.bounds != other.bounds {
+      return Err(AnalyticsError::BucketsMismatch);
+    }
+
+    for (count, other) in self.counts.iter_mut().zip(&other.counts) {
+      *count += other;
+    }
+
+    self.sum += other.sum;
+    self.min = self.min.into_iter().chain(other.min).reduce(f32::min);
+    self.max = self.max.into_iter().chain(other.max).reduce(f32::max);
+
+    Ok(())
+  }
+
+  /// Returns the upper bounds of the buckets, with the number of latencies
+  /// up to each, cumulative. The last bound is infinite.
+  pub fn buckets(&self) -> impl Iterator<Item = (f64, u64)> + '_ {
+    self
+      .bounds
+      .iter()
+      .copied()
+      .chain([f64::INFINITY])
+      .zip(self.counts.iter().scan(0, |total, count| {
+        *total += count;
+        Some(*total)
+      }))
+  }
+
+  /// Returns the number of latencies.
+  pub fn count(&self) -> u64 {
+    self.counts.iter().sum()
+  }
+
+  /// Returns the sum of the latencies, in milliseconds.
+  pub fn sum(&self) -> f64 {
+    self.sum
+  }
+
+  /// Returns the lowest latency.
+  pub fn min(&self) -> Option<f32> {
+    self.min
+  }
+
+  /// Returns the highest latency.
+  pub fn max(&self) -> Option<f32> {
+    self.max
+  }
+
+  /// Returns the average latency.
+  pub fn mean(&self) -> Option<f64> {
+    let count = self.count();
+
+    (count > 0).then(|| self.sum / count as f64)
+  }
+
+  /// Estimates the latency below which the given share of latencies fall,
+  /// between 0 and 1, interpolating within its bucket. The estimate is
+  /// within the lowest and the highest latency.
+  pub fn quantile(&self, quantile: f64) -> Option<f64> {
+    let (min, max) = (f64::from(self.min?), f64::from(self.max?));
+    let rank = quantile.clamp(0.0, 1.0) * self.count() as f64;
+    let mut below = 0;
+
+    for (index, count) in self.counts.iter().enumerate() {
+      if *count == 0 || ((below + count) as f64) < rank {
+        below += count;
+        continue;
+      }
+
+      let lower = index
+        .checked_sub(1)
+        .map_or(min, |index| self.bounds[index])
+        .max(min);
+      let upper = self.bounds.get(index).map_or(max, |bound| bound.min(max));
+      let share = (rank - below as f64) / *count as f64;
+
+      return Some(lower + (upper - lower) * share);
+    }
+
+    Some(max)
+  }
+}
+
+impl Default for LatencyHistogram {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::monitor::errors::{CollectorError, HttpError};
+
+  #[test]
+  fn distribution() {
+    let mut histogram = LatencyHistogram::with_buckets([100.0, 50.0, f64::NAN]);
+
+    for latency in [20.0, 40.0, 60.0, 80.0, 300.0] {
+      histogram.record(&Measurement::fixture(1).latency(latency));
+    }
+    histogram.record(&Measurement {
+      error: Some(CollectorError::Http(HttpError::ClientUnavailable)),
+      ..Measurement::fixture(1).latency(1.0)
+    });
+
+    assert_eq!(histogram.buckets().collect::<Vec<_>>(), [
+      (50.0, 2),
+      (100.0, 4),
+      (f64::INFINITY, 5)
+    ]);
+    assert_eq!(histogram.count(), 5);
+    assert_eq!(histogram.mean(), Some(100.0));
+    assert_eq!(
+      (histogram.min(), histogram.max()),
+      (Some(20.0), Some(300.0))
+    );
+
+    assert_eq!(histogram.quantile(0.0), Some(20.0));
+    assert_eq!(histogram.quantile(0.6), Some(75.0));
+    assert_eq!(histogram.quantile(1.0), Some(300.0));
+    assert_eq!(LatencyHistogram::new().quantile(0.5), None);
+  }
+
+  #[test]
+  fn merge() {
+    let mut first = LatencyHistogram::with_buckets([50.0]);
+    let mut second = LatencyHistogram::with_buckets([50.0]);
+
+    first.observe(10.0);
+    second.observe(70.0);
+    second.observe(30.0);
+
+    let serialized = serde_json::to_string(&second).unwrap();
+    first
+      .merge(&serde_json::from_str(&serialized).unwrap())
+      .unwrap();
+
+    assert_eq!(first.count(), 3);
+    assert_eq!(first.sum(), 110.0);
+    assert_eq!((first.min(), first.max()), (Some(10.0), Some(70.0)));
+
+    assert!(matches!(
+      first.merge(&LatencyHistogram::new()),
+      Err(AnalyticsError::BucketsMismatch)
+    ));
+    assert!(
+      serde_json::from_str::<LatencyHistogram>(
+        r#"{"bounds": [50.0], "counts": [1], "sum": 1.0, "min": 1.0, "max": 1.0}"#
+      )
+      .is_err()
+    );
+  }
+}
@@ -0,0 +1,245 @@
+//! Latency percentiles and failure rates over a sliding window.
+//!
+//! [RollingStats] keep the measurements of a monitor taken within the last
+//! window of time, e.g. the last 5 minutes, and summarize them on demand, so
+//! alert rules and exporters can watch recent behavior rather than all-time
+//! numbers.
+//!
+//! # Example
+//!
+//! ```rust
+//! use std::time::Duration;
+//!
+//! use limon_core::analytics::rolling::RollingStats;
+//! use limon_core::monitor::models::Measurement;
+//!
+//! fn is_slow(stats: &mut RollingStats, measurement: &Measurement) -> bool {
+//!   stats.record(measurement);
+//!
+//!   stats.summary().and_then(|summary| summary.p99).is_some_and(|p99| p99 > 500.0)
+//! }
+//!
+//! let mut stats = RollingStats::new(Duration::from_secs(300));
+//! ```
+
+use std::collections::VecDeque;
+use std::time::Duration;
+
+use super::unix_millis;
+use crate::monitor::models::Measurement;
+
+/// The default maximum number of measurements kept by [RollingStats].
+const DEFAULT_CAPACITY: usize = 4096;
+
+/// Measurements of a single monitor taken within a sliding window.
+///
+/// The window ends at the latest measurement recorded, so the stats don't
+/// depend on the current time. At most [capacity](RollingStats::capacity)
+/// measurements are kept; the oldest ones are dropped first.
+#[derive(Debug, Clone)]
+pub struct RollingStats {
+  window: i64,
+  capacity: usize,
+
+  /// Samples ordered by the moment they were taken.
+  samples: VecDeque<Sample>,
+}
+
+/// The result of a measurement.
+#[derive(Debug, Clone, Copy)]
+struct Sample {
+  /// Unix timestamp, in milliseconds.
+  at: i64,
+
+  /// Latency in milliseconds of a successful measurement.
+  latency: Option<f32>,
+}
+
+/// A summary of the measurements in the window of [RollingStats].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Summary {
+  /// Number of measurements in the window.
+  pub count: usize,
+
+  /// Share of the measurements that failed, between 0 and 1.
+  pub failure_rate: f64,
+
+  /// Median latency in milliseconds of the successful measurements.
+  pub p50: Option<f32>,
+
+  /// 90th percentile of the latency, in milliseconds.
+  pub p90: Option<f32>,
+
+  /// 99th percentile of the latency, in milliseconds.
+  pub p99: Option<f32>,
+}
+
+impl RollingStats {
+  /// Creates stats over the measurements taken within `window`.
+  pub fn new(window: Duration) -> Self {
+    Self {
+      window: window.as_millis() as i64,
+      capacity: DEFAULT_CAPACITY,
+      samples: VecDeque::new(),
+    }
+  }
+
+  /// Sets the maximum number of measurements kept, 4096 by default.
+  pub fn capacity(mut self, capacity: usize) -> Self {
+    self.capacity = capacity.max(1);
+    self
+  }
+
+  /// Records a measurement, dropping the ones that are out of the window
+  /// afterwards. Measurements without data are counted as failed.
+  pub fn record(&mut self, measurement: &Measurement) {
+    let sample = Sample {
+      at: unix_millis(measurement.timestamp),
+      latency: measurement
+        .data
+        .as_ref()
+        .filter(|_| measurement.error.is_none())
+        .map(|data| data.latency()),
+    };
+
+    let index = self.samples.partition_point(|other| other.at <= sample.at);
+    self.samples.insert(index, sample);
+
+    let end = self.samples.back().map_or(sample.at, |last| last.at);
+
+    while self
+      .samples
+      .front()
+      .is_some_and(|first| first.at <= end - self.window)
+      || self.samples.len() > self.capacity
+    {
+      self.samples.pop_front();
+    }
+  }
+
+  /// Returns the number of measurements in the window.
+  pub fn len(&self) -> usize {
+    self.samples.len()
+  }
+
+  /// Returns `true` if there are no measurements in the window.
+  pub fn is_empty(&self) -> bool {
+    self.samples.is_empty()
+  }
+
+  /// Returns the share of the measurements in the window that failed, or
+  /// `None` if there are none.
+  pub fn failure_rate(&self) -> Option<f64> {
+    if self.samples.is_empty() {
+      return None;
+    }
+
+    let failed = self
+      .samples
+      .iter()
+      .filter(|sample| sample.latency.is_none())
+      .count();
+
+    Some(failed as f64 / self.samples.len() as f64)
+  }
+
+  /// Returns the latency, in milliseconds, below or at which `percentile`
+  /// percent of the successful measurements in the window are, or `None` if
+  /// there are none.
+  pub fn percentile(&self, percentile: f64) -> Option<f32> {
+    percentile_of(&mut self.latencies(), percentile)
+  }
+
+  /// Summarizes the measurements in the window, or returns `None` if there are
+  /// none.
+  pub fn summary(&self) -> Option<Summary> {
+    let failure_rate = self.failure_rate()?;
+    let mut latencies = self.latencies();
+
+    Some(Summary {
+      count: self.samples.len(),
+      failure_rate,
+      p50: percentile_of(&mut latencies, 50.0),
+      p90: percentile_of(&mut latencies, 90.0),
+      p99: percentile_of(&mut latencies, 99.0),
+    })
+  }
+
+  fn latencies(&self) -> Vec<f32> {
+    self
+      .samples
+      .iter()
+      .filter_map(|sample| sample.latency)
+      .collect()
+  }
+}
+
+/// Returns the nearest-rank percentile of the latencies, sorting them.
+fn percentile_of(latencies: &mut [f32], percentile: f64) -> Option<f32> {
+  if latencies.is_empty() {
+    return None;
+  }
+
+  latencies.sort_by(f32::total_cmp);
+
+  let rank = (percentile.clamp(0.0, 100.0) / 100.0 * latencies.len() as f64).ceil() as usize;
+
+  Some(latencies[rank.saturating_sub(1)])
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn percentiles() {
+    let mut stats = RollingStats::new(Duration::from_secs(1000));
+
+    for latency in 1..=100 {
+      stats.record(&Measurement::fixture(1).at(latency).latency(latency as f32));
+    }
+    stats.record(&Measurement::fixture(1).at(101).up(false));
+
+    let summary = stats.summary().unwrap();
+
+    assert_eq!(summary.count, 101);
+    assert_eq!(summary.p50, Some(50.0));
+    assert_eq!(summary.p90, Some(90.0));
+    assert_eq!(summary.p99, Some(99.0));
+    assert_eq!(summary.failure_rate, 1.0 / 101.0);
+    assert_eq!(stats.percentile(100.0), Some(100.0));
+  }
+
+  #[test]
+  fn sliding_window() {
+    let mut stats = RollingStats::new(Duration::from_secs(60));
+    assert_eq!(stats.summary(), None);
+
+    stats.record(&Measurement::fixture(1).at(0).up(false));
+    stats.record(&Measurement::fixture(1).at(30).latency(10.0));
+    assert_eq!(stats.failure_rate(), Some(0.5));
+
+    stats.record(&Measurement::fixture(1).at(60).latency(20.0));
+    assert_eq!(stats.len(), 2, "measurements out of the window are dropped");
+    assert_eq!(stats.failure_rate(), Some(0.0));
+
+    stats.record(&Measurement::fixture(1).at(0).up(false));
+    assert_eq!(stats.len(), 2, "late measurements out of the window too");
+
+    stats.record(&Measurement::fixture(1).at(45).latency(30.0));
+    assert_eq!(stats.len(), 3);
+    assert_eq!(stats.percentile(50.0), Some(20.0));
+  }
+
+  #[test]
+  fn bounded_capacity() {
+    let mut stats = RollingStats::new(Duration::from_secs(1000)).capacity(10);
+
+    for seconds in 0..20 {
+      stats.record(&Measurement::fixture(1).at(seconds).latency(seconds as f32));
+    }
+
+    assert_eq!(stats.len(), 10);
+    assert_eq!(stats.percentile(0.0), Some(10.0), "the oldest are dropped");
+  }
+}
@@ -0,0 +1,382 @@
+//! A module splitting a shared set of monitors between agents.
+//!
+//! The ids of the monitors are spread over a fixed number of shards, and
+//! every shard is checked by the single agent holding its [Lease] in a
+//! shared [LeaseStore], e.g. a table of a database the agents can all reach.
+//! A [Coordinator] renews the leases of an agent periodically: it holds
+//! about as many shards as every other live agent, takes over the shards of
+//! the agents that left or stopped renewing theirs, and hands over the ones
+//! beyond its share to the agents that joined.
+//!
+//! As a lease is held by one agent at a time, a monitor is never checked by
+//! two agents at once, as long as an agent stops checking the shards it
+//! couldn't renew once their leases [expire](Ownership::is_expired). The
+//! shards of an agent that stops are checked again once their leases
+//! expire, or right away if it [resigns](Coordinator::resign).
+//!
+//! - **memory** - Keeps the leases in memory, e.g. to coordinate the runners
+//!   of a single process.
+//!
+//! # Example
+//!
+//! ```rust, no_run
+//! use std::time::Duration;
+//!
+//! use limon_core::coordination::{Coordinator, LeaseStore};
+//! use limon_core::monitor::models::{Measurement, Monitor};
+//! use limon_core::schedule::runner::{Runner, Sink};
+//!
+//! async fn coordinate(
+//!   coordinator: Coordinator<impl LeaseStore>,
+//!   runner: &Runner<Monitor, impl Sink<Measurement>>,
+//!   monitors: &[Monitor],
+//! ) {
+//!   loop {
+//!     // Renewed well within the time to live of the leases, 30 seconds.
+//!     if let Ok(ownership) = coordinator.renew().await {
+//!       let owned = monitors.iter().filter(|monitor| ownership.owns(&monitor.id));
+//!       let _ = runner.apply(owned.cloned().collect()).await;
+//!     }
+//!
+//!     tokio::time::sleep(Duration::from_secs(10)).await;
+//!   }
+//! }
+//! ```
+
+use std::collections::{BTreeSet, HashMap};
+use std::future::Future;
+use std::hash::{Hash, Hasher};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use time::OffsetDateTime;
+
+use crate::coordination::errors::CoordinationError;
+
+pub mod errors;
+pub mod memory;
+
+/// The default number of shards monitors are spread over.
+const DEFAULT_SHARDS: u32 = 64;
+
+/// The default time to live of the leases.
+const DEFAULT_TTL: Duration = Duration::from_secs(30);
+
+/// The prefix of the keys of the leases of the live agents.
+const AGENTS: &str = "agent/";
+
+/// The prefix of the keys of the leases of the shards.
+const SHARDS: &str = "shard/";
+
+/// The right of an agent to a key until it expires.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct Lease {
+  /// What's leased, e.g. `shard/7`.
+  pub key: String,
+
+  /// Name of the agent holding the lease.
+  pub holder: String,
+
+  /// When the lease expires, unless it's renewed.
+  #[serde(with = "time::serde::timestamp::milliseconds_i64")]
+  pub expires: OffsetDateTime,
+}
+
+/// A store of leases shared by the agents.
+pub trait LeaseStore: Send + Sync + 'static {
+  /// Acquires the lease of `key` for `holder` for `ttl`, or renews it if
+  /// it's already the holder. Returns `None` if another holder has a lease
+  /// that hasn't expired. It must be atomic for all the agents, e.g. a
+  /// conditional update of a database.
+  fn acquire(
+    &self,
+    key: &str,
+    holder: &str,
+    ttl: Duration,
+  ) -> impl Future<Output = Result<Option<Lease>, CoordinationError>> + Send;
+
+  /// Releases the lease of `key`, if `holder` holds it.
+  fn release(
+    &self,
+    key: &str,
+    holder: &str,
+  ) -> impl Future<Output = Result<(), CoordinationError>> + Send;
+
+  /// Returns the leases that haven't expired with a key starting with
+  /// `prefix`.
+  fn leases(
+    &self,
+    prefix: &str,
+  ) -> impl Future<Output = Result<Vec<Lease>, CoordinationError>> + Send;
+}
+
+impl<S: LeaseStore> LeaseStore for Arc<S> {
+  async fn acquire(
+    &self,
+    key: &str,
+    holder: &str,
+    ttl: Duration,
+  ) -> Result<Option<Lease>, CoordinationError> {
+    self.as_ref().acquire(key, holder, ttl).await
+  }
+
+  async fn release(&self, key: &str, holder: &str) -> Result<(), CoordinationError> {
+    self.as_ref().release(key, holder).await
+  }
+
+  async fn leases(&self, prefix: &str) -> Result<Vec<Lease>, CoordinationError> {
+    self.as_ref().leases(prefix).await
+  }
+}
+
+/// Returns the shard of `id` out of `shards`. It's the same for every agent,
+/// whatever its build, as long as `id` is hashed the same way, e.g. it's a
+/// number or a string.
+pub fn shard<Id: Hash + ?Sized>(id: &Id, shards: u32) -> u32 {
+  let mut hasher = Fnv::default();
+  id.hash(&mut hasher);
+
+  (hasher.finish() % u64::from(shards.max(1))) as u32
+}
+
+/// The FNV-1a hash, with integers hashed as little-endian bytes, so shards
+/// don't depend on the platform or the version of the standard library.
+struct Fnv(u64);
+
+impl Default for Fnv {
+  fn default() -> Self {
+    Self(0xcbf2_9ce4_8422_2325)
+  }
+}
+
+impl Hasher for Fnv {
+  fn finish(&self) -> u64 {
+    self.0
+  }
+
+  fn write(&mut self, bytes: &[u8]) {
+    for byte in bytes {
+      self.0 ^= u64::from(*byte);
+      self.0 = self.0.wrapping_mul(0x0100_0000_01b3);
+    }
+  }
+
+  fn write_u16(&mut self, value: u16) {
+    self.write(&value.to_le_bytes());
+  }
+
+  fn write_u32(&mut self, value: u32) {
+    self.write(&value.to_le_bytes());
+  }
+
+  fn write_u64(&mut self, value: u64) {
+    self.write(&value.to_le_bytes());
+  }
+
+  fn write_u128(&mut self, value: u128) {
+    self.write(&value.to_le_bytes());
+  }
+
+  fn write_usize(&mut self, value: usize) {
+    self.write_u64(value as u64);
+  }
+}
+
+/// The shards an agent holds the leases of, returned by
+/// [Coordinator::renew].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Ownership {
+  shards: BTreeSet<u32>,
+  total: u32,
+
+  /// When the earliest of the leases expires.
+  expires: Instant,
+}
+
+impl Ownership {
+  /// Returns whether the agent should check the monitor with `id`.
+  pub fn owns<Id: Hash + ?Sized>(&self, id: &Id) -> bool {
+    self.shards.contains(&shard(id, self.total))
+  }
+
+  /// Returns the shards held by the agent.
+  pub fn shards(&self) -> &BTreeSet<u32> {
+    &self.shards
+  }
+
+  /// Returns whether the leases expired, after which the agent should stop
+  /// checking the monitors it owned until it renews them.
+  pub fn is_expired(&self) -> bool {
+    Instant::now() >= self.expires
+  }
+}
+
+/// Renews the leases of an agent.
+pub struct Coordinator<S> {
+  store: S,
+  agent: String,
+  shards: u32,
+  ttl: Duration,
+}
+
+impl<S: LeaseStore> Coordinator<S> {
+  /// Creates the coordinator of the agent named `agent`, unique among the
+  /// agents sharing `store`.
+  pub fn new(store: S, agent: impl Into<String>) -> Self {
+    Self {
+      store,
+      agent: agent.into(),
+      shards: DEFAULT_SHARDS,
+      ttl: DEFAULT_TTL,
+    }
+  }
+
+  /// Sets the number of shards monitors are spread over, 64 by default. It
+  /// must be the same for all the agents, and should exceed their number.
+  pub fn shards(mut self, shards: u32) -> Self {
+    self.shards = shards.max(1);
+    self
+  }
+
+  /// Sets the time to live of the leases, 30 seconds by default. They
+  /// should be renewed a few times within it, and it bounds the time the
+  /// monitors of an agent that stopped without resigning aren't checked.
+  pub fn ttl(mut self, ttl: Duration) -> Self {
+    self.ttl = ttl;
+    self
+  }
+
+  /// Renews the leases of the agent and returns the shards it holds. The
+  /// agent keeps its shards up to its share of them, releases the others,
+  /// and takes free shards until it has its share.
+  pub async fn renew(&self) -> Result<Ownership, CoordinationError> {
+    let start = Instant::now();
+
+    self
+      .store
+      .acquire(&format!("{AGENTS}{}", self.agent), &self.agent, self.ttl)
+      .await?;
+
+    let agents = self.store.leases(AGENTS).await?.len().max(1) as u32;
+    let share = self.shards.div_ceil(agents) as usize;
+
+    let held: HashMap<u32, String> = self
+      .store
+      .leases(SHARDS)
+      .await?
+      .into_iter()
+      .filter_map(|lease| Some((lease.key.strip_prefix(SHARDS)?.parse().ok()?, lease.holder)))
+      .collect();
+    let mut own: Vec<u32> = held
+      .iter()
+      .filter(|(_, holder)| **holder == self.agent)
+      .map(|(shard, _)| *shard)
+      .collect();
+    own.sort_unstable();
+
+    let mut shards = BTreeSet::new();
+
+    for shard in own {
+      if shards.len() < share && self.lease(shard).await?.is_some() {
+        shards.insert(shard);
+      } else {
+        self.store.release(&shard_key(shard), &self.agent).await?;
+      }
+    }
+
+    // Agents start looking for free shards at different ones, so they don't
+    // all contend for the same.
+    let first = shard(self.agent.as_str(), self.shards);
+
+    for shard in (0..self.shards).map(|offset| (first + offset) % self.shards) {
+      if shards.len() >= share {
+        break;
+      }
+
+      if !held.contains_key(&shard) && self.lease(shard).await?.is_some() {
+        shards.insert(shard);
+      }
+    }
+
+    Ok(Ownership {
+      shards,
+      total: self.shards,
+      expires: start + self.ttl,
+    })
+  }
+
+  /// Releases the leases of the agent, e.g. as it shuts down, so the other
+  /// agents take its shards over on their next renewal.
+  pub async fn resign(&self) -> Result<(), CoordinationError> {
+    for lease in self.store.leases(SHARDS).await? {
+      if lease.holder == self.agent {
+        self.store.release(&lease.key, &self.agent).await?;
+      }
+    }
+
+    self
+      .store
+      .release(&format!("{AGENTS}{}", self.agent), &self.agent)
+      .await
+  }
+
+  async fn lease(&self, shard: u32) -> Result<Option<Lease>, CoordinationError> {
+    self
+      .store
+      .acquire(&shard_key(shard), &self.agent, self.ttl)
+      .await
+  }
+}
+
+/// Returns the key of the lease of a shard.
+fn shard_key(shard: u32) -> String {
+  format!("{SHARDS}{shard}")
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::coordination::memory::MemoryLeaseStore;
+
+  #[test]
+  fn stable_shards() {
+    assert_eq!(shard(&42_i64, 64), shard(&42_i64, 64));
+    assert_eq!(shard("api", 64), shard(&String::from("api"), 64));
+    assert_eq!(shard(&7_i64, 1), 0);
+    assert!((0..1000_i64).all(|id| shard(&id, 16) < 16));
+  }
+
+  #[tokio::test]
+  async fn split_shards() {
+    let store = Arc::new(MemoryLeaseStore::new());
+    let first = Coordinator::new(Arc::clone(&store), "first").shards(8);
+    let second = Coordinator::new(Arc::clone(&store), "second").shards(8);
+
+    assert_eq!(first.renew().await.unwrap().shards().len(), 8);
+    assert!(
+      second.renew().await.unwrap().shards().is_empty(),
+      "the shards are held by the first agent"
+    );
+
+    // The first agent hands over the shards beyond its share, which the
+    // second takes.
+    let owned = first.renew().await.unwrap();
+    let taken = second.renew().await.unwrap();
+    assert_eq!((owned.shards().len(), taken.shards().len()), (4, 4));
+    assert!(owned.shards().is_disjoint(taken.shards()));
+    assert!(!owned.is_expired());
+
+    let ids: Vec<i64> = (0..100).collect();
+    assert!(
+      ids.iter().all(|id| owned.owns(id) != taken.owns(id)),
+      "every monitor is checked by a single agent"
+    );
+
+    first.resign().await.unwrap();
+    assert_eq!(
+      second.renew().await.unwrap().shards().len(),
+      8,
+      "the shards of a resigned agent are taken over"
+    );
+  }
+}
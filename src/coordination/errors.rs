@@ -0,0 +1,11 @@
+//! A module describing coordination errors.
+
+use thiserror::Error;
+
+/// Errors that can occur when coordinating agents.
+#[derive(Error, Debug)]
+pub enum CoordinationError {
+  /// The lease store failed, e.g. its database couldn't be reached.
+  #[error("Lease store error: {0}")]
+  Store(String),
+}
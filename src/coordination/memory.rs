@@ -0,0 +1,116 @@
+//! Leases kept in memory.
+//!
+//! A [MemoryLeaseStore] coordinates the agents of a single process, e.g.
+//! several runners, and serves as a reference for stores backed by a shared
+//! database.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use time::OffsetDateTime;
+
+use super::errors::CoordinationError;
+use super::{Lease, LeaseStore};
+
+/// A store of leases kept in memory.
+#[derive(Debug, Default)]
+pub struct MemoryLeaseStore {
+  leases: Mutex<HashMap<String, Lease>>,
+}
+
+impl MemoryLeaseStore {
+  /// Creates an empty store.
+  pub fn new() -> Self {
+    Self::default()
+  }
+}
+
+impl LeaseStore for MemoryLeaseStore {
+  async fn acquire(
+    &self,
+    key: &str,
+    holder: &str,
+    ttl: Duration,
+  ) -> Result<Option<Lease>, CoordinationError> {
+    let now = OffsetDateTime::now_utc();
+    let mut leases = self.leases.lock().expect("leases lock");
+
+    if let Some(lease) = leases.get(key)
+      && lease.holder != holder
+      && lease.expires > now
+    {
+      return Ok(None);
+    }
+
+    let lease = Lease {
+      key: key.to_owned(),
+      holder: holder.to_owned(),
+      expires: now + ttl,
+    };
+    leases.insert(key.to_owned(), lease.clone());
+
+    Ok(Some(lease))
+  }
+
+  async fn release(&self, key: &str, holder: &str) -> Result<(), CoordinationError> {
+    let mut leases = self.leases.lock().expect("leases lock");
+
+    if leases.get(key).is_some_and(|lease| lease.holder == holder) {
+      leases.remove(key);
+    }
+
+    Ok(())
+  }
+
+  async fn leases(&self, prefix: &str) -> Result<Vec<Lease>, CoordinationError> {
+    let now = OffsetDateTime::now_utc();
+    let mut leases = self.leases.lock().expect("leases lock");
+
+    leases.retain(|_, lease| lease.expires > now);
+
+    Ok(
+      leases
+        .values()
+        .filter(|lease| lease.key.starts_with(prefix))
+        .cloned()
+        .collect(),
+    )
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[tokio::test]
+  async fn expiring_leases() {
+    let store = MemoryLeaseStore::new();
+    let ttl = Duration::from_millis(20);
+
+    assert!(store.acquire("shard/1", "a", ttl).await.unwrap().is_some());
+    assert!(
+      store.acquire("shard/1", "b", ttl).await.unwrap().is_none(),
+      "the lease is held by another agent"
+    );
+    assert!(store.acquire("shard/1", "a", ttl).await.unwrap().is_some());
+
+    tokio::time::sleep(Duration::from_millis(30)).await;
+    assert!(store.leases("shard/").await.unwrap().is_empty());
+    assert_eq!(
+      store
+        .acquire("shard/1", "b", ttl)
+        .await
+        .unwrap()
+        .unwrap()
+        .holder,
+      "b",
+      "an expired lease is taken over"
+    );
+
+    store.release("shard/1", "a").await.unwrap();
+    assert_eq!(store.leases("shard/").await.unwrap().len(), 1);
+    store.release("shard/1", "b").await.unwrap();
+    assert!(store.leases("shard/").await.unwrap().is_empty());
+  }
+}
@@ -0,0 +1,156 @@
+//! A module exporting measurements to monitoring systems.
+//!
+//! - **csv** - Writes measurements as rows of comma-separated values with a
+//!   stable column layout.
+//! - **prometheus** - Keeps metrics of the measurements and renders them in
+//!   the Prometheus text exposition format.
+//! - **influx** - Converts measurements into the InfluxDB line protocol and
+//!   writes them to an InfluxDB endpoint in batches.
+//! - **ndjson** - Appends measurements as newline-delimited JSON to any
+//!   output, rotating it as it grows.
+//! - **metrics** - Records measurements to the recorder of the `metrics`
+//!   crate. It requires the `metrics` feature.
+//! - **otel** - Sends measurements as OpenTelemetry metrics and spans to an
+//!   OTLP endpoint. It requires the `otel` feature.
+
+use std::time::Duration;
+
+use curl::easy::{Easy2, Handler, List, WriteError};
+use once_cell::sync::Lazy;
+
+use crate::export::errors::ExportError;
+use crate::monitor::collectors::client::Client;
+use crate::monitor::errors::{CollectorError, ErrorKind};
+use crate::monitor::models::{Data, Measurement};
+
+pub mod csv;
+pub mod errors;
+pub mod influx;
+#[cfg(feature = "metrics")]
+pub mod metrics;
+pub mod ndjson;
+#[cfg(feature = "otel")]
+pub mod otel;
+pub mod prometheus;
+
+/// Returns the type of the monitor that took the measurement, `"ping"` or
+/// `"http"`, or `"unknown"` if it can't be told, e.g. of a measurement
+/// without data whose error was reported as a DNS failure.
+fn monitor_type(measurement: &Measurement) -> &'static str {
+  match (&measurement.data, &measurement.error) {
+    (Some(Data::Ping(_)), _) | (None, Some(CollectorError::Ping(_))) => "ping",
+    (Some(Data::Http(_)), _) | (None, Some(CollectorError::Http(_))) => "http",
+    (None, Some(CollectorError::Reported(report))) => match report.kind {
+      ErrorKind::Dns | ErrorKind::Deadline => "unknown",
+      ErrorKind::NoReply
+      | ErrorKind::TtlExceeded
+      | ErrorKind::PtrMismatch
+      | ErrorKind::Unreachable
+      | ErrorKind::Socket
+      | ErrorKind::Task => "ping",
+      _ => "http",
+    },
+    _ => "unknown",
+  }
+}
+
+/// Returns the timings of the data, named after their fields, along with the
+/// packet loss of a ping.
+fn timings(data: &Data) -> Vec<(&'static str, f32)> {
+  match data {
+    Data::Ping(data) => vec![
+      ("dns_lookup", data.dns_lookup),
+      ("ping", data.ping),
+      ("ping_min", data.ping_min),
+      ("ping_max", data.ping_max),
+      ("ping_stddev", data.ping_stddev),
+      ("packet_loss", data.packet_loss),
+    ],
+    Data::Http(data) => vec![
+      ("dns_lookup", data.dns_lookup),
+      ("connect", data.connect),
+      ("tls_handshake", data.tls_handshake),
+      ("data_transfer", data.data_transfer),
+      ("total", data.total),
+    ],
+  }
+}
+
+/// Returns the labels of the measurement ordered by name, except the
+/// `reserved` ones, which clash with what the exporter tags series with. The
+/// agent that took the measurement is labeled `agent` and `region`, in place
+/// of the labels of the monitor with the same names.
+fn labels<'a>(measurement: &'a Measurement, reserved: &[&str]) -> Vec<(&'a str, &'a str)> {
+  let agent = measurement.agent.as_ref().map_or(vec![], |agent| {
+    [
+      ("agent", Some(agent.id.as_str())),
+      ("region", agent.region.as_deref()),
+    ]
+    .into_iter()
+    .filter_map(|(name, value)| Some((name, value?)))
+    .collect()
+  });
+
+  let mut labels: Vec<(&str, &str)> = measurement
+    .labels
+    .iter()
+    .map(|(name, value)| (name.as_str(), value.as_str()))
+    .filter(|(name, _)| !agent.iter().any(|(agent, _)| agent == name))
+    .chain(agent.iter().copied())
+    .filter(|(name, _)| !reserved.contains(name))
+    .collect();
+
+  labels.sort_unstable();
+  labels
+}
+
+static CLIENT: Lazy<Client<Reply>> = Lazy::new(Client::start);
+
+/// The body of an endpoint's response, kept to report rejected writes.
+#[derive(Default)]
+struct Reply {
+  body: Vec<u8>,
+}
+
+impl Handler for Reply {
+  fn write(&mut self, data: &[u8]) -> Result<usize, WriteError> {
+    self.body.extend_from_slice(data);
+
+    Ok(data.len())
+  }
+}
+
+/// Posts `body` to `url` with the given header lines, failing unless the
+/// endpoint replies with a success status.
+async fn post(
+  url: &str,
+  headers: &[String],
+  body: &[u8],
+  timeout: Duration,
+) -> Result<(), ExportError> {
+  let mut list = List::new();
+  for header in headers {
+    list.append(header)?;
+  }
+
+  let mut request = Easy2::new(Reply::default());
+  request.url(url)?;
+  request.http_headers(list)?;
+  request.timeout(timeout)?;
+  request.post(true)?;
+  request.post_fields_copy(body)?;
+
+  let (response, result) = CLIENT.perform(request).await?;
+  result?;
+
+  let status = response.response_code()?;
+
+  if !(200..300).contains(&status) {
+    return Err(ExportError::Rejected {
+      status,
+      body: String::from_utf8_lossy(&response.get_ref().body).into_owned(),
+    });
+  }
+
+  Ok(())
+}
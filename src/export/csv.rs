@@ -0,0 +1,251 @@
+//! Measurements as comma-separated values.
+//!
+//! A [CsvWriter] appends measurements as rows of the [COLUMNS], in that
+//! order, to any [AsyncWrite], for ad-hoc analysis in spreadsheets or
+//! dataframes. The columns are the same for every type of monitor, cells that
+//! don't apply are left empty, and new columns are only ever added at the end.
+//!
+//! # Example
+//!
+//! ```rust, no_run
+//! use limon_core::export::csv::CsvWriter;
+//! use limon_core::export::errors::ExportError;
+//! use limon_core::monitor::models::Measurement;
+//! use tokio::io::AsyncWrite;
+//!
+//! async fn save(
+//!   output: impl AsyncWrite + Unpin + Send,
+//!   measurements: &[Measurement],
+//! ) -> Result<(), ExportError> {
+//!   let mut writer = CsvWriter::new(output);
+//!
+//!   for measurement in measurements {
+//!     writer.write(measurement).await?;
+//!   }
+//!
+//!   writer.flush().await
+//! }
+//! ```
+
+use std::fmt::Write;
+
+use time::format_description::well_known::Rfc3339;
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+
+use super::errors::ExportError;
+use super::monitor_type;
+use crate::monitor::models::{Data, Measurement};
+
+/// The columns of the rows, in order. Timings are in milliseconds, and the
+/// timestamp is in RFC 3339.
+pub const COLUMNS: [&str; 15] = [
+  "timestamp",
+  "monitor_id",
+  "type",
+  "status",
+  "latency",
+  "dns_lookup",
+  "connect",
+  "tls_handshake",
+  "data_transfer",
+  "ping_min",
+  "ping_max",
+  "ping_stddev",
+  "packet_loss",
+  "error_kind",
+  "error",
+];
+
+/// Returns the header row, without the line break.
+pub fn header() -> String {
+  COLUMNS.join(",")
+}
+
+/// Converts a measurement into a row of the [COLUMNS], without the line
+/// break. Cells are quoted if needed.
+pub fn row(measurement: &Measurement) -> String {
+  let mut cells = vec![String::new(); COLUMNS.len()];
+
+  cells[0] = measurement
+    .timestamp
+    .format(&Rfc3339)
+    .unwrap_or_else(|_| measurement.timestamp.unix_timestamp().to_string());
+  cells[1] = measurement.monitor_id.to_string();
+  cells[2] = monitor_type(measurement).to_string();
+  cells[3] = measurement.status().as_str().to_string();
+
+  let timings = match &measurement.data {
+    Some(Data::Ping(data)) => vec![
+      (4, data.ping),
+      (5, data.dns_lookup),
+      (9, data.ping_min),
+      (10, data.ping_max),
+      (11, data.ping_stddev),
+      (12, data.packet_loss),
+    ],
+    Some(Data::Http(data)) => vec![
+      (4, data.total),
+      (5, data.dns_lookup),
+      (6, data.connect),
+      (7, data.tls_handshake),
+      (8, data.data_transfer),
+    ],
+    None => Vec::new(),
+  };
+
+  for (column, value) in timings {
+    cells[column] = value.to_string();
+  }
+
+  if let Some(error) = &measurement.error {
+    cells[13] = error.kind().as_str().to_string();
+    cells[14] = error.to_string();
+  }
+
+  let mut row = String::new();
+
+  for (index, cell) in cells.iter().enumerate() {
+    if index > 0 {
+      row.push(',');
+    }
+
+    if cell.contains([',', '"', '\n', '\r']) {
+      let _ = write!(row, "\"{}\"", cell.replace('"', "\"\""));
+    } else {
+      row.push_str(cell);
+    }
+  }
+
+  row
+}
+
+/// Writes measurements to an output as CSV rows, preceded by the header.
+pub struct CsvWriter<W> {
+  writer: W,
+
+  /// Whether the header is still to be written.
+  header: bool,
+}
+
+impl<W: AsyncWrite + Unpin + Send> CsvWriter<W> {
+  /// Creates a writer to `writer`, writing the header before the first row.
+  pub fn new(writer: W) -> Self {
+    Self {
+      writer,
+      header: true,
+    }
+  }
+
+  /// Sets whether the header is written before the first row, e.g. not when
+  /// appending to an existing file.
+  pub fn with_header(mut self, header: bool) -> Self {
+    self.header = header;
+    self
+  }
+
+  /// Returns the output, without flushing it.
+  pub fn into_inner(self) -> W {
+    self.writer
+  }
+
+  /// Appends the measurement as a row.
+  pub async fn write(&mut self, measurement: &Measurement) -> Result<(), ExportError> {
+    let mut lines = String::new();
+
+    if std::mem::take(&mut self.header) {
+      lines.push_str(&header());
+      lines.push('\n');
+    }
+
+    lines.push_str(&row(measurement));
+    lines.push('\n');
+
+    Ok(self.writer.write_all(lines.as_bytes()).await?)
+  }
+
+  /// Flushes the output.
+  pub async fn flush(&mut self) -> Result<(), ExportError> {
+    Ok(self.writer.flush().await?)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::monitor::errors::{CollectorError, HttpError};
+  use crate::monitor::models::{HttpData, PingData};
+
+  #[test]
+  fn rows() {
+    let ping = Measurement::fixture(5)
+      .at(1_700_000_000)
+      .data(Data::Ping(PingData {
+        ping: 12.5,
+        ping_min: 10.0,
+        ping_max: 15.0,
+        packet_loss: 25.0,
+        ..Default::default()
+      }));
+
+    assert_eq!(
+      row(&ping),
+      "2023-11-14T22:13:20Z,5,ping,up,12.5,0,,,,10,15,0,25,,"
+    );
+
+    let http = Measurement::fixture(5)
+      .at(1_700_000_000)
+      .data(Data::Http(HttpData {
+        total: 100.0,
+        connect: 20.0,
+        ..Default::default()
+      }));
+
+    assert_eq!(
+      row(&http),
+      "2023-11-14T22:13:20Z,5,http,up,100,0,20,0,0,,,,,,"
+    );
+
+    let failed = Measurement::fixture(5)
+      .at(1_700_000_000)
+      .error(CollectorError::Http(HttpError::KeywordNotFound {
+        keyword: String::from("a, b"),
+        snippet: None,
+      }));
+
+    assert_eq!(
+      row(&failed),
+      "2023-11-14T22:13:20Z,5,http,down,,,,,,,,,,keyword_not_found,\
+       \"HTTP error: Keyword '\"\"a, b\"\"' not found in response body\""
+    );
+  }
+
+  #[tokio::test]
+  async fn header_once() {
+    let mut writer = CsvWriter::new(Vec::new());
+    let empty = Measurement {
+      data: None,
+      ..Measurement::fixture(5).at(1_700_000_000)
+    };
+
+    writer.write(&empty).await.unwrap();
+    writer.write(&empty).await.unwrap();
+
+    let output = String::from_utf8(writer.into_inner()).unwrap();
+    let lines: Vec<&str> = output.lines().collect();
+
+    assert_eq!(lines.len(), 3);
+    assert_eq!(lines[0], header());
+    assert_eq!(lines[1].split(',').count(), COLUMNS.len());
+
+    let mut writer = CsvWriter::new(Vec::new()).with_header(false);
+    writer.write(&empty).await.unwrap();
+    assert_eq!(
+      writer
+        .into_inner()
+        .iter()
+        .filter(|byte| **byte == b'\n')
+        .count(),
+      1
+    );
+  }
+}
@@ -0,0 +1,31 @@
+//! A module describing export errors.
+
+use thiserror::Error;
+
+use crate::monitor::errors::HttpError;
+
+/// Errors that can occur when exporting measurements.
+#[derive(Error, Debug)]
+pub enum ExportError {
+  /// The request to the endpoint failed.
+  #[error("HTTP error: {0}")]
+  Http(#[from] HttpError),
+
+  /// The endpoint rejected the write.
+  #[error("Write rejected with status {status}: {body}")]
+  Rejected { status: u32, body: String },
+
+  /// Writing to the output failed.
+  #[error("IO error: {0}")]
+  Io(#[from] std::io::Error),
+
+  /// The measurement couldn't be serialized.
+  #[error("Serialization error: {0}")]
+  Serialize(#[from] serde_json::Error),
+}
+
+impl From<curl::Error> for ExportError {
+  fn from(error: curl::Error) -> Self {
+    ExportError::Http(error.into())
+  }
+}
@@ -0,0 +1,333 @@
+//! Measurements in the InfluxDB line protocol.
+//!
+//! [line()] converts a measurement into a line with the monitor id, type and
+//! labels as tags, and its status, timings and error as fields. An [InfluxWriter]
+//! batches the lines and writes them to an InfluxDB `HTTP` endpoint.
+//!
+//! # Example
+//!
+//! ```rust, no_run
+//! use limon_core::export::errors::ExportError;
+//! use limon_core::export::influx::InfluxWriter;
+//! use limon_core::monitor::models::Measurement;
+//!
+//! async fn export(measurements: &[Measurement]) -> Result<(), ExportError> {
+//!   let mut writer = InfluxWriter::new("http://localhost:8086/api/v2/write?org=limon&bucket=checks")
+//!     .token("secret")
+//!     .batch_size(500);
+//!
+//!   for measurement in measurements {
+//!     writer.write(measurement).await?;
+//!   }
+//!
+//!   writer.flush().await
+//! }
+//! ```
+
+use std::fmt::Write;
+use std::time::Duration;
+
+use super::errors::ExportError;
+use super::{labels, monitor_type, post, timings};
+use crate::monitor::models::Measurement;
+
+/// The default name of the measurement lines are written to.
+const DEFAULT_NAME: &str = "limon";
+
+/// Converts a measurement into a line of the InfluxDB line protocol, named
+/// `name`, with a nanosecond timestamp.
+///
+/// The line is tagged with `monitor_id`, `type` and the
+/// [labels](Measurement#structfield.labels) with a value, and has a `status` field
+/// along with the timings of the data, in milliseconds, or the `error_kind`
+/// and `error` of a failed measurement.
+pub fn line(name: &str, measurement: &Measurement) -> String {
+  let mut line = escape(name, &[',', ' ']);
+
+  let _ = write!(
+    line,
+    ",monitor_id={},type={}",
+    measurement.monitor_id,
+    monitor_type(measurement)
+  );
+
+  for (name, value) in labels(measurement, &["monitor_id", "type"]) {
+    if !value.is_empty() {
+      let special = [',', '=', ' '];
+      let _ = write!(
+        line,
+        ",{}={}",
+        escape(name, &special),
+        escape(value, &special)
+      );
+    }
+  }
+
+  let _ = write!(line, " status=\"{}\"", measurement.status().as_str());
+
+  for (field, value) in measurement.data.iter().flat_map(timings) {
+    let _ = write!(line, ",{field}={value}");
+  }
+
+  if let Some(error) = &measurement.error {
+    let _ = write!(
+      line,
+      ",error_kind=\"{}\",error=\"{}\"",
+      error.kind().as_str(),
+      escape(&error.to_string(), &['"'])
+    );
+  }
+
+  let _ = write!(line, " {}", measurement.timestamp.unix_timestamp_nanos());
+
+  line
+}
+
+/// Escapes backslashes and the given characters with a backslash, and
+/// replaces newlines, which can't be escaped, with spaces.
+fn escape(value: &str, special: &[char]) -> String {
+  let mut escaped = String::with_capacity(value.len());
+
+  for char in value.chars() {
+    match char {
+      '\n' | '\r' => escaped.push(' '),
+      '\\' => escaped.push_str("\\\\"),
+      char if special.contains(&char) => {
+        escaped.push('\\');
+        escaped.push(char);
+      }
+      char => escaped.push(char),
+    }
+  }
+
+  escaped
+}
+
+/// Writes measurements to an InfluxDB `HTTP` write endpoint in batches.
+///
+/// Lines are buffered until the batch is full, or [flush](Self::flush) is
+/// called. If a write fails, the lines are kept, so it can be retried.
+pub struct InfluxWriter {
+  url: String,
+  token: Option<String>,
+  name: String,
+  batch_size: usize,
+  timeout: Duration,
+
+  /// Lines to be written, each ending with a newline.
+  buffer: String,
+  lines: usize,
+}
+
+impl InfluxWriter {
+  /// Creates a writer to the write endpoint `url`, including the parameters
+  /// it requires (e.g. the org and bucket of InfluxDB 2).
+  pub fn new(url: impl Into<String>) -> Self {
+    Self {
+      url: url.into(),
+      token: None,
+      name: String::from(DEFAULT_NAME),
+      batch_size: 1000,
+      timeout: Duration::from_secs(10),
+      buffer: String::new(),
+      lines: 0,
+    }
+  }
+
+  /// Sets the API token sent in the `Authorization` header.
+  pub fn token(mut self, token: impl Into<String>) -> Self {
+    self.token = Some(token.into());
+    self
+  }
+
+  /// Sets the name of the measurement lines are written to, `limon` by
+  /// default.
+  pub fn name(mut self, name: impl Into<String>) -> Self {
+    self.name = name.into();
+    self
+  }
+
+  /// Sets the number of lines written at once, 1000 by default.
+  pub fn batch_size(mut self, batch_size: usize) -> Self {
+    self.batch_size = batch_size.max(1);
+    self
+  }
+
+  /// Sets the maximum time a write may take, 10 seconds by default.
+  pub fn timeout(mut self, timeout: Duration) -> Self {
+    self.timeout = timeout;
+    self
+  }
+
+  /// Returns the number of lines waiting to be written.
+  pub fn pending(&self) -> usize {
+    self.lines
+  }
+
+  /// Buffers the measurement, writing the batch if it's full.
+  pub async fn write(&mut self, measurement: &Measurement) -> Result<(), ExportError> {
+    self.buffer.push_str(&line(&self.name, measurement));
+    self.buffer.push('\n');
+    self.lines += 1;
+
+    if self.lines >= self.batch_size {
+      self.flush().await?;
+    }
+
+    Ok(())
+  }
+
+  /// Writes the buffered lines, if any.
+  pub async fn flush(&mut self) -> Result<(), ExportError> {
+    if self.lines == 0 {
+      return Ok(());
+    }
+
+    let mut headers = vec![String::from("Content-Type: text/plain; charset=utf-8")];
+    if let Some(token) = &self.token {
+      headers.push(format!("Authorization: Token {}", token));
+    }
+
+    post(&self.url, &headers, self.buffer.as_bytes(), self.timeout).await?;
+
+    self.buffer.clear();
+    self.lines = 0;
+
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::collections::HashMap;
+
+  use httpmock::prelude::*;
+
+  use super::*;
+  use crate::monitor::errors::{CollectorError, HttpError};
+  use crate::monitor::models::{Agent, Data, HttpData, PingData};
+
+  #[test]
+  fn lines() {
+    let ping = Measurement::fixture(3)
+      .at(1_700_000_000)
+      .data(Data::Ping(PingData {
+        ping: 12.5,
+        packet_loss: 0.0,
+        ..Default::default()
+      }));
+
+    assert_eq!(
+      line("checks", &ping),
+      "checks,monitor_id=3,type=ping status=\"up\",dns_lookup=0,ping=12.5,ping_min=0,\
+       ping_max=0,ping_stddev=0,packet_loss=0 1700000000000000000"
+    );
+
+    let failed = Measurement::fixture(3)
+      .at(1_700_000_000)
+      .error(CollectorError::Http(HttpError::KeywordNotFound {
+        keyword: String::from("ok"),
+        snippet: None,
+      }));
+
+    let labeled = Measurement {
+      labels: HashMap::from([
+        (String::from("region"), String::from("eu west")),
+        (String::from("env"), String::from("a=b")),
+        (String::from("team"), String::new()),
+      ]),
+      ..Measurement {
+        data: None,
+        ..Measurement::fixture(3).at(1_700_000_000)
+      }
+    };
+
+    assert_eq!(
+      line("checks", &labeled),
+      "checks,monitor_id=3,type=unknown,env=a\\=b,region=eu\\ west status=\"unknown\" \
+       1700000000000000000"
+    );
+
+    let stamped = Measurement {
+      agent: Some(Agent::new("probe-1").region("us-east")),
+      ..labeled
+    };
+
+    assert_eq!(
+      line("checks", &stamped),
+      "checks,monitor_id=3,type=unknown,agent=probe-1,env=a\\=b,region=us-east \
+       status=\"unknown\" 1700000000000000000",
+      "the agent replaces labels of the same name"
+    );
+
+    assert_eq!(
+      line("limon checks", &failed),
+      "limon\\ checks,monitor_id=3,type=http status=\"down\",error_kind=\"keyword_not_found\",\
+       error=\"HTTP error: Keyword '\\\"ok\\\"' not found in response body\" 1700000000000000000"
+    );
+  }
+
+  #[tokio::test]
+  async fn batched_writes() {
+    let server = MockServer::start_async().await;
+    let http = Measurement::fixture(3)
+      .at(1_700_000_000)
+      .data(Data::Http(HttpData {
+        total: 100.0,
+        ..Default::default()
+      }));
+
+    let mock = server
+      .mock_async(|when, then| {
+        when
+          .method(POST)
+          .path("/write")
+          .header("Authorization", "Token secret")
+          .body(format!("{0}\n{0}\n", line(DEFAULT_NAME, &http)));
+        then.status(204);
+      })
+      .await;
+
+    let mut writer = InfluxWriter::new(server.url("/write"))
+      .token("secret")
+      .batch_size(2);
+
+    writer.write(&http).await.unwrap();
+    assert_eq!(writer.pending(), 1);
+    mock.assert_calls_async(0).await;
+
+    writer.write(&http).await.unwrap();
+    assert_eq!(writer.pending(), 0);
+    mock.assert_async().await;
+
+    writer.flush().await.unwrap();
+    mock.assert_calls_async(1).await;
+  }
+
+  #[tokio::test]
+  async fn rejected_write() {
+    let server = MockServer::start_async().await;
+
+    server
+      .mock_async(|when, then| {
+        when.method(POST).path("/write");
+        then.status(400).body("bad line");
+      })
+      .await;
+
+    let mut writer = InfluxWriter::new(server.url("/write"));
+    writer
+      .write(&Measurement {
+        data: None,
+        ..Measurement::fixture(3).at(1_700_000_000)
+      })
+      .await
+      .unwrap();
+
+    assert!(matches!(
+      writer.flush().await,
+      Err(ExportError::Rejected { status: 400, body }) if body == "bad line"
+    ));
+    assert_eq!(writer.pending(), 1, "lines are kept to retry");
+  }
+}
@@ -0,0 +1,199 @@
+//! Measurements as calls to the recorder of the [metrics] crate.
+//!
+//! [record] reports a measurement to the globally installed recorder, e.g.
+//! a Prometheus or StatsD exporter of the `metrics` ecosystem, with the same
+//! names as the [Prometheus exporter](crate::export::prometheus):
+//!
+//! - `limon_up` and `limon_degraded` - gauges of the last measurement;
+//! - `limon_latency_milliseconds` - a gauge of the last successful latency;
+//! - `limon_measurements_total` and `limon_failures_total` - counters;
+//! - `limon_latency_seconds` - a histogram of the successful latencies.
+//!
+//! They're labeled with `monitor_id`, `type` and the
+//! [labels](Measurement#structfield.labels) of the measurement. [MetricsSink]
+//! records every measurement submitted to it, so it's passed to a
+//! [Runner](crate::schedule::runner::Runner) as is.
+//!
+//! It requires the `metrics` feature.
+//!
+//! # Example
+//!
+//! ```rust, no_run
+//! use std::sync::Arc;
+//!
+//! use limon_core::export::metrics::{self, MetricsSink};
+//! use limon_core::monitor::models::Monitor;
+//! use limon_core::schedule::Schedule;
+//! use limon_core::schedule::runner::Runner;
+//!
+//! async fn run(schedule: Arc<Schedule<Monitor>>) {
+//!   metrics::describe();
+//!
+//!   Runner::new(schedule, MetricsSink).run().await;
+//! }
+//! ```
+
+use metrics::{Label, Unit};
+
+use super::{labels, monitor_type};
+use crate::monitor::models::{Measurement, Status};
+use crate::monitor::sink::MeasurementSink;
+
+/// Describes the metrics to the installed recorder, so exporters that
+/// support it publish their help and unit.
+pub fn describe() {
+  metrics::describe_gauge!(
+    "limon_up",
+    "Whether the last measurement of the monitor succeeded."
+  );
+  metrics::describe_gauge!(
+    "limon_degraded",
+    "Whether the last measurement of the monitor exceeded a latency threshold."
+  );
+  metrics::describe_gauge!(
+    "limon_latency_milliseconds",
+    Unit::Milliseconds,
+    "Latency of the last successful measurement of the monitor."
+  );
+  metrics::describe_counter!(
+    "limon_measurements_total",
+    "Number of measurements of the monitor."
+  );
+  metrics::describe_counter!(
+    "limon_failures_total",
+    "Number of failed measurements of the monitor."
+  );
+  metrics::describe_histogram!(
+    "limon_latency_seconds",
+    Unit::Seconds,
+    "Latency of the successful measurements of the monitor."
+  );
+  metrics::describe_counter!(
+    "limon_late_runs_total",
+    "Number of runs started after the moment they were due at."
+  );
+  metrics::describe_histogram!(
+    "limon_run_lateness_seconds",
+    Unit::Seconds,
+    "Delay of the late runs after the moment they were due at."
+  );
+  metrics::describe_counter!(
+    "limon_dropped_measurements_total",
+    "Number of measurements dropped by buffered sinks that couldn't keep up."
+  );
+}
+
+/// Records a measurement to the installed recorder. Measurements with
+/// neither data nor an error are only counted.
+pub fn record(measurement: &Measurement) {
+  let labels: Vec<Label> = [
+    ("monitor_id", measurement.monitor_id.to_string()),
+    ("type", monitor_type(measurement).to_owned()),
+  ]
+  .into_iter()
+  .map(|(name, value)| Label::new(name, value))
+  .chain(
+    labels(measurement, &["monitor_id", "type"])
+      .into_iter()
+      .map(|(name, value)| Label::new(name.to_owned(), value.to_owned())),
+  )
+  .collect();
+
+  metrics::counter!("limon_measurements_total", labels.clone()).increment(1);
+
+  match measurement.status() {
+    Status::Down => {
+      metrics::counter!("limon_failures_total", labels.clone()).increment(1);
+      metrics::gauge!("limon_up", labels.clone()).set(0.0);
+      metrics::gauge!("limon_degraded", labels).set(0.0);
+    }
+    Status::Unknown => {}
+    status => {
+      let latency = measurement.data.as_ref().map_or(0.0, |data| data.latency());
+
+      metrics::gauge!("limon_up", labels.clone()).set(1.0);
+      metrics::gauge!("limon_degraded", labels.clone()).set(f64::from(status == Status::Degraded));
+      metrics::gauge!("limon_latency_milliseconds", labels.clone()).set(latency);
+      metrics::histogram!("limon_latency_seconds", labels).record(f64::from(latency) / 1000.0);
+    }
+  }
+}
+
+/// A sink [recording](record) every measurement.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MetricsSink;
+
+impl MeasurementSink for MetricsSink {
+  async fn submit(&self, measurement: Measurement) {
+    record(&measurement);
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::collections::HashMap;
+
+  use metrics_util::debugging::{DebugValue, DebuggingRecorder};
+
+  use super::*;
+  use crate::monitor::errors::{CollectorError, PingError};
+
+  #[test]
+  fn recorded() {
+    let recorder = DebuggingRecorder::new();
+    let snapshotter = recorder.snapshotter();
+    let measurement = Measurement {
+      labels: HashMap::from([(String::from("env"), String::from("prod"))]),
+      ..Measurement::fixture(4)
+    };
+
+    metrics::with_local_recorder(&recorder, || {
+      record(&measurement.clone().latency(250.0));
+      record(&measurement.clone().latency(150.0));
+      record(&measurement.error(CollectorError::Ping(PingError::Unreachable)));
+    });
+
+    let metrics: HashMap<(String, Vec<String>), DebugValue> = snapshotter
+      .snapshot()
+      .into_vec()
+      .into_iter()
+      .map(|(key, _, _, value)| {
+        let key = key.key();
+        let labels = key
+          .labels()
+          .map(|label| format!("{}={}", label.key(), label.value()))
+          .collect();
+
+        ((key.name().to_owned(), labels), value)
+      })
+      .collect();
+
+    let http = vec![
+      String::from("monitor_id=4"),
+      String::from("type=http"),
+      String::from("env=prod"),
+    ];
+    let ping = vec![
+      String::from("monitor_id=4"),
+      String::from("type=ping"),
+      String::from("env=prod"),
+    ];
+    let value = |name: &str, labels: &Vec<String>| &metrics[&(name.to_owned(), labels.clone())];
+
+    assert_eq!(
+      value("limon_measurements_total", &http),
+      &DebugValue::Counter(2)
+    );
+    assert!(
+      matches!(value("limon_latency_milliseconds", &http), DebugValue::Gauge(latency) if latency.0 == 150.0)
+    );
+    assert!(
+      matches!(value("limon_latency_seconds", &http), DebugValue::Histogram(latencies) if latencies.len() == 2)
+    );
+    assert!(matches!(value("limon_up", &ping), DebugValue::Gauge(up) if up.0 == 0.0));
+    assert_eq!(
+      value("limon_failures_total", &ping),
+      &DebugValue::Counter(1)
+    );
+  }
+}
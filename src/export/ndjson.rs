@@ -0,0 +1,236 @@
+//! Measurements as newline-delimited JSON.
+//!
+//! An [NdjsonWriter] appends each measurement as a line of JSON to any
+//! [AsyncWrite], e.g. a file or stdout, in the serialized form of
+//! [Measurement]. Once the output grows past a number of lines or bytes, or
+//! on demand, it's handed to a [Rotate] hook, which can replace it, e.g. with
+//! a new file.
+//!
+//! # Example
+//!
+//! ```rust, no_run
+//! use limon_core::export::errors::ExportError;
+//! use limon_core::export::ndjson::NdjsonWriter;
+//! use limon_core::monitor::models::Measurement;
+//! use tokio::io::AsyncWrite;
+//!
+//! async fn log(
+//!   output: impl AsyncWrite + Unpin + Send,
+//!   measurements: &[Measurement],
+//! ) -> Result<(), ExportError> {
+//!   let mut writer = NdjsonWriter::new(output);
+//!
+//!   for measurement in measurements {
+//!     writer.write(measurement).await?;
+//!   }
+//!
+//!   writer.flush().await
+//! }
+//! ```
+
+use std::io;
+
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+
+use super::errors::ExportError;
+use crate::monitor::models::Measurement;
+
+/// A hook replacing the output of an [NdjsonWriter] when it's rotated.
+pub trait Rotate<W>: Send {
+  /// Finishes the flushed output, e.g. renames or compresses the file, and
+  /// replaces it with the next one.
+  fn rotate(&mut self, writer: &mut W) -> impl Future<Output = io::Result<()>> + Send;
+}
+
+/// The default hook, which keeps writing to the same output.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct KeepWriter;
+
+impl<W: Send> Rotate<W> for KeepWriter {
+  async fn rotate(&mut self, _writer: &mut W) -> io::Result<()> {
+    Ok(())
+  }
+}
+
+/// Writes measurements to an output as newline-delimited JSON.
+pub struct NdjsonWriter<W, R = KeepWriter> {
+  writer: W,
+  rotation: R,
+  max_lines: Option<usize>,
+  max_bytes: Option<u64>,
+
+  /// Lines and bytes written since the last rotation.
+  lines: usize,
+  bytes: u64,
+}
+
+impl<W: AsyncWrite + Unpin + Send> NdjsonWriter<W> {
+  /// Creates a writer appending to `writer`, never rotating it.
+  pub fn new(writer: W) -> Self {
+    Self {
+      writer,
+      rotation: KeepWriter,
+      max_lines: None,
+      max_bytes: None,
+      lines: 0,
+      bytes: 0,
+    }
+  }
+}
+
+impl<W: AsyncWrite + Unpin + Send, R: Rotate<W>> NdjsonWriter<W, R> {
+  /// Sets the hook called when the output is rotated.
+  pub fn rotation<Hook: Rotate<W>>(self, rotation: Hook) -> NdjsonWriter<W, Hook> {
+    NdjsonWriter {
+      writer: self.writer,
+      rotation,
+      max_lines: self.max_lines,
+      max_bytes: self.max_bytes,
+      lines: self.lines,
+      bytes: self.bytes,
+    }
+  }
+
+  /// Rotates the output once `lines` lines are written to it.
+  pub fn max_lines(mut self, lines: usize) -> Self {
+    self.max_lines = Some(lines.max(1));
+    self
+  }
+
+  /// Rotates the output once at least `bytes` bytes are written to it. Lines
+  /// aren't split, so it can grow slightly larger.
+  pub fn max_bytes(mut self, bytes: u64) -> Self {
+    self.max_bytes = Some(bytes.max(1));
+    self
+  }
+
+  /// Returns the number of lines written since the last rotation.
+  pub fn lines(&self) -> usize {
+    self.lines
+  }
+
+  /// Returns the number of bytes written since the last rotation.
+  pub fn bytes(&self) -> u64 {
+    self.bytes
+  }
+
+  /// Returns the output.
+  pub fn get_ref(&self) -> &W {
+    &self.writer
+  }
+
+  /// Returns the output, without flushing it.
+  pub fn into_inner(self) -> W {
+    self.writer
+  }
+
+  /// Appends the measurement as a line, rotating the output afterwards if it
+  /// reached one of the limits.
+  pub async fn write(&mut self, measurement: &Measurement) -> Result<(), ExportError> {
+    let mut line = serde_json::to_vec(measurement)?;
+    line.push(b'\n');
+
+    self.writer.write_all(&line).await?;
+    self.lines += 1;
+    self.bytes += line.len() as u64;
+
+    if self.max_lines.is_some_and(|max| self.lines >= max)
+      || self.max_bytes.is_some_and(|max| self.bytes >= max)
+    {
+      self.rotate().await?;
+    }
+
+    Ok(())
+  }
+
+  /// Flushes the output.
+  pub async fn flush(&mut self) -> Result<(), ExportError> {
+    Ok(self.writer.flush().await?)
+  }
+
+  /// Flushes the output and calls the rotation hook, e.g. on a timer or a
+  /// signal.
+  pub async fn rotate(&mut self) -> Result<(), ExportError> {
+    self.writer.flush().await?;
+    self.rotation.rotate(&mut self.writer).await?;
+
+    self.lines = 0;
+    self.bytes = 0;
+
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use serde_json::Value;
+
+  use super::*;
+
+  /// Keeps the rotated outputs.
+  #[derive(Default)]
+  struct Archive(Vec<Vec<u8>>);
+
+  impl Rotate<Vec<u8>> for &mut Archive {
+    async fn rotate(&mut self, writer: &mut Vec<u8>) -> io::Result<()> {
+      self.0.push(std::mem::take(writer));
+      Ok(())
+    }
+  }
+
+  #[tokio::test]
+  async fn lines() {
+    let mut writer = NdjsonWriter::new(Vec::new());
+
+    writer
+      .write(&Measurement::fixture(1).at(1_700_000_000))
+      .await
+      .unwrap();
+    writer
+      .write(&Measurement::fixture(2).at(1_700_000_000))
+      .await
+      .unwrap();
+    writer.flush().await.unwrap();
+
+    let output = String::from_utf8(writer.into_inner()).unwrap();
+    let lines: Vec<Value> = output
+      .lines()
+      .map(|line| serde_json::from_str(line).unwrap())
+      .collect();
+
+    assert!(output.ends_with('\n'));
+    assert_eq!(lines.len(), 2);
+    assert_eq!(lines[1]["monitor_id"], 2);
+    assert_eq!(lines[1]["timestamp"], 1_700_000_000_000_i64);
+  }
+
+  #[tokio::test]
+  async fn rotation() {
+    let mut archive = Archive::default();
+    let mut writer = NdjsonWriter::new(Vec::new())
+      .max_lines(2)
+      .rotation(&mut archive);
+
+    for monitor_id in 0..5 {
+      writer
+        .write(&Measurement::fixture(monitor_id).at(1_700_000_000))
+        .await
+        .unwrap();
+    }
+
+    assert_eq!(writer.lines(), 1);
+    assert!(writer.bytes() > 0);
+
+    writer.rotate().await.unwrap();
+    assert_eq!(writer.lines(), 0);
+    assert!(writer.get_ref().is_empty());
+    drop(writer);
+
+    let lines: Vec<usize> = archive
+      .0
+      .iter()
+      .map(|output| output.iter().filter(|byte| **byte == b'\n').count())
+      .collect();
+    assert_eq!(lines, [2, 2, 1]);
+  }
+}
@@ -0,0 +1,481 @@
+//! Measurements as OpenTelemetry metrics and spans.
+//!
+//! An [OtlpExporter] is fed the measurements of any number of monitors and
+//! sends them to an OTLP/HTTP endpoint, e.g. an OpenTelemetry collector, in
+//! the JSON encoding:
+//!
+//! - `limon.up` - a gauge of whether the last measurement of a monitor
+//!   succeeded, possibly degraded;
+//! - `limon.latency` - a histogram of the latencies of the successful
+//!   measurements since the last export, in milliseconds;
+//! - a `limon.measure` span per measurement, lasting its latency, with its
+//!   timings as attributes and its error as the status.
+//!
+//! Data points and spans are attributed with `monitor.id`, `monitor.type` and
+//! the [labels](Measurement#structfield.labels) of the last measurement.
+//!
+//! # Example
+//!
+//! ```rust, no_run
+//! use limon_core::export::errors::ExportError;
+//! use limon_core::export::otel::OtlpExporter;
+//! use limon_core::monitor::models::Measurement;
+//!
+//! async fn export(measurements: &[Measurement]) -> Result<(), ExportError> {
+//!   let mut exporter = OtlpExporter::new("http://localhost:4318").service_name("limon-agent");
+//!
+//!   for measurement in measurements {
+//!     exporter.record(measurement);
+//!   }
+//!
+//!   exporter.flush().await
+//! }
+//! ```
+
+use std::collections::BTreeMap;
+use std::hash::{BuildHasher, RandomState};
+use std::time::Duration;
+
+use serde_json::{Value, json};
+
+use super::errors::ExportError;
+use super::{labels, monitor_type, post, timings};
+use crate::monitor::models::{Measurement, Status};
+
+/// Default upper bounds, in milliseconds, of the latency histogram buckets.
+const DEFAULT_BUCKETS: [f64; 11] = [
+  5.0, 10.0, 25.0, 50.0, 100.0, 250.0, 500.0, 1000.0, 2500.0, 5000.0, 10000.0,
+];
+
+/// Delta aggregation temporality, as numbered by OTLP.
+const DELTA: u8 = 1;
+
+/// Client span kind, as numbered by OTLP.
+const CLIENT: u8 = 3;
+
+/// Error span status code, as numbered by OTLP.
+const ERROR: u8 = 2;
+
+/// Collects metrics and spans of measurements and sends them to an OTLP/HTTP
+/// endpoint.
+pub struct OtlpExporter {
+  endpoint: String,
+  headers: Vec<String>,
+  service_name: String,
+  buckets: Vec<f64>,
+  traces: bool,
+  timeout: Duration,
+
+  /// Metrics since the last export, by monitor id and type.
+  series: BTreeMap<(i64, &'static str), Series>,
+
+  /// Spans not exported yet.
+  spans: Vec<Value>,
+}
+
+/// Metrics of a single monitor since the last export.
+#[derive(Debug, Clone, Default)]
+struct Series {
+  /// The labels of the last measurement, as attributes.
+  labels: Vec<Value>,
+
+  /// Whether the last measurement succeeded, and when it was taken.
+  up: Option<(bool, i128)>,
+
+  /// When the first and the last measurement were taken, in nanoseconds.
+  start: i128,
+  end: i128,
+
+  /// Number of latencies in each bucket, the last one being unbounded.
+  buckets: Vec<u64>,
+  sum: f64,
+  min: f64,
+  max: f64,
+}
+
+impl OtlpExporter {
+  /// Creates an exporter sending to the OTLP/HTTP endpoint at `endpoint`, e.g.
+  /// `http://localhost:4318`, to which the `/v1/metrics` and `/v1/traces`
+  /// paths are appended.
+  pub fn new(endpoint: impl Into<String>) -> Self {
+    Self {
+      endpoint: endpoint.into().trim_end_matches('/').to_string(),
+      headers: vec![String::from("Content-Type: application/json")],
+      service_name: String::from("limon"),
+      buckets: DEFAULT_BUCKETS.to_vec(),
+      traces: true,
+      timeout: Duration::from_secs(10),
+      series: BTreeMap::new(),
+      spans: Vec::new(),
+    }
+  }
+
+  /// Adds a header sent with every export, e.g. for authentication.
+  pub fn header(mut self, name: &str, value: &str) -> Self {
+    self.headers.push(format!("{}: {}", name, value));
+    self
+  }
+
+  /// Sets the `service.name` of the exported resource, `limon` by default.
+  pub fn service_name(mut self, name: impl Into<String>) -> Self {
+    self.service_name = name.into();
+    self
+  }
+
+  /// Sets the upper bounds, in milliseconds, of the latency histogram
+  /// buckets. Metrics recorded beforehand are forgotten.
+  pub fn buckets(mut self, buckets: impl IntoIterator<Item = f64>) -> Self {
+    self.series.clear();
+    self.buckets = buckets
+      .into_iter()
+      .filter(|bound| bound.is_finite())
+      .collect();
+    self.buckets.sort_by(f64::total_cmp);
+    self.buckets.dedup();
+    self
+  }
+
+  /// Sets whether a span is exported per measurement, `true` by default.
+  pub fn traces(mut self, traces: bool) -> Self {
+    self.traces = traces;
+    self
+  }
+
+  /// Sets the maximum time an export may take, 10 seconds by default.
+  pub fn timeout(mut self, timeout: Duration) -> Self {
+    self.timeout = timeout;
+    self
+  }
+
+  /// Updates the metrics of the monitor that took the measurement and adds
+  /// its span.
+  pub fn record(&mut self, measurement: &Measurement) {
+    let at = measurement.timestamp.unix_timestamp_nanos();
+    let buckets = self.buckets.len() + 1;
+    let series = self
+      .series
+      .entry((measurement.monitor_id, monitor_type(measurement)))
+      .or_insert_with(|| Series {
+        start: at,
+        buckets: vec![0; buckets],
+        min: f64::INFINITY,
+        max: f64::NEG_INFINITY,
+        ..Default::default()
+      });
+
+    series.labels = label_attributes(measurement);
+    series.start = series.start.min(at);
+    series.end = series.end.max(at);
+
+    let status = measurement.status();
+
+    match status {
+      Status::Down => series.up = Some((false, at)),
+      Status::Up | Status::Degraded => series.up = Some((true, at)),
+      Status::Unknown => {}
+    }
+
+    if status != Status::Down
+      && let Some(data) = &measurement.data
+    {
+      let latency = f64::from(data.latency());
+      let bucket = self.buckets.partition_point(|bound| *bound < latency);
+
+      series.buckets[bucket] += 1;
+      series.sum += latency;
+      series.min = series.min.min(latency);
+      series.max = series.max.max(latency);
+    }
+
+    if self.traces {
+      self.spans.push(span(measurement));
+    }
+  }
+
+  /// Returns the number of spans waiting to be exported.
+  pub fn pending_spans(&self) -> usize {
+    self.spans.len()
+  }
+
+  /// Exports the metrics and spans recorded since the last export. If an
+  /// export fails, what it contains is kept, so it can be retried.
+  pub async fn flush(&mut self) -> Result<(), ExportError> {
+    if !self.series.is_empty() {
+      let url = format!("{}/v1/metrics", self.endpoint);
+      let body = serde_json::to_vec(&self.metrics())?;
+
+      post(&url, &self.headers, &body, self.timeout).await?;
+      self.series.clear();
+    }
+
+    if !self.spans.is_empty() {
+      let url = format!("{}/v1/traces", self.endpoint);
+      let body = serde_json::to_vec(&self.traces_payload())?;
+
+      post(&url, &self.headers, &body, self.timeout).await?;
+      self.spans.clear();
+    }
+
+    Ok(())
+  }
+
+  /// Builds the `ExportMetricsServiceRequest` of the recorded metrics.
+  fn metrics(&self) -> Value {
+    let mut gauge = Vec::new();
+    let mut histogram = Vec::new();
+
+    for ((id, monitor_type), series) in &self.series {
+      let mut attributes = monitor_attributes(*id, monitor_type);
+      attributes.extend(series.labels.iter().cloned());
+
+      if let Some((up, at)) = series.up {
+        gauge.push(json!({
+          "attributes": attributes,
+          "timeUnixNano": at.to_string(),
+          "asInt": u8::from(up).to_string(),
+        }));
+      }
+
+      let count: u64 = series.buckets.iter().sum();
+
+      if count > 0 {
+        histogram.push(json!({
+          "attributes": attributes,
+          "startTimeUnixNano": series.start.to_string(),
+          "timeUnixNano": series.end.to_string(),
+          "count": count.to_string(),
+          "sum": series.sum,
+          "min": series.min,
+          "max": series.max,
+          "bucketCounts": series.buckets.iter().map(u64::to_string).collect::<Vec<_>>(),
+          "explicitBounds": self.buckets,
+        }));
+      }
+    }
+
+    json!({
+      "resourceMetrics": [{
+        "resource": self.resource(),
+        "scopeMetrics": [{
+          "scope": scope(),
+          "metrics": [
+            {
+              "name": "limon.up",
+              "description": "Whether the last measurement of the monitor succeeded.",
+              "unit": "1",
+              "gauge": { "dataPoints": gauge },
+            },
+            {
+              "name": "limon.latency",
+              "description": "Latency of the successful measurements of the monitor.",
+              "unit": "ms",
+              "histogram": { "aggregationTemporality": DELTA, "dataPoints": histogram },
+            },
+          ],
+        }],
+      }],
+    })
+  }
+
+  /// Builds the `ExportTraceServiceRequest` of the recorded spans.
+  fn traces_payload(&self) -> Value {
+    json!({
+      "resourceSpans": [{
+        "resource": self.resource(),
+        "scopeSpans": [{ "scope": scope(), "spans": self.spans }],
+      }],
+    })
+  }
+
+  fn resource(&self) -> Value {
+    json!({ "attributes": [attribute("service.name", json!({ "stringValue": self.service_name }))] })
+  }
+}
+
+/// Builds the span of a measurement, starting when it was taken and lasting
+/// its latency.
+fn span(measurement: &Measurement) -> Value {
+  let ids = RandomState::new();
+  let start = measurement.timestamp.unix_timestamp_nanos();
+  let latency = measurement.data.as_ref().map_or(0.0, |data| data.latency());
+  let end = start + (f64::from(latency) * 1_000_000.0) as i128;
+
+  let mut attributes = monitor_attributes(measurement.monitor_id, monitor_type(measurement));
+  attributes.extend(label_attributes(measurement));
+  attributes.push(attribute(
+    "limon.status",
+    json!({ "stringValue": measurement.status().as_str() }),
+  ));
+
+  for (name, value) in measurement.data.iter().flat_map(timings) {
+    attributes.push(attribute(
+      &format!("limon.{}", name),
+      json!({ "doubleValue": value }),
+    ));
+  }
+
+  let mut status = json!({});
+
+  if let Some(error) = &measurement.error {
+    attributes.push(attribute(
+      "error.type",
+      json!({ "stringValue": error.kind().as_str() }),
+    ));
+    status = json!({ "code": ERROR, "message": error.to_string() });
+  }
+
+  json!({
+    "traceId": format!("{:016x}{:016x}", ids.hash_one(0), ids.hash_one(1)),
+    "spanId": format!("{:016x}", ids.hash_one(2)),
+    "name": "limon.measure",
+    "kind": CLIENT,
+    "startTimeUnixNano": start.to_string(),
+    "endTimeUnixNano": end.to_string(),
+    "attributes": attributes,
+    "status": status,
+  })
+}
+
+fn monitor_attributes(id: i64, monitor_type: &str) -> Vec<Value> {
+  vec![
+    attribute("monitor.id", json!({ "intValue": id.to_string() })),
+    attribute("monitor.type", json!({ "stringValue": monitor_type })),
+  ]
+}
+
+fn label_attributes(measurement: &Measurement) -> Vec<Value> {
+  labels(measurement, &["monitor.id", "monitor.type"])
+    .into_iter()
+    .map(|(name, value)| attribute(name, json!({ "stringValue": value })))
+    .collect()
+}
+
+fn attribute(key: &str, value: Value) -> Value {
+  json!({ "key": key, "value": value })
+}
+
+fn scope() -> Value {
+  json!({ "name": env!("CARGO_PKG_NAME"), "version": env!("CARGO_PKG_VERSION") })
+}
+
+#[cfg(test)]
+mod tests {
+  use std::collections::HashMap;
+
+  use httpmock::prelude::*;
+
+  use super::*;
+  use crate::monitor::errors::{CollectorError, PingError};
+
+  #[test]
+  fn payloads() {
+    let mut exporter = OtlpExporter::new("http://localhost:4318/").buckets([100.0]);
+
+    exporter.record(&Measurement::fixture(4).at(10).latency(50.0));
+    exporter.record(&Measurement {
+      labels: HashMap::from([(String::from("env"), String::from("prod"))]),
+      ..Measurement::fixture(4).at(20).latency(150.0)
+    });
+    exporter.record(
+      &Measurement::fixture(4)
+        .at(30)
+        .error(CollectorError::Ping(PingError::Unreachable)),
+    );
+
+    let metrics = exporter.metrics();
+    let resource = &metrics["resourceMetrics"][0];
+    let [up, latency] = &resource["scopeMetrics"][0]["metrics"].as_array().unwrap()[..] else {
+      panic!("expected two metrics");
+    };
+
+    assert_eq!(
+      resource["resource"]["attributes"][0]["value"]["stringValue"],
+      "limon"
+    );
+
+    let points = up["gauge"]["dataPoints"].as_array().unwrap();
+    assert_eq!(points.len(), 2, "a point per monitor type");
+    assert_eq!(points[0]["attributes"][0]["value"]["intValue"], "4");
+    assert_eq!(points[0]["attributes"][1]["value"]["stringValue"], "http");
+    assert_eq!(
+      points[0]["attributes"][2],
+      attribute("env", json!({ "stringValue": "prod" })),
+      "the labels of the last measurement"
+    );
+    assert_eq!(points[1]["attributes"].as_array().unwrap().len(), 2);
+    assert_eq!(points[0]["asInt"], "1");
+    assert_eq!(points[1]["asInt"], "0");
+
+    let histogram = &latency["histogram"];
+    let point = &histogram["dataPoints"][0];
+    assert_eq!(histogram["aggregationTemporality"], 1);
+    assert_eq!(histogram["dataPoints"].as_array().unwrap().len(), 1);
+    assert_eq!(point["count"], "2");
+    assert_eq!(point["sum"], 200.0);
+    assert_eq!(point["bucketCounts"], json!(["1", "1"]));
+    assert_eq!(point["startTimeUnixNano"], "10000000000");
+    assert_eq!(point["timeUnixNano"], "20000000000");
+
+    let traces = exporter.traces_payload();
+    let spans = traces["resourceSpans"][0]["scopeSpans"][0]["spans"]
+      .as_array()
+      .unwrap();
+    assert_eq!(spans.len(), 3);
+    assert_eq!(spans[0]["traceId"].as_str().unwrap().len(), 32);
+    assert_eq!(spans[0]["spanId"].as_str().unwrap().len(), 16);
+    assert_ne!(spans[0]["traceId"], spans[1]["traceId"]);
+    assert_eq!(spans[0]["endTimeUnixNano"], "10050000000");
+    assert_eq!(spans[0]["status"], json!({}));
+    assert_eq!(
+      spans[1]["attributes"][2],
+      attribute("env", json!({ "stringValue": "prod" }))
+    );
+    assert_eq!(spans[2]["status"]["code"], 2);
+    assert!(
+      spans[2]["attributes"]
+        .as_array()
+        .unwrap()
+        .contains(&attribute(
+          "error.type",
+          json!({ "stringValue": "unreachable" })
+        ))
+    );
+  }
+
+  #[tokio::test]
+  async fn export() {
+    let server = MockServer::start_async().await;
+
+    let metrics = server
+      .mock_async(|when, then| {
+        when
+          .method(POST)
+          .path("/v1/metrics")
+          .header("Content-Type", "application/json")
+          .header("Authorization", "Bearer secret")
+          .body_includes("\"limon.latency\"");
+        then.status(200);
+      })
+      .await;
+    let traces = server
+      .mock_async(|when, then| {
+        when
+          .method(POST)
+          .path("/v1/traces")
+          .body_includes("\"limon.measure\"");
+        then.status(200);
+      })
+      .await;
+
+    let mut exporter =
+      OtlpExporter::new(server.base_url()).header("Authorization", "Bearer secret");
+
+    exporter.record(&Measurement::fixture(4).at(10).latency(50.0));
+    exporter.flush().await.unwrap();
+    assert_eq!(exporter.pending_spans(), 0);
+
+    exporter.flush().await.unwrap();
+    metrics.assert_async().await;
+    traces.assert_async().await;
+  }
+}
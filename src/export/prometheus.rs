@@ -0,0 +1,440 @@
+//! Metrics of measurements in the Prometheus text exposition format.
+//!
+//! A [PrometheusExporter] is fed the measurements of any number of monitors
+//! and keeps, per monitor id and type, labeled with the
+//! [labels](Measurement#structfield.labels) of the last measurement:
+//!
+//! - `limon_up` - whether the last measurement succeeded, possibly degraded;
+//! - `limon_degraded` - whether the last measurement was degraded;
+//! - `limon_latency_milliseconds` - the latency of the last successful one;
+//! - `limon_last_measurement_timestamp_seconds` - when the last one was taken;
+//! - `limon_measurements_total` and `limon_failures_total` - counters;
+//! - `limon_latency_seconds` - a histogram of the successful latencies.
+//!
+//! The [stats](RunnerStats) of a runner are rendered by
+//! [render_runner_stats], e.g. to be served along with them.
+//!
+//! # Example
+//!
+//! ```rust
+//! use limon_core::export::prometheus::PrometheusExporter;
+//! use limon_core::monitor::models::Measurement;
+//!
+//! fn metrics(measurements: &[Measurement]) -> String {
+//!   let mut exporter = PrometheusExporter::new();
+//!
+//!   for measurement in measurements {
+//!     exporter.record(measurement);
+//!   }
+//!
+//!   // Served as `text/plain; version=0.0.4` on `/metrics`.
+//!   exporter.render()
+//! }
+//! ```
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+use super::{labels, monitor_type};
+use crate::monitor::models::{Measurement, Status};
+use crate::schedule::runner::RunnerStats;
+
+/// Default upper bounds, in seconds, of the latency histogram buckets.
+const DEFAULT_BUCKETS: [f64; 11] = [
+  0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+/// The name, help and value of a metric of a monitor.
+type Metric<T> = (&'static str, &'static str, fn(&Series) -> T);
+
+/// Keeps metrics of measurements and renders them for Prometheus to scrape.
+#[derive(Debug, Clone)]
+pub struct PrometheusExporter {
+  buckets: Vec<f64>,
+
+  /// Metrics by monitor id and type, ordered so the output is stable.
+  series: BTreeMap<(i64, &'static str), Series>,
+}
+
+/// Metrics of a single monitor.
+#[derive(Debug, Clone, Default)]
+struct Series {
+  /// The labels of the last measurement, rendered with a leading comma.
+  labels: String,
+
+  up: Option<bool>,
+  degraded: bool,
+  latency: Option<f32>,
+
+  /// Unix timestamp of the last measurement, in milliseconds.
+  timestamp: i64,
+
+  measurements: u64,
+  failures: u64,
+
+  /// Number of latencies in each bucket, not cumulative, the last one being
+  /// `+Inf`.
+  buckets: Vec<u64>,
+  sum: f64,
+}
+
+impl PrometheusExporter {
+  /// Creates an exporter without metrics, with the default latency buckets.
+  pub fn new() -> Self {
+    Self {
+      buckets: DEFAULT_BUCKETS.to_vec(),
+      series: BTreeMap::new(),
+    }
+  }
+
+  /// Sets the upper bounds, in seconds, of the latency histogram buckets. The
+  /// `+Inf` bucket is always added. Metrics recorded beforehand are forgotten.
+  pub fn buckets(mut self, buckets: impl IntoIterator<Item = f64>) -> Self {
+    self.series.clear();
+    self.buckets = buckets
+      .into_iter()
+      .filter(|bound| bound.is_finite())
+      .collect();
+    self.buckets.sort_by(f64::total_cmp);
+    self.buckets.dedup();
+    self
+  }
+
+  /// Updates the metrics of the monitor that took the measurement.
+  pub fn record(&mut self, measurement: &Measurement) {
+    let buckets = self.buckets.len() + 1;
+    let series = self
+      .series
+      .entry((measurement.monitor_id, monitor_type(measurement)))
+      .or_insert_with(|| Series {
+        buckets: vec![0; buckets],
+        ..Default::default()
+      });
+
+    series.labels.clear();
+
+    for (name, value) in labels(measurement, &["monitor_id", "type", "le"]) {
+      let _ = write!(
+        series.labels,
+        ",{}=\"{}\"",
+        label_name(name),
+        value
+          .replace('\\', "\\\\")
+          .replace('"', "\\\"")
+          .replace('\n', "\\n")
+      );
+    }
+
+    series.measurements += 1;
+    series.timestamp = (measurement.timestamp.unix_timestamp_nanos() / 1_000_000) as i64;
+
+    match measurement.status() {
+      Status::Down => {
+        series.up = Some(false);
+        series.degraded = false;
+        series.failures += 1;
+        return;
+      }
+      Status::Unknown => return,
+      status => {
+        series.up = Some(true);
+        series.degraded = status == Status::Degraded;
+      }
+    }
+
+    if let Some(data) = &measurement.data {
+      let latency = data.latency();
+      let seconds = f64::from(latency) / 1000.0;
+      let bucket = self.buckets.partition_point(|bound| *bound < seconds);
+
+      series.latency = Some(latency);
+      series.buckets[bucket] += 1;
+      series.sum += seconds;
+    }
+  }
+
+  /// Forgets the metrics of a monitor, e.g. after it's deleted.
+  pub fn remove(&mut self, monitor_id: i64) {
+    self.series.retain(|(id, _), _| *id != monitor_id);
+  }
+
+  /// Returns the number of monitors with metrics.
+  pub fn len(&self) -> usize {
+    self.series.len()
+  }
+
+  /// Returns `true` if no measurement was recorded.
+  pub fn is_empty(&self) -> bool {
+    self.series.is_empty()
+  }
+
+  /// Renders the metrics in the text exposition format.
+  pub fn render(&self) -> String {
+    let mut out = String::new();
+
+    let gauges: [Metric<Option<f64>>; 4] = [
+      (
+        "limon_up",
+        "Whether the last measurement of the monitor succeeded.",
+        |series| series.up.map(f64::from),
+      ),
+      (
+        "limon_degraded",
+        "Whether the last measurement of the monitor exceeded a latency threshold.",
+        |series| series.up.map(|_| f64::from(series.degraded)),
+      ),
+      (
+        "limon_latency_milliseconds",
+        "Latency of the last successful measurement of the monitor.",
+        |series| series.latency.map(f64::from),
+      ),
+      (
+        "limon_last_measurement_timestamp_seconds",
+        "Unix timestamp of the last measurement of the monitor.",
+        |series| Some(series.timestamp as f64 / 1000.0),
+      ),
+    ];
+
+    for (name, help, value) in gauges {
+      self.family(&mut out, name, "gauge", help, |out, labels, series| {
+        if let Some(value) = value(series) {
+          let _ = writeln!(out, "{name}{{{labels}}} {value}");
+        }
+      });
+    }
+
+    let counters: [Metric<u64>; 2] = [
+      (
+        "limon_measurements_total",
+        "Number of measurements of the monitor.",
+        |series| series.measurements,
+      ),
+      (
+        "limon_failures_total",
+        "Number of failed measurements of the monitor.",
+        |series| series.failures,
+      ),
+    ];
+
+    for (name, help, value) in counters {
+      self.family(&mut out, name, "counter", help, |out, labels, series| {
+        let _ = writeln!(out, "{name}{{{labels}}} {}", value(series));
+      });
+    }
+
+    let name = "limon_latency_seconds";
+    let help = "Latency of the successful measurements of the monitor.";
+
+    self.family(&mut out, name, "histogram", help, |out, labels, series| {
+      let mut count = 0;
+
+      for (index, observations) in series.buckets.iter().enumerate() {
+        count += observations;
+
+        let bound = match self.buckets.get(index) {
+          Some(bound) => bound.to_string(),
+          None => "+Inf".to_string(),
+        };
+
+        let _ = writeln!(out, "{name}_bucket{{{labels},le=\"{bound}\"}} {count}");
+      }
+
+      let _ = writeln!(out, "{name}_sum{{{labels}}} {}", series.sum);
+      let _ = writeln!(out, "{name}_count{{{labels}}} {count}");
+    });
+
+    out
+  }
+
+  /// Writes the header of a metric family and the samples of every monitor.
+  fn family(
+    &self,
+    out: &mut String,
+    name: &str,
+    kind: &str,
+    help: &str,
+    mut samples: impl FnMut(&mut String, &str, &Series),
+  ) {
+    let _ = writeln!(out, "# HELP {name} {help}");
+    let _ = writeln!(out, "# TYPE {name} {kind}");
+
+    for ((id, monitor_type), series) in &self.series {
+      let labels = format!(
+        "monitor_id=\"{id}\",type=\"{monitor_type}\"{}",
+        series.labels
+      );
+      samples(out, &labels, series);
+    }
+  }
+}
+
+/// Renders the statistics of a runner in the text exposition format, as
+/// `limon_runner_*` metrics.
+pub fn render_runner_stats(stats: &RunnerStats) -> String {
+  let mut out = String::new();
+
+  let metrics: [(&str, &str, &str, f64); 7] = [
+    (
+      "limon_runner_in_flight",
+      "gauge",
+      "Number of runs in progress.",
+      stats.in_flight as f64,
+    ),
+    (
+      "limon_runner_queued",
+      "gauge",
+      "Number of due items waiting to be run.",
+      stats.queued as f64,
+    ),
+    (
+      "limon_runner_last_tick_seconds",
+      "gauge",
+      "Time the last tick took to start the due runs.",
+      stats.last_tick.as_secs_f64(),
+    ),
+    (
+      "limon_runner_runs_total",
+      "counter",
+      "Number of completed runs.",
+      stats.executed as f64,
+    ),
+    (
+      "limon_runner_failures_total",
+      "counter",
+      "Number of completed runs that failed or exceeded their deadline.",
+      stats.failed as f64,
+    ),
+    (
+      "limon_runner_late_runs_total",
+      "counter",
+      "Number of runs started after the moment they were due at.",
+      stats.late as f64,
+    ),
+    (
+      "limon_runner_deduplicated_total",
+      "counter",
+      "Number of outputs shared by a run of another item.",
+      stats.deduplicated as f64,
+    ),
+  ];
+
+  for (name, kind, help, value) in metrics {
+    let _ = writeln!(out, "# HELP {name} {help}");
+    let _ = writeln!(out, "# TYPE {name} {kind}");
+    let _ = writeln!(out, "{name} {value}");
+  }
+
+  out
+}
+
+/// Replaces the characters not allowed in label names with underscores.
+fn label_name(name: &str) -> String {
+  let mut label: String = name
+    .chars()
+    .map(|char| match char {
+      'a'..='z' | 'A'..='Z' | '0'..='9' | '_' => char,
+      _ => '_',
+    })
+    .collect();
+
+  if label.is_empty() || label.starts_with(|char: char| char.is_ascii_digit()) {
+    label.insert(0, '_');
+  }
+
+  label
+}
+
+impl Default for PrometheusExporter {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::collections::HashMap;
+
+  use super::*;
+  use crate::monitor::errors::{CollectorError, PingError};
+  use crate::monitor::models::Degradation;
+
+  #[test]
+  fn exposition() {
+    let mut exporter = PrometheusExporter::new().buckets([0.5, 0.2]);
+
+    exporter.record(&Measurement::fixture(1).at(1_700_000_000).latency(125.0));
+    exporter.record(&Measurement::fixture(1).at(1_700_000_000).latency(250.0));
+    exporter.record(&Measurement {
+      degradation: Some(Degradation::Warning),
+      ..Measurement::fixture(1).at(1_700_000_000).latency(750.0)
+    });
+    exporter.record(&Measurement {
+      labels: HashMap::from([
+        (String::from("team"), String::from("core")),
+        (String::from("cloud.region"), String::from("eu \"west\"")),
+        (String::from("type"), String::from("reserved")),
+      ]),
+      ..Measurement::fixture(2)
+        .at(1_700_000_000)
+        .error(CollectorError::Ping(PingError::Unreachable))
+    });
+
+    let metrics = exporter.render();
+    let http = "monitor_id=\"1\",type=\"http\"";
+    let ping = "monitor_id=\"2\",type=\"ping\",cloud_region=\"eu \\\"west\\\"\",team=\"core\"";
+
+    for line in [
+      "# TYPE limon_up gauge".to_string(),
+      format!("limon_up{{{http}}} 1"),
+      format!("limon_up{{{ping}}} 0"),
+      format!("limon_degraded{{{http}}} 1"),
+      format!("limon_latency_milliseconds{{{http}}} 750"),
+      format!("limon_last_measurement_timestamp_seconds{{{ping}}} 1700000000"),
+      "# TYPE limon_measurements_total counter".to_string(),
+      format!("limon_measurements_total{{{http}}} 3"),
+      format!("limon_failures_total{{{ping}}} 1"),
+      "# TYPE limon_latency_seconds histogram".to_string(),
+      format!("limon_latency_seconds_bucket{{{http},le=\"0.2\"}} 1"),
+      format!("limon_latency_seconds_bucket{{{http},le=\"0.5\"}} 2"),
+      format!("limon_latency_seconds_bucket{{{http},le=\"+Inf\"}} 3"),
+      format!("limon_latency_seconds_sum{{{http}}} 1.125"),
+      format!("limon_latency_seconds_count{{{ping}}} 0"),
+    ] {
+      assert!(metrics.lines().any(|other| other == line), "missing {line}");
+    }
+
+    assert!(
+      !metrics.contains(&format!("limon_latency_milliseconds{{{ping}}}")),
+      "no latency without a successful measurement"
+    );
+
+    exporter.remove(2);
+    assert_eq!(exporter.len(), 1);
+    assert!(!exporter.render().contains(ping));
+  }
+
+  #[test]
+  fn runner_stats() {
+    let metrics = render_runner_stats(&RunnerStats {
+      in_flight: 4,
+      executed: 120,
+      failed: 3,
+      deduplicated: 40,
+      last_tick: std::time::Duration::from_millis(250),
+      ..Default::default()
+    });
+
+    for line in [
+      "# TYPE limon_runner_in_flight gauge",
+      "limon_runner_in_flight 4",
+      "limon_runner_queued 0",
+      "limon_runner_last_tick_seconds 0.25",
+      "# TYPE limon_runner_runs_total counter",
+      "limon_runner_runs_total 120",
+      "limon_runner_failures_total 3",
+      "limon_runner_deduplicated_total 40",
+    ] {
+      assert!(metrics.lines().any(|other| other == line), "missing {line}");
+    }
+  }
+}
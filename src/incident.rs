@@ -0,0 +1,305 @@
+//! A module turning the state changes of monitors into incidents.
+//!
+//! An [IncidentTracker] follows the measurements of a monitor through its
+//! [MonitorState]: an [Incident] is opened when the monitor is confirmed
+//! down and resolved when it's confirmed back up. In between, it can be
+//! acknowledged, or resolved by hand. Resolved incidents are kept as the
+//! monitor's history.
+//!
+//! # Example
+//!
+//! ```rust
+//! use limon_core::incident::IncidentTracker;
+//! use limon_core::monitor::models::{Measurement, Monitor};
+//! use limon_core::monitor::state::MonitorState;
+//!
+//! fn track(monitor: &Monitor, measurements: &[Measurement]) -> IncidentTracker {
+//!   let mut tracker = IncidentTracker::new(MonitorState::from_config(&monitor.config));
+//!
+//!   for measurement in measurements {
+//!     tracker.record(measurement);
+//!   }
+//!
+//!   tracker
+//! }
+//! ```
+
+use std::time::Duration;
+
+use time::OffsetDateTime;
+
+use crate::monitor::errors::ErrorReport;
+use crate::monitor::models::{Measurement, Status};
+use crate::monitor::state::{MonitorState, State, Transition};
+
+/// Lifecycle stage of an [Incident].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum IncidentStatus {
+  /// The monitor is down and nobody has acknowledged it yet.
+  Open,
+
+  /// Somebody is looking into it.
+  Acknowledged,
+
+  /// The monitor recovered, or the incident was resolved by hand.
+  Resolved,
+}
+
+/// A period during which a monitor was down.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct Incident {
+  /// Identifier of the monitor that went down.
+  pub monitor_id: i64,
+
+  /// Current lifecycle stage.
+  pub status: IncidentStatus,
+
+  /// When the first failed measurement was taken.
+  #[serde(with = "time::serde::timestamp::milliseconds_i64")]
+  pub start: OffsetDateTime,
+
+  /// When the monitor recovered, or the incident was resolved by hand.
+  #[serde(with = "time::serde::timestamp::milliseconds_i64::option")]
+  pub end: Option<OffsetDateTime>,
+
+  /// When the incident was acknowledged.
+  #[serde(with = "time::serde::timestamp::milliseconds_i64::option")]
+  pub acknowledged: Option<OffsetDateTime>,
+
+  /// Unix timestamps, in milliseconds, of the failed measurements that
+  /// confirmed the incident. Along with the monitor id, they identify the
+  /// measurements.
+  pub causes: Vec<i64>,
+
+  /// Report of the error of the last failed measurement that confirmed the
+  /// incident.
+  pub error: Option<ErrorReport>,
+}
+
+impl Incident {
+  /// Returns `true` if the incident isn't resolved yet.
+  pub fn is_open(&self) -> bool {
+    self.status != IncidentStatus::Resolved
+  }
+
+  /// Returns how long the incident lasted, or `None` if it's still open.
+  pub fn duration(&self) -> Option<Duration> {
+    self
+      .end
+      .map(|end| (end - self.start).try_into().unwrap_or_default())
+  }
+
+  /// Acknowledges the incident at `at`. Returns `false` if it was already
+  /// acknowledged or resolved.
+  pub fn acknowledge(&mut self, at: OffsetDateTime) -> bool {
+    if self.status != IncidentStatus::Open {
+      return false;
+    }
+
+    self.status = IncidentStatus::Acknowledged;
+    self.acknowledged = Some(at);
+
+    true
+  }
+
+  /// Resolves the incident at `at`. Returns `false` if it was already
+  /// resolved.
+  pub fn resolve(&mut self, at: OffsetDateTime) -> bool {
+    if !self.is_open() {
+      return false;
+    }
+
+    self.status = IncidentStatus::Resolved;
+    self.end = Some(at);
+
+    true
+  }
+}
+
+/// Opens and resolves the incidents of a single monitor from its successive
+/// measurements.
+#[derive(Debug, Clone)]
+pub struct IncidentTracker {
+  state: MonitorState,
+
+  /// Failed measurements since the monitor was last seen up.
+  suspects: Vec<(i64, Option<ErrorReport>)>,
+
+  open: Option<Incident>,
+  history: Vec<Incident>,
+}
+
+impl IncidentTracker {
+  /// Creates a tracker following the given state of the monitor.
+  pub fn new(state: MonitorState) -> Self {
+    Self {
+      state,
+      suspects: Vec::new(),
+      open: None,
+      history: Vec::new(),
+    }
+  }
+
+  /// Returns the state of the monitor.
+  pub fn state(&self) -> &MonitorState {
+    &self.state
+  }
+
+  /// Records the next measurement of the monitor, opening or resolving an
+  /// incident if it confirms a transition, which is returned.
+  ///
+  /// If the open incident was resolved by hand while the monitor is still
+  /// down, no incident is opened until it recovers and goes down again.
+  pub fn record(&mut self, measurement: &Measurement) -> Option<Transition> {
+    match (self.state.state(), measurement.status()) {
+      (State::Up, Status::Down) => self.suspects.push((
+        (measurement.timestamp.unix_timestamp_nanos() / 1_000_000) as i64,
+        measurement.error.as_ref().map(|error| error.report()),
+      )),
+      (State::Up, Status::Up | Status::Degraded) => self.suspects.clear(),
+      _ => {}
+    }
+
+    let transition = self.state.record(measurement)?;
+
+    match transition.to {
+      State::Down => {
+        let suspects = std::mem::take(&mut self.suspects);
+        let error = suspects.last().and_then(|(_, error)| error.clone());
+
+        self.open = Some(Incident {
+          monitor_id: measurement.monitor_id,
+          status: IncidentStatus::Open,
+          start: transition.since,
+          end: None,
+          acknowledged: None,
+          causes: suspects.into_iter().map(|(at, _)| at).collect(),
+          error,
+        });
+      }
+      State::Up => {
+        self.resolve(transition.since);
+      }
+    }
+
+    Some(transition)
+  }
+
+  /// Returns the open incident, if any.
+  pub fn open(&self) -> Option<&Incident> {
+    self.open.as_ref()
+  }
+
+  /// Acknowledges the open incident at `at`. Returns `false` if there's no
+  /// open incident or it was already acknowledged.
+  pub fn acknowledge(&mut self, at: OffsetDateTime) -> bool {
+    self
+      .open
+      .as_mut()
+      .is_some_and(|incident| incident.acknowledge(at))
+  }
+
+  /// Resolves the open incident at `at`, moving it to the history. Returns
+  /// `false` if there's no open incident.
+  pub fn resolve(&mut self, at: OffsetDateTime) -> bool {
+    let Some(mut incident) = self.open.take() else {
+      return false;
+    };
+
+    incident.resolve(at);
+    self.history.push(incident);
+
+    true
+  }
+
+  /// Returns the resolved incidents, oldest first.
+  pub fn history(&self) -> &[Incident] {
+    &self.history
+  }
+
+  /// Takes the resolved incidents, e.g. to persist them, leaving the history
+  /// empty.
+  pub fn take_history(&mut self) -> Vec<Incident> {
+    std::mem::take(&mut self.history)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::monitor::errors::ErrorKind;
+
+  fn at(seconds: i64) -> OffsetDateTime {
+    OffsetDateTime::from_unix_timestamp(seconds).unwrap()
+  }
+
+  #[test]
+  fn lifecycle() {
+    let mut tracker = IncidentTracker::new(MonitorState::new(2, 2));
+
+    for (seconds, up) in [(0, true), (10, false), (20, true), (30, false)] {
+      assert_eq!(
+        tracker.record(&Measurement::fixture(7).at(seconds).up(up)),
+        None
+      );
+    }
+
+    assert!(
+      tracker
+        .record(&Measurement::fixture(7).at(40).up(false))
+        .is_some()
+    );
+
+    let incident = tracker.open().unwrap();
+    assert_eq!(incident.monitor_id, 7);
+    assert_eq!(incident.status, IncidentStatus::Open);
+    assert_eq!(incident.start, at(30));
+    assert_eq!(incident.causes, [30_000, 40_000]);
+    assert_eq!(
+      incident.error.as_ref().unwrap().kind,
+      ErrorKind::ClientUnavailable
+    );
+    assert_eq!(incident.duration(), None);
+
+    assert!(tracker.acknowledge(at(45)));
+    assert!(!tracker.acknowledge(at(46)), "already acknowledged");
+
+    tracker.record(&Measurement::fixture(7).at(50).up(false));
+    tracker.record(&Measurement::fixture(7).at(60));
+    assert!(tracker.record(&Measurement::fixture(7).at(70)).is_some());
+    assert!(tracker.open().is_none());
+
+    let incident = &tracker.history()[0];
+    assert_eq!(incident.status, IncidentStatus::Resolved);
+    assert_eq!(incident.acknowledged, Some(at(45)));
+    assert_eq!(incident.end, Some(at(60)));
+    assert_eq!(incident.duration(), Some(Duration::from_secs(30)));
+    assert_eq!(incident.causes.len(), 2, "only the confirming measurements");
+  }
+
+  #[test]
+  fn resolved_by_hand() {
+    let mut tracker = IncidentTracker::new(MonitorState::new(1, 1));
+
+    tracker.record(&Measurement::fixture(7).at(0).up(false));
+    assert!(tracker.resolve(at(5)));
+    assert!(!tracker.resolve(at(6)));
+
+    tracker.record(&Measurement::fixture(7).at(10).up(false));
+    assert!(tracker.open().is_none(), "still the same outage");
+
+    tracker.record(&Measurement::fixture(7).at(20));
+    tracker.record(&Measurement::fixture(7).at(30).up(false));
+    assert!(tracker.open().is_some());
+
+    let history = tracker.take_history();
+    assert_eq!(history.len(), 1);
+    assert_eq!(history[0].duration(), Some(Duration::from_secs(5)));
+    assert!(tracker.history().is_empty());
+
+    let json = serde_json::to_value(&history[0]).unwrap();
+    assert_eq!(json["status"], "resolved");
+    assert_eq!(json["end"], 5000);
+  }
+}
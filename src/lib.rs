@@ -11,9 +11,39 @@
 //!   and the [`Schedule`](schedule::Schedule) struct for managing objects that
 //!   are polled or executed at regular intervals. Items implementing
 //!   [`Schedulable`](schedule::Schedulable) have a unique `id` and an associated
-//!   interval, allowing efficient lookup and grouping.
+//!   interval, allowing efficient lookup and grouping. Due items can be run
+//!   periodically by the [`Runner`](schedule::runner::Runner).
+//!
+//! - **analytics** - Aggregates measurements into the numbers shown on status
+//!   pages, such as the [`Uptime`](analytics::Uptime) of a monitor.
+//!
+//! - **incident** - Turns the confirmed state changes of monitors into
+//!   [`Incident`](incident::Incident)s, which can be acknowledged and resolved.
+//!
+//! - **coordination** - Splits a shared set of monitors between agents with
+//!   leases, so each monitor is checked by a single one.
+//!
+//! - **export** - Exports measurements to monitoring systems, such as
+//!   [Prometheus](export::prometheus).
+//!
+//! - **storage** - Persists measurements, e.g. in an embedded SQLite database
+//!   with the `sqlite` feature.
+//!
+//! - **wire** - Encodes measurements in a compact binary format, for agents
+//!   shipping them to a collector. It requires the `protobuf` feature.
+//!
+//! With the `tracing` feature, measurements, collectors and runners are
+//! instrumented with [`tracing`](https://docs.rs/tracing) spans, e.g. to
+//! find slow ticks or stuck checks of a large agent.
 
 extern crate openssl;
 
+pub mod analytics;
+pub mod coordination;
+pub mod export;
+pub mod incident;
 pub mod monitor;
 pub mod schedule;
+pub mod storage;
+#[cfg(feature = "protobuf")]
+pub mod wire;
@@ -0,0 +1,153 @@
+//! A shared `HTTP` client.
+//!
+//! All requests are performed by a single curl multi handle driven by
+//! a dedicated thread, so checks don't occupy a blocking thread each, and
+//! repeated checks against the same host reuse connections, DNS entries and
+//! TLS sessions.
+
+use std::collections::HashMap;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread;
+use std::time::Duration;
+
+use curl::MultiError;
+use curl::easy::{Easy2, Handler};
+use curl::multi::{Easy2Handle, Multi, MultiWaker};
+use tokio::sync::oneshot;
+
+use crate::monitor::errors::HttpError;
+
+/// A finished request along with the result of its transfer, so the caller
+/// can still inspect the request (e.g. its timings) when the transfer failed.
+pub type Transfer<H> = (Easy2<H>, Result<(), curl::Error>);
+
+type Reply<H> = oneshot::Sender<Result<Transfer<H>, HttpError>>;
+
+/// The maximum time the worker waits for socket activity before checking
+/// transfers for timeouts.
+const POLL_TIMEOUT: Duration = Duration::from_millis(100);
+
+struct Job<H> {
+  request: Easy2<H>,
+  reply: Reply<H>,
+}
+
+/// A client performing requests on a shared multi handle.
+pub struct Client<H> {
+  jobs: Sender<Job<H>>,
+  waker: MultiWaker,
+}
+
+impl<H: Handler + Send + 'static> Client<H> {
+  /// Starts a worker thread that performs requests submitted to the client.
+  pub fn start() -> Self {
+    let (jobs, receiver) = mpsc::channel();
+    let (waker_sender, waker) = mpsc::sync_channel(1);
+
+    thread::Builder::new()
+      .name(String::from("limon-http"))
+      .spawn(move || {
+        let multi = Multi::new();
+        let _ = waker_sender.send(multi.waker());
+
+        run(multi, receiver);
+      })
+      .expect("HTTP client thread");
+
+    Self {
+      jobs,
+      waker: waker.recv().expect("HTTP client waker"),
+    }
+  }
+
+  /// Performs a request and returns it back once the transfer is finished.
+  pub async fn perform(&self, request: Easy2<H>) -> Result<Transfer<H>, HttpError> {
+    let (reply, result) = oneshot::channel();
+
+    self
+      .jobs
+      .send(Job { request, reply })
+      .map_err(|_| HttpError::ClientUnavailable)?;
+    self.waker.wakeup()?;
+
+    result.await.map_err(|_| HttpError::ClientUnavailable)?
+  }
+}
+
+fn run<H: Handler>(multi: Multi, jobs: Receiver<Job<H>>) {
+  let mut transfers: HashMap<usize, (Easy2Handle<H>, Reply<H>)> = HashMap::new();
+  let mut token: usize = 0;
+
+  loop {
+    // Block while there is nothing to do, otherwise pick up queued jobs only.
+    let job = if transfers.is_empty() {
+      match jobs.recv() {
+        Ok(job) => Some(job),
+        Err(_) => return,
+      }
+    } else {
+      None
+    };
+
+    for Job { request, reply } in job.into_iter().chain(jobs.try_iter()) {
+      match multi.add2(request) {
+        Ok(mut handle) => {
+          token = token.wrapping_add(1);
+
+          match handle.set_token(token) {
+            Ok(()) => {
+              transfers.insert(token, (handle, reply));
+            }
+            Err(error) => {
+              let _ = multi.remove2(handle);
+              let _ = reply.send(Err(error.into()));
+            }
+          }
+        }
+        Err(error) => {
+          let _ = reply.send(Err(error.into()));
+        }
+      }
+    }
+
+    if let Err(error) = multi.perform() {
+      fail_all(&multi, &mut transfers, error);
+      continue;
+    }
+
+    let mut finished = Vec::new();
+    multi.messages(|message| {
+      if let (Ok(token), Some(result)) = (message.token(), message.result()) {
+        finished.push((token, result));
+      }
+    });
+
+    for (token, result) in finished {
+      if let Some((handle, reply)) = transfers.remove(&token) {
+        let response = match multi.remove2(handle) {
+          Ok(request) => Ok((request, result)),
+          Err(error) => Err(error.into()),
+        };
+
+        let _ = reply.send(response);
+      }
+    }
+
+    if !transfers.is_empty()
+      && let Err(error) = multi.poll(&mut [], POLL_TIMEOUT)
+    {
+      fail_all(&multi, &mut transfers, error);
+    }
+  }
+}
+
+fn fail_all<H>(
+  multi: &Multi,
+  transfers: &mut HashMap<usize, (Easy2Handle<H>, Reply<H>)>,
+  error: MultiError,
+) {
+  for (_, (handle, reply)) in transfers.drain() {
+    let _ = multi.remove2(handle);
+    let _ = reply.send(Err(error.clone().into()));
+  }
+}
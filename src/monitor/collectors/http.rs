@@ -1,35 +1,376 @@
-use std::time::Duration;
+use std::borrow::Cow;
+use std::net::IpAddr;
+use std::time::{Duration, Instant};
 
-use curl::easy::{Easy2, Handler, HttpVersion, List, WriteError};
-use tokio::task;
+use curl::easy::{Easy2, Handler, HttpVersion as CurlVersion, InfoType, List, WriteError};
+use once_cell::sync::Lazy;
+use openssl::sha::sha256;
+use scraper::{Html, Selector};
 
-use crate::monitor::errors::HttpError;
-use crate::monitor::models::{Data, HttpConfig, HttpData};
+use crate::measure;
+use crate::monitor::collectors::client::Client;
+use crate::monitor::collectors::{ip_literal, millis, resolver, tls};
+use crate::monitor::errors::{HttpError, ResponseSnippet, TimeoutPhase};
+use crate::monitor::models::{
+  Certificate, Data, ElementAssertion, Header, HttpConfig, HttpData, HttpVersion, IpFamily, Method,
+  Partial, Phase, Scheme,
+};
 
+static CLIENT: Lazy<Client<Response>> = Lazy::new(Client::start);
+
+#[derive(Default)]
+struct Response {
+  body: Vec<u8>,
+
+  /// Maximum size of the body, beyond which the transfer is stopped.
+  limit: Option<usize>,
+  truncated: bool,
+
+  headers: Vec<Header>,
+
+  /// Version of the last status line.
+  version: Option<HttpVersion>,
+
+  certificate: Option<Certificate>,
+
+  /// When the client started the TLS handshake.
+  handshake_started: Option<Instant>,
+
+  /// Whether the request was sent, i.e. the connection is established.
+  request_sent: bool,
+
+  /// When the transfer was started by the client, which may be later than
+  /// when it was queued.
+  transfer_started: Option<Instant>,
+
+  /// Times the connection and the TLS handshake may take, checked as the
+  /// transfer progresses.
+  connect_timeout: Option<Duration>,
+  tls_timeout: Option<Duration>,
+
+  /// The phase that exceeded its timeout, if the transfer was stopped.
+  timed_out: Option<TimeoutPhase>,
+
+  trace: Option<Trace>,
+}
+
+/// Request headers whose values are left out of traces, as they usually hold
+/// credentials.
+const REDACTED_HEADERS: &[&str] = &["authorization", "proxy-authorization", "cookie"];
+
+/// A transcript of the request built from curl's debug output, in the format
+/// of `curl --verbose`, with the time elapsed since the first line.
 #[derive(Default)]
-struct ResponseBody(Vec<u8>);
+struct Trace {
+  started: Option<Instant>,
+  lines: String,
+
+  /// Name of the configured request header, whose value is left out as well.
+  header: Option<String>,
+}
+
+impl Trace {
+  fn new(config: &HttpConfig) -> Self {
+    Self {
+      header: config.header.as_ref().map(|header| header.name.clone()),
+      ..Default::default()
+    }
+  }
+
+  fn record(&mut self, kind: InfoType, data: &[u8]) {
+    let prefix = match kind {
+      InfoType::Text => '*',
+      InfoType::HeaderIn => '<',
+      InfoType::HeaderOut => '>',
+      _ => return,
+    };
+
+    let elapsed = self.started.get_or_insert_with(Instant::now).elapsed();
+
+    for line in String::from_utf8_lossy(data).lines() {
+      let line = match kind {
+        InfoType::HeaderOut => self.redact(line).map_or(line.into(), Cow::Owned),
+        // HTTP/2 requests log their headers as `[HTTP/2] [1] [name: value]`.
+        InfoType::Text => line
+          .strip_suffix(']')
+          .and_then(|line| line.rsplit_once(" ["))
+          .and_then(|(start, header)| Some(format!("{} [{}]", start, self.redact(header)?)))
+          .map_or(line.into(), Cow::Owned),
+        _ => line.into(),
+      };
+
+      self.lines.push_str(&format!(
+        "[{:8.1}ms] {} {}\n",
+        millis(elapsed),
+        prefix,
+        line
+      ));
+    }
+  }
+
+  /// Returns the header with its value left out, if it's sensitive.
+  fn redact(&self, header: &str) -> Option<String> {
+    let (name, value) = header.split_once(':')?;
+    let name = name.trim();
+
+    let sensitive = REDACTED_HEADERS
+      .iter()
+      .copied()
+      .chain(self.header.as_deref())
+      .any(|redacted| name.eq_ignore_ascii_case(redacted));
+
+    (sensitive && !value.trim().is_empty()).then(|| format!("{}: [redacted]", name))
+  }
+}
 
-impl Handler for ResponseBody {
+impl Handler for Response {
   fn write(&mut self, data: &[u8]) -> Result<usize, WriteError> {
-    self.0.extend_from_slice(data);
+    let room = self
+      .limit
+      .map_or(data.len(), |limit| limit.saturating_sub(self.body.len()));
+
+    // Taking fewer bytes than passed makes curl stop the transfer.
+    if data.len() > room {
+      self.body.extend_from_slice(&data[..room]);
+      self.truncated = true;
+
+      return Ok(room);
+    }
+
+    self.body.extend_from_slice(data);
 
     Ok(data.len())
   }
+
+  fn header(&mut self, data: &[u8]) -> bool {
+    let line = String::from_utf8_lossy(data);
+
+    // A status line starts a new response (e.g. after a redirect), so only
+    // the headers of the final response are kept.
+    if line.starts_with("HTTP/") {
+      self.headers.clear();
+      self.version = HttpVersion::from_status_line(&line);
+    } else if let Some((name, value)) = line.split_once(':') {
+      self.headers.push(Header {
+        name: name.trim().into(),
+        value: value.trim().into(),
+      });
+    }
+
+    true
+  }
+
+  fn debug(&mut self, kind: InfoType, data: &[u8]) {
+    if let Some(trace) = &mut self.trace {
+      trace.record(kind, data);
+    }
+
+    match kind {
+      InfoType::HeaderOut => self.request_sent = true,
+      // The first handshake message sent, or its description from backends
+      // that don't pass the messages themselves.
+      InfoType::SslDataOut => self.start_handshake(),
+      InfoType::Text if data.windows(13).any(|text| text == b"TLS handshake") => {
+        self.start_handshake()
+      }
+      InfoType::SslDataIn => {
+        if let Some(certificate) = tls::parse_handshake(data) {
+          self.certificate = Some(certificate);
+        }
+      }
+      _ => {}
+    }
+  }
+
+  // Called as soon as the client starts the transfer, and then periodically.
+  fn progress(&mut self, _: f64, _: f64, _: f64, _: f64) -> bool {
+    if self.request_sent {
+      return true;
+    }
+
+    let now = Instant::now();
+    let started = *self.transfer_started.get_or_insert(now);
+
+    // Returning false makes curl stop the transfer.
+    self.timed_out = match self.handshake_started {
+      None => self
+        .connect_timeout
+        .filter(|timeout| now - started >= *timeout)
+        .map(|_| TimeoutPhase::Connect),
+      Some(started) => self
+        .tls_timeout
+        .filter(|timeout| now - started >= *timeout)
+        .map(|_| TimeoutPhase::Tls),
+    };
+
+    self.timed_out.is_none()
+  }
 }
 
-impl ResponseBody {
+impl Response {
+  fn start_handshake(&mut self) {
+    self.handshake_started.get_or_insert_with(Instant::now);
+  }
+
   pub fn get_body(&self) -> String {
-    String::from_utf8_lossy(&self.0).into()
+    String::from_utf8_lossy(&self.body).into()
+  }
+
+  /// Returns the hex-encoded SHA-256 digest of the response body.
+  pub fn digest(&self) -> String {
+    sha256(&self.body)
+      .iter()
+      .map(|byte| format!("{:02x}", byte))
+      .collect()
+  }
+
+  /// Builds a snippet of the response for a failed check, if it's
+  /// configured.
+  pub fn snippet(&self, config: &HttpConfig) -> Option<ResponseSnippet> {
+    if config.error_snippet_size.is_none() && config.error_snippet_headers.is_empty() {
+      return None;
+    }
+
+    let size = config.error_snippet_size.unwrap_or(0).min(self.body.len());
+    let headers = self
+      .headers
+      .iter()
+      .filter(|header| {
+        config
+          .error_snippet_headers
+          .iter()
+          .any(|name| name.eq_ignore_ascii_case(&header.name))
+      })
+      .map(|header| Header {
+        name: header.name.clone(),
+        value: header.value.clone(),
+      })
+      .collect();
+
+    Some(ResponseSnippet {
+      body: String::from_utf8_lossy(&self.body[..size]).into(),
+      headers,
+    })
   }
 }
 
+/// Splits the monitor host into a host name and a port. If the host doesn't
+/// contain a port, the configured one or the protocol default is used.
+fn split_host(host: &str, config: &HttpConfig) -> (String, u16) {
+  let default_port = config.port.unwrap_or(config.protocol.default_port());
+
+  let (name, port) = match host.strip_prefix('[').and_then(|host| host.split_once(']')) {
+    Some((name, rest)) => (name, rest.strip_prefix(':')),
+    None => match host.rsplit_once(':') {
+      Some((name, port)) if !name.contains(':') => (name, Some(port)),
+      _ => (host, None),
+    },
+  };
+
+  (
+    name.into(),
+    port
+      .and_then(|port| port.parse().ok())
+      .unwrap_or(default_port),
+  )
+}
+
+/// Checks whether an element matching the selector of the assertion contains
+/// its text.
+fn contains_element(body: &str, assertion: &ElementAssertion) -> Result<bool, HttpError> {
+  let selector =
+    Selector::parse(&assertion.selector).map_err(|error| HttpError::InvalidSelector {
+      selector: assertion.selector.clone(),
+      reason: error.to_string(),
+    })?;
+  let text = collapse_whitespace(&assertion.text);
+
+  Ok(
+    Html::parse_document(body)
+      .select(&selector)
+      .any(|element| collapse_whitespace(&element.text().collect::<String>()).contains(&text)),
+  )
+}
+
+fn collapse_whitespace(text: &str) -> String {
+  text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Determines the phase of a timed out request by the timings it reached.
+/// curl records the connect time only once the TLS handshake is done, so
+/// the handshake is detected from the debug output.
+fn timeout_phase(response: &mut Easy2<Response>) -> Result<TimeoutPhase, curl::Error> {
+  Ok(if !response.pretransfer_time()?.is_zero() {
+    TimeoutPhase::Transfer
+  } else if response.get_ref().handshake_started.is_some() {
+    TimeoutPhase::Tls
+  } else if !response.namelookup_time()?.is_zero() {
+    TimeoutPhase::Connect
+  } else {
+    TimeoutPhase::Dns
+  })
+}
+
+/// Returns the last phase of the request that completed, from the times curl
+/// recorded. The transfer is known to be complete once the request succeeds
+/// only.
+fn completed_phase(response: &mut Easy2<Response>) -> Result<Option<Phase>, curl::Error> {
+  Ok(if !response.appconnect_time()?.is_zero() {
+    Some(Phase::Tls)
+  } else if !response.connect_time()?.is_zero() {
+    Some(Phase::Connect)
+  } else if !response.namelookup_time()?.is_zero() {
+    Some(Phase::Dns)
+  } else {
+    None
+  })
+}
+
+/// The outcome of a check, along with what's known of it if it failed.
+pub struct Attempt {
+  pub result: Result<Data, HttpError>,
+
+  /// Transcript of the request, if it failed and tracing is enabled.
+  pub trace: Option<String>,
+
+  /// How far the request got, if it failed or its body was truncated.
+  pub partial: Option<Partial>,
+}
+
 pub struct Http;
 
 impl Http {
-  pub async fn measure(host: &String, config: &HttpConfig) -> Result<Data, HttpError> {
+  /// Performs the measurement and returns the transcript of the request
+  /// along with the result if it failed and tracing is enabled.
+  #[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(name = "http", level = "debug", skip(config))
+  )]
+  pub async fn measure_traced(host: &String, config: &HttpConfig) -> Attempt {
+    let mut trace = None;
+    let mut partial = Partial::default();
+    let result = Self::perform(host, config, &mut trace, &mut partial).await;
+
+    if result.is_ok() {
+      trace = None;
+    }
+
+    Attempt {
+      partial: (result.is_err() || partial.truncated).then_some(partial),
+      result,
+      trace,
+    }
+  }
+
+  async fn perform(
+    host: &String,
+    config: &HttpConfig,
+    trace: &mut Option<String>,
+    partial: &mut Partial,
+  ) -> Result<Data, HttpError> {
     let url = format!(
       "{}://{}{}{}",
-      config.protocol.to_lowercase(),
+      config.protocol.as_str(),
       host,
       config
         .port
@@ -42,36 +383,183 @@ impl Http {
       headers.append(&format!("{}: {}", header.name, header.value))?;
     }
 
-    let mut request = Easy2::new(ResponseBody::default());
+    let https = config.protocol == Scheme::Https;
+    let connect_timeout = config.connect_timeout_ms.map(Duration::from_millis);
+    let tls_timeout = config
+      .tls_timeout_ms
+      .filter(|_| https)
+      .map(Duration::from_millis);
+
+    let mut request = Easy2::new(Response {
+      limit: config.max_body_size,
+      trace: config.debug_trace.then(|| Trace::new(config)),
+      ..Default::default()
+    });
     request.url(url.as_str())?;
     request.http_headers(headers)?;
     request.timeout(Duration::from_secs(config.timeout as u64))?;
+
+    // curl's connect timeout covers the TLS handshake as well, so over
+    // `HTTPS` the phases are timed separately as the transfer progresses.
+    let timed_phases = https && (connect_timeout.is_some() || tls_timeout.is_some());
+
+    if timed_phases {
+      request.progress(true)?;
+    } else if let Some(timeout) = connect_timeout {
+      request.connect_timeout(timeout)?;
+    }
     request.cookie_file("")?;
     request.follow_location(config.follow_redirects)?;
-    request.http_version(HttpVersion::V2)?;
-
-    match config.method.to_lowercase().as_str() {
-      "get" => request.get(true)?,
-      "post" => request.post(true)?,
-      "put" => request.put(true)?,
-      "patch" => request.custom_request("PATCH")?,
-      "head" => {
+    request.http_version(CurlVersion::V2)?;
+
+    let interface = match (&config.source_interface, config.source_ip) {
+      (Some(interface), Some(ip)) => Some(format!("ifhost!{}!{}", interface, ip)),
+      (Some(interface), None) => Some(format!("if!{}", interface)),
+      (None, Some(ip)) => Some(format!("host!{}", ip)),
+      (None, None) => None,
+    };
+
+    // Pooled connections aren't matched by their local end, so a bound check
+    // could otherwise reuse a connection opened through another path.
+    if let Some(interface) = interface {
+      request.interface(&interface)?;
+      request.fresh_connect(true)?;
+    }
+
+    // Handshake messages and sent headers are passed to the debug callback in
+    // verbose mode only.
+    if config.debug_trace || https {
+      request.verbose(true)?;
+    }
+
+    // A reused connection doesn't perform the handshake at all.
+    if config.capture_certificate && https {
+      request.fresh_connect(true)?;
+    }
+
+    match config.method {
+      Method::Get => request.get(true)?,
+      Method::Post => request.post(true)?,
+      Method::Put => request.put(true)?,
+      Method::Head => {
         request.nobody(true)?;
         request.custom_request("HEAD")?
       }
-      _ => unimplemented!("Unimplemented HTTP method"),
+      method => request.custom_request(method.as_str())?,
     };
 
     if let Some(body) = config.body.clone() {
       request.post_fields_copy(body.as_bytes())?;
     }
 
-    let response = task::spawn_blocking(move || match request.perform() {
-      Ok(()) => Ok(request),
-      Err(error) => Err(HttpError::Unknown(error)),
-    })
-    .await
-    .expect("curl request")?;
+    let mut dns_lookup = Duration::ZERO;
+    let (name, port) = split_host(host, config);
+    // Addresses are connected to without a lookup.
+    let literal = ip_literal(&name);
+
+    if literal.is_some() {
+      partial.completed = Some(Phase::Dns);
+      partial.resolved_ip = literal;
+    }
+
+    if let Some(dns) = &config.dns
+      && literal.is_none()
+    {
+      // The host is resolved here and pinned, so curl doesn't query the
+      // system resolver.
+      if let Some(resolver) = resolver::with_config(dns) {
+        let (lookup, duration) = measure!({ resolver.lookup_ip(name.as_str()).await? });
+
+        let addresses = lookup
+          .iter()
+          .map(|ip| {
+            if ip.is_ipv6() {
+              format!("[{}]", ip)
+            } else {
+              ip.to_string()
+            }
+          })
+          .collect::<Vec<_>>()
+          .join(",");
+
+        let mut resolve = List::new();
+        resolve.append(&format!("{}:{}:{}", name, port, addresses))?;
+        request.resolve(resolve)?;
+
+        dns_lookup = duration;
+        partial.completed = Some(Phase::Dns);
+        partial.resolved_ip = lookup.iter().next();
+      }
+
+      request.doh_url(dns.doh_url.as_deref())?;
+    }
+
+    if timed_phases {
+      let response = request.get_mut();
+      response.connect_timeout = connect_timeout;
+      response.tls_timeout = tls_timeout;
+    }
+
+    let (mut response, result) = CLIENT.perform(request).await?;
+    *trace = response.get_mut().trace.take().map(|trace| trace.lines);
+    partial.completed = partial.completed.max(completed_phase(&mut response)?);
+    partial.truncated = response.get_ref().truncated;
+
+    // curl records the address once it tries to connect to it.
+    let resolved_ip = response
+      .primary_ip()?
+      .and_then(|ip| ip.parse::<IpAddr>().ok());
+    partial.resolved_ip = resolved_ip.or(partial.resolved_ip);
+
+    // A transfer stopped at the maximum body size is complete as far as the
+    // check goes.
+    if let Err(error) = result
+      && !(error.is_write_error() && partial.truncated)
+    {
+      if error.is_operation_timedout() {
+        return Err(HttpError::Timeout {
+          phase: timeout_phase(&mut response)?,
+        });
+      }
+
+      if error.is_aborted_by_callback()
+        && let Some(phase) = response.get_ref().timed_out
+      {
+        return Err(HttpError::Timeout { phase });
+      }
+
+      return Err(error.into());
+    }
+
+    partial.completed = Some(Phase::Transfer);
+    let connect_time = response.connect_time()?;
+
+    if connect_timeout.is_some_and(|timeout| connect_time > timeout) {
+      return Err(HttpError::Timeout {
+        phase: TimeoutPhase::Connect,
+      });
+    }
+
+    if tls_timeout.is_some_and(|timeout| {
+      response
+        .appconnect_time()
+        .is_ok_and(|time| time.saturating_sub(connect_time) > timeout)
+    }) {
+      return Err(HttpError::Timeout {
+        phase: TimeoutPhase::Tls,
+      });
+    }
+
+    let ip_family = resolved_ip.map(IpFamily::from);
+
+    if let Some(expected) = config.expected_ip_family
+      && ip_family != Some(expected)
+    {
+      return Err(HttpError::IpFamilyMismatch {
+        expected,
+        actual: ip_family,
+      });
+    }
 
     let response_status = response.response_code()? as u16;
     let expected_status_code = config.expected_status_code as u16;
@@ -80,6 +568,7 @@ impl Http {
       return Err(HttpError::StatusMismatch {
         expected: expected_status_code,
         actual: response_status,
+        snippet: response.get_ref().snippet(config),
       });
     }
 
@@ -87,39 +576,135 @@ impl Http {
       let response_body = response.get_ref().get_body();
 
       if !response_body.contains(keyword.as_str()) {
-        return Err(HttpError::KeywordNotFound { keyword });
+        return Err(HttpError::KeywordNotFound {
+          keyword,
+          snippet: response.get_ref().snippet(config),
+        });
+      }
+    }
+
+    if let Some(assertion) = &config.element
+      && !contains_element(&response.get_ref().get_body(), assertion)?
+    {
+      return Err(HttpError::ElementNotFound {
+        selector: assertion.selector.clone(),
+        text: assertion.text.clone(),
+        snippet: response.get_ref().snippet(config),
+      });
+    }
+
+    if let Some(expected) = &config.expected_sha256 {
+      let actual = response.get_ref().digest();
+
+      if !actual.eq_ignore_ascii_case(expected.trim()) {
+        return Err(HttpError::DigestMismatch {
+          expected: expected.clone(),
+          actual,
+        });
       }
     }
 
     Ok(Data::Http(HttpData {
-      dns_lookup: response.namelookup_time()?.as_secs_f32(),
-      connect: response.connect_time()?.as_secs_f32(),
-      tls_handshake: response.appconnect_time()?.as_secs_f32(),
-      data_transfer: (response.total_time()? - response.starttransfer_time()?).as_secs_f32(),
+      dns_lookup: if literal.is_some() {
+        0.0
+      } else {
+        millis(dns_lookup + response.namelookup_time()?)
+      },
+      connect: millis(response.connect_time()?),
+      tls_handshake: millis(response.appconnect_time()?),
+      data_transfer: millis(response.total_time()? - response.starttransfer_time()?),
+      total: millis(dns_lookup + response.total_time()?),
+      ip_family,
+      resolved_ip,
+      remote_port: response.primary_port().ok().filter(|port| *port != 0),
+      status_code: Some(response_status),
+      http_version: response.get_ref().version,
+      certificate: response.get_mut().certificate.take(),
     }))
   }
 }
 
 #[cfg(test)]
 mod tests {
+  use std::io::{Read, Write};
+  use std::net::{Ipv4Addr, SocketAddr, TcpListener, TcpStream, UdpSocket};
+  use std::sync::Arc;
+  use std::sync::atomic::{AtomicUsize, Ordering};
+
   use httpmock::prelude::*;
+  use openssl::asn1::Asn1Time;
+  use openssl::hash::MessageDigest;
+  use openssl::pkey::PKey;
+  use openssl::rsa::Rsa;
+  use openssl::ssl::{SslAcceptor, SslMethod};
+  use openssl::x509::{X509, X509NameBuilder};
+  use socket2::{Domain, Socket, Type};
 
   use super::*;
-  use crate::monitor::models::Header;
+  use crate::monitor::models::{DnsConfig, Header, Method};
+
+  impl Http {
+    async fn measure(host: &String, config: &HttpConfig) -> Result<Data, HttpError> {
+      Self::measure_traced(host, config).await.result
+    }
+  }
+
+  /// Starts a DNS server answering every `A` query with `127.0.0.1`.
+  fn dns_server() -> SocketAddr {
+    let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let address = socket.local_addr().unwrap();
+
+    std::thread::spawn(move || {
+      let mut buffer = [0; 512];
+
+      while let Ok((size, peer)) = socket.recv_from(&mut buffer) {
+        // Skip the header and the queried name to find the query type.
+        let mut position = 12;
+        while position < size && buffer[position] != 0 {
+          position += buffer[position] as usize + 1;
+        }
+        let query_type = buffer[position + 2];
+
+        let mut response = buffer[..position + 5].to_vec();
+        response[2..4].copy_from_slice(&[0x81, 0x80]);
+        response[6..12].copy_from_slice(&[0, 0, 0, 0, 0, 0]);
+
+        if query_type == 1 {
+          response[7] = 1;
+          response.extend([0xc0, 0x0c, 0, 1, 0, 1, 0, 0, 0, 60, 0, 4, 127, 0, 0, 1]);
+        }
+
+        let _ = socket.send_to(&response, peer);
+      }
+    });
+
+    address
+  }
 
   #[test]
   fn response_body() {
-    let mut response_body = ResponseBody([0].into());
+    let mut response = Response {
+      body: [0].into(),
+      ..Default::default()
+    };
 
-    assert!(
-      response_body.write(&[0]).is_ok(),
-      "response body is writable"
-    );
-    assert_eq!(
-      response_body.get_body(),
-      "\0\0",
-      "response body is readable"
-    );
+    assert!(response.write(&[0]).is_ok(), "response body is writable");
+    assert_eq!(response.get_body(), "\0\0", "response body is readable");
+  }
+
+  #[test]
+  fn response_headers() {
+    let mut response = Response::default();
+
+    response.header(b"HTTP/1.1 301 Moved Permanently\r\n");
+    response.header(b"Location: /check\r\n");
+    response.header(b"HTTP/1.1 200 OK\r\n");
+    response.header(b"Content-Type: text/plain\r\n");
+    response.header(b"\r\n");
+
+    assert_eq!(response.headers.len(), 1, "only final headers are kept");
+    assert_eq!(response.headers[0].name, "Content-Type");
+    assert_eq!(response.headers[0].value, "text/plain");
   }
 
   #[tokio::test]
@@ -138,8 +723,8 @@ mod tests {
 
     let result = Http::measure(&server.host(), &HttpConfig {
       timeout: 3,
-      method: String::from("GET"),
-      protocol: String::from("HTTP"),
+      method: Method::Get,
+      protocol: Scheme::Http,
       port: Some(server.port()),
       path: Some(String::from("/check")),
       header: Some(Header {
@@ -169,8 +754,8 @@ mod tests {
 
     let result = Http::measure(&server.host(), &HttpConfig {
       timeout: 3,
-      method: String::from("POST"),
-      protocol: String::from("HTTP"),
+      method: Method::Post,
+      protocol: Scheme::Http,
       port: Some(server.port()),
       path: Some(String::from("/check")),
       body: Some(String::from("test")),
@@ -184,22 +769,56 @@ mod tests {
     assert!(result.is_ok(), "request body is correct");
   }
 
+  #[test]
+  fn parsed_method_and_scheme() {
+    for name in ["\"PATCH\"", "\"patch\"", "\"Patch\""] {
+      assert_eq!(serde_json::from_str::<Method>(name).unwrap(), Method::Patch);
+    }
+
+    assert_eq!(
+      serde_json::from_str::<Scheme>("\"https\"").unwrap(),
+      Scheme::Https
+    );
+    assert_eq!(
+      serde_json::from_str::<Method>("\"gEt\"").unwrap(),
+      Method::Get,
+      "names are matched in any case"
+    );
+    assert_eq!(
+      serde_json::from_str::<Scheme>("\"HTTPs\"").unwrap(),
+      Scheme::Https
+    );
+    assert_eq!(serde_json::to_string(&Method::Get).unwrap(), "\"GET\"");
+    assert!(
+      serde_json::from_str::<Method>("\"GTE\"").is_err(),
+      "unknown methods are rejected"
+    );
+  }
+
   #[tokio::test]
   async fn methods() {
     let server = MockServer::start_async().await;
 
-    for method in ["GET", "POST", "PUT", "PATCH", "HEAD"] {
+    for method in [
+      Method::Get,
+      Method::Post,
+      Method::Put,
+      Method::Patch,
+      Method::Delete,
+      Method::Head,
+      Method::Options,
+    ] {
       let mock = server
         .mock_async(|when, then| {
-          when.method(Method::from(method)).path("/check");
+          when.method(method.as_str()).path("/check");
           then.status(200);
         })
         .await;
 
       let result = Http::measure(&server.host(), &HttpConfig {
         timeout: 3,
-        method: String::from(method),
-        protocol: String::from("HTTP"),
+        method,
+        protocol: Scheme::Http,
         port: Some(server.port()),
         path: Some(String::from("/check")),
         expected_status_code: 200,
@@ -226,8 +845,8 @@ mod tests {
 
     let result = Http::measure(&server.host(), &HttpConfig {
       timeout: 3,
-      method: String::from("GET"),
-      protocol: String::from("HTTP"),
+      method: Method::Get,
+      protocol: Scheme::Http,
       port: Some(server.port()),
       path: Some(String::from("/check")),
       expected_status_code: 200,
@@ -253,8 +872,8 @@ mod tests {
 
     let result = Http::measure(&server.host(), &HttpConfig {
       timeout: 3,
-      method: String::from("GET"),
-      protocol: String::from("HTTP"),
+      method: Method::Get,
+      protocol: Scheme::Http,
       port: Some(server.port()),
       path: Some(String::from("/check")),
       expected_status_code: 200,
@@ -268,11 +887,881 @@ mod tests {
     assert!(result.is_err(), "response doesn't contain expected keyword");
   }
 
+  #[test]
+  fn element_assertion() {
+    let body = r#"
+      <html>
+        <head><title>Status</title></head>
+        <body>
+          <div id="status"><span class="ok">All systems
+            operational</span></div>
+          <script>var status = "Major outage";</script>
+        </body>
+      </html>
+    "#;
+
+    let assertion = |selector: &str, text: &str| ElementAssertion {
+      selector: String::from(selector),
+      text: String::from(text),
+    };
+
+    assert!(
+      contains_element(body, &assertion("title", "Status")).unwrap(),
+      "title matches"
+    );
+    assert!(
+      contains_element(body, &assertion("#status .ok", "systems operational")).unwrap(),
+      "whitespace is collapsed"
+    );
+    assert!(
+      contains_element(body, &assertion("#status", "")).unwrap(),
+      "element exists"
+    );
+    assert!(
+      !contains_element(body, &assertion("#status", "Major outage")).unwrap(),
+      "text of other elements isn't matched"
+    );
+    assert!(
+      !contains_element(body, &assertion(".missing", "")).unwrap(),
+      "element doesn't exist"
+    );
+    assert!(
+      matches!(
+        contains_element(body, &assertion("#", "")),
+        Err(HttpError::InvalidSelector { .. })
+      ),
+      "selector is invalid"
+    );
+  }
+
+  #[tokio::test]
+  async fn response_doesnt_contain_element() {
+    let server = MockServer::start_async().await;
+
+    let mock = server
+      .mock_async(|when, then| {
+        when.method(GET).path("/status");
+        then
+          .status(200)
+          .body("<h1 class='state'>Degraded performance</h1>");
+      })
+      .await;
+
+    let result = Http::measure(&server.host(), &HttpConfig {
+      timeout: 3,
+      method: Method::Get,
+      protocol: Scheme::Http,
+      port: Some(server.port()),
+      path: Some(String::from("/status")),
+      expected_status_code: 200,
+      element: Some(ElementAssertion {
+        selector: String::from("h1.state"),
+        text: String::from("Operational"),
+      }),
+      ..Default::default()
+    })
+    .await;
+
+    mock.assert();
+
+    assert!(
+      matches!(result, Err(HttpError::ElementNotFound { .. })),
+      "element doesn't have expected text"
+    );
+  }
+
+  #[tokio::test]
+  async fn response_digest() {
+    let server = MockServer::start_async().await;
+
+    let mock = server
+      .mock_async(|when, then| {
+        when.method(GET).path("/installer");
+        then.status(200).body("hello");
+      })
+      .await;
+
+    let config = HttpConfig {
+      timeout: 3,
+      method: Method::Get,
+      protocol: Scheme::Http,
+      port: Some(server.port()),
+      path: Some(String::from("/installer")),
+      expected_status_code: 200,
+      expected_sha256: Some(String::from(
+        "2CF24DBA5FB0A30E26E83B2AC5B9E29E1B161E5C1FA7425E73043362938B9824",
+      )),
+      ..Default::default()
+    };
+
+    let result = Http::measure(&server.host(), &config).await;
+    assert!(result.is_ok(), "response body digest matches");
+
+    let result = Http::measure(&server.host(), &HttpConfig {
+      expected_sha256: Some(String::from("00")),
+      ..config
+    })
+    .await;
+
+    mock.assert_calls(2);
+    assert!(
+      matches!(result, Err(HttpError::DigestMismatch { actual, .. })
+        if actual == "2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824"),
+      "response body digest doesn't match"
+    );
+  }
+
+  #[tokio::test]
+  async fn debug_trace() {
+    let server = MockServer::start_async().await;
+
+    server
+      .mock_async(|when, then| {
+        when.method(GET).path("/check");
+        then.status(503);
+      })
+      .await;
+
+    let config = HttpConfig {
+      timeout: 3,
+      method: Method::Get,
+      protocol: Scheme::Http,
+      port: Some(server.port()),
+      path: Some(String::from("/check")),
+      expected_status_code: 503,
+      debug_trace: true,
+      ..Default::default()
+    };
+
+    let attempt = Http::measure_traced(&server.host(), &config).await;
+
+    assert!(attempt.result.is_ok(), "request is successful");
+    assert!(attempt.trace.is_none(), "successful request isn't traced");
+
+    let attempt = Http::measure_traced(&server.host(), &HttpConfig {
+      expected_status_code: 200,
+      ..config
+    })
+    .await;
+    let trace = attempt.trace.expect("failed request is traced");
+
+    assert!(attempt.result.is_err(), "request fails");
+    assert!(trace.contains("> GET /check"), "trace has request headers");
+    assert!(
+      trace.contains("< HTTP/1.1 503"),
+      "trace has response headers"
+    );
+  }
+
+  #[tokio::test]
+  async fn redacted_trace() {
+    let server = MockServer::start_async().await;
+
+    server
+      .mock_async(|when, then| {
+        when.method(GET).path("/check");
+        then.status(503);
+      })
+      .await;
+
+    let config = HttpConfig {
+      timeout: 3,
+      method: Method::Get,
+      protocol: Scheme::Http,
+      port: Some(server.port()),
+      path: Some(String::from("/check")),
+      header: Some(Header {
+        name: String::from("X-Api-Key"),
+        value: String::from("secret-key"),
+      }),
+      debug_trace: true,
+      ..Default::default()
+    };
+
+    let attempt = Http::measure_traced(&server.host(), &config).await;
+    let trace = attempt.trace.expect("failed request is traced");
+
+    assert!(!trace.contains("secret-key"), "header value is left out");
+    assert!(
+      trace.contains("> X-Api-Key: [redacted]"),
+      "header name is kept"
+    );
+
+    let mut trace = Trace::new(&config);
+    trace.record(
+      InfoType::HeaderOut,
+      b"GET / HTTP/1.1\r\nAuthorization: Bearer token\r\ncookie: id=1\r\nAccept: */*\r\n",
+    );
+    trace.record(
+      InfoType::Text,
+      b"[HTTP/2] [1] [proxy-authorization: Basic dXNlcg==]\n",
+    );
+
+    assert!(trace.lines.contains("> Authorization: [redacted]"));
+    assert!(trace.lines.contains("> cookie: [redacted]"));
+    assert!(
+      trace.lines.contains("> Accept: */*"),
+      "other headers are kept"
+    );
+    assert!(
+      trace
+        .lines
+        .contains("* [HTTP/2] [1] [proxy-authorization: [redacted]]")
+    );
+  }
+
+  #[tokio::test]
+  async fn truncated_body() {
+    let server = MockServer::start_async().await;
+
+    server
+      .mock_async(|when, then| {
+        when.method(GET).path("/check");
+        then.status(200).body("0123456789");
+      })
+      .await;
+
+    let config = HttpConfig {
+      timeout: 3,
+      method: Method::Get,
+      protocol: Scheme::Http,
+      port: Some(server.port()),
+      path: Some(String::from("/check")),
+      expected_status_code: 200,
+      keyword: Some(String::from("0123")),
+      max_body_size: Some(4),
+      ..Default::default()
+    };
+
+    let attempt = Http::measure_traced(&server.host(), &config).await;
+
+    assert!(attempt.result.is_ok(), "stopped transfer is successful");
+    assert_eq!(
+      attempt.partial,
+      Some(Partial {
+        completed: Some(Phase::Transfer),
+        truncated: true,
+        resolved_ip: Some(IpAddr::from([127, 0, 0, 1])),
+      })
+    );
+
+    let attempt = Http::measure_traced(&server.host(), &HttpConfig {
+      keyword: Some(String::from("789")),
+      ..config.clone()
+    })
+    .await;
+
+    assert!(matches!(
+      attempt.result,
+      Err(HttpError::KeywordNotFound { .. })
+    ));
+    assert!(attempt.partial.unwrap().truncated);
+
+    let attempt = Http::measure_traced(&server.host(), &HttpConfig {
+      max_body_size: None,
+      ..config
+    })
+    .await;
+
+    assert!(attempt.result.is_ok());
+    assert!(attempt.partial.is_none(), "complete checks aren't partial");
+  }
+
+  #[tokio::test]
+  async fn completed_phases() {
+    let server = MockServer::start_async().await;
+
+    server
+      .mock_async(|when, then| {
+        when.method(GET);
+        then.status(503);
+      })
+      .await;
+
+    let config = HttpConfig {
+      timeout: 3,
+      method: Method::Get,
+      protocol: Scheme::Http,
+      expected_status_code: 200,
+      ..Default::default()
+    };
+
+    let attempt = Http::measure_traced(&String::from("127.0.0.1"), &HttpConfig {
+      port: Some(server.port()),
+      ..config.clone()
+    })
+    .await;
+
+    assert!(attempt.result.is_err());
+    assert_eq!(
+      attempt.partial.unwrap().completed,
+      Some(Phase::Transfer),
+      "the response arrived"
+    );
+
+    let closed = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = closed.local_addr().unwrap().port();
+    drop(closed);
+
+    let attempt = Http::measure_traced(&String::from("127.0.0.1"), &HttpConfig {
+      port: Some(port),
+      ..config
+    })
+    .await;
+
+    assert!(attempt.result.is_err());
+    assert_eq!(
+      attempt.partial.unwrap().completed,
+      Some(Phase::Dns),
+      "the connection was refused"
+    );
+  }
+
+  #[tokio::test]
+  async fn resolved_ip_of_failed_check() {
+    let server = MockServer::start_async().await;
+
+    server
+      .mock_async(|when, then| {
+        when.method(GET);
+        then.status(503);
+      })
+      .await;
+
+    let config = HttpConfig {
+      timeout: 3,
+      method: Method::Get,
+      protocol: Scheme::Http,
+      port: Some(server.port()),
+      expected_status_code: 200,
+      dns: Some(DnsConfig {
+        nameservers: vec![dns_server()],
+        ..Default::default()
+      }),
+      ..Default::default()
+    };
+    let loopback = Some(IpAddr::from([127, 0, 0, 1]));
+
+    let attempt = Http::measure_traced(&String::from("limon.test"), &config).await;
+
+    assert!(matches!(
+      attempt.result,
+      Err(HttpError::StatusMismatch { .. })
+    ));
+    assert_eq!(attempt.partial.unwrap().resolved_ip, loopback);
+
+    let closed = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = closed.local_addr().unwrap().port();
+    drop(closed);
+
+    let attempt = Http::measure_traced(&String::from("limon.test"), &HttpConfig {
+      port: Some(port),
+      ..config
+    })
+    .await;
+
+    assert!(attempt.result.is_err());
+    assert_eq!(
+      attempt.partial.unwrap().resolved_ip,
+      loopback,
+      "the address is kept when the connection is refused"
+    );
+  }
+
+  #[tokio::test]
+  async fn response_snippet() {
+    let server = MockServer::start_async().await;
+
+    let mock = server
+      .mock_async(|when, then| {
+        when.method(GET).path("/check");
+        then
+          .status(503)
+          .header("Retry-After", "120")
+          .body("service unavailable");
+      })
+      .await;
+
+    let result = Http::measure(&server.host(), &HttpConfig {
+      timeout: 3,
+      method: Method::Get,
+      protocol: Scheme::Http,
+      port: Some(server.port()),
+      path: Some(String::from("/check")),
+      expected_status_code: 200,
+      error_snippet_size: Some(7),
+      error_snippet_headers: vec![String::from("retry-after")],
+      ..Default::default()
+    })
+    .await;
+
+    mock.assert();
+
+    let Err(HttpError::StatusMismatch {
+      snippet: Some(snippet),
+      ..
+    }) = result
+    else {
+      panic!("response snippet is attached to the error");
+    };
+
+    assert_eq!(snippet.body, "service", "snippet body is truncated");
+    assert_eq!(snippet.headers.len(), 1, "only selected headers are kept");
+    assert_eq!(snippet.headers[0].value, "120");
+  }
+
+  #[tokio::test]
+  async fn source_binding() {
+    let server = MockServer::start_async().await;
+
+    let mock = server
+      .mock_async(|when, then| {
+        when.method(GET).path("/check");
+        then.status(200);
+      })
+      .await;
+
+    let config = HttpConfig {
+      timeout: 3,
+      method: Method::Get,
+      protocol: Scheme::Http,
+      port: Some(server.port()),
+      path: Some(String::from("/check")),
+      expected_status_code: 200,
+      source_ip: Some("127.0.0.1".parse().unwrap()),
+      ..Default::default()
+    };
+
+    let result = Http::measure(&String::from("127.0.0.1"), &config).await;
+
+    mock.assert();
+    assert!(result.is_ok(), "request is sent from the source address");
+
+    let result = Http::measure(&String::from("127.0.0.1"), &HttpConfig {
+      timeout: 3,
+      method: Method::Get,
+      protocol: Scheme::Http,
+      port: Some(server.port()),
+      path: Some(String::from("/check")),
+      expected_status_code: 200,
+      source_interface: Some(String::from("limon-missing0")),
+      ..Default::default()
+    })
+    .await;
+
+    assert!(result.is_err(), "interface doesn't exist");
+  }
+
+  #[test]
+  fn host_with_port() {
+    let config = HttpConfig {
+      protocol: Scheme::Https,
+      ..Default::default()
+    };
+
+    assert_eq!(
+      split_host("example.com", &config),
+      (String::from("example.com"), 443)
+    );
+    assert_eq!(
+      split_host("example.com:8080", &config),
+      (String::from("example.com"), 8080)
+    );
+    assert_eq!(
+      split_host("[::1]:8080", &config),
+      (String::from("::1"), 8080)
+    );
+    assert_eq!(split_host("[::1]", &config), (String::from("::1"), 443));
+  }
+
+  #[tokio::test]
+  async fn custom_nameservers() {
+    let server = MockServer::start_async().await;
+
+    let mock = server
+      .mock_async(|when, then| {
+        when.method(GET).path("/check");
+        then.status(200);
+      })
+      .await;
+
+    let result = Http::measure(&String::from("limon.test"), &HttpConfig {
+      timeout: 3,
+      method: Method::Get,
+      protocol: Scheme::Http,
+      port: Some(server.port()),
+      path: Some(String::from("/check")),
+      expected_status_code: 200,
+      dns: Some(DnsConfig {
+        nameservers: vec![dns_server()],
+        ..Default::default()
+      }),
+      ..Default::default()
+    })
+    .await;
+
+    mock.assert();
+
+    assert!(
+      result.is_ok(),
+      "host is resolved through custom name servers"
+    );
+  }
+
+  #[tokio::test]
+  async fn ip_literal_host() {
+    let server = MockServer::start_async().await;
+
+    server
+      .mock_async(|when, then| {
+        when.method(GET).path("/check");
+        then.status(200);
+      })
+      .await;
+
+    let result = Http::measure(&String::from("127.0.0.1"), &HttpConfig {
+      timeout: 3,
+      method: Method::Get,
+      protocol: Scheme::Http,
+      port: Some(server.port()),
+      path: Some(String::from("/check")),
+      expected_status_code: 200,
+      dns: Some(DnsConfig {
+        nameservers: vec![SocketAddr::from(([127, 0, 0, 1], 9))],
+        ..Default::default()
+      }),
+      ..Default::default()
+    })
+    .await;
+
+    let Ok(Data::Http(data)) = result else {
+      panic!("address is connected to without a lookup: {:?}", result);
+    };
+    assert_eq!(data.dns_lookup, 0.0);
+  }
+
+  #[tokio::test]
+  async fn connection_reuse() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let connections = Arc::new(AtomicUsize::new(0));
+
+    let accepted = Arc::clone(&connections);
+    std::thread::spawn(move || {
+      for mut stream in listener.incoming().flatten() {
+        accepted.fetch_add(1, Ordering::SeqCst);
+
+        std::thread::spawn(move || {
+          let mut buffer = [0; 1024];
+
+          while let Ok(size) = stream.read(&mut buffer) {
+            if size == 0 {
+              break;
+            }
+
+            let _ = stream.write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n");
+          }
+        });
+      }
+    });
+
+    for _ in 0..3 {
+      let result = Http::measure(&String::from("127.0.0.1"), &HttpConfig {
+        timeout: 3,
+        method: Method::Get,
+        protocol: Scheme::Http,
+        port: Some(port),
+        expected_status_code: 200,
+        ..Default::default()
+      })
+      .await;
+
+      assert!(result.is_ok(), "request is successful");
+    }
+
+    assert_eq!(
+      connections.load(Ordering::SeqCst),
+      1,
+      "connection is reused between checks"
+    );
+  }
+
+  #[tokio::test]
+  async fn concurrent_requests() {
+    let server = MockServer::start_async().await;
+
+    let mock = server
+      .mock_async(|when, then| {
+        when.method(GET).path("/check");
+        then.status(200).delay(Duration::from_millis(100));
+      })
+      .await;
+
+    let config = Arc::new(HttpConfig {
+      timeout: 3,
+      method: Method::Get,
+      protocol: Scheme::Http,
+      port: Some(server.port()),
+      path: Some(String::from("/check")),
+      expected_status_code: 200,
+      ..Default::default()
+    });
+
+    let mut requests = tokio::task::JoinSet::new();
+    for _ in 0..20 {
+      let (host, config) = (server.host(), Arc::clone(&config));
+      requests.spawn(async move { Http::measure(&host, &config).await });
+    }
+
+    let results = requests.join_all().await;
+
+    mock.assert_calls(20);
+
+    assert!(
+      results.iter().all(|result| result.is_ok()),
+      "concurrent requests are successful"
+    );
+  }
+
+  #[tokio::test]
+  async fn tls_timeout() {
+    // Accepts connections but never answers the TLS handshake.
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+
+    std::thread::spawn(move || {
+      let streams = listener.incoming().flatten().collect::<Vec<_>>();
+      drop(streams);
+    });
+
+    let started = Instant::now();
+    let result = Http::measure(&String::from("127.0.0.1"), &HttpConfig {
+      timeout: 3,
+      tls_timeout_ms: Some(200),
+      method: Method::Get,
+      protocol: Scheme::Https,
+      port: Some(port),
+      expected_status_code: 200,
+      ..Default::default()
+    })
+    .await;
+
+    assert!(
+      matches!(
+        result,
+        Err(HttpError::Timeout {
+          phase: TimeoutPhase::Tls
+        })
+      ),
+      "TLS handshake times out"
+    );
+    assert!(
+      started.elapsed() < Duration::from_secs(2),
+      "handshake is stopped before the request times out"
+    );
+  }
+
+  /// Starts a server completing TLS handshakes with a self-signed certificate.
+  fn tls_server() -> u16 {
+    let key = PKey::from_rsa(Rsa::generate(2048).unwrap()).unwrap();
+
+    let mut name = X509NameBuilder::new().unwrap();
+    name.append_entry_by_text("CN", "localhost").unwrap();
+    let name = name.build();
+
+    let mut certificate = X509::builder().unwrap();
+    certificate.set_version(2).unwrap();
+    certificate.set_subject_name(&name).unwrap();
+    certificate.set_issuer_name(&name).unwrap();
+    certificate.set_pubkey(&key).unwrap();
+    certificate
+      .set_not_before(&Asn1Time::days_from_now(0).unwrap())
+      .unwrap();
+    certificate
+      .set_not_after(&Asn1Time::days_from_now(1).unwrap())
+      .unwrap();
+    certificate.sign(&key, MessageDigest::sha256()).unwrap();
+
+    let mut acceptor = SslAcceptor::mozilla_intermediate_v5(SslMethod::tls()).unwrap();
+    acceptor.set_private_key(&key).unwrap();
+    acceptor.set_certificate(&certificate.build()).unwrap();
+    let acceptor = acceptor.build();
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+
+    std::thread::spawn(move || {
+      for stream in listener.incoming().flatten() {
+        let _ = acceptor.accept(stream);
+      }
+    });
+
+    port
+  }
+
+  #[tokio::test]
+  async fn handshake_within_tls_timeout() {
+    let port = tls_server();
+
+    let result = Http::measure(&String::from("127.0.0.1"), &HttpConfig {
+      timeout: 3,
+      connect_timeout_ms: Some(1000),
+      tls_timeout_ms: Some(1000),
+      method: Method::Get,
+      protocol: Scheme::Https,
+      port: Some(port),
+      expected_status_code: 200,
+      ..Default::default()
+    })
+    .await;
+
+    // The handshake completes, and the certificate is verified as usual.
+    assert!(
+      matches!(
+        result,
+        Err(HttpError::Unknown(error)) if error.is_peer_failed_verification()
+      ),
+      "self-signed certificate is rejected"
+    );
+  }
+
+  #[tokio::test]
+  async fn connect_timeout() {
+    // A listener whose backlog is full drops further connection attempts.
+    let listener = Socket::new(Domain::IPV4, Type::STREAM, None).unwrap();
+    listener
+      .bind(&SocketAddr::from((Ipv4Addr::LOCALHOST, 0)).into())
+      .unwrap();
+    listener.listen(0).unwrap();
+    let address = listener.local_addr().unwrap().as_socket().unwrap();
+
+    let _pending = (0..8)
+      .map_while(|_| TcpStream::connect_timeout(&address, Duration::from_millis(200)).ok())
+      .collect::<Vec<_>>();
+
+    let started = Instant::now();
+    let result = Http::measure(&String::from("127.0.0.1"), &HttpConfig {
+      timeout: 3,
+      connect_timeout_ms: Some(300),
+      method: Method::Get,
+      protocol: Scheme::Https,
+      port: Some(address.port()),
+      expected_status_code: 200,
+      ..Default::default()
+    })
+    .await;
+
+    assert!(
+      matches!(
+        result,
+        Err(HttpError::Timeout {
+          phase: TimeoutPhase::Connect
+        })
+      ),
+      "connection times out"
+    );
+    assert!(
+      started.elapsed() < Duration::from_secs(2),
+      "connection is stopped before the request times out"
+    );
+  }
+
+  #[tokio::test]
+  async fn transfer_timeout() {
+    let server = MockServer::start_async().await;
+
+    server
+      .mock_async(|when, then| {
+        when.method(GET).path("/check");
+        then.status(200).delay(Duration::from_millis(1500));
+      })
+      .await;
+
+    let result = Http::measure(&server.host(), &HttpConfig {
+      timeout: 1,
+      connect_timeout_ms: Some(500),
+      method: Method::Get,
+      protocol: Scheme::Http,
+      port: Some(server.port()),
+      path: Some(String::from("/check")),
+      expected_status_code: 200,
+      ..Default::default()
+    })
+    .await;
+
+    assert!(
+      matches!(
+        result,
+        Err(HttpError::Timeout {
+          phase: TimeoutPhase::Transfer
+        })
+      ),
+      "response times out"
+    );
+  }
+
+  #[tokio::test]
+  async fn ip_family() {
+    let server = MockServer::start_async().await;
+
+    server
+      .mock_async(|when, then| {
+        when.method(GET).path("/check");
+        then.status(200);
+      })
+      .await;
+
+    let config = HttpConfig {
+      timeout: 3,
+      method: Method::Get,
+      protocol: Scheme::Http,
+      port: Some(server.port()),
+      path: Some(String::from("/check")),
+      expected_status_code: 200,
+      expected_ip_family: Some(IpFamily::V4),
+      ..Default::default()
+    };
+
+    let result = Http::measure(&String::from("127.0.0.1"), &config).await;
+
+    assert!(
+      matches!(
+        result,
+        Ok(Data::Http(HttpData {
+          ip_family: Some(IpFamily::V4),
+          resolved_ip: Some(IpAddr::V4(Ipv4Addr::LOCALHOST)),
+          remote_port: Some(port),
+          status_code: Some(200),
+          http_version: Some(HttpVersion::Http11),
+          ..
+        })) if port == server.port()
+      ),
+      "connection family, address and response are reported"
+    );
+
+    let result = Http::measure(&String::from("127.0.0.1"), &HttpConfig {
+      expected_ip_family: Some(IpFamily::V6),
+      ..config
+    })
+    .await;
+
+    assert!(
+      matches!(
+        result,
+        Err(HttpError::IpFamilyMismatch {
+          expected: IpFamily::V6,
+          actual: Some(IpFamily::V4),
+        })
+      ),
+      "unexpected family is detected"
+    );
+  }
+
   #[tokio::test]
   async fn unknown_error() {
     let result = Http::measure(&String::from("127.0.0.1"), &HttpConfig {
-      method: String::from("GET"),
-      protocol: String::from("HTTP"),
+      method: Method::Get,
+      protocol: Scheme::Http,
       port: Some(5555),
       expected_status_code: 200,
       ..Default::default()
@@ -0,0 +1,768 @@
+//! A minimal ICMP echo (ping) implementation over raw or datagram sockets.
+//!
+//! Sockets are shared by all requests with the same [Options] and driven by
+//! a dedicated runtime, where a task per socket hands the received replies over
+//! to the pending requests. So checks don't occupy a thread and a socket each.
+
+use std::collections::HashMap;
+use std::future::Future;
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::atomic::{AtomicU16, Ordering};
+use std::sync::{Arc, Mutex, OnceLock, mpsc};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use once_cell::sync::Lazy;
+use socket2::{Domain, Protocol, Socket, Type};
+use tokio::net::UdpSocket;
+use tokio::runtime::{self, Handle};
+use tokio::sync::oneshot;
+use tokio::task::JoinHandle;
+use tokio::time;
+
+use crate::monitor::models::{IcmpSocket, IpFamily};
+
+/// Size of the ICMP echo request, including the 8 bytes header.
+pub const DEFAULT_PACKET_SIZE: usize = 1000;
+
+/// Size of the buffer replies are received into. Only the headers are parsed,
+/// so longer replies may be truncated.
+const RECEIVE_BUFFER_SIZE: usize = 1500;
+
+const ECHO_REQUEST_V4: u8 = 8;
+const ECHO_REPLY_V4: u8 = 0;
+const TIME_EXCEEDED_V4: u8 = 11;
+const ECHO_REQUEST_V6: u8 = 128;
+const ECHO_REPLY_V6: u8 = 129;
+const TIME_EXCEEDED_V6: u8 = 3;
+
+/// Length of the IPv6 header, which is quoted in ICMPv6 errors.
+const IPV6_HEADER_LENGTH: usize = 40;
+
+/// Pingers are shared by targets of the same family pinged with the same
+/// options.
+type Key = (IpFamily, Options);
+
+static IDENTIFIER: AtomicU16 = AtomicU16::new(0);
+
+static RUNTIME: Lazy<io::Result<Handle>> = Lazy::new(|| {
+  let (sender, handle) = mpsc::sync_channel(1);
+
+  thread::Builder::new()
+    .name(String::from("limon-ping"))
+    .spawn(move || {
+      let runtime = runtime::Builder::new_current_thread().enable_all().build();
+      let _ = sender.send(
+        runtime
+          .as_ref()
+          .map(|runtime| runtime.handle().clone())
+          .map_err(|error| io::Error::new(error.kind(), error.to_string())),
+      );
+
+      if let Ok(runtime) = runtime {
+        runtime.block_on(std::future::pending::<()>());
+      }
+    })?;
+
+  handle
+    .recv()
+    .unwrap_or_else(|_| Err(io::Error::other("ping thread exited")))
+});
+
+static PINGERS: Lazy<Mutex<HashMap<Key, Arc<Pinger>>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// Runs the `future` on the runtime driving the shared sockets. Requests must
+/// be sent from there. Fails if the runtime couldn't be started.
+pub fn spawn<F>(future: F) -> io::Result<JoinHandle<F::Output>>
+where
+  F: Future + Send + 'static,
+  F::Output: Send + 'static,
+{
+  Ok(runtime()?.spawn(future))
+}
+
+/// Returns the runtime driving the shared sockets, started on first use.
+fn runtime() -> io::Result<&'static Handle> {
+  RUNTIME
+    .as_ref()
+    .map_err(|error| io::Error::new(error.kind(), error.to_string()))
+}
+
+/// Socket options of a [Pinger].
+#[derive(Debug, Default, Clone, PartialEq, Eq, Hash)]
+pub struct Options {
+  /// Local address the socket is bound to.
+  pub source_ip: Option<IpAddr>,
+
+  /// Network interface the socket is bound to.
+  pub source_interface: Option<String>,
+
+  /// Time to live (hop limit for IPv6) of the sent packets.
+  pub ttl: Option<u32>,
+
+  /// Differentiated services code point the sent packets are marked with.
+  pub dscp: Option<u8>,
+
+  /// Kind of the socket.
+  pub socket: IcmpSocket,
+}
+
+impl Options {
+  /// Binds the socket to the source address and interface, and sets its time
+  /// to live for sending to the `target`.
+  pub fn apply(&self, socket: &Socket, target: IpAddr) -> io::Result<()> {
+    if let Some(ip) = self.source_ip {
+      socket.bind(&SocketAddr::new(ip, 0).into())?;
+    }
+
+    if let Some(interface) = &self.source_interface {
+      bind_device(socket, interface)?;
+    }
+
+    if let Some(ttl) = self.ttl {
+      match target {
+        IpAddr::V4(_) => socket.set_ttl_v4(ttl)?,
+        IpAddr::V6(_) => socket.set_unicast_hops_v6(ttl)?,
+      }
+    }
+
+    if let Some(dscp) = self.dscp {
+      set_dscp(socket, target, dscp)?;
+    }
+
+    Ok(())
+  }
+}
+
+/// A reply to an echo request.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Reply {
+  /// The target replied after the round-trip time.
+  Echo(Duration),
+
+  /// The request's time to live expired at the router.
+  TimeExceeded(IpAddr),
+}
+
+/// An ICMP message related to an echo request.
+#[derive(Debug, PartialEq, Eq)]
+enum Message {
+  EchoReply { identifier: u16, sequence: u16 },
+  TimeExceeded { identifier: u16, sequence: u16 },
+}
+
+/// An echo request waiting for a reply.
+struct Pending {
+  target: IpAddr,
+  sent: Instant,
+  reply: oneshot::Sender<Reply>,
+}
+
+/// Sends ICMP echo requests over a shared socket and hands the replies over to
+/// the requests by their sequence numbers.
+pub struct Pinger {
+  socket: UdpSocket,
+  family: IpFamily,
+  identifier: u16,
+  sequence: AtomicU16,
+  pending: Mutex<HashMap<u16, Pending>>,
+
+  /// Kind of the error the socket stopped receiving on, after which requests
+  /// fail right away.
+  failure: OnceLock<io::ErrorKind>,
+}
+
+impl Pinger {
+  /// Returns the pinger for targets of the same family as the `target`,
+  /// opening its socket on first use. Sockets are kept open for reuse.
+  pub fn shared(target: IpAddr, options: &Options) -> io::Result<Arc<Self>> {
+    let mut pingers = PINGERS.lock().expect("pingers lock");
+    let key = (IpFamily::from(target), options.clone());
+
+    if let Some(pinger) = pingers.get(&key) {
+      return Ok(Arc::clone(pinger));
+    }
+
+    let pinger = Arc::new(Self::open(target, options)?);
+    runtime()?.spawn(Arc::clone(&pinger).receive());
+    pingers.insert(key, Arc::clone(&pinger));
+
+    Ok(pinger)
+  }
+
+  fn open(target: IpAddr, options: &Options) -> io::Result<Self> {
+    let (domain, protocol) = match target {
+      IpAddr::V4(_) => (Domain::IPV4, Protocol::ICMPV4),
+      IpAddr::V6(_) => (Domain::IPV6, Protocol::ICMPV6),
+    };
+
+    let socket = match options.socket {
+      IcmpSocket::Raw => Socket::new(domain, Type::RAW, Some(protocol))?,
+      IcmpSocket::Dgram => Socket::new(domain, Type::DGRAM, Some(protocol))?,
+      IcmpSocket::Auto => match Socket::new(domain, Type::RAW, Some(protocol)) {
+        Err(error) if error.kind() == io::ErrorKind::PermissionDenied => {
+          Socket::new(domain, Type::DGRAM, Some(protocol))?
+        }
+        socket => socket?,
+      },
+    };
+    let datagram = socket.r#type()? == Type::DGRAM;
+
+    options.apply(&socket, target)?;
+
+    // Datagram sockets are bound in any case, since the port is the
+    // identifier of their requests.
+    if options.source_ip.is_none() && datagram {
+      let ip = match target {
+        IpAddr::V4(_) => IpAddr::from([0; 4]),
+        IpAddr::V6(_) => IpAddr::from([0; 16]),
+      };
+
+      socket.bind(&SocketAddr::new(ip, 0).into())?;
+    }
+
+    // Linux replaces the identifier of requests sent over a datagram socket
+    // with its port.
+    let port = if datagram {
+      socket
+        .local_addr()?
+        .as_socket()
+        .map(|address| address.port())
+    } else {
+      None
+    };
+    let identifier = port.filter(|port| *port != 0).unwrap_or_else(|| {
+      (std::process::id() as u16)
+        .wrapping_add(IDENTIFIER.fetch_add(1, Ordering::Relaxed))
+        .rotate_left(8)
+    });
+
+    socket.set_nonblocking(true)?;
+    let _runtime = runtime()?.enter();
+
+    Ok(Self {
+      socket: UdpSocket::from_std(socket.into())?,
+      family: IpFamily::from(target),
+      identifier,
+      sequence: AtomicU16::new(0),
+      pending: Mutex::new(HashMap::new()),
+      failure: OnceLock::new(),
+    })
+  }
+
+  /// Sends an echo request of the given `size` to the `target` and returns the
+  /// reply, or `None` if no reply arrived within the `timeout`.
+  pub async fn ping(
+    &self,
+    target: IpAddr,
+    size: usize,
+    timeout: Duration,
+  ) -> io::Result<Option<Reply>> {
+    let (reply, received) = oneshot::channel();
+    let sequence = self.register(target, reply)?;
+    let request = echo_request(target, self.identifier, sequence, size);
+
+    let sent = self
+      .socket
+      .send_to(&request, SocketAddr::new(target, 0))
+      .await;
+    let reply = match sent {
+      Ok(_) => time::timeout(timeout, received)
+        .await
+        .ok()
+        .and_then(Result::ok),
+      Err(_) => None,
+    };
+
+    self
+      .pending
+      .lock()
+      .expect("pending requests lock")
+      .remove(&sequence);
+
+    sent.map(|_| reply)
+  }
+
+  /// Assigns a free sequence number to a request sent now, unless the socket
+  /// stopped receiving replies.
+  fn register(&self, target: IpAddr, reply: oneshot::Sender<Reply>) -> io::Result<u16> {
+    let mut pending = self.pending.lock().expect("pending requests lock");
+
+    if let Some(kind) = self.failure.get() {
+      return Err(io::Error::new(
+        *kind,
+        "the socket stopped receiving replies",
+      ));
+    }
+
+    let sequence = loop {
+      let sequence = self.sequence.fetch_add(1, Ordering::Relaxed);
+
+      if !pending.contains_key(&sequence) {
+        break sequence;
+      }
+    };
+
+    pending.insert(sequence, Pending {
+      target,
+      sent: Instant::now(),
+      reply,
+    });
+
+    Ok(sequence)
+  }
+
+  /// Receives messages on the socket for as long as the process runs, or
+  /// until the socket fails. A failed pinger is replaced on next use, and its
+  /// pending requests get no reply.
+  async fn receive(self: Arc<Self>) {
+    let mut buffer = vec![0; RECEIVE_BUFFER_SIZE];
+
+    loop {
+      let (length, source) = match self.socket.recv_from(&mut buffer).await {
+        Ok(received) => received,
+        // Errors caused by one of the requests time it out anyway.
+        Err(error) if is_transient(&error) => continue,
+        Err(error) => {
+          self.fail(error.kind());
+          return;
+        }
+      };
+
+      let mut pending = self.pending.lock().expect("pending requests lock");
+
+      let reply = match parse_message(self.family, &buffer[..length]) {
+        Some(Message::EchoReply {
+          identifier,
+          sequence,
+        }) if identifier == self.identifier
+          && pending
+            .get(&sequence)
+            .is_some_and(|request| request.target == source.ip()) =>
+        {
+          pending
+            .remove(&sequence)
+            .map(|request| (request.reply, Reply::Echo(request.sent.elapsed())))
+        }
+        Some(Message::TimeExceeded {
+          identifier,
+          sequence,
+        }) if identifier == self.identifier => pending
+          .remove(&sequence)
+          .map(|request| (request.reply, Reply::TimeExceeded(source.ip()))),
+        _ => None,
+      };
+
+      if let Some((sender, reply)) = reply {
+        let _ = sender.send(reply);
+      }
+    }
+  }
+
+  /// Stops the requests over the socket, and forgets the pinger so a new
+  /// socket is opened for the next ones.
+  fn fail(self: &Arc<Self>, kind: io::ErrorKind) {
+    PINGERS
+      .lock()
+      .expect("pingers lock")
+      .retain(|_, pinger| !Arc::ptr_eq(pinger, self));
+
+    let mut pending = self.pending.lock().expect("pending requests lock");
+    let _ = self.failure.set(kind);
+    pending.clear();
+  }
+}
+
+#[cfg(any(target_os = "linux", target_os = "android", target_os = "fuchsia"))]
+fn bind_device(socket: &Socket, interface: &str) -> io::Result<()> {
+  socket.bind_device(Some(interface.as_bytes()))
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android", target_os = "fuchsia")))]
+fn bind_device(_: &Socket, _: &str) -> io::Result<()> {
+  Err(io::Error::new(
+    io::ErrorKind::Unsupported,
+    "binding to an interface isn't supported on this platform",
+  ))
+}
+
+/// Sets the DSCP bits of the TOS byte (traffic class for IPv6) of the packets
+/// sent to the `target`, leaving the ECN bits clear.
+#[cfg(any(
+  target_os = "linux",
+  target_os = "android",
+  target_os = "fuchsia",
+  target_os = "freebsd",
+  target_os = "macos"
+))]
+fn set_dscp(socket: &Socket, target: IpAddr, dscp: u8) -> io::Result<()> {
+  if dscp > 63 {
+    return Err(io::Error::new(
+      io::ErrorKind::InvalidInput,
+      format!("DSCP must be within 0 and 63, got {}", dscp),
+    ));
+  }
+
+  let traffic_class = u32::from(dscp) << 2;
+
+  match target {
+    IpAddr::V4(_) => socket.set_tos_v4(traffic_class),
+    IpAddr::V6(_) => socket.set_tclass_v6(traffic_class),
+  }
+}
+
+#[cfg(not(any(
+  target_os = "linux",
+  target_os = "android",
+  target_os = "fuchsia",
+  target_os = "freebsd",
+  target_os = "macos"
+)))]
+fn set_dscp(_: &Socket, _: IpAddr, _: u8) -> io::Result<()> {
+  Err(io::Error::new(
+    io::ErrorKind::Unsupported,
+    "marking packets with DSCP isn't supported on this platform",
+  ))
+}
+
+/// Builds an echo request packet of `size` bytes (at least the header).
+fn echo_request(target: IpAddr, identifier: u16, sequence: u16, size: usize) -> Vec<u8> {
+  let mut packet = vec![0; size.max(8)];
+
+  packet[0] = match target {
+    IpAddr::V4(_) => ECHO_REQUEST_V4,
+    IpAddr::V6(_) => ECHO_REQUEST_V6,
+  };
+  packet[4..6].copy_from_slice(&identifier.to_be_bytes());
+  packet[6..8].copy_from_slice(&sequence.to_be_bytes());
+
+  // The kernel calculates ICMPv6 checksums itself.
+  if target.is_ipv4() {
+    let checksum = checksum(&packet);
+    packet[2..4].copy_from_slice(&checksum.to_be_bytes());
+  }
+
+  packet
+}
+
+/// Parses an echo reply or an error quoting an echo request.
+///
+/// Raw IPv4 sockets (and datagram ones on some platforms) receive packets
+/// along with the IP header, which is skipped.
+fn parse_message(family: IpFamily, data: &[u8]) -> Option<Message> {
+  let packet = match family {
+    IpFamily::V4 if *data.first()? >> 4 == 4 => skip_ipv4_header(data)?,
+    _ => data,
+  };
+
+  match (family, *packet.first()?) {
+    (IpFamily::V4, ECHO_REPLY_V4) | (IpFamily::V6, ECHO_REPLY_V6) => {
+      let (identifier, sequence) = echo_fields(packet)?;
+
+      Some(Message::EchoReply {
+        identifier,
+        sequence,
+      })
+    }
+    (IpFamily::V4, TIME_EXCEEDED_V4) | (IpFamily::V6, TIME_EXCEEDED_V6) => {
+      // The error quotes the header of the expired packet and the beginning of
+      // the echo request.
+      let quoted = packet.get(8..)?;
+      let (kind, request) = match family {
+        IpFamily::V4 => (ECHO_REQUEST_V4, skip_ipv4_header(quoted)?),
+        IpFamily::V6 => (ECHO_REQUEST_V6, quoted.get(IPV6_HEADER_LENGTH..)?),
+      };
+
+      if *request.first()? != kind {
+        return None;
+      }
+
+      let (identifier, sequence) = echo_fields(request)?;
+
+      Some(Message::TimeExceeded {
+        identifier,
+        sequence,
+      })
+    }
+    _ => None,
+  }
+}
+
+/// Returns whether a receive error was reported for one of the requests, e.g.
+/// by an ICMP error, rather than by a socket that can't receive anymore.
+fn is_transient(error: &io::Error) -> bool {
+  matches!(
+    error.kind(),
+    io::ErrorKind::ConnectionRefused
+      | io::ErrorKind::ConnectionReset
+      | io::ErrorKind::HostUnreachable
+      | io::ErrorKind::NetworkUnreachable
+      | io::ErrorKind::TimedOut
+      | io::ErrorKind::Interrupted
+      | io::ErrorKind::WouldBlock
+  )
+}
+
+fn skip_ipv4_header(data: &[u8]) -> Option<&[u8]> {
+  let length = (*data.first()? as usize & 0x0f) * 4;
+
+  data.get(length..)
+}
+
+/// Returns the identifier and the sequence number of an echo message.
+fn echo_fields(packet: &[u8]) -> Option<(u16, u16)> {
+  let fields = packet.get(4..8)?;
+
+  Some((
+    u16::from_be_bytes([fields[0], fields[1]]),
+    u16::from_be_bytes([fields[2], fields[3]]),
+  ))
+}
+
+/// The Internet checksum (RFC 1071).
+fn checksum(data: &[u8]) -> u16 {
+  let mut sum = data
+    .chunks(2)
+    .map(|chunk| u16::from_be_bytes([chunk[0], *chunk.get(1).unwrap_or(&0)]) as u32)
+    .sum::<u32>();
+
+  while sum >> 16 != 0 {
+    sum = (sum & 0xffff) + (sum >> 16);
+  }
+
+  !(sum as u16)
+}
+
+/// Returns whether ICMP sockets can be opened, which takes raw sockets or
+/// unprivileged ICMP sockets to be permitted. Tests expect hosts to be pinged
+/// over the fallback otherwise.
+#[cfg(test)]
+pub(super) fn permitted() -> bool {
+  Pinger::open(IpAddr::from([127, 0, 0, 1]), &Options::default()).is_ok()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const V4: IpAddr = IpAddr::V4(std::net::Ipv4Addr::LOCALHOST);
+  const V6: IpAddr = IpAddr::V6(std::net::Ipv6Addr::LOCALHOST);
+
+  #[test]
+  fn echo_request_v4() {
+    let packet = echo_request(V4, 0x1234, 7, 64);
+
+    assert_eq!(packet.len(), 64, "packet has requested size");
+    assert_eq!(packet[0], ECHO_REQUEST_V4);
+    assert_eq!(&packet[4..8], &[0x12, 0x34, 0, 7]);
+    assert_eq!(checksum(&packet), 0, "checksum is valid");
+  }
+
+  #[test]
+  fn echo_request_v6() {
+    let packet = echo_request(V6, 1, 2, 4);
+
+    assert_eq!(packet.len(), 8, "packet contains at least the header");
+    assert_eq!(packet[0], ECHO_REQUEST_V6);
+    assert_eq!(&packet[2..4], &[0, 0], "checksum is left to the kernel");
+  }
+
+  fn ipv4_header() -> Vec<u8> {
+    let mut header = vec![0x45];
+    header.extend([0; 19]);
+    header
+  }
+
+  #[test]
+  fn parse_echo_reply() {
+    let mut reply = ipv4_header();
+    reply.extend([ECHO_REPLY_V4, 0, 0, 0, 0x12, 0x34, 0, 7]);
+
+    assert_eq!(
+      parse_message(IpFamily::V4, &reply),
+      Some(Message::EchoReply {
+        identifier: 0x1234,
+        sequence: 7
+      })
+    );
+    assert_eq!(
+      parse_message(IpFamily::V6, &[ECHO_REPLY_V6, 0, 0, 0, 0, 1, 0, 2]),
+      Some(Message::EchoReply {
+        identifier: 1,
+        sequence: 2
+      })
+    );
+    assert_eq!(
+      parse_message(IpFamily::V6, &[ECHO_REQUEST_V6, 0, 0, 0, 0, 1, 0, 2]),
+      None,
+      "requests aren't replies"
+    );
+    assert_eq!(
+      parse_message(IpFamily::V4, &[0x45]),
+      None,
+      "truncated packet"
+    );
+  }
+
+  #[test]
+  fn parse_time_exceeded() {
+    let mut error = ipv4_header();
+    error.extend([TIME_EXCEEDED_V4, 0, 0, 0, 0, 0, 0, 0]);
+    error.extend(ipv4_header());
+    error.extend([ECHO_REQUEST_V4, 0, 0, 0, 0x12, 0x34, 0, 7]);
+
+    assert_eq!(
+      parse_message(IpFamily::V4, &error),
+      Some(Message::TimeExceeded {
+        identifier: 0x1234,
+        sequence: 7
+      })
+    );
+
+    let mut error = vec![TIME_EXCEEDED_V6, 0, 0, 0, 0, 0, 0, 0];
+    error.extend([0; IPV6_HEADER_LENGTH]);
+    error.extend([ECHO_REQUEST_V6, 0, 0, 0, 0, 1, 0, 2]);
+
+    assert_eq!(
+      parse_message(IpFamily::V6, &error),
+      Some(Message::TimeExceeded {
+        identifier: 1,
+        sequence: 2
+      })
+    );
+
+    error.truncate(8 + IPV6_HEADER_LENGTH + 4);
+    assert_eq!(
+      parse_message(IpFamily::V6, &error),
+      None,
+      "quote is truncated"
+    );
+  }
+
+  #[test]
+  fn parse_reply_without_ip_header() {
+    assert_eq!(
+      parse_message(IpFamily::V4, &[ECHO_REPLY_V4, 0, 0, 0, 0x12, 0x34, 0, 7]),
+      Some(Message::EchoReply {
+        identifier: 0x1234,
+        sequence: 7
+      }),
+      "datagram sockets strip the header"
+    );
+  }
+
+  #[tokio::test]
+  async fn ping_loopback() {
+    if let Err(error) = Pinger::open(V4, &Options::default()) {
+      assert_eq!(
+        error.kind(),
+        io::ErrorKind::PermissionDenied,
+        "ICMP sockets aren't permitted"
+      );
+      return;
+    }
+
+    let reply = spawn(async {
+      let pinger = Pinger::shared(V4, &Options::default()).expect("socket is opened");
+      pinger.ping(V4, 64, Duration::from_secs(1)).await
+    })
+    .unwrap()
+    .await
+    .unwrap()
+    .expect("request is sent");
+
+    assert!(
+      matches!(reply, Some(Reply::Echo(_))),
+      "loopback replies to echo requests"
+    );
+  }
+
+  #[tokio::test]
+  async fn concurrent_pings() {
+    if let Err(error) = Pinger::open(V4, &Options::default()) {
+      assert_eq!(
+        error.kind(),
+        io::ErrorKind::PermissionDenied,
+        "ICMP sockets aren't permitted"
+      );
+      return;
+    }
+
+    let replies = spawn(async {
+      let pinger = Pinger::shared(V4, &Options::default()).expect("socket is opened");
+      let same = Pinger::shared(V4, &Options::default()).expect("socket is opened");
+      assert!(Arc::ptr_eq(&pinger, &same), "socket is shared");
+
+      let mut requests = tokio::task::JoinSet::new();
+
+      for _ in 0..10 {
+        let pinger = Arc::clone(&pinger);
+        requests.spawn(async move { pinger.ping(V4, 64, Duration::from_secs(1)).await });
+      }
+
+      requests.join_all().await
+    })
+    .unwrap()
+    .await
+    .unwrap();
+
+    assert!(
+      replies
+        .iter()
+        .all(|reply| matches!(reply, Ok(Some(Reply::Echo(_))))),
+      "every request gets its reply"
+    );
+  }
+
+  #[tokio::test]
+  async fn failed_socket() {
+    // Receiving on a stream socket that isn't connected fails every time.
+    let socket = Socket::new(Domain::IPV4, Type::STREAM, None).unwrap();
+    socket.set_nonblocking(true).unwrap();
+
+    let pinger = Arc::new(Pinger {
+      socket: UdpSocket::from_std(socket.into()).unwrap(),
+      family: IpFamily::V4,
+      identifier: 1,
+      sequence: AtomicU16::new(0),
+      pending: Mutex::new(HashMap::new()),
+      failure: OnceLock::new(),
+    });
+
+    time::timeout(Duration::from_secs(1), Arc::clone(&pinger).receive())
+      .await
+      .expect("receiving stops on a persistent error");
+    assert!(
+      pinger.ping(V4, 64, Duration::from_secs(1)).await.is_err(),
+      "requests fail right away"
+    );
+  }
+
+  #[test]
+  fn dscp_marking() {
+    let options = Options {
+      dscp: Some(46),
+      ..Default::default()
+    };
+
+    let socket = Socket::new(Domain::IPV4, Type::DGRAM, None).unwrap();
+    options.apply(&socket, V4).unwrap();
+    assert_eq!(socket.tos_v4().unwrap(), 0xb8, "expedited forwarding");
+
+    let socket = Socket::new(Domain::IPV6, Type::DGRAM, None).unwrap();
+    options.apply(&socket, V6).unwrap();
+    assert_eq!(socket.tclass_v6().unwrap(), 0xb8);
+
+    let options = Options {
+      dscp: Some(64),
+      ..Default::default()
+    };
+    assert!(options.apply(&socket, V6).is_err(), "DSCP has six bits");
+  }
+
+  #[test]
+  fn checksum_odd_length() {
+    assert_eq!(checksum(&[0xff]), !0xff00);
+  }
+}
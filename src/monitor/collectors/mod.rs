@@ -1,7 +1,30 @@
+pub(crate) mod client;
 mod http;
+mod icmp;
 #[cfg(not(tarpaulin_include))]
-// Excluded from coverage since ping requires raw sockets and elevated privileges.
+// Excluded from coverage since ping requires raw sockets or unprivileged ICMP
+// sockets to be permitted.
 mod ping;
+mod resolver;
+mod tcp;
+mod tls;
 
 pub use http::Http;
 pub use ping::Ping;
+pub use resolver::set_default_cache as set_default_dns_cache;
+
+/// Parses a host that is an IP address (possibly enclosed in brackets), so
+/// it needs no DNS lookup.
+fn ip_literal(host: &str) -> Option<std::net::IpAddr> {
+  host
+    .strip_prefix('[')
+    .and_then(|host| host.strip_suffix(']'))
+    .unwrap_or(host)
+    .parse()
+    .ok()
+}
+
+/// Converts a duration into fractional milliseconds.
+fn millis(duration: std::time::Duration) -> f32 {
+  duration.as_secs_f32() * 1000.0
+}
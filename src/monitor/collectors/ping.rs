@@ -1,54 +1,578 @@
+use std::future::Future;
+use std::io;
+use std::net::{IpAddr, SocketAddr};
 use std::sync::Arc;
 use std::time::Duration;
 
-use fastping_rs::{PingResult, Pinger};
-use once_cell::sync::Lazy;
-use tokio::task;
-use trust_dns_resolver::{TokioAsyncResolver, config::ResolverOpts, error::ResolveError};
+use tokio::task::{self, JoinSet};
+use tokio::time;
+use trust_dns_resolver::TokioAsyncResolver;
+use trust_dns_resolver::error::{ResolveError, ResolveErrorKind};
 
 use crate::measure;
+use crate::monitor::collectors::icmp::{self, Options, Pinger, Reply};
+use crate::monitor::collectors::{ip_literal, millis, resolver, tcp};
 use crate::monitor::errors::PingError;
-use crate::monitor::models::{Data, PingConfig, PingData};
+use crate::monitor::models::{Data, IpFamily, Partial, Phase, PingConfig, PingData, PingProtocol};
 
-static RESOLVER: Lazy<Arc<TokioAsyncResolver>> = Lazy::new(|| {
-  let mut opts = ResolverOpts::default();
-  opts.cache_size = 0;
-  opts.positive_min_ttl = Some(Duration::ZERO);
-  opts.positive_max_ttl = Some(Duration::ZERO);
-  opts.negative_min_ttl = Some(Duration::ZERO);
-  opts.negative_max_ttl = Some(Duration::ZERO);
+const DEFAULT_INTERVAL: Duration = Duration::from_secs(1);
 
-  Arc::new(TokioAsyncResolver::tokio_from_system_conf().expect("system resolver"))
-});
+/// The outcome of a check, along with what's known of it if it failed.
+pub struct Attempt {
+  pub result: Result<Data, PingError>,
 
+  /// How far the check got, if it failed.
+  pub partial: Option<Partial>,
+}
+
+/// A collector measuring the round-trip time to hosts with ICMP echo requests.
 pub struct Ping;
 
 impl Ping {
-  pub async fn measure(host: &String, config: &PingConfig) -> Result<Data, PingError> {
-    let (lookup, lookup_duration) = measure!({ Arc::clone(&RESOLVER).lookup_ip(host).await? });
-    let rtt = (config.timeout as u64).checked_mul(1000);
-    let ip_address = lookup
+  /// Pings the host and aggregates the round-trip times of the replies.
+  pub async fn measure(host: &str, config: &PingConfig) -> Result<Data, PingError> {
+    Self::measure_partial(host, config).await.result
+  }
+
+  /// Performs the measurement and returns how far it got along with the
+  /// result if it failed.
+  pub async fn measure_partial(host: &str, config: &PingConfig) -> Attempt {
+    let mut partial = Partial::default();
+    let result = Self::perform(host, config, &mut partial).await;
+
+    Attempt {
+      partial: result.is_err().then_some(partial),
+      result,
+    }
+  }
+
+  #[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(name = "ping", level = "debug", skip(config, partial), err(Display))
+  )]
+  async fn perform(
+    host: &str,
+    config: &PingConfig,
+    partial: &mut Partial,
+  ) -> Result<Data, PingError> {
+    let (ip_address, lookup_duration) = resolve(host, config).await?;
+    partial.completed = Some(Phase::Dns);
+    partial.resolved_ip = Some(ip_address);
+
+    let ptr = match &config.expected_ptr {
+      Some(pattern) => Some(verify_ptr(ip_address, pattern, config).await?),
+      None => None,
+    };
+    let config = config.clone();
+
+    icmp::spawn(async move {
+      let packet_size = config.packet_size.unwrap_or(icmp::DEFAULT_PACKET_SIZE);
+
+      let rtts = ping_icmp(ip_address, &config).await;
+      let (rtts, protocol) = fall_back(rtts, ip_address, &config).await?;
+
+      Ok(Data::Ping(PingData {
+        protocol,
+        ptr,
+        resolved_ip: Some(ip_address),
+        ..aggregate(&rtts, count(&config), millis(lookup_duration), packet_size)
+      }))
+    })
+    .map_err(PingError::Runtime)?
+    .await?
+  }
+
+  /// Measures several hosts with the same configuration at once, returning the
+  /// results in the order of the hosts. Requests to all of them are sent over
+  /// the same shared sockets.
+  pub async fn measure_many<S: AsRef<str>>(
+    hosts: &[S],
+    config: &PingConfig,
+  ) -> Vec<Result<Data, PingError>> {
+    let config = Arc::new(config.clone());
+
+    let checks = hosts
+      .iter()
+      .map(|host| {
+        let (host, config) = (host.as_ref().to_owned(), Arc::clone(&config));
+
+        task::spawn(async move { Self::measure(&host, &config).await })
+      })
+      .collect::<Vec<_>>();
+
+    let mut results = Vec::with_capacity(checks.len());
+
+    for check in checks {
+      results.push(check.await.unwrap_or_else(|error| Err(error.into())));
+    }
+
+    results
+  }
+}
+
+/// Returns the address to ping along with the time the DNS lookup took.
+/// Addresses are pinged without a lookup.
+async fn resolve(host: &str, config: &PingConfig) -> Result<(IpAddr, Duration), PingError> {
+  if let Some(ip_address) = ip_literal(host) {
+    return Ok((ip_address, Duration::ZERO));
+  }
+
+  let resolver = configured_resolver(config).await?;
+  let (lookup, duration) = measure!({ resolver.lookup_ip(host).await? });
+  let ip_address = select_address(&lookup.iter().collect::<Vec<_>>(), config.ip_family)
+    .ok_or(ResolveError::from("No records found"))?;
+
+  Ok((ip_address, duration))
+}
+
+/// Returns the resolver of the DNS settings, or the system one.
+async fn configured_resolver(config: &PingConfig) -> Result<Arc<TokioAsyncResolver>, PingError> {
+  let policy = resolver::policy(config.dns.as_ref());
+
+  Ok(match &config.dns {
+    Some(dns) => match (&dns.doh_url, resolver::with_config(dns)) {
+      (Some(url), _) => resolver::with_doh(url, policy).await?,
+      (None, Some(resolver)) => resolver,
+      (None, None) => resolver::system(policy)?,
+    },
+    None => resolver::system(policy)?,
+  })
+}
+
+/// Looks up the PTR record of the address and checks that it matches the
+/// pattern. Returns the name of the record.
+async fn verify_ptr(
+  ip_address: IpAddr,
+  pattern: &str,
+  config: &PingConfig,
+) -> Result<String, PingError> {
+  let resolver = configured_resolver(config).await?;
+
+  let name = match resolver.reverse_lookup(ip_address).await {
+    Ok(lookup) => lookup
       .iter()
       .next()
-      .ok_or(ResolveError::from("No records found"))?;
-
-    task::spawn_blocking(move || {
-      let (pinger, results) = Pinger::new(rtt, Some(1000)).unwrap();
-      pinger.add_ipaddr(&ip_address.to_string().as_str());
-      pinger.run_pinger();
-
-      match results.recv() {
-        Ok(PingResult::Receive { addr: _, rtt }) => Ok(Data::Ping(PingData {
-          dns_lookup: lookup_duration.as_secs_f32(),
-          ping: rtt.as_secs_f32(),
-        })),
-        Ok(PingResult::Idle { addr }) => Err(PingError::NoReply {
-          addr: addr.to_string(),
-        }),
-        Err(_) => Err(PingError::Unreachable),
-      }
+      .map(|name| name.to_utf8().trim_end_matches('.').to_owned()),
+    Err(error) if matches!(error.kind(), ResolveErrorKind::NoRecordsFound { .. }) => None,
+    Err(error) => return Err(error.into()),
+  };
+
+  match name {
+    Some(name) if matches_pattern(&name, pattern) => Ok(name),
+    actual => Err(PingError::PtrMismatch {
+      expected: pattern.into(),
+      actual,
+    }),
+  }
+}
+
+/// Checks whether a domain name matches a case-insensitive pattern, where `*`
+/// matches any characters.
+fn matches_pattern(name: &str, pattern: &str) -> bool {
+  let name = name.trim_end_matches('.').to_ascii_lowercase();
+  let pattern = pattern.trim_end_matches('.').to_ascii_lowercase();
+
+  let mut parts = pattern.split('*');
+  let Some(mut rest) = name.strip_prefix(parts.next().unwrap_or_default()) else {
+    return false;
+  };
+  let parts = parts.collect::<Vec<_>>();
+
+  let Some((last, middle)) = parts.split_last() else {
+    return rest.is_empty();
+  };
+
+  for part in middle {
+    match rest.find(part) {
+      Some(index) => rest = &rest[index + part.len()..],
+      None => return false,
+    }
+  }
+
+  rest.ends_with(last)
+}
+
+/// Number of probes sent per check.
+fn count(config: &PingConfig) -> u16 {
+  config.count.unwrap_or(1).max(1)
+}
+
+fn options(config: &PingConfig) -> Options {
+  Options {
+    source_ip: config.source_ip,
+    source_interface: config.source_interface.clone(),
+    ttl: config.ttl,
+    dscp: config.dscp,
+    socket: config.icmp_socket,
+  }
+}
+
+/// Starts the configured number of probes, one per interval without waiting
+/// for the previous ones to finish, and collects their results. A warm-up
+/// probe is awaited before them, if configured.
+async fn repeat<T, F>(config: &PingConfig, probe: impl Fn() -> F) -> io::Result<Vec<T>>
+where
+  F: Future<Output = io::Result<T>> + Send + 'static,
+  T: Send + 'static,
+{
+  let interval = config
+    .interval_ms
+    .map_or(DEFAULT_INTERVAL, Duration::from_millis);
+  let mut probes = JoinSet::new();
+
+  if config.warmup {
+    // Only the delivery matters, the result is skewed by first-packet
+    // effects.
+    let _ = probe().await;
+  }
+
+  for sequence in 0..count(config) {
+    if sequence > 0 {
+      time::sleep(interval).await;
+    }
+
+    probes.spawn(probe());
+  }
+
+  probes.join_all().await.into_iter().collect()
+}
+
+/// Returns round-trip times of the replied echo requests.
+async fn ping_icmp(ip_address: IpAddr, config: &PingConfig) -> Result<Vec<Duration>, PingError> {
+  let timeout = Duration::from_secs(config.timeout as u64);
+  let packet_size = config.packet_size.unwrap_or(icmp::DEFAULT_PACKET_SIZE);
+  let pinger = Pinger::shared(ip_address, &options(config)).map_err(PingError::Socket)?;
+
+  let replies = repeat(config, || {
+    let pinger = Arc::clone(&pinger);
+    async move { pinger.ping(ip_address, packet_size, timeout).await }
+  })
+  .await
+  .map_err(PingError::Socket)?;
+
+  let rtts = replies
+    .iter()
+    .filter_map(|reply| match reply {
+      Some(Reply::Echo(rtt)) => Some(*rtt),
+      _ => None,
+    })
+    .collect::<Vec<_>>();
+
+  if rtts.is_empty() {
+    let expired_at = replies.iter().find_map(|reply| match reply {
+      Some(Reply::TimeExceeded(router)) => Some(router),
+      _ => None,
+    });
+
+    return Err(match expired_at {
+      Some(router) => PingError::TtlExceeded {
+        addr: router.to_string(),
+      },
+      None => PingError::NoReply {
+        addr: ip_address.to_string(),
+      },
+    });
+  }
+
+  Ok(rtts)
+}
+
+/// Returns the round-trip times of the echo requests, or of connections to
+/// the fallback port if ICMP is filtered or ICMP sockets aren't permitted.
+async fn fall_back(
+  rtts: Result<Vec<Duration>, PingError>,
+  ip_address: IpAddr,
+  config: &PingConfig,
+) -> Result<(Vec<Duration>, PingProtocol), PingError> {
+  match rtts {
+    Err(error) if config.fallback_tcp_port.is_some() && blocked(&error) => {
+      Ok((ping_tcp(ip_address, config).await?, PingProtocol::Tcp))
+    }
+    rtts => Ok((rtts?, PingProtocol::Icmp)),
+  }
+}
+
+/// Returns whether the error means ICMP is filtered or isn't permitted, e.g.
+/// opening the socket failed with `EPERM` or `EACCES`.
+fn blocked(error: &PingError) -> bool {
+  match error {
+    PingError::NoReply { .. } | PingError::Unreachable => true,
+    PingError::Socket(error) => error.kind() == io::ErrorKind::PermissionDenied,
+    _ => false,
+  }
+}
+
+/// Returns round-trip times of the completed connections to the fallback
+/// port.
+async fn ping_tcp(ip_address: IpAddr, config: &PingConfig) -> Result<Vec<Duration>, PingError> {
+  let timeout = Duration::from_secs(config.timeout as u64);
+  let target = SocketAddr::new(ip_address, config.fallback_tcp_port.unwrap_or_default());
+  let options = options(config);
+
+  let rtts = repeat(config, || tcp::connect(target, options.clone(), timeout))
+    .await
+    .map_err(PingError::Socket)?
+    .into_iter()
+    .flatten()
+    .collect::<Vec<_>>();
+
+  if rtts.is_empty() {
+    return Err(PingError::NoReply {
+      addr: target.to_string(),
+    });
+  }
+
+  Ok(rtts)
+}
+
+/// Returns the first address of the preferred family, or the first address
+/// if there is none.
+fn select_address(addresses: &[IpAddr], family: Option<IpFamily>) -> Option<IpAddr> {
+  family
+    .and_then(|family| {
+      addresses
+        .iter()
+        .find(|address| IpFamily::from(**address) == family)
+    })
+    .or(addresses.first())
+    .copied()
+}
+
+/// Aggregates round-trip times of the replied requests out of `sent` ones.
+fn aggregate(rtts: &[Duration], sent: u16, dns_lookup: f32, packet_size: usize) -> PingData {
+  let rtts = rtts.iter().copied().map(millis).collect::<Vec<_>>();
+  let count = rtts.len() as f32;
+
+  let average = rtts.iter().sum::<f32>() / count;
+  let variance = rtts.iter().map(|rtt| (rtt - average).powi(2)).sum::<f32>() / count;
+
+  PingData {
+    dns_lookup,
+    ping: average,
+    ping_min: rtts.iter().copied().fold(f32::INFINITY, f32::min),
+    ping_max: rtts.iter().copied().fold(0.0, f32::max),
+    ping_stddev: variance.sqrt(),
+    packet_loss: (1.0 - count / sent as f32) * 100.0,
+    packet_size,
+    protocol: PingProtocol::Icmp,
+    ptr: None,
+    resolved_ip: None,
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::sync::atomic::{AtomicU16, Ordering};
+
+  use super::*;
+  use crate::monitor::models::DnsConfig;
+
+  #[test]
+  fn preferred_address_family() {
+    let v4 = IpAddr::from([192, 0, 2, 1]);
+    let v6 = IpAddr::from([0x2001, 0xdb8, 0, 0, 0, 0, 0, 1]);
+
+    assert_eq!(select_address(&[v4, v6], None), Some(v4));
+    assert_eq!(select_address(&[v4, v6], Some(IpFamily::V6)), Some(v6));
+    assert_eq!(
+      select_address(&[v6], Some(IpFamily::V4)),
+      Some(v6),
+      "AAAA-only host is pinged over IPv6"
+    );
+    assert_eq!(select_address(&[], None), None);
+  }
+
+  #[tokio::test]
+  async fn ip_literal_host() {
+    let config = PingConfig {
+      dns: Some(DnsConfig {
+        nameservers: vec![SocketAddr::from(([127, 0, 0, 1], 9))],
+        ..Default::default()
+      }),
+      ..Default::default()
+    };
+
+    for host in ["192.0.2.1", "2001:db8::1", "[2001:db8::1]"] {
+      let (ip_address, lookup) = resolve(host, &config)
+        .await
+        .expect("address is used without a lookup");
+
+      assert_eq!(ip_address, ip_literal(host).unwrap());
+      assert_eq!(lookup, Duration::ZERO, "no DNS lookup is performed");
+    }
+  }
+
+  #[tokio::test]
+  async fn resolved_ip_of_failed_check() {
+    let attempt = Ping::measure_partial("192.0.2.1", &PingConfig {
+      timeout: 1,
+      expected_ptr: Some(String::from("*.example.com")),
+      dns: Some(DnsConfig {
+        nameservers: vec![SocketAddr::from(([127, 0, 0, 1], 9))],
+        timeout_ms: Some(100),
+        attempts: Some(1),
+        ..Default::default()
+      }),
+      ..Default::default()
+    })
+    .await;
+
+    assert!(attempt.result.is_err(), "PTR record can't be verified");
+    assert_eq!(
+      attempt.partial,
+      Some(Partial {
+        completed: Some(Phase::Dns),
+        truncated: false,
+        resolved_ip: Some(IpAddr::from([192, 0, 2, 1])),
+      })
+    );
+  }
+
+  #[tokio::test]
+  async fn denied_socket_fallback() {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let config = PingConfig {
+      timeout: 1,
+      fallback_tcp_port: Some(listener.local_addr().unwrap().port()),
+      ..Default::default()
+    };
+    let ip_address = IpAddr::from([127, 0, 0, 1]);
+
+    // EPERM and EACCES, as returned without the privileges to open sockets.
+    for code in [1, 13] {
+      let denied = Err(PingError::Socket(io::Error::from_raw_os_error(code)));
+      let (rtts, protocol) = fall_back(denied, ip_address, &config).await.unwrap();
+
+      assert_eq!(protocol, PingProtocol::Tcp, "connection time is measured");
+      assert_eq!(rtts.len(), 1);
+    }
+
+    let failed = Err(PingError::Socket(io::Error::from(
+      io::ErrorKind::AddrNotAvailable,
+    )));
+    assert!(
+      matches!(
+        fall_back(failed, ip_address, &config).await,
+        Err(PingError::Socket(_))
+      ),
+      "other socket errors are reported"
+    );
+  }
+
+  #[tokio::test]
+  async fn socket_error() {
+    let result = ping_icmp(IpAddr::from([127, 0, 0, 1]), &PingConfig {
+      timeout: 1,
+      source_interface: Some(String::from("limon-missing0")),
+      ..Default::default()
+    })
+    .await;
+
+    assert!(
+      matches!(result, Err(PingError::Socket(_))),
+      "check fails instead of panicking"
+    );
+  }
+
+  #[tokio::test]
+  async fn tcp_socket_error() {
+    let result = ping_tcp(IpAddr::from([127, 0, 0, 1]), &PingConfig {
+      timeout: 1,
+      fallback_tcp_port: Some(9),
+      source_interface: Some(String::from("limon-missing0")),
+      ..Default::default()
+    })
+    .await;
+
+    assert!(
+      matches!(result, Err(PingError::Socket(_))),
+      "socket error isn't reported as an unreachable host"
+    );
+  }
+
+  #[test]
+  fn ptr_pattern() {
+    assert!(matches_pattern(
+      "ec2-1-2-3-4.compute-1.amazonaws.com",
+      "*.amazonaws.com"
+    ));
+    assert!(matches_pattern("Host.Example.com.", "host.example.com"));
+    assert!(matches_pattern("lb-12.fra.example.net", "lb-*.fra.*"));
+    assert!(
+      !matches_pattern("example.com.evil.net", "*.example.com"),
+      "pattern matches the whole name"
+    );
+    assert!(!matches_pattern("amazonaws.com", "*.amazonaws.com"));
+    assert!(!matches_pattern("host.example.com", "host.example"));
+  }
+
+  #[tokio::test]
+  async fn measure_many_hosts() {
+    // Where ICMP sockets aren't permitted, the hosts are pinged over TCP.
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let config = PingConfig {
+      timeout: 1,
+      fallback_tcp_port: Some(listener.local_addr().unwrap().port()),
+      ..Default::default()
+    };
+    let expected = if icmp::permitted() {
+      PingProtocol::Icmp
+    } else {
+      PingProtocol::Tcp
+    };
+
+    let results = Ping::measure_many(&["127.0.0.1", "invalid host", "::1"], &config).await;
+
+    assert_eq!(results.len(), 3);
+    assert!(
+      matches!(results[1], Err(PingError::Dns(_))),
+      "results are in the order of the hosts"
+    );
+
+    for result in [&results[0], &results[2]] {
+      assert!(
+        matches!(result, Ok(Data::Ping(data)) if data.protocol == expected),
+        "loopback is pinged over {expected:?}: {result:?}"
+      );
+    }
+  }
+
+  #[tokio::test]
+  async fn warmup_probe() {
+    let probes = Arc::new(AtomicU16::new(0));
+    let config = PingConfig {
+      count: Some(3),
+      interval_ms: Some(1),
+      warmup: true,
+      ..Default::default()
+    };
+
+    let results = repeat(&config, || {
+      let sequence = probes.fetch_add(1, Ordering::SeqCst);
+      async move { Ok(sequence) }
     })
     .await
-    .expect("ping request")
+    .unwrap();
+
+    assert_eq!(probes.load(Ordering::SeqCst), 4, "warm-up probe is sent");
+    assert!(
+      !results.contains(&0),
+      "result of the warm-up probe is discarded"
+    );
+    assert_eq!(results.len(), 3);
+  }
+
+  #[test]
+  fn ping_statistics() {
+    let rtts = [10, 20, 30].map(Duration::from_millis);
+    let data = aggregate(&rtts, 4, 5.0, 64);
+
+    assert_eq!(data.dns_lookup, 5.0);
+    assert_eq!(data.packet_size, 64);
+    assert_eq!(data.ping, 20.0, "average round-trip time");
+    assert_eq!(data.ping_min, 10.0);
+    assert_eq!(data.ping_max, 30.0);
+    assert!((data.ping_stddev - 8.165).abs() < 0.001, "jitter");
+    assert_eq!(data.packet_loss, 25.0, "one of four requests is lost");
   }
 }
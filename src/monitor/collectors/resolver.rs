@@ -0,0 +1,354 @@
+//! DNS resolvers shared by the collectors.
+//!
+//! Resolvers are created lazily and reused between measurements, keyed by
+//! their upstream servers and lookup policy.
+
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Duration;
+
+use once_cell::sync::Lazy;
+use trust_dns_resolver::TokioAsyncResolver;
+use trust_dns_resolver::config::{
+  LookupIpStrategy, NameServerConfig, Protocol, ResolverConfig, ResolverOpts,
+};
+use trust_dns_resolver::error::ResolveError;
+use trust_dns_resolver::system_conf;
+
+use crate::monitor::models::{DnsCache, DnsConfig};
+
+/// The default port of DNS-over-HTTPS servers.
+const HTTPS_PORT: u16 = 443;
+
+/// Resolvers are shared by lookups with the same upstream and policy.
+type Key = (Upstream, Policy);
+
+static RESOLVERS: Lazy<Mutex<HashMap<Key, Arc<TokioAsyncResolver>>>> =
+  Lazy::new(|| Mutex::new(HashMap::new()));
+
+static DEFAULT_CACHE: Lazy<RwLock<DnsCache>> = Lazy::new(Default::default);
+
+/// Options of a resolver configured by the DNS settings of a monitor.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct Policy {
+  cache: DnsCache,
+  timeout: Option<Duration>,
+  attempts: Option<usize>,
+}
+
+/// Servers a resolver sends queries to.
+#[derive(Clone, PartialEq, Eq, Hash)]
+enum Upstream {
+  /// The name servers of the system configuration.
+  System,
+
+  /// Plain DNS over UDP and TCP.
+  Plain(Vec<SocketAddr>),
+
+  /// DNS over TLS, with the name the servers' certificates are verified for.
+  Tls(Vec<SocketAddr>, String),
+
+  /// DNS over HTTPS, by the endpoint URL.
+  Https(String),
+}
+
+/// Sets the cache policy of resolvers for checks that don't configure one.
+pub fn set_default_cache(cache: DnsCache) {
+  *DEFAULT_CACHE.write().expect("default cache lock") = cache;
+}
+
+/// Returns the policy configured by the DNS settings. Unset options are left
+/// to the defaults.
+pub fn policy(dns: Option<&DnsConfig>) -> Policy {
+  let cache = dns
+    .and_then(|dns| dns.cache)
+    .unwrap_or_else(|| *DEFAULT_CACHE.read().expect("default cache lock"));
+
+  Policy {
+    cache,
+    timeout: dns
+      .and_then(|dns| dns.timeout_ms)
+      .map(Duration::from_millis),
+    attempts: dns.and_then(|dns| dns.attempts),
+  }
+}
+
+/// Returns the resolver built from the system configuration. If the
+/// configuration can't be read, it's read again on the next call.
+pub fn system(policy: Policy) -> Result<Arc<TokioAsyncResolver>, ResolveError> {
+  let key = (Upstream::System, policy);
+
+  if let Some(resolver) = RESOLVERS.lock().expect("resolvers lock").get(&key) {
+    return Ok(Arc::clone(resolver));
+  }
+
+  let (config, opts) = system_conf::read_system_conf()?;
+
+  Ok(cached(key, || (config, opts)))
+}
+
+/// Returns a resolver that sends queries to the given name servers.
+pub fn with_nameservers(nameservers: &[SocketAddr], policy: Policy) -> Arc<TokioAsyncResolver> {
+  custom((Upstream::Plain(nameservers.to_vec()), policy), || {
+    nameservers
+      .iter()
+      .flat_map(|address| {
+        [Protocol::Udp, Protocol::Tcp].map(|protocol| NameServerConfig::new(*address, protocol))
+      })
+      .collect()
+  })
+}
+
+/// Returns a resolver for the name servers of the DNS settings, if any are
+/// configured. Queries are sent over TLS if the settings have a
+/// [`tls_name`](DnsConfig#structfield.tls_name).
+pub fn with_config(dns: &DnsConfig) -> Option<Arc<TokioAsyncResolver>> {
+  if dns.nameservers.is_empty() {
+    return None;
+  }
+
+  let policy = policy(Some(dns));
+
+  let Some(tls_name) = &dns.tls_name else {
+    return Some(with_nameservers(&dns.nameservers, policy));
+  };
+
+  let upstream = Upstream::Tls(dns.nameservers.clone(), tls_name.clone());
+
+  Some(custom((upstream, policy), || {
+    dns
+      .nameservers
+      .iter()
+      .map(|address| NameServerConfig {
+        tls_dns_name: Some(tls_name.clone()),
+        ..NameServerConfig::new(*address, Protocol::Tls)
+      })
+      .collect()
+  }))
+}
+
+/// Returns a resolver that sends queries to a DNS-over-HTTPS endpoint. The
+/// host of the endpoint is resolved by the [system] resolver once.
+pub async fn with_doh(url: &str, policy: Policy) -> Result<Arc<TokioAsyncResolver>, ResolveError> {
+  let key = (Upstream::Https(url.into()), policy);
+
+  if let Some(resolver) = RESOLVERS.lock().expect("resolvers lock").get(&key) {
+    return Ok(Arc::clone(resolver));
+  }
+
+  let (host, port) = parse_doh_url(url)?;
+  let addresses = system(policy)?.lookup_ip(host.as_str()).await?;
+
+  Ok(custom(key, || {
+    addresses
+      .iter()
+      .map(|ip| NameServerConfig {
+        tls_dns_name: Some(host.clone()),
+        ..NameServerConfig::new(SocketAddr::new(ip, port), Protocol::Https)
+      })
+      .collect()
+  }))
+}
+
+fn custom(
+  key: Key,
+  nameservers: impl FnOnce() -> Vec<NameServerConfig>,
+) -> Arc<TokioAsyncResolver> {
+  cached(key, || {
+    (
+      ResolverConfig::from_parts(None, vec![], nameservers()),
+      ResolverOpts::default(),
+    )
+  })
+}
+
+fn cached(
+  key: Key,
+  config: impl FnOnce() -> (ResolverConfig, ResolverOpts),
+) -> Arc<TokioAsyncResolver> {
+  let mut resolvers = RESOLVERS.lock().expect("resolvers lock");
+  let policy = key.1;
+
+  let resolver = resolvers.entry(key).or_insert_with(|| {
+    let (config, mut opts) = config();
+    configure(&mut opts, policy);
+
+    Arc::new(TokioAsyncResolver::tokio(config, opts))
+  });
+
+  Arc::clone(resolver)
+}
+
+/// Splits a DNS-over-HTTPS URL into the host and the port. Only the standard
+/// `/dns-query` endpoint is supported.
+fn parse_doh_url(url: &str) -> Result<(String, u16), ResolveError> {
+  let invalid = || ResolveError::from(format!("Unsupported DNS-over-HTTPS URL: {}", url));
+
+  let rest = url.strip_prefix("https://").ok_or_else(invalid)?;
+  let (authority, path) = rest.split_once('/').unwrap_or((rest, ""));
+
+  if !matches!(path, "" | "dns-query") {
+    return Err(invalid());
+  }
+
+  let (host, port) = match authority.strip_prefix('[') {
+    Some(authority) => {
+      let (host, port) = authority.split_once(']').ok_or_else(invalid)?;
+      (host, port.strip_prefix(':'))
+    }
+    None => match authority.split_once(':') {
+      Some((host, port)) => (host, Some(port)),
+      None => (authority, None),
+    },
+  };
+
+  let port = match port {
+    Some(port) => port.parse().map_err(|_| invalid())?,
+    None => HTTPS_PORT,
+  };
+
+  Ok((host.into(), port))
+}
+
+/// Addresses of both families are looked up, so collectors can choose between
+/// them. Unless caching is enabled, every measurement performs a real lookup.
+fn configure(
+  opts: &mut ResolverOpts,
+  Policy {
+    cache,
+    timeout,
+    attempts,
+  }: Policy,
+) {
+  opts.ip_strategy = LookupIpStrategy::Ipv4AndIpv6;
+  opts.cache_size = cache.size;
+
+  if let Some(timeout) = timeout {
+    opts.timeout = timeout;
+  }
+
+  if let Some(attempts) = attempts {
+    opts.attempts = attempts;
+  }
+
+  let (min_ttl, max_ttl) = if cache.size == 0 {
+    (Some(Duration::ZERO), Some(Duration::ZERO))
+  } else {
+    (
+      cache.min_ttl.map(Duration::from_secs),
+      cache.max_ttl.map(Duration::from_secs),
+    )
+  };
+
+  opts.positive_min_ttl = min_ttl;
+  opts.positive_max_ttl = max_ttl;
+  opts.negative_min_ttl = min_ttl;
+  opts.negative_max_ttl = max_ttl;
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn cache_options() {
+    let mut opts = ResolverOpts::default();
+    configure(&mut opts, Policy::default());
+
+    assert_eq!(opts.cache_size, 0);
+    assert_eq!(
+      opts.positive_max_ttl,
+      Some(Duration::ZERO),
+      "lookups aren't cached by default"
+    );
+
+    configure(&mut opts, Policy {
+      cache: DnsCache {
+        size: 128,
+        min_ttl: Some(30),
+        max_ttl: None,
+      },
+      ..Default::default()
+    });
+
+    assert_eq!(opts.cache_size, 128);
+    assert_eq!(opts.positive_min_ttl, Some(Duration::from_secs(30)));
+    assert_eq!(opts.positive_max_ttl, None, "record TTLs are respected");
+  }
+
+  #[test]
+  fn lookup_options() {
+    let dns = DnsConfig {
+      timeout_ms: Some(500),
+      attempts: Some(1),
+      ..Default::default()
+    };
+
+    let mut opts = ResolverOpts::default();
+    configure(&mut opts, policy(Some(&dns)));
+
+    assert_eq!(opts.timeout, Duration::from_millis(500));
+    assert_eq!(opts.attempts, 1);
+
+    let mut opts = ResolverOpts::default();
+    configure(&mut opts, policy(None));
+
+    assert_eq!(
+      (opts.timeout, opts.attempts),
+      (
+        ResolverOpts::default().timeout,
+        ResolverOpts::default().attempts
+      ),
+      "defaults are kept"
+    );
+  }
+
+  #[tokio::test]
+  async fn resolvers_by_policy() {
+    let nameservers = [SocketAddr::from(([192, 0, 2, 53], 53))];
+    let policy = Policy {
+      cache: DnsCache {
+        size: 16,
+        ..Default::default()
+      },
+      ..Default::default()
+    };
+
+    let uncached = with_nameservers(&nameservers, Policy::default());
+    let cached = with_nameservers(&nameservers, policy);
+
+    assert!(
+      !Arc::ptr_eq(&uncached, &cached),
+      "policies use own resolvers"
+    );
+    assert!(Arc::ptr_eq(
+      &cached,
+      &with_nameservers(&nameservers, policy)
+    ));
+  }
+
+  #[test]
+  fn doh_url() {
+    assert_eq!(
+      parse_doh_url("https://dns.google/dns-query").unwrap(),
+      (String::from("dns.google"), 443)
+    );
+    assert_eq!(
+      parse_doh_url("https://[2606:4700::1111]:8443/dns-query").unwrap(),
+      (String::from("2606:4700::1111"), 8443)
+    );
+    assert_eq!(
+      parse_doh_url("https://1.1.1.1").unwrap(),
+      (String::from("1.1.1.1"), 443)
+    );
+    assert!(
+      parse_doh_url("http://dns.google/dns-query").is_err(),
+      "DNS-over-HTTPS requires HTTPS"
+    );
+    assert!(
+      parse_doh_url("https://dns.example/resolve").is_err(),
+      "custom endpoints aren't supported"
+    );
+  }
+}
@@ -0,0 +1,61 @@
+//! TCP connect probes, used to measure the round-trip time to hosts that
+//! filter ICMP.
+
+use std::io;
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+
+use socket2::{Domain, Protocol, Socket, Type};
+use tokio::net::TcpSocket;
+use tokio::time;
+
+use crate::monitor::collectors::icmp::Options;
+
+/// Connects to the `target` and returns the time the handshake took, or
+/// `None` if it didn't complete within the `timeout`.
+///
+/// A refused connection counts as a reply, since the host answered it.
+pub async fn connect(
+  target: SocketAddr,
+  options: Options,
+  timeout: Duration,
+) -> io::Result<Option<Duration>> {
+  let socket = Socket::new(
+    Domain::for_address(target),
+    Type::STREAM,
+    Some(Protocol::TCP),
+  )?;
+  options.apply(&socket, target.ip())?;
+  socket.set_nonblocking(true)?;
+
+  let socket = TcpSocket::from_std_stream(socket.into());
+  let start = Instant::now();
+
+  match time::timeout(timeout, socket.connect(target)).await {
+    Ok(Ok(_)) => Ok(Some(start.elapsed())),
+    Ok(Err(error)) if error.kind() == io::ErrorKind::ConnectionRefused => Ok(Some(start.elapsed())),
+    Ok(Err(error)) => Err(error),
+    Err(_) => Ok(None),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::net::TcpListener;
+
+  use super::*;
+
+  #[tokio::test]
+  async fn connect_to_listener() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let target = listener.local_addr().unwrap();
+
+    let rtt = connect(target, Options::default(), Duration::from_secs(1)).await;
+    assert!(matches!(rtt, Ok(Some(_))), "connection is accepted");
+
+    drop(listener);
+
+    let rtt = connect(target, Options::default(), Duration::from_secs(1)).await;
+    assert!(matches!(rtt, Ok(Some(_))), "connection is refused");
+  }
+}
@@ -0,0 +1,204 @@
+//! Extraction of the server certificate from TLS handshake messages.
+//!
+//! curl passes decrypted handshake messages to the debug callback when
+//! verbose mode is enabled, which gives access to the certificate chain
+//! without `CURLINFO_CERTINFO` (it isn't exposed by the `curl`
+//! crate).
+
+use std::net::IpAddr;
+
+use openssl::asn1::Asn1Time;
+use openssl::x509::{X509, X509NameRef};
+use time::OffsetDateTime;
+
+use crate::monitor::models::Certificate;
+
+/// Handshake message type of the `Certificate` message.
+const CERTIFICATE: u8 = 11;
+
+/// Parses a TLS handshake message and returns the details of the leaf
+/// certificate if it's a `Certificate` message.
+pub fn parse_handshake(message: &[u8]) -> Option<Certificate> {
+  let (&kind, rest) = message.split_first()?;
+
+  if kind != CERTIFICATE {
+    return None;
+  }
+
+  let body = rest.get(3..3 + read_u24(rest)?)?;
+  let x509 = X509::from_der(leaf_certificate(body)?).ok()?;
+
+  certificate(&x509)
+}
+
+/// Returns the DER encoding of the first certificate in the list. Handles
+/// both TLS 1.2 layout and TLS 1.3 one, where the list is prefixed with
+/// a request context.
+fn leaf_certificate(body: &[u8]) -> Option<&[u8]> {
+  let list = if read_u24(body)? + 3 == body.len() {
+    &body[3..]
+  } else {
+    let context = *body.first()? as usize;
+    let rest = body.get(1 + context..)?;
+
+    if read_u24(rest)? + 3 != rest.len() {
+      return None;
+    }
+
+    &rest[3..]
+  };
+
+  list.get(3..3 + read_u24(list)?)
+}
+
+fn read_u24(data: &[u8]) -> Option<usize> {
+  let bytes = data.get(..3)?;
+
+  Some((bytes[0] as usize) << 16 | (bytes[1] as usize) << 8 | bytes[2] as usize)
+}
+
+fn certificate(x509: &X509) -> Option<Certificate> {
+  let expires_in = Asn1Time::from_unix(0).ok()?.diff(x509.not_after()).ok()?;
+  let not_after =
+    OffsetDateTime::from_unix_timestamp(expires_in.days as i64 * 86_400 + expires_in.secs as i64)
+      .ok()?;
+
+  let subject_alt_names = x509
+    .subject_alt_names()
+    .map(|names| {
+      names
+        .iter()
+        .filter_map(|name| {
+          name.dnsname().map(String::from).or_else(|| {
+            let ip = match name.ipaddress()? {
+              &[a, b, c, d] => IpAddr::from([a, b, c, d]),
+              bytes => IpAddr::from(<[u8; 16]>::try_from(bytes).ok()?),
+            };
+
+            Some(ip.to_string())
+          })
+        })
+        .collect()
+    })
+    .unwrap_or_default();
+
+  Some(Certificate {
+    subject: name_to_string(x509.subject_name()),
+    issuer: name_to_string(x509.issuer_name()),
+    not_after,
+    subject_alt_names,
+  })
+}
+
+fn name_to_string(name: &X509NameRef) -> String {
+  name
+    .entries()
+    .map(|entry| {
+      let key = entry.object().nid().short_name().unwrap_or("?");
+      let value = entry
+        .data()
+        .as_utf8()
+        .map(|value| value.to_string())
+        .unwrap_or_default();
+
+      format!("{}={}", key, value)
+    })
+    .collect::<Vec<_>>()
+    .join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+  use openssl::hash::MessageDigest;
+  use openssl::pkey::PKey;
+  use openssl::rsa::Rsa;
+  use openssl::x509::X509NameBuilder;
+  use openssl::x509::extension::SubjectAlternativeName;
+
+  use super::*;
+
+  fn self_signed() -> Vec<u8> {
+    let key = PKey::from_rsa(Rsa::generate(2048).unwrap()).unwrap();
+
+    let mut name = X509NameBuilder::new().unwrap();
+    name.append_entry_by_text("CN", "localhost").unwrap();
+    let name = name.build();
+
+    let mut builder = X509::builder().unwrap();
+    builder.set_version(2).unwrap();
+    builder.set_subject_name(&name).unwrap();
+    builder.set_issuer_name(&name).unwrap();
+    builder.set_pubkey(&key).unwrap();
+    builder
+      .set_not_before(&Asn1Time::from_unix(0).unwrap())
+      .unwrap();
+    builder
+      .set_not_after(&Asn1Time::from_unix(2_000_000_000).unwrap())
+      .unwrap();
+
+    let san = SubjectAlternativeName::new()
+      .dns("localhost")
+      .ip("127.0.0.1")
+      .build(&builder.x509v3_context(None, None))
+      .unwrap();
+    builder.append_extension(san).unwrap();
+    builder.sign(&key, MessageDigest::sha256()).unwrap();
+
+    builder.build().to_der().unwrap()
+  }
+
+  fn u24(value: usize) -> [u8; 3] {
+    [(value >> 16) as u8, (value >> 8) as u8, value as u8]
+  }
+
+  fn handshake(body: Vec<u8>) -> Vec<u8> {
+    let mut message = vec![CERTIFICATE];
+    message.extend(u24(body.len()));
+    message.extend(body);
+    message
+  }
+
+  #[test]
+  fn parse_tls12_certificate() {
+    let der = self_signed();
+
+    let mut entry = u24(der.len()).to_vec();
+    entry.extend(&der);
+
+    let mut body = u24(entry.len()).to_vec();
+    body.extend(entry);
+
+    let certificate = parse_handshake(&handshake(body)).expect("certificate is parsed");
+
+    assert_eq!(certificate.subject, "CN=localhost");
+    assert_eq!(certificate.issuer, "CN=localhost");
+    assert_eq!(certificate.not_after.unix_timestamp(), 2_000_000_000);
+    assert_eq!(certificate.subject_alt_names, ["localhost", "127.0.0.1"]);
+  }
+
+  #[test]
+  fn parse_tls13_certificate() {
+    let der = self_signed();
+
+    let mut entry = u24(der.len()).to_vec();
+    entry.extend(&der);
+    entry.extend([0, 0]);
+
+    let mut body = vec![0];
+    body.extend(u24(entry.len()));
+    body.extend(entry);
+
+    let certificate = parse_handshake(&handshake(body)).expect("certificate is parsed");
+
+    assert_eq!(certificate.subject, "CN=localhost");
+  }
+
+  #[test]
+  fn ignore_other_messages() {
+    assert!(
+      parse_handshake(&[2, 0, 0, 1, 0]).is_none(),
+      "not a certificate"
+    );
+    assert!(parse_handshake(&[CERTIFICATE, 0, 0]).is_none(), "malformed");
+  }
+}
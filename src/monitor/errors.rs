@@ -1,6 +1,14 @@
 //! A module describing monitor measurement errors.
 
+use std::fmt;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
 use thiserror::Error;
+use trust_dns_resolver::error::{ResolveError, ResolveErrorKind};
+use trust_dns_resolver::proto::op::ResponseCode;
+
+use crate::monitor::models::{Header, IpFamily};
 
 /// Represents all possible errors that can occur during monitoring.
 ///
@@ -14,6 +22,441 @@ pub enum CollectorError {
   /// An error occurred during an HTTP measurement.
   #[error("HTTP error: {0}")]
   Http(#[from] HttpError),
+
+  /// The measurement didn't complete before its
+  /// [deadline](crate::monitor::models::Monitor::measure_with_deadline).
+  #[error("Deadline of {0:?} exceeded")]
+  DeadlineExceeded(Duration),
+
+  /// An error restored from its [report](ErrorReport), e.g. of a deserialized
+  /// measurement.
+  #[error("{}", .0.message)]
+  Reported(ErrorReport),
+}
+
+impl CollectorError {
+  /// Returns the kind of the error.
+  pub fn kind(&self) -> ErrorKind {
+    match self {
+      CollectorError::Ping(error) => match error {
+        PingError::Dns(_) => ErrorKind::Dns,
+        PingError::NoReply { .. } => ErrorKind::NoReply,
+        PingError::TtlExceeded { .. } => ErrorKind::TtlExceeded,
+        PingError::PtrMismatch { .. } => ErrorKind::PtrMismatch,
+        PingError::Unreachable => ErrorKind::Unreachable,
+        PingError::Socket(_) => ErrorKind::Socket,
+        PingError::Task(_) | PingError::Runtime(_) => ErrorKind::Task,
+      },
+      CollectorError::Http(error) => match error {
+        HttpError::StatusMismatch { .. } => ErrorKind::StatusMismatch,
+        HttpError::KeywordNotFound { .. } => ErrorKind::KeywordNotFound,
+        HttpError::ElementNotFound { .. } => ErrorKind::ElementNotFound,
+        HttpError::InvalidSelector { .. } => ErrorKind::InvalidSelector,
+        HttpError::DigestMismatch { .. } => ErrorKind::DigestMismatch,
+        HttpError::Timeout { .. } => ErrorKind::Timeout,
+        HttpError::IpFamilyMismatch { .. } => ErrorKind::IpFamilyMismatch,
+        HttpError::Dns(_) => ErrorKind::Dns,
+        HttpError::Client(_) => ErrorKind::Client,
+        HttpError::ClientUnavailable => ErrorKind::ClientUnavailable,
+        HttpError::Unknown(_) => ErrorKind::Unknown,
+      },
+      CollectorError::DeadlineExceeded(_) => ErrorKind::Deadline,
+      CollectorError::Reported(report) => report.kind,
+    }
+  }
+
+  /// Returns the stable code of the error, refined by the cause of DNS and
+  /// `curl` errors.
+  pub fn code(&self) -> ErrorCode {
+    match self {
+      CollectorError::Ping(PingError::Dns(error)) | CollectorError::Http(HttpError::Dns(error)) => {
+        ErrorCode::from(error)
+      }
+      CollectorError::Http(HttpError::Unknown(error)) => ErrorCode::from(error),
+      CollectorError::Reported(report) => report.code,
+      error => ErrorCode::from(error.kind()),
+    }
+  }
+
+  /// Returns a serializable [report](ErrorReport) of the error.
+  pub fn report(&self) -> ErrorReport {
+    match self {
+      CollectorError::Reported(report) => report.clone(),
+      error => ErrorReport {
+        kind: error.kind(),
+        code: error.code(),
+        message: error.to_string(),
+      },
+    }
+  }
+}
+
+/// The sources of Ping and HTTP errors can't be cloned, so a clone is the
+/// [report](ErrorReport) of the error.
+impl Clone for CollectorError {
+  fn clone(&self) -> Self {
+    CollectorError::Reported(self.report())
+  }
+}
+
+/// A serializable representation of a [CollectorError], with its kind, code
+/// and message. The sources of the error aren't kept.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ErrorReport {
+  /// The kind of the error.
+  pub kind: ErrorKind,
+
+  /// The stable code of the error.
+  pub code: ErrorCode,
+
+  /// The message of the error, as it's displayed.
+  pub message: String,
+}
+
+/// The kind of a [CollectorError], named after the variant of the Ping or HTTP
+/// error.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorKind {
+  /// DNS resolution failed.
+  Dns,
+
+  /// The host didn't reply to the echo requests.
+  NoReply,
+
+  /// The time to live of the echo requests expired.
+  TtlExceeded,
+
+  /// The PTR record doesn't match the expected one.
+  PtrMismatch,
+
+  /// The host is unreachable.
+  Unreachable,
+
+  /// A socket couldn't be opened.
+  Socket,
+
+  /// The task performing the requests failed.
+  Task,
+
+  /// The status code didn't match the expected one.
+  StatusMismatch,
+
+  /// The keyword wasn't found in the response body.
+  KeywordNotFound,
+
+  /// No element contains the expected text.
+  ElementNotFound,
+
+  /// The CSS selector can't be parsed.
+  InvalidSelector,
+
+  /// The digest of the response body didn't match.
+  DigestMismatch,
+
+  /// The request timed out.
+  Timeout,
+
+  /// The connection was established over an unexpected address family.
+  IpFamilyMismatch,
+
+  /// The shared `HTTP` client failed.
+  Client,
+
+  /// The shared `HTTP` client isn't running.
+  ClientUnavailable,
+
+  /// Any other `HTTP` error.
+  Unknown,
+
+  /// The measurement didn't complete before its deadline.
+  Deadline,
+}
+
+impl ErrorKind {
+  /// Returns the name of the kind, as it's serialized.
+  pub fn as_str(&self) -> &'static str {
+    match self {
+      ErrorKind::Dns => "dns",
+      ErrorKind::NoReply => "no_reply",
+      ErrorKind::TtlExceeded => "ttl_exceeded",
+      ErrorKind::PtrMismatch => "ptr_mismatch",
+      ErrorKind::Unreachable => "unreachable",
+      ErrorKind::Socket => "socket",
+      ErrorKind::Task => "task",
+      ErrorKind::StatusMismatch => "status_mismatch",
+      ErrorKind::KeywordNotFound => "keyword_not_found",
+      ErrorKind::ElementNotFound => "element_not_found",
+      ErrorKind::InvalidSelector => "invalid_selector",
+      ErrorKind::DigestMismatch => "digest_mismatch",
+      ErrorKind::Timeout => "timeout",
+      ErrorKind::IpFamilyMismatch => "ip_family_mismatch",
+      ErrorKind::Client => "client",
+      ErrorKind::ClientUnavailable => "client_unavailable",
+      ErrorKind::Unknown => "unknown",
+      ErrorKind::Deadline => "deadline",
+    }
+  }
+}
+
+/// A stable, machine-readable code of a [CollectorError], for branching on
+/// the class of an error without parsing its message.
+///
+/// Unlike the [ErrorKind], which follows the variants of the errors, codes
+/// tell apart the causes of DNS and `curl` errors, e.g. a domain that doesn't
+/// exist or an invalid certificate. Codes are serialized in
+/// `SCREAMING_SNAKE_CASE`, and are never renamed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum ErrorCode {
+  /// The domain doesn't exist.
+  DnsNxdomain,
+
+  /// The domain has no records of the queried type.
+  DnsNoRecords,
+
+  /// The name servers didn't answer in time.
+  DnsTimeout,
+
+  /// DNS resolution failed otherwise.
+  DnsError,
+
+  /// The host didn't reply to the echo requests.
+  NoReply,
+
+  /// The time to live of the echo requests expired.
+  TtlExceeded,
+
+  /// The PTR record doesn't match the expected one.
+  PtrMismatch,
+
+  /// The host is unreachable.
+  HostUnreachable,
+
+  /// The connection couldn't be established, e.g. it was refused.
+  ConnectionFailed,
+
+  /// The connection failed while sending or receiving, or no response was
+  /// received.
+  ConnectionError,
+
+  /// The check timed out.
+  Timeout,
+
+  /// The TLS handshake failed.
+  TlsError,
+
+  /// The server certificate couldn't be verified.
+  CertificateError,
+
+  /// The request was redirected too many times.
+  TooManyRedirects,
+
+  /// The response violates the protocol, e.g. an `HTTP/2` stream error.
+  ProtocolError,
+
+  /// The status code didn't match the expected one.
+  StatusMismatch,
+
+  /// The keyword wasn't found in the response body.
+  KeywordNotFound,
+
+  /// No element contains the expected text.
+  ElementNotFound,
+
+  /// The CSS selector can't be parsed.
+  InvalidSelector,
+
+  /// The digest of the response body didn't match.
+  DigestMismatch,
+
+  /// The connection was established over an unexpected address family.
+  IpFamilyMismatch,
+
+  /// The request can't be sent as configured, e.g. its URL is malformed.
+  InvalidRequest,
+
+  /// A socket couldn't be opened.
+  SocketError,
+
+  /// The collector itself failed, e.g. its task or the shared `HTTP` client.
+  Internal,
+
+  /// Any other error.
+  Unknown,
+}
+
+impl ErrorCode {
+  /// Returns whether the error is likely transient, i.e. a check failing
+  /// with it may succeed if it's performed again, like a timeout or a failed
+  /// connection, unlike e.g. a missing keyword.
+  pub fn is_transient(&self) -> bool {
+    matches!(
+      self,
+      ErrorCode::DnsTimeout
+        | ErrorCode::DnsError
+        | ErrorCode::NoReply
+        | ErrorCode::HostUnreachable
+        | ErrorCode::ConnectionFailed
+        | ErrorCode::ConnectionError
+        | ErrorCode::Timeout
+        | ErrorCode::SocketError
+    )
+  }
+
+  /// Returns the code, as it's serialized.
+  pub fn as_str(&self) -> &'static str {
+    match self {
+      ErrorCode::DnsNxdomain => "DNS_NXDOMAIN",
+      ErrorCode::DnsNoRecords => "DNS_NO_RECORDS",
+      ErrorCode::DnsTimeout => "DNS_TIMEOUT",
+      ErrorCode::DnsError => "DNS_ERROR",
+      ErrorCode::NoReply => "NO_REPLY",
+      ErrorCode::TtlExceeded => "TTL_EXCEEDED",
+      ErrorCode::PtrMismatch => "PTR_MISMATCH",
+      ErrorCode::HostUnreachable => "HOST_UNREACHABLE",
+      ErrorCode::ConnectionFailed => "CONNECTION_FAILED",
+      ErrorCode::ConnectionError => "CONNECTION_ERROR",
+      ErrorCode::Timeout => "TIMEOUT",
+      ErrorCode::TlsError => "TLS_ERROR",
+      ErrorCode::CertificateError => "CERTIFICATE_ERROR",
+      ErrorCode::TooManyRedirects => "TOO_MANY_REDIRECTS",
+      ErrorCode::ProtocolError => "PROTOCOL_ERROR",
+      ErrorCode::StatusMismatch => "STATUS_MISMATCH",
+      ErrorCode::KeywordNotFound => "KEYWORD_NOT_FOUND",
+      ErrorCode::ElementNotFound => "ELEMENT_NOT_FOUND",
+      ErrorCode::InvalidSelector => "INVALID_SELECTOR",
+      ErrorCode::DigestMismatch => "DIGEST_MISMATCH",
+      ErrorCode::IpFamilyMismatch => "IP_FAMILY_MISMATCH",
+      ErrorCode::InvalidRequest => "INVALID_REQUEST",
+      ErrorCode::SocketError => "SOCKET_ERROR",
+      ErrorCode::Internal => "INTERNAL",
+      ErrorCode::Unknown => "UNKNOWN",
+    }
+  }
+}
+
+impl fmt::Display for ErrorCode {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.write_str(self.as_str())
+  }
+}
+
+impl From<&CollectorError> for ErrorCode {
+  fn from(error: &CollectorError) -> Self {
+    error.code()
+  }
+}
+
+/// The code of a kind, without the cause of the error.
+impl From<ErrorKind> for ErrorCode {
+  fn from(kind: ErrorKind) -> Self {
+    match kind {
+      ErrorKind::Dns => ErrorCode::DnsError,
+      ErrorKind::NoReply => ErrorCode::NoReply,
+      ErrorKind::TtlExceeded => ErrorCode::TtlExceeded,
+      ErrorKind::PtrMismatch => ErrorCode::PtrMismatch,
+      ErrorKind::Unreachable => ErrorCode::HostUnreachable,
+      ErrorKind::Socket => ErrorCode::SocketError,
+      ErrorKind::StatusMismatch => ErrorCode::StatusMismatch,
+      ErrorKind::KeywordNotFound => ErrorCode::KeywordNotFound,
+      ErrorKind::ElementNotFound => ErrorCode::ElementNotFound,
+      ErrorKind::InvalidSelector => ErrorCode::InvalidSelector,
+      ErrorKind::DigestMismatch => ErrorCode::DigestMismatch,
+      ErrorKind::Timeout | ErrorKind::Deadline => ErrorCode::Timeout,
+      ErrorKind::IpFamilyMismatch => ErrorCode::IpFamilyMismatch,
+      ErrorKind::Task | ErrorKind::Client | ErrorKind::ClientUnavailable => ErrorCode::Internal,
+      ErrorKind::Unknown => ErrorCode::Unknown,
+    }
+  }
+}
+
+impl From<&ResolveError> for ErrorCode {
+  fn from(error: &ResolveError) -> Self {
+    match error.kind() {
+      ResolveErrorKind::NoRecordsFound { response_code, .. } => {
+        if *response_code == ResponseCode::NXDomain {
+          ErrorCode::DnsNxdomain
+        } else {
+          ErrorCode::DnsNoRecords
+        }
+      }
+      ResolveErrorKind::Timeout => ErrorCode::DnsTimeout,
+      _ => ErrorCode::DnsError,
+    }
+  }
+}
+
+impl From<&curl::Error> for ErrorCode {
+  fn from(error: &curl::Error) -> Self {
+    if error.is_couldnt_resolve_host() || error.is_couldnt_resolve_proxy() {
+      ErrorCode::DnsError
+    } else if error.is_couldnt_connect() || error.is_interface_failed() {
+      ErrorCode::ConnectionFailed
+    } else if error.is_send_error() || error.is_recv_error() || error.is_got_nothing() {
+      ErrorCode::ConnectionError
+    } else if error.is_operation_timedout() {
+      ErrorCode::Timeout
+    } else if error.is_peer_failed_verification()
+      || error.is_ssl_cacert()
+      || error.is_ssl_cacert_badfile()
+      || error.is_ssl_certproblem()
+      || error.is_ssl_crl_badfile()
+      || error.is_ssl_issuer_error()
+    {
+      ErrorCode::CertificateError
+    } else if error.is_ssl_connect_error()
+      || error.is_ssl_cipher()
+      || error.is_ssl_engine_notfound()
+      || error.is_ssl_engine_setfailed()
+      || error.is_ssl_engine_initfailed()
+      || error.is_ssl_shutdown_failed()
+      || error.is_use_ssl_failed()
+    {
+      ErrorCode::TlsError
+    } else if error.is_too_many_redirects() {
+      ErrorCode::TooManyRedirects
+    } else if error.is_http2_error()
+      || error.is_http2_stream_error()
+      || error.is_bad_content_encoding()
+      || error.is_chunk_failed()
+      || error.is_partial_file()
+    {
+      ErrorCode::ProtocolError
+    } else if error.is_url_malformed()
+      || error.is_unsupported_protocol()
+      || error.is_unknown_option()
+      || error.is_bad_function_argument()
+    {
+      ErrorCode::InvalidRequest
+    } else {
+      ErrorCode::Unknown
+    }
+  }
+}
+
+/// Serializes an optional [CollectorError] as its [report](ErrorReport), and
+/// deserializes it as a [CollectorError::Reported].
+pub(crate) mod report {
+  use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+  use super::{CollectorError, ErrorReport};
+
+  pub fn serialize<S: Serializer>(
+    error: &Option<CollectorError>,
+    serializer: S,
+  ) -> Result<S::Ok, S::Error> {
+    error
+      .as_ref()
+      .map(CollectorError::report)
+      .serialize(serializer)
+  }
+
+  pub fn deserialize<'de, D: Deserializer<'de>>(
+    deserializer: D,
+  ) -> Result<Option<CollectorError>, D::Error> {
+    Ok(Option::<ErrorReport>::deserialize(deserializer)?.map(CollectorError::Reported))
+  }
 }
 
 /// Errors that can occur during a Ping measurement.
@@ -27,23 +470,203 @@ pub enum PingError {
   #[error("No reply from {addr:?} timeout")]
   NoReply { addr: String },
 
+  /// The time to live of the echo requests expired before reaching the
+  /// target.
+  #[error("Time to live exceeded at {addr:?}")]
+  TtlExceeded { addr: String },
+
+  /// The PTR record of the pinged address is missing or doesn't match the
+  /// expected pattern.
+  #[error("PTR record {actual:?} doesn't match {expected:?}")]
+  PtrMismatch {
+    expected: String,
+    actual: Option<String>,
+  },
+
   /// The target host is unreachable.
   #[error("The target host is unreachable")]
   Unreachable,
+
+  /// The socket for sending requests couldn't be opened, bound to the
+  /// source address or send them (e.g., it's not permitted, or file
+  /// descriptors are exhausted).
+  #[error("Socket error: {0}")]
+  Socket(#[source] std::io::Error),
+
+  /// The task performing the requests panicked or was cancelled.
+  #[error("Ping task failed: {0}")]
+  Task(#[from] tokio::task::JoinError),
+
+  /// The runtime driving the sockets couldn't be started.
+  #[error("Failed to start the ping runtime: {0}")]
+  Runtime(#[source] std::io::Error),
 }
 
 /// Errors that can occur during an HTTP measurement.
 #[derive(Error, Debug)]
 pub enum HttpError {
   /// The HTTP response status code did not match the expected code.
-  #[error("Unexpected status code. Expected: {expected:?}, actual: {actual:?}")]
-  StatusMismatch { expected: u16, actual: u16 },
+  #[error(
+    "Unexpected status code. Expected: {expected:?}, actual: {actual:?}{}",
+    ResponseSnippet::suffix(snippet)
+  )]
+  StatusMismatch {
+    expected: u16,
+    actual: u16,
+    snippet: Option<ResponseSnippet>,
+  },
 
   /// The specified keyword was not found in the response body.
-  #[error("Keyword '{keyword:?}' not found in response body")]
-  KeywordNotFound { keyword: String },
+  #[error(
+    "Keyword '{keyword:?}' not found in response body{}",
+    ResponseSnippet::suffix(snippet)
+  )]
+  KeywordNotFound {
+    keyword: String,
+    snippet: Option<ResponseSnippet>,
+  },
+
+  /// No element matching the selector contains the expected text.
+  #[error(
+    "No element matching '{selector}' contains {text:?}{}",
+    ResponseSnippet::suffix(snippet)
+  )]
+  ElementNotFound {
+    selector: String,
+    text: String,
+    snippet: Option<ResponseSnippet>,
+  },
+
+  /// The CSS selector of the element assertion can't be parsed.
+  #[error("Invalid CSS selector '{selector}': {reason}")]
+  InvalidSelector { selector: String, reason: String },
+
+  /// The SHA-256 digest of the response body did not match the expected one.
+  #[error("Response body digest mismatch. Expected: {expected}, actual: {actual}")]
+  DigestMismatch { expected: String, actual: String },
+
+  /// The request didn't complete within one of the configured timeouts.
+  #[error("Request timed out during {phase}")]
+  Timeout { phase: TimeoutPhase },
+
+  /// The connection was established over an unexpected address family.
+  #[error("Connected over {actual:?}, expected {expected:?}")]
+  IpFamilyMismatch {
+    expected: IpFamily,
+    actual: Option<IpFamily>,
+  },
+
+  /// DNS resolution through the configured name servers failed.
+  #[error("DNS resolve error: {0}")]
+  Dns(#[from] trust_dns_resolver::error::ResolveError),
+
+  /// The shared `HTTP` client failed to drive the request.
+  #[error("HTTP client error: {0}")]
+  Client(#[from] curl::MultiError),
+
+  /// The shared `HTTP` client isn't running.
+  #[error("HTTP client is unavailable")]
+  ClientUnavailable,
 
   /// Any other unknown error that occurred during the HTTP request.
   #[error("Unknown error: {0}")]
   Unknown(#[from] curl::Error),
 }
+
+/// The phase of an `HTTP` request that exceeded its timeout.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimeoutPhase {
+  /// Resolving the host name.
+  Dns,
+
+  /// Establishing the TCP connection.
+  Connect,
+
+  /// Performing the TLS handshake.
+  Tls,
+
+  /// Sending the request and receiving the response.
+  Transfer,
+}
+
+impl fmt::Display for TimeoutPhase {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.write_str(match self {
+      TimeoutPhase::Dns => "DNS lookup",
+      TimeoutPhase::Connect => "connect",
+      TimeoutPhase::Tls => "TLS handshake",
+      TimeoutPhase::Transfer => "transfer",
+    })
+  }
+}
+
+/// A part of the `HTTP` response attached to a failed check, so the failure
+/// can be diagnosed without reproducing the request.
+#[derive(Debug, Default)]
+pub struct ResponseSnippet {
+  /// The first bytes of the response body (lossy UTF-8).
+  pub body: String,
+
+  /// Response headers selected by
+  /// [`error_snippet_headers`](crate::monitor::models::HttpConfig#structfield.error_snippet_headers).
+  pub headers: Vec<Header>,
+}
+
+impl ResponseSnippet {
+  fn suffix(snippet: &Option<ResponseSnippet>) -> String {
+    let Some(snippet) = snippet else {
+      return String::new();
+    };
+
+    let headers = snippet
+      .headers
+      .iter()
+      .map(|header| format!("{}: {}", header.name, header.value))
+      .collect::<Vec<_>>()
+      .join(", ");
+
+    format!(". Headers: [{}], body: {:?}", headers, snippet.body)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn codes() {
+    let curl = |code| CollectorError::Http(HttpError::Unknown(curl::Error::new(code)));
+
+    assert_eq!(curl(60).code(), ErrorCode::CertificateError);
+    assert_eq!(curl(35).code(), ErrorCode::TlsError);
+    assert_eq!(curl(7).code(), ErrorCode::ConnectionFailed);
+    assert_eq!(curl(52).code(), ErrorCode::ConnectionError);
+    assert_eq!(curl(47).code(), ErrorCode::TooManyRedirects);
+    assert_eq!(curl(92).code(), ErrorCode::ProtocolError);
+    assert_eq!(curl(3).code(), ErrorCode::InvalidRequest);
+
+    let mismatch = CollectorError::Http(HttpError::StatusMismatch {
+      expected: 200,
+      actual: 503,
+      snippet: None,
+    });
+
+    assert_eq!(mismatch.code(), ErrorCode::StatusMismatch);
+    assert_eq!(mismatch.code().to_string(), "STATUS_MISMATCH");
+    assert_eq!(mismatch.clone().code(), ErrorCode::StatusMismatch);
+    assert_eq!(ErrorCode::from(&mismatch), ErrorCode::StatusMismatch);
+  }
+
+  #[test]
+  fn reported_codes() {
+    let report = CollectorError::Http(HttpError::Unknown(curl::Error::new(60))).report();
+    let serialized = serde_json::to_value(&report).unwrap();
+
+    assert_eq!(serialized["kind"], "unknown");
+    assert_eq!(serialized["code"], "CERTIFICATE_ERROR");
+    assert_eq!(
+      serde_json::from_value::<ErrorReport>(serialized).unwrap(),
+      report
+    );
+  }
+}
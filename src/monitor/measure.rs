@@ -1,8 +1,58 @@
+use std::collections::HashMap;
+use std::sync::{Mutex, RwLock};
+use std::time::Duration;
+
+use once_cell::sync::Lazy;
 use time::OffsetDateTime;
+use tokio_util::sync::CancellationToken;
+use uuid::Uuid;
 
 use crate::monitor::collectors::{Http, Ping};
 use crate::monitor::errors::CollectorError;
-use crate::monitor::models::{Config, Data, Measurement, Monitor};
+use crate::monitor::models::{Agent, Config, Data, Measurement, Monitor};
+
+static AGENT: Lazy<RwLock<Option<Agent>>> = Lazy::new(Default::default);
+
+/// Sets the identity of the agent stamped on the measurements taken from now
+/// on, or clears it with `None`. It's unset by default.
+pub fn set_agent(agent: Option<Agent>) {
+  *AGENT.write().expect("agent lock") = agent;
+}
+
+/// Returns the identity of the agent, if it's set.
+pub fn agent() -> Option<Agent> {
+  AGENT.read().expect("agent lock").clone()
+}
+
+/// Last sequence numbers by monitor id.
+static SEQUENCES: Lazy<Mutex<HashMap<i64, u64>>> = Lazy::new(Default::default);
+
+/// Continues the sequence numbers of a monitor after `last`, e.g. the one of
+/// its latest stored measurement once the agent restarts. Otherwise, they
+/// start from 1 again, and a restart looks like a reset downstream.
+pub fn resume_sequence(monitor_id: i64, last: u64) {
+  let mut sequences = SEQUENCES.lock().expect("sequences lock");
+  let sequence = sequences.entry(monitor_id).or_default();
+
+  *sequence = (*sequence).max(last);
+}
+
+/// Drops the sequence numbers of a monitor, e.g. once it's removed, so they
+/// start from 1 again if it's added back.
+pub fn forget_sequence(monitor_id: i64) {
+  SEQUENCES
+    .lock()
+    .expect("sequences lock")
+    .remove(&monitor_id);
+}
+
+fn next_sequence(monitor_id: i64) -> u64 {
+  let mut sequences = SEQUENCES.lock().expect("sequences lock");
+  let sequence = sequences.entry(monitor_id).or_default();
+
+  *sequence += 1;
+  *sequence
+}
 
 #[doc(hidden)]
 #[macro_export]
@@ -27,51 +77,239 @@ impl Monitor {
   ///   such as method, path, timeout, expected status code, and follow redirects.
   ///
   /// The returned [`Measurement`] includes:
+  /// - [`id`](Measurement#structfield.id) and
+  ///   [`sequence`](Measurement#structfield.sequence): a new identifier and
+  ///   the next sequence number of the monitor, as
+  ///   [resumed](resume_sequence).
+  /// - [`agent`](Measurement#structfield.agent): the identity of the agent,
+  ///   if it's [set](set_agent).
   /// - [`data`](Measurement#structfield.data): containing the collected
   ///   measurement if successful.
+  /// - [`degradation`](Measurement#structfield.degradation): set if the
+  ///   latency of a successful measurement exceeded the configured thresholds.
   /// - [`error`](Measurement#structfield.error): containing any error
   ///   that occurred during the measurement.
+  /// - [`trace`](Measurement#structfield.trace): the transcript of a failed
+  ///   `HTTP` request, if it's enabled.
+  /// - [`partial`](Measurement#structfield.partial): how far the check got
+  ///   and the address it resolved, if it failed or the response body was
+  ///   truncated.
+  ///
+  /// If the monitor has a [retry policy](crate::monitor::models::RetryPolicy),
+  /// a check failing with a retried error is performed again after its
+  /// backoff, and only the last attempt is measured.
+  #[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(
+      name = "measure",
+      skip(self),
+      fields(monitor_id = self.id, host = %self.host, outcome, latency_ms, attempts)
+    )
+  )]
   pub async fn measure(&self) -> Measurement {
-    let mut measure = Measurement {
-      timestamp: OffsetDateTime::now_utc(),
-      monitor_id: self.id,
-      data: None,
-      error: None,
+    let mut attempt = 1;
+
+    let mut measure = loop {
+      let measure = self.attempt().await;
+
+      match (&self.retry, &measure.error) {
+        (Some(retry), Some(error)) if retry.retries(attempt, error) => {
+          #[cfg(feature = "tracing")]
+          tracing::debug!(attempt, %error, "retrying failed check");
+
+          tokio::time::sleep(retry.backoff(attempt)).await;
+          attempt += 1;
+        }
+        _ => break measure,
+      }
     };
 
+    measure.id = Uuid::now_v7();
+    measure.sequence = next_sequence(self.id);
+
+    #[cfg(feature = "tracing")]
+    {
+      let span = tracing::Span::current();
+
+      span.record("outcome", measure.status().as_str());
+      span.record("attempts", attempt);
+      if let Some(data) = &measure.data {
+        span.record("latency_ms", data.latency());
+      }
+    }
+
+    measure
+  }
+
+  /// Performs a single check, measured without an identifier or a sequence
+  /// number.
+  async fn attempt(&self) -> Measurement {
+    let mut measure = self.measurement();
+
     let result: Result<Data, CollectorError> = match &self.config {
       #[cfg(not(tarpaulin_include))]
       // This branch is excluded from code coverage (`tarpaulin_include`) because
-      // raw sockets are required for performing ICMP (ping) measurements.
-      // Such operations usually cannot be executed in test environments, since
-      // they require elevated privileges or special OS-level capabilities.
-      Config::Ping(config) => Ping::measure(&self.host, config)
-        .await
-        .map_err(|error| error.into()),
-      Config::Http(config) => Http::measure(&self.host, config)
-        .await
-        .map_err(|error| error.into()),
+      // ICMP (ping) measurements require either raw sockets, which need
+      // elevated privileges, or unprivileged ICMP sockets, which are disabled
+      // on some systems. Test environments don't necessarily permit either.
+      Config::Ping(config) => {
+        let attempt = Ping::measure_partial(&self.host, config).await;
+        measure.partial = attempt.partial;
+
+        attempt.result.map_err(|error| error.into())
+      }
+      Config::Http(config) => {
+        let attempt = Http::measure_traced(&self.host, config).await;
+        measure.trace = attempt.trace;
+        measure.partial = attempt.partial;
+
+        attempt.result.map_err(|error| error.into())
+      }
     };
 
-    if result.is_ok() {
-      measure.data = result.ok();
-    } else {
-      measure.error = result.err();
+    match result {
+      Ok(data) => {
+        measure.degradation = self.config.degradation(data.latency());
+        measure.data = Some(data);
+      }
+      Err(error) => measure.error = Some(error),
     }
 
     measure
   }
+
+  /// Performs a measurement, like [measure](Monitor::measure), unless
+  /// `cancel` is cancelled first. A cancelled check is abandoned without a
+  /// measurement, though its request may still run in the background until
+  /// it completes or times out.
+  pub async fn measure_until(&self, cancel: &CancellationToken) -> Option<Measurement> {
+    cancel.run_until_cancelled(self.measure()).await
+  }
+
+  /// Performs a measurement, like [measure](Monitor::measure), but fails it
+  /// with [DeadlineExceeded](CollectorError::DeadlineExceeded) if it doesn't
+  /// complete within `deadline`, retries included. The timeouts of the
+  /// configuration bound the requests, while the deadline also bounds a
+  /// collector that hangs, e.g. on a blocking task. The abandoned check may
+  /// still run in the background.
+  pub async fn measure_with_deadline(&self, deadline: Duration) -> Measurement {
+    let mut expired = self.measurement();
+
+    match tokio::time::timeout(deadline, self.measure()).await {
+      Ok(measure) => measure,
+      Err(_) => {
+        #[cfg(feature = "tracing")]
+        tracing::warn!(monitor_id = self.id, host = %self.host, ?deadline, "check exceeded its deadline");
+
+        expired.id = Uuid::now_v7();
+        expired.sequence = next_sequence(self.id);
+        expired.error = Some(CollectorError::DeadlineExceeded(deadline));
+        expired
+      }
+    }
+  }
+
+  /// Turns a measurement of a monitor with the same host and configuration
+  /// into one of this monitor, with its own identifier, sequence number and
+  /// labels.
+  pub(crate) fn adopt(&self, mut measurement: Measurement) -> Measurement {
+    measurement.id = Uuid::now_v7();
+    measurement.monitor_id = self.id;
+    measurement.sequence = next_sequence(self.id);
+    measurement.labels = self.labels.clone();
+    measurement
+  }
+
+  /// Returns a measurement of the monitor started now, without an
+  /// identifier, a sequence number or results.
+  fn measurement(&self) -> Measurement {
+    Measurement {
+      id: Uuid::nil(),
+      timestamp: OffsetDateTime::now_utc(),
+      monitor_id: self.id,
+      sequence: 0,
+      labels: self.labels.clone(),
+      agent: agent(),
+      data: None,
+      degradation: None,
+      error: None,
+      trace: None,
+      partial: None,
+    }
+  }
 }
 
 #[cfg(test)]
 mod tests {
+  use std::collections::HashMap;
   use std::time::Duration;
 
   use httpmock::Method::GET;
   use httpmock::MockServer;
 
   use super::*;
-  use crate::monitor::models::{Header, HttpConfig};
+  use crate::monitor::errors::{ErrorCode, ErrorKind, HttpError};
+  use crate::monitor::models::{
+    Degradation, Header, HttpConfig, HttpData, HttpVersion, IpFamily, Method, RetryPolicy, Scheme,
+    Status,
+  };
+
+  #[test]
+  fn serialized_measurement() {
+    let error = CollectorError::Http(HttpError::StatusMismatch {
+      expected: 200,
+      actual: 503,
+      snippet: None,
+    });
+    let measurement = Measurement {
+      timestamp: OffsetDateTime::from_unix_timestamp_nanos(1_700_000_000_123_000_000).unwrap(),
+      labels: HashMap::from([(String::from("env"), String::from("prod"))]),
+      data: Some(Data::Http(HttpData {
+        total: 120.5,
+        ip_family: Some(IpFamily::V4),
+        http_version: Some(HttpVersion::Http2),
+        ..Default::default()
+      })),
+      degradation: Some(Degradation::Warning),
+      error: Some(error),
+      ..Measurement::fixture(1)
+    };
+
+    let json = serde_json::to_value(&measurement).unwrap();
+
+    assert_eq!(json["timestamp"], 1_700_000_000_123_i64);
+    assert_eq!(json["data"]["type"], "http");
+    assert_eq!(json["data"]["ip_family"], "v4");
+    assert_eq!(json["data"]["http_version"], "2");
+    assert_eq!(json["degradation"], "warning");
+    assert_eq!(json["error"]["kind"], "status_mismatch");
+    assert_eq!(json["labels"]["env"], "prod");
+
+    let unlabeled = Measurement {
+      labels: HashMap::new(),
+      ..measurement.clone()
+    };
+    assert!(
+      serde_json::to_value(unlabeled)
+        .unwrap()
+        .get("labels")
+        .is_none(),
+      "empty labels are omitted"
+    );
+
+    let restored: Measurement = serde_json::from_value(json).unwrap();
+    let error = restored.error.unwrap();
+
+    assert_eq!(restored.timestamp, measurement.timestamp);
+    assert_eq!(restored.labels, measurement.labels);
+    assert_eq!(restored.data.unwrap().latency(), 120.5);
+    assert_eq!(error.kind(), ErrorKind::StatusMismatch);
+    assert_eq!(
+      error.to_string(),
+      measurement.error.unwrap().to_string(),
+      "the message is kept"
+    );
+  }
 
   #[test]
   fn measure_macro() {
@@ -98,11 +336,16 @@ mod tests {
 
     let monitor = Monitor {
       id: 1,
+      name: None,
+      description: None,
+      group_id: None,
       host: format!("{}:{}", &server.host(), &server.port()),
-      config: Config::Http(HttpConfig {
+      labels: HashMap::from([(String::from("team"), String::from("core"))]),
+      retry: None,
+      config: Config::Http(Box::new(HttpConfig {
         timeout: 3,
-        method: String::from("GET"),
-        protocol: String::from("HTTP"),
+        method: Method::Get,
+        protocol: Scheme::Http,
         path: Some(String::from("/check")),
         header: Some(Header {
           name: String::from("Authorization"),
@@ -111,7 +354,7 @@ mod tests {
         expected_status_code: 200,
         keyword: Some(String::from("index")),
         ..Default::default()
-      }),
+      })),
     };
 
     let result = monitor.measure().await;
@@ -122,6 +365,134 @@ mod tests {
       result.data.is_some() && result.error.is_none(),
       "monitor measurement has data"
     );
+    assert_eq!(result.status(), Status::Up);
+    assert_eq!(result.labels, monitor.labels, "the labels are copied");
+  }
+
+  #[tokio::test]
+  async fn measure_stamped_with_agent() {
+    let server = MockServer::start_async().await;
+
+    server
+      .mock_async(|when, then| {
+        when.method(GET).path("/");
+        then.status(200);
+      })
+      .await;
+
+    let monitor = Monitor {
+      id: 1,
+      name: None,
+      description: None,
+      group_id: None,
+      host: format!("{}:{}", &server.host(), &server.port()),
+      labels: Default::default(),
+      retry: None,
+      config: Config::Http(Box::new(HttpConfig {
+        timeout: 3,
+        protocol: Scheme::Http,
+        expected_status_code: 200,
+        ..Default::default()
+      })),
+    };
+
+    set_agent(Some(Agent::new("probe-1").region("eu-west")));
+    let result = monitor.measure().await;
+    set_agent(None);
+
+    let agent = result.agent.as_ref().unwrap();
+    assert_eq!(
+      (agent.id.as_str(), agent.region.as_deref()),
+      ("probe-1", Some("eu-west"))
+    );
+
+    let json = serde_json::to_value(&result).unwrap();
+    assert_eq!(json["agent"]["region"], "eu-west");
+
+    let restored: Measurement = serde_json::from_value(json).unwrap();
+    assert_eq!(restored.agent, result.agent);
+  }
+
+  #[tokio::test]
+  async fn measure_sequenced() {
+    let server = MockServer::start_async().await;
+
+    server
+      .mock_async(|when, then| {
+        when.method(GET).path("/");
+        then.status(200);
+      })
+      .await;
+
+    let monitor = Monitor {
+      id: 910,
+      name: None,
+      description: None,
+      group_id: None,
+      host: format!("{}:{}", &server.host(), &server.port()),
+      labels: Default::default(),
+      retry: None,
+      config: Config::Http(Box::new(HttpConfig {
+        timeout: 3,
+        protocol: Scheme::Http,
+        expected_status_code: 200,
+        ..Default::default()
+      })),
+    };
+
+    resume_sequence(monitor.id, 41);
+    resume_sequence(monitor.id, 7);
+
+    let first = monitor.measure().await;
+    let second = monitor.measure().await;
+
+    assert_eq!((first.sequence, second.sequence), (42, 43));
+    assert!(!first.id.is_nil());
+    assert!(first.id < second.id, "identifiers are ordered");
+
+    forget_sequence(monitor.id);
+    assert_eq!(monitor.measure().await.sequence, 1);
+  }
+
+  #[tokio::test]
+  async fn measure_http_degraded() {
+    let server = MockServer::start_async().await;
+
+    server
+      .mock_async(|when, then| {
+        when.method(GET).path("/check");
+        then.status(200).delay(Duration::from_millis(100));
+      })
+      .await;
+
+    let monitor = Monitor {
+      id: 1,
+      name: None,
+      description: None,
+      group_id: None,
+      host: format!("{}:{}", &server.host(), &server.port()),
+      labels: Default::default(),
+      retry: None,
+      config: Config::Http(Box::new(HttpConfig {
+        timeout: 3,
+        method: Method::Get,
+        protocol: Scheme::Http,
+        path: Some(String::from("/check")),
+        expected_status_code: 200,
+        latency_warning_ms: Some(50),
+        ..Default::default()
+      })),
+    };
+
+    let result = monitor.measure().await;
+
+    assert!(result.data.is_some(), "monitor measurement has data");
+    assert_eq!(
+      result.degradation,
+      Some(Degradation::Warning),
+      "monitor measurement is degraded"
+    );
+    assert_eq!(result.status(), Status::Degraded);
   }
 
   #[tokio::test]
@@ -137,15 +508,20 @@ mod tests {
 
     let monitor = Monitor {
       id: 1,
+      name: None,
+      description: None,
+      group_id: None,
       host: format!("{}:{}", &server.host(), &server.port()),
-      config: Config::Http(HttpConfig {
+      labels: Default::default(),
+      retry: None,
+      config: Config::Http(Box::new(HttpConfig {
         timeout: 3,
-        method: String::from("GET"),
-        protocol: String::from("HTTP"),
+        method: Method::Get,
+        protocol: Scheme::Http,
         path: Some(String::from("/check")),
         expected_status_code: 200,
         ..Default::default()
-      }),
+      })),
     };
 
     let result = monitor.measure().await;
@@ -156,5 +532,104 @@ mod tests {
       result.data.is_none() && result.error.is_some(),
       "monitor measurement has error"
     );
+    assert_eq!(result.status(), Status::Down);
+  }
+
+  #[tokio::test]
+  async fn measure_http_retried() {
+    let server = MockServer::start_async().await;
+
+    let mock = server
+      .mock_async(|when, then| {
+        when.method(GET).path("/check");
+        then.status(503);
+      })
+      .await;
+
+    let mut monitor = Monitor {
+      id: 913,
+      name: None,
+      description: None,
+      group_id: None,
+      host: format!("{}:{}", &server.host(), &server.port()),
+      labels: Default::default(),
+      retry: Some(RetryPolicy {
+        backoff_ms: 10,
+        ..Default::default()
+      }),
+      config: Config::Http(Box::new(HttpConfig {
+        timeout: 3,
+        method: Method::Get,
+        protocol: Scheme::Http,
+        path: Some(String::from("/check")),
+        expected_status_code: 200,
+        ..Default::default()
+      })),
+    };
+
+    let result = monitor.measure().await;
+    mock.assert_calls(1);
+    assert_eq!(result.error.unwrap().code(), ErrorCode::StatusMismatch);
+
+    monitor.retry = Some(RetryPolicy {
+      backoff_ms: 10,
+      retry_on: vec![ErrorCode::StatusMismatch],
+      ..Default::default()
+    });
+
+    let result = monitor.measure().await;
+    mock.assert_calls(4);
+    assert_eq!(result.sequence, 2, "only the last attempt is measured");
+  }
+
+  #[tokio::test]
+  async fn measure_http_with_deadline() {
+    let server = MockServer::start_async().await;
+
+    server
+      .mock_async(|when, then| {
+        when.method(GET).path("/check");
+        then.status(200).delay(Duration::from_secs(2));
+      })
+      .await;
+
+    let monitor = Monitor {
+      id: 914,
+      name: None,
+      description: None,
+      group_id: None,
+      host: format!("{}:{}", &server.host(), &server.port()),
+      labels: Default::default(),
+      retry: None,
+      config: Config::Http(Box::new(HttpConfig {
+        timeout: 3,
+        method: Method::Get,
+        protocol: Scheme::Http,
+        path: Some(String::from("/check")),
+        expected_status_code: 200,
+        ..Default::default()
+      })),
+    };
+
+    let result = monitor
+      .measure_with_deadline(Duration::from_millis(100))
+      .await;
+
+    let error = result.error.unwrap();
+    assert_eq!(error.kind(), ErrorKind::Deadline);
+    assert_eq!(error.code(), ErrorCode::Timeout);
+    assert_eq!(result.sequence, 1);
+    assert!(!result.id.is_nil());
+  }
+
+  #[test]
+  fn unknown_status() {
+    let measurement = Measurement {
+      timestamp: OffsetDateTime::now_utc(),
+      data: None,
+      ..Measurement::fixture(1)
+    };
+
+    assert_eq!(measurement.status(), Status::Unknown);
   }
 }
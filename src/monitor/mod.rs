@@ -3,16 +3,23 @@
 //! # Example
 //!
 //! ```rust, no_run
+//! use std::collections::HashMap;
+//!
 //! use limon_core::monitor::models::{Config, HttpConfig, PingConfig, Monitor, Measurement};
 //!
 //! async fn measure_ping() {
 //!   let monitor = Monitor {
 //!     id: 2,
+//!     name: Some("Google".into()),
+//!     description: None,
+//!     group_id: None,
 //!     host: "google.com".into(),
-//!     config: Config::Ping(PingConfig {
+//!     labels: HashMap::from([("region".into(), "eu-west".into())]),
+//!     retry: None,
+//!     config: Config::Ping(Box::new(PingConfig {
 //!       timeout: 5,
 //!       ..Default::default()
-//!     })
+//!     }))
 //!   };
 //!
 //!   let measure = monitor.measure().await;
@@ -25,8 +32,17 @@
 //! # })
 //! ```
 
-mod collectors;
+pub(crate) mod collectors;
 mod measure;
 
 pub mod errors;
 pub mod models;
+pub mod sink;
+pub mod state;
+
+pub use collectors::{Ping, set_default_dns_cache};
+pub use measure::{agent, forget_sequence, resume_sequence, set_agent};
+
+/// A [Runner](crate::schedule::runner::Runner) of monitors, sending their
+/// measurements to a sink. At most 100 checks are in flight by default.
+pub type MonitorRunner<S> = crate::schedule::runner::Runner<models::Monitor, S>;
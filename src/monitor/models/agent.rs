@@ -0,0 +1,29 @@
+/// Identity of the agent taking measurements, e.g. one of several probing a
+/// monitor from different regions. It's set once for the library with
+/// [`set_agent`](crate::monitor::set_agent) and stamped on every
+/// [Measurement](crate::monitor::models::Measurement).
+#[derive(Debug, Clone, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]
+pub struct Agent {
+  /// Unique identifier of the agent.
+  pub id: String,
+
+  /// Region the agent measures from, e.g. `eu-west`.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub region: Option<String>,
+}
+
+impl Agent {
+  /// Creates the identity of an agent without a region.
+  pub fn new(id: impl Into<String>) -> Self {
+    Self {
+      id: id.into(),
+      region: None,
+    }
+  }
+
+  /// Sets the region the agent measures from.
+  pub fn region(mut self, region: impl Into<String>) -> Self {
+    self.region = Some(region.into());
+    self
+  }
+}
@@ -0,0 +1,130 @@
+use std::collections::HashMap;
+
+use crate::monitor::models::{Measurement, Monitor, Status};
+
+/// A named group of monitors, e.g. the checks of a service shown together on
+/// a dashboard. Monitors join a group with their
+/// [`group_id`](Monitor#structfield.group_id).
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct MonitorGroup {
+  /// Group identifier.
+  pub id: i64,
+
+  /// Human-readable name of the group.
+  pub name: String,
+
+  /// Optional description of the group.
+  pub description: Option<String>,
+}
+
+impl MonitorGroup {
+  /// Returns `true` if the monitor belongs to the group.
+  pub fn contains(&self, monitor: &Monitor) -> bool {
+    monitor.group_id == Some(self.id)
+  }
+
+  /// Returns the monitors of the group.
+  pub fn members<'a>(
+    &self,
+    monitors: impl IntoIterator<Item = &'a Monitor>,
+  ) -> impl Iterator<Item = &'a Monitor> {
+    monitors
+      .into_iter()
+      .filter(move |monitor| self.contains(monitor))
+  }
+
+  /// Returns the status of the group, rolled up from the latest measurements
+  /// of its monitors, by monitor id. Monitors without a measurement are
+  /// unknown.
+  pub fn status<'a>(
+    &self,
+    monitors: impl IntoIterator<Item = &'a Monitor>,
+    latest: &HashMap<i64, Measurement>,
+  ) -> Status {
+    Self::rollup(self.members(monitors).map(|monitor| {
+      latest
+        .get(&monitor.id)
+        .map_or(Status::Unknown, Measurement::status)
+    }))
+  }
+
+  /// Rolls up the statuses of monitors: down if all the known ones are down,
+  /// degraded if any is down or degraded, and up if they're all up. Unknown
+  /// statuses are ignored, unless none is known.
+  pub fn rollup(statuses: impl IntoIterator<Item = Status>) -> Status {
+    let (mut up, mut degraded, mut down) = (0, 0, 0);
+
+    for status in statuses {
+      match status {
+        Status::Up => up += 1,
+        Status::Degraded => degraded += 1,
+        Status::Down => down += 1,
+        Status::Unknown => {}
+      }
+    }
+
+    if up + degraded + down == 0 {
+      Status::Unknown
+    } else if up + degraded == 0 {
+      Status::Down
+    } else if degraded + down > 0 {
+      Status::Degraded
+    } else {
+      Status::Up
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::monitor::models::Config;
+
+  fn monitor(id: i64, group_id: Option<i64>) -> Monitor {
+    Monitor {
+      id,
+      name: None,
+      description: None,
+      group_id,
+      host: String::from("localhost"),
+      labels: Default::default(),
+      retry: None,
+      config: Config::Ping(Box::default()),
+    }
+  }
+
+  #[test]
+  fn rollup() {
+    use Status::*;
+
+    assert_eq!(MonitorGroup::rollup([]), Unknown);
+    assert_eq!(MonitorGroup::rollup([Unknown, Up, Up]), Up);
+    assert_eq!(MonitorGroup::rollup([Up, Degraded]), Degraded);
+    assert_eq!(MonitorGroup::rollup([Up, Down]), Degraded);
+    assert_eq!(MonitorGroup::rollup([Down, Unknown, Down]), Down);
+  }
+
+  #[test]
+  fn status() {
+    let group = MonitorGroup {
+      id: 7,
+      name: String::from("API"),
+      description: None,
+    };
+    let monitors = [monitor(1, Some(7)), monitor(2, Some(7)), monitor(3, None)];
+    let mut latest = HashMap::from([
+      (1, Measurement::fixture(1)),
+      (3, Measurement::fixture(3).up(false)),
+    ]);
+
+    assert_eq!(group.members(&monitors).count(), 2);
+    assert_eq!(
+      group.status(&monitors, &latest),
+      Status::Up,
+      "other monitors are ignored"
+    );
+
+    latest.insert(2, Measurement::fixture(2).up(false));
+    assert_eq!(group.status(&monitors, &latest), Status::Degraded);
+  }
+}
@@ -1,28 +1,216 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::net::IpAddr;
+
 use time::OffsetDateTime;
+use uuid::Uuid;
 
 use crate::monitor::errors::CollectorError;
+use crate::monitor::models::{Agent, IpFamily};
 
 /// Represents a single measurement performed by a monitor.
 ///
 /// Each `Measurement` records the timestamp of the check, the ID of the monitor,
 /// and either the collected data or an error if the measurement failed.
-#[derive(Debug)]
+///
+/// Measurements can be serialized, e.g. to be sent over the wire. The error is
+/// serialized as its [report](crate::monitor::errors::ErrorReport), and deserialized as a
+/// [CollectorError::Reported]. Likewise, a clone of a measurement keeps the
+/// report of its error only.
+///
+/// A measurement is displayed as a one-line summary, e.g.
+/// `monitor 42 HTTP ok 183ms (dns 12ms, tls 56ms)` or
+/// `monitor 42 down: HTTP error: HTTP client is unavailable`.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct Measurement {
-  /// Unix timestamp when the measurement was taken.
+  /// Unique identifier of the measurement, a UUID version 7, so identifiers
+  /// sort by the time they were generated.
+  pub id: Uuid,
+
+  /// Unix timestamp when the measurement was taken. It's serialized in
+  /// milliseconds.
+  #[serde(with = "time::serde::timestamp::milliseconds_i64")]
   pub timestamp: OffsetDateTime,
 
   /// Unique identifier of the monitor that produced this measurement.
   pub monitor_id: i64,
 
+  /// Position of the measurement among the ones of its monitor, from 1, so
+  /// duplicates and gaps can be told apart downstream. It's 0 for
+  /// measurements that weren't taken by [`Monitor::measure`](crate::monitor::models::Monitor::measure).
+  #[serde(default)]
+  pub sequence: u64,
+
+  /// Labels of the monitor that produced this measurement, copied from
+  /// [`Monitor::labels`](crate::monitor::models::Monitor#structfield.labels).
+  /// They're omitted from the serialized measurement if empty.
+  #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+  pub labels: HashMap<String, String>,
+
+  /// Agent that took this measurement, as set by
+  /// [`set_agent`](crate::monitor::set_agent). It's omitted from the
+  /// serialized measurement if unset.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub agent: Option<Agent>,
+
   /// Measurement data, if the operation was successful.
   pub data: Option<Data>,
 
+  /// Set if the measurement was successful, but its latency exceeded one of
+  /// the configured thresholds.
+  pub degradation: Option<Degradation>,
+
   /// Error that occurred during the measurement.
+  #[serde(with = "crate::monitor::errors::report")]
   pub error: Option<CollectorError>,
+
+  /// Transcript of a failed `HTTP` request, if
+  /// [`debug_trace`](crate::monitor::models::HttpConfig#structfield.debug_trace)
+  /// is enabled.
+  pub trace: Option<String>,
+
+  /// How far a check got, if it failed partway or its data is incomplete.
+  /// It's omitted from the serialized measurement if unset.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub partial: Option<Partial>,
+}
+
+impl Measurement {
+  /// Returns the status of the monitor the measurement shows: down if it
+  /// failed, degraded if its latency exceeded a threshold, up if it collected
+  /// data, and unknown otherwise.
+  pub fn status(&self) -> Status {
+    if self.error.is_some() {
+      Status::Down
+    } else if self.degradation.is_some() {
+      Status::Degraded
+    } else if self.data.is_some() {
+      Status::Up
+    } else {
+      Status::Unknown
+    }
+  }
+}
+
+/// Builds measurements for tests, e.g.
+/// `Measurement::fixture(1).at(60).latency(250.0)`.
+#[cfg(test)]
+impl Measurement {
+  /// Returns a successful `HTTP` measurement of the monitor with
+  /// `monitor_id`, taken at the Unix epoch.
+  pub(crate) fn fixture(monitor_id: i64) -> Self {
+    Self {
+      id: Default::default(),
+      timestamp: OffsetDateTime::UNIX_EPOCH,
+      monitor_id,
+      sequence: 0,
+      labels: Default::default(),
+      agent: None,
+      data: Some(Data::Http(Default::default())),
+      degradation: None,
+      error: None,
+      trace: None,
+      partial: None,
+    }
+  }
+
+  /// Sets the time the measurement was taken, in seconds since the Unix
+  /// epoch.
+  pub(crate) fn at(mut self, seconds: i64) -> Self {
+    self.timestamp = OffsetDateTime::from_unix_timestamp(seconds).unwrap();
+    self
+  }
+
+  /// Sets the data of a successful measurement.
+  pub(crate) fn data(mut self, data: Data) -> Self {
+    self.data = Some(data);
+    self.error = None;
+    self
+  }
+
+  /// Sets the total time of the `HTTP` check, in milliseconds.
+  pub(crate) fn latency(self, total: f32) -> Self {
+    self.data(Data::Http(HttpData {
+      total,
+      ..Default::default()
+    }))
+  }
+
+  /// Fails the measurement with `error`, without data.
+  pub(crate) fn error(mut self, error: CollectorError) -> Self {
+    self.data = None;
+    self.error = Some(error);
+    self
+  }
+
+  /// Fails the measurement with an unavailable `HTTP` client, unless `up`.
+  pub(crate) fn up(self, up: bool) -> Self {
+    if up {
+      self
+    } else {
+      self.error(CollectorError::Http(
+        crate::monitor::errors::HttpError::ClientUnavailable,
+      ))
+    }
+  }
+}
+
+impl fmt::Display for Measurement {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "monitor {}", self.monitor_id)?;
+
+    if let Some(error) = &self.error {
+      return write!(f, " down: {error}");
+    }
+
+    let Some(data) = &self.data else {
+      return f.write_str(" unknown");
+    };
+
+    let status = match self.degradation {
+      None => "ok",
+      Some(Degradation::Warning) => "slow",
+      Some(Degradation::Critical) => "critical",
+    };
+
+    write!(f, " {} {status} ", data.kind())?;
+    data.fmt_timings(f)
+  }
+}
+
+/// Status of a monitor, classified by [Measurement::status].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Status {
+  /// The measurement succeeded within the latency thresholds.
+  Up,
+
+  /// The measurement failed.
+  Down,
+
+  /// The measurement succeeded, but its latency exceeded one of the
+  /// thresholds.
+  Degraded,
+
+  /// The measurement has neither data nor an error.
+  Unknown,
+}
+
+impl Status {
+  /// Returns the name of the status, as it's serialized.
+  pub fn as_str(&self) -> &'static str {
+    match self {
+      Status::Up => "up",
+      Status::Down => "down",
+      Status::Degraded => "degraded",
+      Status::Unknown => "unknown",
+    }
+  }
 }
 
 /// The collected data of a measurement, which can be either a ping or HTTP measurement.
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
 pub enum Data {
   /// Data collected from a ping monitor.
   Ping(PingData),
@@ -31,24 +219,188 @@ pub enum Data {
   Http(HttpData),
 }
 
+impl Data {
+  /// Returns the total latency of the measurement in milliseconds.
+  pub fn latency(&self) -> f32 {
+    match self {
+      Data::Ping(data) => data.ping,
+      Data::Http(data) => data.total,
+    }
+  }
+
+  /// Returns the kind of the check, as displayed.
+  fn kind(&self) -> &'static str {
+    match self {
+      Data::Ping(data) if data.protocol == PingProtocol::Tcp => "TCP ping",
+      Data::Ping(_) => "ping",
+      Data::Http(_) => "HTTP",
+    }
+  }
+
+  /// Writes the latency, followed by the phases that took any time.
+  fn fmt_timings(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    let details = match self {
+      Data::Ping(data) => vec![
+        ("dns", Millis(data.dns_lookup).to_string()),
+        ("jitter", Millis(data.ping_stddev).to_string()),
+        ("loss", format!("{}%", data.packet_loss)),
+      ],
+      Data::Http(data) => vec![
+        ("dns", Millis(data.dns_lookup).to_string()),
+        ("connect", Millis(data.connect).to_string()),
+        ("tls", Millis(data.tls_handshake).to_string()),
+        ("transfer", Millis(data.data_transfer).to_string()),
+      ],
+    };
+
+    write!(f, "{}", Millis(self.latency()))?;
+
+    let details: Vec<String> = details
+      .into_iter()
+      .filter(|(_, value)| !matches!(value.as_str(), "0ms" | "0%"))
+      .map(|(name, value)| format!("{name} {value}"))
+      .collect();
+
+    if !details.is_empty() {
+      write!(f, " ({})", details.join(", "))?;
+    }
+
+    Ok(())
+  }
+}
+
+/// Data is displayed as the kind of the check and its timings, e.g.
+/// `HTTP 183ms (dns 12ms, tls 56ms)`.
+impl fmt::Display for Data {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "{} ", self.kind())?;
+    self.fmt_timings(f)
+  }
+}
+
+/// A duration in milliseconds, displayed with a decimal below 10ms.
+struct Millis(f32);
+
+impl fmt::Display for Millis {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    if self.0 < 9.95 {
+      write!(f, "{}ms", (self.0 * 10.0).round() / 10.0)
+    } else {
+      write!(f, "{:.0}ms", self.0)
+    }
+  }
+}
+
+/// The degree of success of a check that didn't fully succeed, e.g. one
+/// whose host resolved but didn't accept the connection, or whose response
+/// body was cut at the
+/// [maximum size](crate::monitor::models::HttpConfig#structfield.max_body_size).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct Partial {
+  /// The last phase the check completed, if any.
+  pub completed: Option<Phase>,
+
+  /// Whether the response body was cut at the maximum size, so the checks
+  /// of its content only saw the beginning.
+  #[serde(default)]
+  pub truncated: bool,
+
+  /// Address the host resolved to, or the one connected to, if the check got
+  /// that far. It's omitted from the serialized measurement if unset.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub resolved_ip: Option<IpAddr>,
+}
+
+/// A phase of a check, in the order they're performed. Ping checks only
+/// resolve the host before they send echo requests.
+#[derive(
+  Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, serde::Serialize, serde::Deserialize,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum Phase {
+  /// Resolving the host name.
+  Dns,
+
+  /// Establishing the TCP connection.
+  Connect,
+
+  /// Performing the TLS handshake.
+  Tls,
+
+  /// Sending the request and receiving the whole response.
+  Transfer,
+}
+
+/// Severity of a latency degradation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Degradation {
+  /// The latency exceeded the warning threshold.
+  Warning,
+
+  /// The latency exceeded the critical threshold.
+  Critical,
+}
+
 /// Data returned by a ping monitor.
 ///
-/// Contains timing information for DNS lookup and ICMP ping.
-#[derive(Debug)]
+/// Contains timing information for DNS lookup and ICMP ping, aggregated over
+/// the echo requests sent by the check.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
 #[cfg_attr(test, derive(Default))]
 pub struct PingData {
   /// Time in milliseconds spent on DNS resolution.
   pub dns_lookup: f32,
 
-  /// Time in milliseconds spent performing the ping.
+  /// Average round-trip time in milliseconds of the replied echo requests.
   pub ping: f32,
+
+  /// Minimum round-trip time in milliseconds.
+  pub ping_min: f32,
+
+  /// Maximum round-trip time in milliseconds.
+  pub ping_max: f32,
+
+  /// Standard deviation of the round-trip times in milliseconds (jitter).
+  pub ping_stddev: f32,
+
+  /// Percentage of echo requests that weren't replied.
+  pub packet_loss: f32,
+
+  /// Size in bytes of the echo requests sent.
+  pub packet_size: usize,
+
+  /// Protocol the round-trip times were measured with.
+  pub protocol: PingProtocol,
+
+  /// Name of the PTR record of the pinged address, if
+  /// [`expected_ptr`](crate::monitor::models::PingConfig#structfield.expected_ptr)
+  /// is configured.
+  pub ptr: Option<String>,
+
+  /// Address the host resolved to and was pinged at.
+  pub resolved_ip: Option<IpAddr>,
+}
+
+/// Protocol used to measure the round-trip time of a ping check.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PingProtocol {
+  /// ICMP echo requests.
+  #[default]
+  Icmp,
+
+  /// TCP connections to the
+  /// [fallback port](crate::monitor::models::PingConfig#structfield.fallback_tcp_port),
+  /// since echo requests weren't replied.
+  Tcp,
 }
 
 /// Data returned by an HTTP monitor.
 ///
 /// Contains timing information for DNS resolution, TCP connection, TLS handshake,
 /// and data transfer.
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
 #[cfg_attr(test, derive(Default))]
 pub struct HttpData {
   /// Time in milliseconds spent on DNS resolution.
@@ -62,4 +414,130 @@ pub struct HttpData {
 
   /// Time in milliseconds spent transferring the HTTP response body.
   pub data_transfer: f32,
+
+  /// Total time in milliseconds of the request.
+  pub total: f32,
+
+  /// Address family of the connection the request was sent over, if known.
+  pub ip_family: Option<IpFamily>,
+
+  /// Address of the server the request was sent to, if known.
+  pub resolved_ip: Option<IpAddr>,
+
+  /// Port of the server the request was sent to, if known.
+  pub remote_port: Option<u16>,
+
+  /// Status code of the final response, if known.
+  pub status_code: Option<u16>,
+
+  /// Version of `HTTP` the final response was received over, if known.
+  pub http_version: Option<HttpVersion>,
+
+  /// Details of the server certificate, if
+  /// [`capture_certificate`](crate::monitor::models::HttpConfig#structfield.capture_certificate)
+  /// is enabled and the check is performed over `HTTPS`.
+  pub certificate: Option<Certificate>,
+}
+
+/// Version of the `HTTP` protocol negotiated with a server. It's serialized
+/// as it's written in a status line, e.g. `"1.1"` or `"2"`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]
+pub enum HttpVersion {
+  /// `HTTP/1.0`.
+  #[serde(rename = "1.0")]
+  Http10,
+
+  /// `HTTP/1.1`.
+  #[serde(rename = "1.1")]
+  Http11,
+
+  /// `HTTP/2`.
+  #[serde(rename = "2")]
+  Http2,
+
+  /// `HTTP/3`.
+  #[serde(rename = "3")]
+  Http3,
+}
+
+impl HttpVersion {
+  /// Parses the version of a status line, e.g. `HTTP/2 200`.
+  pub(crate) fn from_status_line(line: &str) -> Option<Self> {
+    let version = line.strip_prefix("HTTP/")?.split_whitespace().next()?;
+
+    match version {
+      "1.0" => Some(HttpVersion::Http10),
+      "1.1" => Some(HttpVersion::Http11),
+      "2" | "2.0" => Some(HttpVersion::Http2),
+      "3" | "3.0" => Some(HttpVersion::Http3),
+      _ => None,
+    }
+  }
+}
+
+/// Details of a TLS certificate presented by a server.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct Certificate {
+  /// Subject distinguished name (e.g., `CN=example.com, O=Example`).
+  pub subject: String,
+
+  /// Issuer distinguished name.
+  pub issuer: String,
+
+  /// Expiration time of the certificate. It's serialized as a Unix timestamp,
+  /// in seconds.
+  #[serde(with = "time::serde::timestamp")]
+  pub not_after: OffsetDateTime,
+
+  /// DNS names and IP addresses from the Subject Alternative Name extension.
+  pub subject_alt_names: Vec<String>,
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn display() {
+    let http = Data::Http(HttpData {
+      dns_lookup: 12.2,
+      tls_handshake: 56.0,
+      total: 183.4,
+      ..Default::default()
+    });
+
+    assert_eq!(http.to_string(), "HTTP 183ms (dns 12ms, tls 56ms)");
+    assert_eq!(
+      Measurement::fixture(42).data(http).to_string(),
+      "monitor 42 HTTP ok 183ms (dns 12ms, tls 56ms)"
+    );
+
+    let ping = Measurement::fixture(42).data(Data::Ping(PingData {
+      ping: 2.54,
+      packet_loss: 25.0,
+      protocol: PingProtocol::Tcp,
+      ..Default::default()
+    }));
+
+    assert_eq!(
+      Measurement {
+        degradation: Some(Degradation::Warning),
+        ..ping
+      }
+      .to_string(),
+      "monitor 42 TCP ping slow 2.5ms (loss 25%)"
+    );
+
+    let failed = Measurement::fixture(42).up(false);
+
+    assert_eq!(
+      failed.to_string(),
+      "monitor 42 down: HTTP error: HTTP client is unavailable"
+    );
+    let unknown = Measurement {
+      data: None,
+      ..Measurement::fixture(42)
+    };
+    assert_eq!(unknown.to_string(), "monitor 42 unknown");
+  }
 }
@@ -1,7 +1,17 @@
 //! A module containing a set of models for monitor measurement.
 
+mod agent;
+mod group;
 mod measurement;
 mod monitor;
 
-pub use measurement::{Data, HttpData, Measurement, PingData};
-pub use monitor::{Config, Header, HttpConfig, Monitor, PingConfig};
+pub use agent::Agent;
+pub use group::MonitorGroup;
+pub use measurement::{
+  Certificate, Data, Degradation, HttpData, HttpVersion, Measurement, Partial, Phase, PingData,
+  PingProtocol, Status,
+};
+pub use monitor::{
+  Config, DnsCache, DnsConfig, ElementAssertion, Header, HttpConfig, IcmpSocket, IpFamily, Method,
+  Monitor, PingConfig, RetryPolicy, Scheme,
+};
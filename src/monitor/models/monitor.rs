@@ -1,64 +1,194 @@
+use std::collections::HashMap;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::net::{IpAddr, SocketAddr};
+use std::time::Duration;
+
+use crate::monitor::errors::{CollectorError, ErrorCode};
+use crate::monitor::forget_sequence;
+use crate::monitor::models::{Degradation, Measurement};
 use crate::schedule::Schedulable;
+use crate::schedule::runner::Runnable;
 
 /// Represents a monitor for a host, which can be measured.
-#[derive(Debug)]
+///
+/// Monitors can be compared, e.g. to reconcile the running monitors with a
+/// new configuration, and serialized with their config tagged by its
+/// `type`, `"ping"` or `"http"`.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
 pub struct Monitor {
   /// Monitor identifier.
   pub id: i64,
 
+  /// Optional human-readable name of the monitor.
+  pub name: Option<String>,
+
+  /// Optional description of what the monitor checks.
+  pub description: Option<String>,
+
+  /// Optional identifier of the [MonitorGroup](crate::monitor::models::MonitorGroup)
+  /// the monitor belongs to.
+  pub group_id: Option<i64>,
+
   /// Host without protocol specified.
   pub host: String,
 
+  /// Labels copied onto every measurement of the monitor, e.g. its team,
+  /// environment or region, so exporters can tag series with them.
+  #[serde(default)]
+  pub labels: HashMap<String, String>,
+
+  /// Optional policy for retrying failed checks before their failure is
+  /// measured. If `None`, a failed check is measured right away.
+  #[serde(default)]
+  pub retry: Option<RetryPolicy>,
+
   /// Monitor's config.
   pub config: Config,
 }
 
 /// Configuration type for a monitor.
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
 pub enum Config {
   /// Ping monitor configuration.
-  Ping(PingConfig),
+  Ping(Box<PingConfig>),
 
   /// HTTP monitor configuration.
-  Http(HttpConfig),
+  Http(Box<HttpConfig>),
+}
+
+impl Config {
+  /// Returns the degradation of a successful check with the given latency
+  /// in milliseconds, according to the configured thresholds.
+  pub fn degradation(&self, latency: f32) -> Option<Degradation> {
+    let (warning, critical) = match self {
+      Config::Ping(config) => (config.latency_warning_ms, config.latency_critical_ms),
+      Config::Http(config) => (config.latency_warning_ms, config.latency_critical_ms),
+    };
+
+    let exceeds =
+      |threshold: Option<u64>| threshold.is_some_and(|threshold| latency > threshold as f32);
+
+    if exceeds(critical) {
+      Some(Degradation::Critical)
+    } else if exceeds(warning) {
+      Some(Degradation::Warning)
+    } else {
+      None
+    }
+  }
 }
 
 /// Configuration for a Ping monitor.
-#[derive(Debug, Default, serde::Deserialize)]
+#[derive(Debug, Default, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
 pub struct PingConfig {
   /// How often the monitor should perform a check, in seconds.
   pub check_frequency: i64,
 
-  /// Number of consecutive successful checks required to confirm a state change.
+  /// Number of consecutive failed checks required to confirm the monitor is
+  /// down.
   pub confirmation_period: i64,
 
-  /// Number of consecutive failed checks required to consider the monitor recovered.
+  /// Number of consecutive successful checks required to consider the monitor
+  /// recovered.
   pub recovery_period: i64,
 
   /// Maximum time, in seconds, to wait for a ping response before timing out.
   pub timeout: i64,
+
+  /// Number of echo requests sent per check. If `None`, a single one is sent.
+  pub count: Option<u16>,
+
+  /// Interval, in milliseconds, between echo requests of a check. If `None`,
+  /// requests are sent a second apart.
+  pub interval_ms: Option<u64>,
+
+  /// Whether to send an extra echo request before the measured ones and
+  /// discard its result, so the statistics aren't skewed by first-packet
+  /// effects (e.g., ARP resolution or route cache misses).
+  #[serde(default)]
+  pub warmup: bool,
+
+  /// Size in bytes of the echo requests, including the 8 bytes ICMP header.
+  /// If `None`, 1000 bytes are sent.
+  pub packet_size: Option<usize>,
+
+  /// Optional TCP port to measure the connection time to instead, if the
+  /// host doesn't reply to echo requests (e.g., ICMP is filtered) or ICMP
+  /// sockets aren't permitted.
+  pub fallback_tcp_port: Option<u16>,
+
+  /// Kind of socket echo requests are sent with.
+  #[serde(default)]
+  pub icmp_socket: IcmpSocket,
+
+  /// Optional DNS settings. If `None`, the system resolver is used.
+  pub dns: Option<DnsConfig>,
+
+  /// Optional time to live (hop limit for IPv6) of the echo requests. If it
+  /// expires before reaching the host, the check fails.
+  pub ttl: Option<u32>,
+
+  /// Optional pattern the PTR record of the pinged address must match, where
+  /// `*` matches any characters (e.g., `"*.compute.amazonaws.com"`). The check
+  /// fails if the address has no PTR record or it doesn't match.
+  pub expected_ptr: Option<String>,
+
+  /// Optional differentiated services code point (0-63) to mark the echo
+  /// requests and the fallback TCP probes with, so latency is measured within
+  /// a QoS class (e.g., `46` for expedited forwarding).
+  pub dscp: Option<u8>,
+
+  /// Optional preferred address family. If the host has no address of this
+  /// family, another one is pinged.
+  pub ip_family: Option<IpFamily>,
+
+  /// Round-trip time, in milliseconds, above which a successful check is
+  /// considered degraded with a warning.
+  pub latency_warning_ms: Option<u64>,
+
+  /// Round-trip time, in milliseconds, above which a successful check is
+  /// considered critically degraded.
+  pub latency_critical_ms: Option<u64>,
+
+  /// Optional local address to send packets from.
+  pub source_ip: Option<IpAddr>,
+
+  /// Optional network interface to send packets through (e.g., `"eth1"`).
+  pub source_interface: Option<String>,
 }
 
 /// Configuration for an `HTTP` monitor.
-#[derive(Debug, Default, serde::Deserialize)]
+#[derive(Debug, Default, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
 pub struct HttpConfig {
   /// How often the monitor should perform a check, in seconds.
   pub check_frequency: i64,
 
-  /// Number of consecutive successful checks required to confirm a state change.
+  /// Number of consecutive failed checks required to confirm the monitor is
+  /// down.
   pub confirmation_period: i64,
 
-  /// Number of consecutive failed checks required to consider the monitor recovered.
+  /// Number of consecutive successful checks required to consider the monitor
+  /// recovered.
   pub recovery_period: i64,
 
   /// Maximum time, in seconds, to wait for an `HTTP` response before timing out.
+  #[serde(alias = "total_timeout")]
   pub timeout: i32,
 
+  /// Optional maximum time, in milliseconds, to resolve the host and
+  /// establish the TCP connection.
+  pub connect_timeout_ms: Option<u64>,
+
+  /// Optional maximum time, in milliseconds, to perform the TLS handshake of
+  /// `HTTPS` checks.
+  pub tls_timeout_ms: Option<u64>,
+
   /// HTTP method to use (e.g., `GET`, `POST`).
-  pub method: String,
+  pub method: Method,
 
   /// Protocol to use (`HTTP` or `HTTPS`).
-  pub protocol: String,
+  pub protocol: Scheme,
 
   /// Optional port number. If `None`, defaults to 80 for `HTTP` and 443 for `HTTPS`.
   pub port: Option<u16>,
@@ -72,6 +202,13 @@ pub struct HttpConfig {
   /// Optional keyword to search for in the response body.
   pub keyword: Option<String>,
 
+  /// Optional assertion on an element of an `HTML` response, for pages where
+  /// a raw keyword would also match markup or scripts.
+  pub element: Option<ElementAssertion>,
+
+  /// Optional expected SHA-256 digest of the response body, hex-encoded.
+  pub expected_sha256: Option<String>,
+
   /// Expected `HTTP` status code.
   pub expected_status_code: i32,
 
@@ -83,10 +220,281 @@ pub struct HttpConfig {
 
   /// Optional `HTTP` headers to include in the request.
   pub header: Option<Header>,
+
+  /// Optional maximum size of the response body, in bytes. A longer body is
+  /// cut and the transfer is stopped, so the check still succeeds, but its
+  /// measurement is flagged as [truncated](crate::monitor::models::Partial).
+  pub max_body_size: Option<usize>,
+
+  /// Number of response body bytes to attach to a failed check. If `None`,
+  /// the response body isn't attached.
+  pub error_snippet_size: Option<usize>,
+
+  /// Names of response headers to attach to a failed check.
+  #[serde(default)]
+  pub error_snippet_headers: Vec<String>,
+
+  /// Whether to capture the server certificate details for `HTTPS` checks.
+  #[serde(default)]
+  pub capture_certificate: bool,
+
+  /// Whether to record curl's transcript of the request and attach it to
+  /// the measurement if the check fails. The values of the credential
+  /// headers and of the configured header are left out.
+  #[serde(default)]
+  pub debug_trace: bool,
+
+  /// Optional DNS settings. If `None`, the system resolver is used.
+  pub dns: Option<DnsConfig>,
+
+  /// Optional address family the connection is expected to use. When the
+  /// host has both `A` and `AAAA` records, curl connects over both families
+  /// (RFC 8305) and a check connected over the other one fails, so a broken
+  /// family isn't hidden by the fallback.
+  pub expected_ip_family: Option<IpFamily>,
+
+  /// Total request time, in milliseconds, above which a successful check is
+  /// considered degraded with a warning.
+  pub latency_warning_ms: Option<u64>,
+
+  /// Total request time, in milliseconds, above which a successful check is
+  /// considered critically degraded.
+  pub latency_critical_ms: Option<u64>,
+
+  /// Optional local address to connect from.
+  pub source_ip: Option<IpAddr>,
+
+  /// Optional network interface to connect through (e.g., `"eth1"`).
+  pub source_interface: Option<String>,
+}
+
+/// Method of an `HTTP` request. It's parsed from its name in any case (e.g.,
+/// `"GET"`, `"get"` or `"Get"`).
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash, serde::Serialize)]
+pub enum Method {
+  /// `GET`.
+  #[default]
+  #[serde(rename = "GET")]
+  Get,
+
+  /// `POST`.
+  #[serde(rename = "POST")]
+  Post,
+
+  /// `PUT`.
+  #[serde(rename = "PUT")]
+  Put,
+
+  /// `PATCH`.
+  #[serde(rename = "PATCH")]
+  Patch,
+
+  /// `DELETE`.
+  #[serde(rename = "DELETE")]
+  Delete,
+
+  /// `HEAD`, requesting the headers only.
+  #[serde(rename = "HEAD")]
+  Head,
+
+  /// `OPTIONS`.
+  #[serde(rename = "OPTIONS")]
+  Options,
+}
+
+impl Method {
+  const NAMES: &[&str] = &["GET", "POST", "PUT", "PATCH", "DELETE", "HEAD", "OPTIONS"];
+  const ALL: [Method; 7] = [
+    Method::Get,
+    Method::Post,
+    Method::Put,
+    Method::Patch,
+    Method::Delete,
+    Method::Head,
+    Method::Options,
+  ];
+
+  /// Returns the name of the method, as it's sent in a request.
+  pub fn as_str(&self) -> &'static str {
+    match self {
+      Method::Get => "GET",
+      Method::Post => "POST",
+      Method::Put => "PUT",
+      Method::Patch => "PATCH",
+      Method::Delete => "DELETE",
+      Method::Head => "HEAD",
+      Method::Options => "OPTIONS",
+    }
+  }
+}
+
+/// Scheme of the `URL` an `HTTP` monitor requests. It's parsed from its name
+/// in any case (e.g., `"HTTPS"`, `"https"` or `"Https"`).
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash, serde::Serialize)]
+pub enum Scheme {
+  /// Plain `HTTP`.
+  #[default]
+  #[serde(rename = "HTTP")]
+  Http,
+
+  /// `HTTP` over TLS.
+  #[serde(rename = "HTTPS")]
+  Https,
+}
+
+impl<'de> serde::Deserialize<'de> for Method {
+  fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+    deserialize_named(deserializer, &Method::ALL, Method::NAMES)
+  }
+}
+
+impl Scheme {
+  const NAMES: &[&str] = &["HTTP", "HTTPS"];
+  const ALL: [Scheme; 2] = [Scheme::Http, Scheme::Https];
+
+  /// Returns the name of the scheme, as it's written in a `URL`.
+  pub fn as_str(&self) -> &'static str {
+    match self {
+      Scheme::Http => "http",
+      Scheme::Https => "https",
+    }
+  }
+
+  /// Returns the port requests are sent to by default.
+  pub fn default_port(&self) -> u16 {
+    match self {
+      Scheme::Http => 80,
+      Scheme::Https => 443,
+    }
+  }
+}
+
+impl<'de> serde::Deserialize<'de> for Scheme {
+  fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+    deserialize_named(deserializer, &Scheme::ALL, Scheme::NAMES)
+  }
+}
+
+/// Deserializes the variant whose name, at the same index in `names`, matches
+/// the string regardless of its case.
+fn deserialize_named<'de, D: serde::Deserializer<'de>, T: Copy>(
+  deserializer: D,
+  variants: &[T],
+  names: &'static [&'static str],
+) -> Result<T, D::Error> {
+  let name = <std::borrow::Cow<str> as serde::Deserialize>::deserialize(deserializer)?;
+
+  names
+    .iter()
+    .position(|known| known.eq_ignore_ascii_case(&name))
+    .map(|index| variants[index])
+    .ok_or_else(|| serde::de::Error::unknown_variant(&name, names))
+}
+
+/// Kind of socket used to send ICMP echo requests.
+#[derive(
+  Debug, Default, Clone, Copy, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum IcmpSocket {
+  /// A raw socket if it's permitted, a datagram one otherwise.
+  #[default]
+  Auto,
+
+  /// A raw socket, which requires elevated privileges (e.g., `CAP_NET_RAW`).
+  Raw,
+
+  /// A datagram socket, which doesn't require privileges on Linux (if the
+  /// group is within `net.ipv4.ping_group_range`) and macOS. Expired requests
+  /// aren't reported as such on these sockets.
+  Dgram,
+}
+
+/// Internet protocol address family.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum IpFamily {
+  /// IPv4.
+  V4,
+
+  /// IPv6.
+  V6,
+}
+
+impl From<IpAddr> for IpFamily {
+  fn from(ip: IpAddr) -> Self {
+    match ip {
+      IpAddr::V4(_) => IpFamily::V4,
+      IpAddr::V6(_) => IpFamily::V6,
+    }
+  }
+}
+
+/// DNS settings used to resolve the monitor's host.
+#[derive(Debug, Default, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct DnsConfig {
+  /// Name servers to send queries to instead of the system ones
+  /// (e.g., `"10.0.0.53:53"`).
+  #[serde(default)]
+  pub nameservers: Vec<SocketAddr>,
+
+  /// Optional name the certificates of the name servers are verified for. If
+  /// set, queries are sent over DNS-over-TLS (e.g., to `"1.1.1.1:853"` with
+  /// `"cloudflare-dns.com"`).
+  pub tls_name: Option<String>,
+
+  /// Optional DNS-over-HTTPS endpoint (e.g., `"https://dns.google/dns-query"`).
+  /// Ping monitors only support the standard `/dns-query` path.
+  pub doh_url: Option<String>,
+
+  /// Optional time, in milliseconds, to wait for a response to a query before
+  /// retrying it. If `None`, the timeout of the system configuration, or 5
+  /// seconds for custom name servers, is used.
+  pub timeout_ms: Option<u64>,
+
+  /// Optional number of times a query is attempted. If `None`, the number of
+  /// the system configuration, or 2 for custom name servers, is used.
+  pub attempts: Option<usize>,
+
+  /// Optional caching of lookup results. If `None`, the default policy is
+  /// used, which disables caching unless it's changed with
+  /// [`set_default_dns_cache`](crate::monitor::set_default_dns_cache).
+  pub cache: Option<DnsCache>,
+}
+
+/// Caching of DNS lookup results between checks. Checks of DNS freshness
+/// should disable it, so every check performs a real lookup.
+#[derive(
+  Debug, Default, Clone, Copy, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize,
+)]
+pub struct DnsCache {
+  /// Maximum number of cached lookups. If zero, lookups aren't cached.
+  #[serde(default)]
+  pub size: usize,
+
+  /// Optional minimum time, in seconds, a lookup is cached for, even if its
+  /// records have a lower TTL.
+  pub min_ttl: Option<u64>,
+
+  /// Optional maximum time, in seconds, a lookup is cached for, even if its
+  /// records have a higher TTL.
+  pub max_ttl: Option<u64>,
+}
+
+/// Asserts that an element of an `HTML` response contains the expected text.
+#[derive(Debug, Default, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct ElementAssertion {
+  /// CSS selector of the element (e.g., `"title"`, `"#status .ok"`).
+  pub selector: String,
+
+  /// Text the element must contain, compared with collapsed whitespace. An
+  /// empty text only requires the element to exist.
+  #[serde(default)]
+  pub text: String,
 }
 
 /// Represents a single `HTTP` header (name-value pair).
-#[derive(Debug, serde::Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
 pub struct Header {
   /// The name of the `HTTP` header (e.g., `"Content-Type"`).
   pub name: String,
@@ -95,6 +503,71 @@ pub struct Header {
   pub value: String,
 }
 
+/// Retries of a failed check, so a transient error, e.g. a lost packet or a
+/// DNS timeout, doesn't fail the measurement. Only the last attempt is
+/// measured, and the retries wait between them with an exponential backoff,
+/// which should leave the check enough time to complete before the next
+/// one is due.
+///
+/// Unlike the [attempts](DnsConfig#structfield.attempts) of a DNS query, the
+/// whole check is performed again.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct RetryPolicy {
+  /// Maximum number of attempts of a check, including the first one.
+  pub max_attempts: u32,
+
+  /// Time, in milliseconds, to wait before the first retry.
+  pub backoff_ms: u64,
+
+  /// Factor the time waited is multiplied by after every retry.
+  pub multiplier: u32,
+
+  /// Optional maximum time, in milliseconds, to wait before a retry.
+  pub max_backoff_ms: Option<u64>,
+
+  /// Codes of the errors that are retried. If empty, the
+  /// [transient](ErrorCode::is_transient) ones are.
+  pub retry_on: Vec<ErrorCode>,
+}
+
+impl Default for RetryPolicy {
+  fn default() -> Self {
+    Self {
+      max_attempts: 3,
+      backoff_ms: 500,
+      multiplier: 2,
+      max_backoff_ms: None,
+      retry_on: vec![],
+    }
+  }
+}
+
+impl RetryPolicy {
+  /// Returns whether a check failing with `error` on its `attempt`, counted
+  /// from 1, is retried.
+  pub fn retries(&self, attempt: u32, error: &CollectorError) -> bool {
+    let code = error.code();
+
+    attempt < self.max_attempts
+      && if self.retry_on.is_empty() {
+        code.is_transient()
+      } else {
+        self.retry_on.contains(&code)
+      }
+  }
+
+  /// Returns the time to wait before retrying a check that failed on its
+  /// `attempt`, counted from 1.
+  pub fn backoff(&self, attempt: u32) -> Duration {
+    let backoff = u64::from(self.multiplier)
+      .saturating_pow(attempt.saturating_sub(1))
+      .saturating_mul(self.backoff_ms);
+
+    Duration::from_millis(self.max_backoff_ms.map_or(backoff, |max| backoff.min(max)))
+  }
+}
+
 /// Trait implementation for scheduling monitors.
 impl Schedulable for Monitor {
   type Id = i64;
@@ -112,19 +585,89 @@ impl Schedulable for Monitor {
   }
 }
 
+/// Trait implementation for running monitors when they're due.
+impl Runnable for Monitor {
+  type Output = Measurement;
+
+  async fn run(&self) -> Measurement {
+    self.measure().await
+  }
+
+  /// A measurement with an error is a failure.
+  fn is_failure(output: &Measurement) -> bool {
+    output.error.is_some()
+  }
+
+  /// Runs of monitors of the same host, including its port, are rate
+  /// limited together.
+  fn target(&self) -> Option<&str> {
+    Some(&self.host)
+  }
+
+  /// A failure is confirmed by the confirmation period of the monitor, as
+  /// by its [state](crate::monitor::state::MonitorState).
+  fn confirmations(&self) -> Option<u32> {
+    let period = match &self.config {
+      Config::Ping(config) => config.confirmation_period,
+      Config::Http(config) => config.confirmation_period,
+    };
+
+    Some(period.clamp(1, i64::from(u32::MAX)) as u32)
+  }
+
+  /// The sequence numbers of a removed monitor are dropped.
+  fn forget(id: &i64) {
+    forget_sequence(*id);
+  }
+
+  /// Monitors are queued by their type, `"ping"` or `"http"`.
+  fn class(&self) -> Option<&str> {
+    Some(match self.config {
+      Config::Ping(_) => "ping",
+      Config::Http(_) => "http",
+    })
+  }
+
+  /// Monitors sending the same check, i.e. with the same host,
+  /// configuration and retry policy, share a key, whatever their labels.
+  fn dedup_key(&self) -> Option<u64> {
+    let check = serde_json::to_vec(&(&self.host, &self.config, &self.retry)).ok()?;
+    let mut hasher = DefaultHasher::new();
+    check.hash(&mut hasher);
+
+    Some(hasher.finish())
+  }
+
+  fn share(&self, output: Measurement) -> Measurement {
+    self.adopt(output)
+  }
+
+  /// A run exceeding the deadline is measured as failed, so the monitor
+  /// doesn't go silent.
+  async fn run_with_deadline(&self, deadline: Duration) -> Option<Measurement> {
+    Some(self.measure_with_deadline(deadline).await)
+  }
+}
+
 #[cfg(test)]
 mod tests {
   use super::*;
+  use crate::monitor::errors::{HttpError, PingError};
 
   #[test]
   fn monitor_ping_is_schedulable() {
     let monitor = Monitor {
       id: 1,
+      name: None,
+      description: None,
+      group_id: None,
       host: String::from("test"),
-      config: Config::Ping(PingConfig {
+      labels: Default::default(),
+      retry: None,
+      config: Config::Ping(Box::new(PingConfig {
         check_frequency: 10,
         ..Default::default()
-      }),
+      })),
     };
 
     assert_eq!(monitor.get_id(), 1, "monitor id is correct");
@@ -135,14 +678,158 @@ mod tests {
   fn monitor_http_is_schedulable() {
     let monitor = Monitor {
       id: 1,
+      name: None,
+      description: None,
+      group_id: None,
       host: String::from("test"),
-      config: Config::Http(HttpConfig {
+      labels: Default::default(),
+      retry: None,
+      config: Config::Http(Box::new(HttpConfig {
         check_frequency: 10,
         ..Default::default()
-      }),
+      })),
     };
 
     assert_eq!(monitor.get_id(), 1, "monitor id is correct");
     assert_eq!(monitor.get_interval(), 10, "monitor interval is correct");
   }
+
+  #[test]
+  fn serialized_monitor() {
+    let monitor = Monitor {
+      id: 3,
+      name: Some(String::from("API")),
+      description: None,
+      group_id: Some(1),
+      host: String::from("example.com"),
+      labels: HashMap::from([(String::from("env"), String::from("prod"))]),
+      retry: None,
+      config: Config::Http(Box::new(HttpConfig {
+        check_frequency: 30,
+        method: Method::Post,
+        header: Some(Header {
+          name: String::from("Accept"),
+          value: String::from("text/plain"),
+        }),
+        ..Default::default()
+      })),
+    };
+
+    let json = serde_json::to_value(&monitor).unwrap();
+
+    assert_eq!(json["config"]["type"], "http");
+    assert_eq!(json["config"]["method"], "POST");
+    assert_eq!(
+      serde_json::from_value::<Monitor>(json).unwrap(),
+      monitor,
+      "the monitor is restored"
+    );
+
+    let mut changed = monitor.clone();
+    assert_eq!(changed, monitor);
+
+    if let Config::Http(config) = &mut changed.config {
+      config.check_frequency = 60;
+    }
+    assert_ne!(changed, monitor, "a changed config is told apart");
+  }
+
+  #[test]
+  fn shared_checks() {
+    let monitor = |id: i64, env: &str, path: &str| Monitor {
+      id,
+      name: None,
+      description: None,
+      group_id: None,
+      host: String::from("gateway.example.com"),
+      labels: HashMap::from([(String::from("env"), env.to_owned())]),
+      retry: None,
+      config: Config::Http(Box::new(HttpConfig {
+        path: Some(path.to_owned()),
+        ..Default::default()
+      })),
+    };
+    let (first, second) = (monitor(1, "prod", "/"), monitor(2, "staging", "/"));
+
+    assert_eq!(
+      first.dedup_key(),
+      second.dedup_key(),
+      "the same check of other monitors is shared"
+    );
+    assert_ne!(first.dedup_key(), monitor(3, "prod", "/health").dedup_key());
+
+    let measurement = Measurement {
+      timestamp: time::OffsetDateTime::UNIX_EPOCH,
+      sequence: 1,
+      labels: first.labels.clone(),
+      data: None,
+      error: Some(CollectorError::Ping(PingError::Unreachable)),
+      ..Measurement::fixture(1)
+    };
+    let shared = second.share(measurement.clone());
+
+    assert_eq!(shared.monitor_id, 2);
+    assert_eq!(shared.labels, second.labels);
+    assert_ne!(shared.id, measurement.id);
+    assert!(shared.error.is_some(), "the results are kept");
+  }
+
+  #[test]
+  fn latency_degradation() {
+    let config = Config::Http(Box::new(HttpConfig {
+      latency_warning_ms: Some(100),
+      latency_critical_ms: Some(500),
+      ..Default::default()
+    }));
+
+    assert_eq!(config.degradation(50.0), None, "latency is fine");
+    assert_eq!(
+      config.degradation(150.0),
+      Some(Degradation::Warning),
+      "latency exceeds warning threshold"
+    );
+    assert_eq!(
+      config.degradation(600.0),
+      Some(Degradation::Critical),
+      "latency exceeds critical threshold"
+    );
+    assert_eq!(
+      Config::Ping(Box::default()).degradation(600.0),
+      None,
+      "thresholds aren't configured"
+    );
+  }
+
+  #[test]
+  fn retry_policy() {
+    let unreachable = CollectorError::Ping(PingError::Unreachable);
+    let mismatch = CollectorError::Http(HttpError::StatusMismatch {
+      expected: 200,
+      actual: 503,
+      snippet: None,
+    });
+
+    let policy = RetryPolicy {
+      max_backoff_ms: Some(1500),
+      ..Default::default()
+    };
+    assert!(policy.retries(1, &unreachable));
+    assert!(policy.retries(2, &unreachable));
+    assert!(!policy.retries(3, &unreachable), "attempts are exhausted");
+    assert!(!policy.retries(1, &mismatch), "error isn't transient");
+    assert_eq!(policy.backoff(1), Duration::from_millis(500));
+    assert_eq!(policy.backoff(2), Duration::from_millis(1000));
+    assert_eq!(policy.backoff(3), Duration::from_millis(1500));
+
+    let policy = RetryPolicy {
+      retry_on: vec![ErrorCode::StatusMismatch],
+      ..Default::default()
+    };
+    assert!(policy.retries(1, &mismatch));
+    assert!(!policy.retries(1, &unreachable));
+
+    let policy: RetryPolicy = serde_json::from_str(r#"{"max_attempts": 5}"#).unwrap();
+    assert_eq!(policy.max_attempts, 5);
+    assert_eq!(policy.backoff_ms, 500);
+  }
 }
@@ -0,0 +1,1024 @@
+//! Destinations of measurements.
+//!
+//! A [MeasurementSink] consumes the measurements of monitors, e.g. exports
+//! or stores them. Every measurement sink is also a [Sink] of the
+//! [Runner](crate::schedule::runner::Runner), so the same sinks serve
+//! monitors run by the runner and measured by hand. Conversely, any
+//! `Sink<Measurement>`, such as a bounded channel, can be combined with the
+//! sinks of this module:
+//!
+//! - [FanOut] submits every measurement to two sinks, and can be nested;
+//! - [Buffered] decouples a slow sink with a bounded buffer. Once it's full,
+//!   measurements are dropped, the submitters wait, or measurements are
+//!   spilled to disk, depending on its [Overflow] policy;
+//! - [Pipeline] submits every measurement to a list of sinks in order, each
+//!   receiving only the measurements matching its [Filter], e.g. a store
+//!   receiving all of them and an alerter receiving the failures.
+//!
+//! # Example
+//!
+//! ```rust, no_run
+//! use std::sync::Arc;
+//!
+//! use limon_core::monitor::models::{Measurement, Monitor};
+//! use limon_core::monitor::sink::{Buffered, MeasurementSink};
+//! use limon_core::schedule::Schedule;
+//! use limon_core::schedule::runner::Runner;
+//! use tokio::sync::mpsc;
+//!
+//! struct Log;
+//!
+//! impl MeasurementSink for Log {
+//!   async fn submit(&self, measurement: Measurement) {
+//!     println!("{:?}", measurement);
+//!   }
+//! }
+//!
+//! async fn run() {
+//!   let schedule = Arc::new(Schedule::<Monitor>::new());
+//!   let (sink, mut measurements) = mpsc::channel::<Measurement>(1024);
+//!
+//!   let sink = Buffered::new(Log, 1024).and(sink);
+//!   tokio::spawn(Runner::new(Arc::clone(&schedule), sink).run());
+//!
+//!   while let Some(measurement) = measurements.recv().await {
+//!     // ...
+//!   }
+//! }
+//! ```
+
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::io::{self, BufRead, SeekFrom};
+use std::path::Path;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use tokio::fs::File;
+use tokio::io::{AsyncBufReadExt, AsyncSeekExt, AsyncWriteExt, BufReader};
+use tokio::sync::{Notify, mpsc, oneshot};
+
+use crate::monitor::models::{Measurement, Monitor, MonitorGroup, Status};
+use crate::schedule::runner::Sink;
+
+/// A destination of measurements.
+pub trait MeasurementSink: Send + Sync + 'static {
+  /// Consumes a measurement.
+  fn submit(&self, measurement: Measurement) -> impl Future<Output = ()> + Send;
+
+  /// Consumes several measurements, e.g. sent by a
+  /// [Batched](crate::schedule::runner::Batched) sink. By default, they're
+  /// submitted one by one.
+  fn submit_batch(&self, measurements: Vec<Measurement>) -> impl Future<Output = ()> + Send {
+    async move {
+      for measurement in measurements {
+        self.submit(measurement).await;
+      }
+    }
+  }
+
+  /// Waits until the measurements submitted so far are consumed. By default,
+  /// there's nothing to wait for.
+  fn flush(&self) -> impl Future<Output = ()> + Send {
+    async {}
+  }
+
+  /// Combines the sink with another one, both receiving every measurement.
+  fn and<Other: Sink<Measurement>>(self, other: Other) -> FanOut<Self, Other>
+  where
+    Self: Sized,
+  {
+    FanOut::new(self, other)
+  }
+}
+
+impl<S: MeasurementSink> Sink<Measurement> for S {
+  async fn send(&self, measurement: Measurement) {
+    self.submit(measurement).await;
+  }
+
+  async fn send_batch(&self, measurements: Vec<Measurement>) {
+    self.submit_batch(measurements).await;
+  }
+
+  async fn flush(&self) {
+    MeasurementSink::flush(self).await;
+  }
+}
+
+/// Measurements are sent to the channel. They're dropped if the receiver is
+/// closed.
+impl MeasurementSink for mpsc::UnboundedSender<Measurement> {
+  async fn submit(&self, measurement: Measurement) {
+    let _ = self.send(measurement);
+  }
+}
+
+/// Submits every measurement to two sinks concurrently, e.g. an exporter
+/// and a store. The first one receives a [clone](Measurement#impl-Clone-for-Measurement).
+pub struct FanOut<A, B> {
+  first: A,
+  second: B,
+}
+
+impl<A: Sink<Measurement>, B: Sink<Measurement>> FanOut<A, B> {
+  /// Creates a sink submitting to `first` and `second`.
+  pub fn new(first: A, second: B) -> Self {
+    Self { first, second }
+  }
+
+  /// Returns the sinks.
+  pub fn into_inner(self) -> (A, B) {
+    (self.first, self.second)
+  }
+}
+
+impl<A: Sink<Measurement>, B: Sink<Measurement>> MeasurementSink for FanOut<A, B> {
+  async fn submit(&self, measurement: Measurement) {
+    tokio::join!(
+      self.first.send(measurement.clone()),
+      self.second.send(measurement)
+    );
+  }
+
+  async fn submit_batch(&self, measurements: Vec<Measurement>) {
+    tokio::join!(
+      self.first.send_batch(measurements.clone()),
+      self.second.send_batch(measurements)
+    );
+  }
+
+  async fn flush(&self) {
+    tokio::join!(self.first.flush(), self.second.flush());
+  }
+}
+
+/// What a [Buffered] sink does with a measurement submitted while its buffer
+/// is full.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Overflow {
+  /// The measurement is dropped.
+  #[default]
+  DropNewest,
+
+  /// The oldest waiting measurement is dropped to make room for it.
+  DropOldest,
+
+  /// The submitter waits until there's room, so a sink that can't keep up
+  /// slows down the submitters, e.g. a runner with
+  /// [backpressure](crate::schedule::runner::Runner::backpressure).
+  Block,
+}
+
+/// Submits measurements to a sink from a background task, so a slow sink
+/// doesn't delay the submitters. Once `capacity` measurements wait for the
+/// sink, newer ones are handled according to its [Overflow] policy, or
+/// written to a [spill file](Buffered::spilling).
+pub struct Buffered {
+  queue: Arc<Queue>,
+}
+
+/// The buffer shared by a [Buffered] sink and its task.
+struct Queue {
+  capacity: usize,
+  overflow: Overflow,
+  state: Mutex<State>,
+
+  /// The spill file, locked for as long as it's written or read, so the
+  /// state isn't locked during its I/O.
+  spill: Option<tokio::sync::Mutex<Spill>>,
+
+  /// Wakes the task once a message is queued or the sink is dropped.
+  queued: Notify,
+
+  /// Wakes a blocked submitter once a measurement is taken by the task.
+  taken: Notify,
+
+  dropped: AtomicU64,
+  spilled: AtomicU64,
+}
+
+struct State {
+  messages: VecDeque<Message>,
+
+  /// Number of measurements among the messages.
+  measurements: usize,
+
+  /// Number of measurements in the spill file not read back yet.
+  unread: usize,
+
+  closed: bool,
+}
+
+/// A message to the task of a [Buffered] sink.
+enum Message {
+  Measurement(Box<Measurement>),
+
+  /// Flushes the sink once the measurements before are submitted, then
+  /// replies.
+  Flush(oneshot::Sender<()>),
+}
+
+/// A file measurements overflowing the buffer are appended to, as lines of
+/// JSON, until the task reads them back.
+struct Spill {
+  file: File,
+
+  /// Offset of the first line not read back yet.
+  offset: u64,
+}
+
+impl Spill {
+  /// Opens the file at `path`, keeping the lines left by a previous process,
+  /// and returns it along with their number.
+  fn open(path: &Path) -> io::Result<(Self, usize)> {
+    let file = std::fs::File::options()
+      .read(true)
+      .append(true)
+      .create(true)
+      .open(path)?;
+    let lines = io::BufReader::new(&file).lines().count();
+
+    Ok((
+      Self {
+        file: File::from_std(file),
+        offset: 0,
+      },
+      lines,
+    ))
+  }
+
+  async fn write(&mut self, measurement: &Measurement) -> io::Result<()> {
+    let mut line = serde_json::to_vec(measurement)?;
+    line.push(b'\n');
+
+    self.file.write_all(&line).await?;
+    self.file.flush().await
+  }
+
+  /// Reads back up to `count` lines, fewer if the file ends first. Lines
+  /// that can't be read are returned as errors.
+  async fn read(&mut self, count: usize) -> io::Result<Vec<serde_json::Result<Measurement>>> {
+    self.file.seek(SeekFrom::Start(self.offset)).await?;
+    let mut reader = BufReader::new(&mut self.file);
+
+    let mut measurements = Vec::new();
+    let mut line = String::new();
+
+    while measurements.len() < count {
+      line.clear();
+
+      let read = reader.read_line(&mut line).await?;
+      if read == 0 {
+        break;
+      }
+
+      self.offset += read as u64;
+      measurements.push(serde_json::from_str(&line));
+    }
+
+    Ok(measurements)
+  }
+
+  /// Empties the file once all its lines are read back.
+  async fn truncate(&mut self) -> io::Result<()> {
+    self.offset = 0;
+    self.file.set_len(0).await
+  }
+}
+
+impl Buffered {
+  /// Spawns the task submitting to `sink` onto the current Tokio runtime,
+  /// dropping the measurements overflowing the buffer. It stops once the
+  /// buffered sink is dropped and the waiting measurements are submitted.
+  ///
+  /// # Panics
+  ///
+  /// Panics if called outside of a Tokio runtime.
+  pub fn new<S: Sink<Measurement>>(sink: S, capacity: usize) -> Self {
+    Self::with_overflow(sink, capacity, Overflow::default())
+  }
+
+  /// Spawns the task submitting to `sink`, handling the measurements
+  /// overflowing the buffer according to `overflow`.
+  ///
+  /// # Panics
+  ///
+  /// Panics if called outside of a Tokio runtime.
+  pub fn with_overflow<S: Sink<Measurement>>(sink: S, capacity: usize, overflow: Overflow) -> Self {
+    Self::spawn(sink, capacity, overflow, None)
+  }
+
+  /// Spawns the task submitting to `sink`, appending the measurements
+  /// overflowing the buffer to the file at `path`. They're submitted in
+  /// order once the buffer has room again, along with the ones left in the
+  /// file by a previous process, e.g. one stopped while its sink was down.
+  ///
+  /// # Panics
+  ///
+  /// Panics if called outside of a Tokio runtime.
+  pub fn spilling<S: Sink<Measurement>>(
+    sink: S,
+    capacity: usize,
+    path: impl AsRef<Path>,
+  ) -> io::Result<Self> {
+    let (spill, unread) = Spill::open(path.as_ref())?;
+
+    Ok(Self::spawn(
+      sink,
+      capacity,
+      Overflow::default(),
+      Some((spill, unread)),
+    ))
+  }
+
+  fn spawn<S: Sink<Measurement>>(
+    sink: S,
+    capacity: usize,
+    overflow: Overflow,
+    spill: Option<(Spill, usize)>,
+  ) -> Self {
+    let unread = spill.as_ref().map_or(0, |(_, unread)| *unread);
+
+    let queue = Arc::new(Queue {
+      capacity: capacity.max(1),
+      overflow,
+      state: Mutex::new(State {
+        messages: VecDeque::new(),
+        measurements: 0,
+        unread,
+        closed: false,
+      }),
+      spill: spill.map(|(spill, _)| tokio::sync::Mutex::new(spill)),
+      queued: Notify::new(),
+      taken: Notify::new(),
+      dropped: AtomicU64::new(0),
+      spilled: AtomicU64::new(0),
+    });
+
+    // Spilled measurements are read back right away.
+    queue.queued.notify_one();
+
+    let task = Arc::clone(&queue);
+    tokio::spawn(async move {
+      loop {
+        let queued = task.queued.notified();
+        tokio::pin!(queued);
+        queued.as_mut().enable();
+
+        match task.take().await {
+          Some(Message::Measurement(measurement)) => sink.send(*measurement).await,
+          Some(Message::Flush(reply)) => {
+            sink.flush().await;
+            let _ = reply.send(());
+          }
+          None if task.state().closed => break,
+          None => queued.await,
+        }
+      }
+    });
+
+    Self { queue }
+  }
+
+  /// Returns the number of measurements dropped because the buffer was full,
+  /// or they couldn't be spilled or read back.
+  pub fn dropped(&self) -> u64 {
+    self.queue.dropped.load(Ordering::Relaxed)
+  }
+
+  /// Returns the number of measurements written to the spill file.
+  pub fn spilled(&self) -> u64 {
+    self.queue.spilled.load(Ordering::Relaxed)
+  }
+
+  /// Returns the number of measurements waiting for the sink, in the buffer
+  /// and the spill file.
+  pub fn pending(&self) -> usize {
+    let state = self.queue.state();
+
+    state.measurements + state.unread
+  }
+}
+
+impl Queue {
+  fn state(&self) -> MutexGuard<'_, State> {
+    self.state.lock().expect("buffer lock")
+  }
+
+  fn drop_measurements(&self, count: u64) {
+    self.dropped.fetch_add(count, Ordering::Relaxed);
+
+    #[cfg(feature = "metrics")]
+    metrics::counter!("limon_dropped_measurements_total").increment(count);
+  }
+
+  /// Queues a measurement, or returns it if the submitter has to wait for
+  /// room.
+  async fn push(&self, measurement: Measurement) -> Option<Measurement> {
+    // The spill file stays locked until the measurement is queued, so it's
+    // queued in the order it was submitted.
+    let mut spill = match &self.spill {
+      Some(spill) => Some(spill.lock().await),
+      None => None,
+    };
+
+    if let Some(spill) = &mut spill {
+      let spilling = {
+        let state = self.state();
+        state.unread > 0 || state.measurements >= self.capacity
+      };
+
+      // Once measurements are spilled, newer ones are too, to keep the order.
+      if spilling {
+        match spill.write(&measurement).await {
+          Ok(()) => {
+            self.state().unread += 1;
+            self.spilled.fetch_add(1, Ordering::Relaxed);
+          }
+          Err(_) => self.drop_measurements(1),
+        }
+        self.queued.notify_one();
+
+        return None;
+      }
+    }
+
+    let mut state = self.state();
+
+    if state.measurements >= self.capacity {
+      match self.overflow {
+        Overflow::DropNewest => {
+          self.drop_measurements(1);
+          return None;
+        }
+        Overflow::DropOldest => {
+          let oldest = state
+            .messages
+            .iter()
+            .position(|message| matches!(message, Message::Measurement(_)));
+
+          if let Some(oldest) = oldest {
+            state.messages.remove(oldest);
+            state.measurements -= 1;
+            self.drop_measurements(1);
+          }
+        }
+        Overflow::Block => return Some(measurement),
+      }
+    }
+
+    state
+      .messages
+      .push_back(Message::Measurement(Box::new(measurement)));
+    state.measurements += 1;
+    self.queued.notify_one();
+
+    None
+  }
+
+  /// Takes the next message, reading spilled measurements back once the
+  /// buffer has none left.
+  async fn take(&self) -> Option<Message> {
+    let unread = self.state().unread;
+
+    if let Some(spill) = &self.spill
+      && unread > 0
+    {
+      let mut spill = spill.lock().await;
+      let count = {
+        let state = self.state();
+        if state.measurements == 0 {
+          state.unread.min(self.capacity)
+        } else {
+          0
+        }
+      };
+
+      if count > 0 {
+        self.read_back(&mut spill, count).await;
+      }
+    }
+
+    let mut state = self.state();
+    let message = state.messages.pop_front()?;
+    if let Message::Measurement(_) = message {
+      state.measurements -= 1;
+      self.taken.notify_one();
+    }
+
+    Some(message)
+  }
+
+  /// Queues `count` spilled measurements ahead of the messages, and empties
+  /// the spill file once they're all read back.
+  async fn read_back(&self, spill: &mut Spill, count: usize) {
+    let read = spill.read(count).await;
+    let unread = {
+      let mut state = self.state();
+
+      match read {
+        Ok(lines) => {
+          // Lines missing from the file are lost.
+          state.unread = if lines.len() == count {
+            state.unread - count
+          } else {
+            0
+          };
+
+          // They precede the flushes queued since they were spilled.
+          for line in lines.into_iter().rev() {
+            match line {
+              Ok(measurement) => {
+                state
+                  .messages
+                  .push_front(Message::Measurement(Box::new(measurement)));
+                state.measurements += 1;
+              }
+              Err(_) => self.drop_measurements(1),
+            }
+          }
+        }
+        Err(_) => {
+          self.drop_measurements(state.unread as u64);
+          state.unread = 0;
+        }
+      }
+
+      state.unread
+    };
+
+    if unread == 0 {
+      let _ = spill.truncate().await;
+    }
+  }
+}
+
+impl Drop for Buffered {
+  fn drop(&mut self) {
+    self.queue.state().closed = true;
+    self.queue.queued.notify_one();
+  }
+}
+
+impl MeasurementSink for Buffered {
+  async fn submit(&self, mut measurement: Measurement) {
+    loop {
+      let taken = self.queue.taken.notified();
+      tokio::pin!(taken);
+      taken.as_mut().enable();
+
+      match self.queue.push(measurement).await {
+        Some(waiting) => measurement = waiting,
+        None => return,
+      }
+
+      taken.await;
+    }
+  }
+
+  /// Waits until the buffered and spilled measurements are submitted to the
+  /// sink, then flushes it.
+  async fn flush(&self) {
+    let (reply, flushed) = oneshot::channel();
+
+    self.queue.state().messages.push_back(Message::Flush(reply));
+    self.queue.queued.notify_one();
+
+    let _ = flushed.await;
+  }
+}
+
+/// Which measurements a stage of a [Pipeline] receives. A measurement
+/// matches if it matches every condition set, so an empty filter matches
+/// them all.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Filter {
+  statuses: Option<HashSet<Status>>,
+  monitors: Option<HashSet<i64>>,
+  labels: HashMap<String, String>,
+}
+
+impl Filter {
+  /// Creates a filter matching every measurement.
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// Creates a filter matching the failed measurements.
+  pub fn failures() -> Self {
+    Self::new().status(Status::Down)
+  }
+
+  /// Matches the measurements with the status, or any of the statuses added
+  /// before.
+  pub fn status(mut self, status: Status) -> Self {
+    self.statuses.get_or_insert_default().insert(status);
+    self
+  }
+
+  /// Matches the measurements of the monitors, or of any monitor added
+  /// before.
+  pub fn monitors(mut self, monitor_ids: impl IntoIterator<Item = i64>) -> Self {
+    self.monitors.get_or_insert_default().extend(monitor_ids);
+    self
+  }
+
+  /// Matches the measurements of the members of the group among `monitors`.
+  /// Monitors joining the group afterwards aren't matched.
+  pub fn group<'a>(
+    self,
+    group: &MonitorGroup,
+    monitors: impl IntoIterator<Item = &'a Monitor>,
+  ) -> Self {
+    let members: Vec<i64> = group.members(monitors).map(|monitor| monitor.id).collect();
+
+    self.monitors(members)
+  }
+
+  /// Matches the measurements labeled with the value.
+  pub fn label(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+    self.labels.insert(name.into(), value.into());
+    self
+  }
+
+  /// Returns `true` if the measurement matches the filter.
+  pub fn matches(&self, measurement: &Measurement) -> bool {
+    self
+      .statuses
+      .as_ref()
+      .is_none_or(|statuses| statuses.contains(&measurement.status()))
+      && self
+        .monitors
+        .as_ref()
+        .is_none_or(|monitors| monitors.contains(&measurement.monitor_id))
+      && self
+        .labels
+        .iter()
+        .all(|(name, value)| measurement.labels.get(name) == Some(value))
+  }
+}
+
+/// A future of a type-erased sink.
+type SinkFuture<'a> = Pin<Box<dyn Future<Output = ()> + Send + 'a>>;
+
+/// A [Sink] of measurements that can be boxed.
+trait DynSink: Send + Sync {
+  fn send(&self, measurement: Measurement) -> SinkFuture<'_>;
+
+  fn send_batch(&self, measurements: Vec<Measurement>) -> SinkFuture<'_>;
+
+  fn flush(&self) -> SinkFuture<'_>;
+}
+
+impl<S: Sink<Measurement>> DynSink for S {
+  fn send(&self, measurement: Measurement) -> SinkFuture<'_> {
+    Box::pin(Sink::send(self, measurement))
+  }
+
+  fn send_batch(&self, measurements: Vec<Measurement>) -> SinkFuture<'_> {
+    Box::pin(Sink::send_batch(self, measurements))
+  }
+
+  fn flush(&self) -> SinkFuture<'_> {
+    Box::pin(Sink::flush(self))
+  }
+}
+
+/// Submits every measurement to a list of sinks, one after the other, in
+/// the order they were added. Each sink only receives the measurements
+/// matching its [Filter], as a [clone](Measurement#impl-Clone-for-Measurement).
+///
+/// ```rust, no_run
+/// use limon_core::monitor::models::Measurement;
+/// use limon_core::monitor::sink::{Filter, Pipeline};
+/// use tokio::sync::mpsc;
+///
+/// let (store, _) = mpsc::channel::<Measurement>(1024);
+/// let (alerts, _) = mpsc::channel::<Measurement>(64);
+///
+/// let pipeline = Pipeline::new()
+///   .sink(store)
+///   .filtered(alerts, Filter::failures().label("env", "prod"));
+/// ```
+#[derive(Default)]
+pub struct Pipeline {
+  stages: Vec<(Filter, Box<dyn DynSink>)>,
+}
+
+impl Pipeline {
+  /// Creates a pipeline without sinks.
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// Adds a sink receiving every measurement.
+  pub fn sink<S: Sink<Measurement>>(self, sink: S) -> Self {
+    self.filtered(sink, Filter::new())
+  }
+
+  /// Adds a sink receiving the measurements matching the filter.
+  pub fn filtered<S: Sink<Measurement>>(mut self, sink: S, filter: Filter) -> Self {
+    self.stages.push((filter, Box::new(sink)));
+    self
+  }
+}
+
+impl MeasurementSink for Pipeline {
+  async fn submit(&self, measurement: Measurement) {
+    for (filter, sink) in &self.stages {
+      if filter.matches(&measurement) {
+        sink.send(measurement.clone()).await;
+      }
+    }
+  }
+
+  async fn submit_batch(&self, measurements: Vec<Measurement>) {
+    for (filter, sink) in &self.stages {
+      let matching: Vec<Measurement> = measurements
+        .iter()
+        .filter(|measurement| filter.matches(measurement))
+        .cloned()
+        .collect();
+
+      if !matching.is_empty() {
+        sink.send_batch(matching).await;
+      }
+    }
+  }
+
+  async fn flush(&self) {
+    for (_, sink) in &self.stages {
+      sink.flush().await;
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::sync::Mutex;
+  use std::time::Duration;
+
+  use super::*;
+  use crate::monitor::models::Config;
+
+  /// Collects the ids of the monitors of the measurements.
+  #[derive(Clone, Default)]
+  struct Collect(Arc<Mutex<Vec<i64>>>);
+
+  impl MeasurementSink for Collect {
+    async fn submit(&self, measurement: Measurement) {
+      self.0.lock().unwrap().push(measurement.monitor_id);
+    }
+  }
+
+  /// Waits until it's released.
+  struct Stuck(Arc<tokio::sync::Notify>);
+
+  impl MeasurementSink for Stuck {
+    async fn submit(&self, _measurement: Measurement) {
+      self.0.notified().await;
+    }
+  }
+
+  #[tokio::test]
+  async fn fan_out() {
+    let collect = Collect::default();
+    let (bounded, mut first) = mpsc::channel(4);
+    let (unbounded, mut second) = mpsc::unbounded_channel();
+
+    let sink = collect.clone().and(bounded).and(unbounded);
+    sink.submit(Measurement::fixture(1).up(false)).await;
+    Sink::send(&sink, Measurement::fixture(2).up(false)).await;
+
+    assert_eq!(*collect.0.lock().unwrap(), [1, 2]);
+    assert_eq!(first.recv().await.unwrap().monitor_id, 1);
+    assert_eq!(first.recv().await.unwrap().monitor_id, 2);
+
+    let copy = second.recv().await.unwrap();
+    assert_eq!(copy.monitor_id, 1);
+    assert_eq!(
+      copy.error.unwrap().to_string(),
+      Measurement::fixture(1).up(false).error.unwrap().to_string(),
+      "the error is kept as its report"
+    );
+  }
+
+  #[tokio::test]
+  async fn pipeline() {
+    let (all, failures, group) = (Collect::default(), Collect::default(), Collect::default());
+    let monitors: Vec<Monitor> = (1..=3)
+      .map(|id| Monitor {
+        id,
+        name: None,
+        description: None,
+        group_id: (id != 2).then_some(10),
+        host: String::from("example.com"),
+        labels: Default::default(),
+        retry: None,
+        config: Config::Ping(Default::default()),
+      })
+      .collect();
+    let checkout = MonitorGroup {
+      id: 10,
+      name: String::from("Checkout"),
+      description: None,
+    };
+
+    let pipeline = Pipeline::new()
+      .sink(all.clone())
+      .filtered(failures.clone(), Filter::failures())
+      .filtered(
+        group.clone(),
+        Filter::new()
+          .group(&checkout, &monitors)
+          .label("env", "prod"),
+      );
+
+    let mut succeeded = Measurement::fixture(3);
+    succeeded
+      .labels
+      .insert(String::from("env"), String::from("prod"));
+
+    pipeline.submit(Measurement::fixture(1).up(false)).await;
+    pipeline
+      .submit_batch(vec![Measurement::fixture(2).up(false), succeeded])
+      .await;
+    MeasurementSink::flush(&pipeline).await;
+
+    assert_eq!(*all.0.lock().unwrap(), [1, 2, 3]);
+    assert_eq!(*failures.0.lock().unwrap(), [1, 2]);
+    assert_eq!(
+      *group.0.lock().unwrap(),
+      [3],
+      "labeled measurements of the group"
+    );
+  }
+
+  #[tokio::test]
+  async fn buffered_flush() {
+    let collect = Collect::default();
+    let sink = Buffered::new(collect.clone(), 8);
+
+    for monitor_id in 0..3 {
+      sink
+        .submit(Measurement::fixture(monitor_id).up(false))
+        .await;
+    }
+    MeasurementSink::flush(&sink).await;
+
+    assert_eq!(
+      *collect.0.lock().unwrap(),
+      [0, 1, 2],
+      "buffered measurements are submitted"
+    );
+  }
+
+  #[tokio::test]
+  async fn buffered() {
+    let release = Arc::new(tokio::sync::Notify::new());
+    let (sender, mut receiver) = mpsc::channel(8);
+    let sink = Buffered::new(Stuck(Arc::clone(&release)).and(sender), 1);
+
+    // The first one is taken by the task, the second one waits in the buffer.
+    for monitor_id in 0..4 {
+      sink
+        .submit(Measurement::fixture(monitor_id).up(false))
+        .await;
+      tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+
+    assert_eq!(sink.dropped(), 2);
+
+    release.notify_one();
+    release.notify_one();
+    drop(sink);
+
+    assert_eq!(receiver.recv().await.unwrap().monitor_id, 0);
+    assert_eq!(receiver.recv().await.unwrap().monitor_id, 1);
+    assert!(receiver.recv().await.is_none());
+  }
+
+  #[tokio::test]
+  async fn overflow() {
+    let release = Arc::new(tokio::sync::Notify::new());
+    let (sender, mut receiver) = mpsc::channel(8);
+    let sink = Buffered::with_overflow(
+      Stuck(Arc::clone(&release)).and(sender),
+      1,
+      Overflow::DropOldest,
+    );
+
+    for monitor_id in 0..4 {
+      sink
+        .submit(Measurement::fixture(monitor_id).up(false))
+        .await;
+      tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+
+    assert_eq!(sink.dropped(), 2);
+    assert_eq!(sink.pending(), 1);
+
+    release.notify_one();
+    release.notify_one();
+    drop(sink);
+
+    assert_eq!(receiver.recv().await.unwrap().monitor_id, 0);
+    assert_eq!(
+      receiver.recv().await.unwrap().monitor_id,
+      3,
+      "the oldest waiting measurements are dropped"
+    );
+    assert!(receiver.recv().await.is_none());
+
+    let release = Arc::new(tokio::sync::Notify::new());
+    let (sender, mut receiver) = mpsc::channel(8);
+    let sink = Arc::new(Buffered::with_overflow(
+      Stuck(Arc::clone(&release)).and(sender),
+      1,
+      Overflow::Block,
+    ));
+
+    let submit = tokio::spawn({
+      let sink = Arc::clone(&sink);
+      async move {
+        for monitor_id in 0..3 {
+          sink
+            .submit(Measurement::fixture(monitor_id).up(false))
+            .await;
+        }
+      }
+    });
+
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert!(!submit.is_finished(), "the submitter waits for room");
+
+    for _ in 0..3 {
+      release.notify_one();
+      tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    submit.await.unwrap();
+    MeasurementSink::flush(&*sink).await;
+
+    assert_eq!(sink.dropped(), 0);
+    for monitor_id in 0..3 {
+      assert_eq!(receiver.recv().await.unwrap().monitor_id, monitor_id);
+    }
+  }
+
+  #[tokio::test]
+  async fn spilling() {
+    let path = std::env::temp_dir().join(format!("limon-spill-{}.ndjson", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+
+    let release = Arc::new(tokio::sync::Notify::new());
+    let collect = Collect::default();
+    let sink =
+      Buffered::spilling(Stuck(Arc::clone(&release)).and(collect.clone()), 1, &path).unwrap();
+
+    for monitor_id in 0..5 {
+      sink
+        .submit(Measurement::fixture(monitor_id).up(false))
+        .await;
+      tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+
+    assert_eq!(sink.spilled(), 3);
+    assert_eq!(sink.pending(), 4);
+    assert_eq!(std::fs::read_to_string(&path).unwrap().lines().count(), 3);
+
+    for _ in 0..5 {
+      release.notify_one();
+      tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    MeasurementSink::flush(&sink).await;
+
+    assert_eq!(sink.dropped(), 0);
+    assert_eq!(
+      *collect.0.lock().unwrap(),
+      [0, 1, 2, 3, 4],
+      "spilled measurements are submitted in order"
+    );
+    assert_eq!(std::fs::metadata(&path).unwrap().len(), 0);
+    drop(sink);
+
+    std::fs::write(
+      &path,
+      format!(
+        "{}\n",
+        serde_json::to_string(&Measurement::fixture(7).up(false)).unwrap()
+      ),
+    )
+    .unwrap();
+    let collect = Collect::default();
+    let sink = Buffered::spilling(collect.clone(), 1, &path).unwrap();
+    MeasurementSink::flush(&sink).await;
+
+    assert_eq!(
+      *collect.0.lock().unwrap(),
+      [7],
+      "measurements left by a previous process are submitted"
+    );
+    std::fs::remove_file(&path).unwrap();
+  }
+}
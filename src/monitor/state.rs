@@ -0,0 +1,245 @@
+//! Confirmed state of a monitor.
+//!
+//! A single failed check is often a blip rather than an outage, so a
+//! [MonitorState] only changes its [State] after a number of consecutive
+//! results disagree with it: the
+//! [`confirmation_period`](crate::monitor::models::HttpConfig#structfield.confirmation_period)
+//! to go down, and the
+//! [`recovery_period`](crate::monitor::models::HttpConfig#structfield.recovery_period)
+//! to go back up.
+//!
+//! # Example
+//!
+//! ```rust
+//! use limon_core::monitor::models::Measurement;
+//! use limon_core::monitor::state::{MonitorState, Transition};
+//!
+//! fn notify(state: &mut MonitorState, measurement: &Measurement) {
+//!   if let Some(Transition { from, to, .. }) = state.record(measurement) {
+//!     println!("monitor {} went from {from:?} to {to:?}", measurement.monitor_id);
+//!   }
+//! }
+//! ```
+
+use time::OffsetDateTime;
+
+use crate::monitor::models::{Config, Measurement, Status};
+
+/// Confirmed state of a monitor.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum State {
+  /// The monitor's checks succeed, possibly degraded.
+  Up,
+
+  /// The monitor's checks fail.
+  Down,
+}
+
+/// A confirmed change of the state of a monitor, returned by
+/// [MonitorState::record].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Transition {
+  /// The state before the change.
+  pub from: State,
+
+  /// The state after the change.
+  pub to: State,
+
+  /// When the first of the consecutive measurements that caused the change
+  /// was taken.
+  pub since: OffsetDateTime,
+
+  /// When the measurement that confirmed the change was taken.
+  pub at: OffsetDateTime,
+}
+
+/// Tracks the state of a single monitor from its successive measurements.
+///
+/// Monitors are assumed to be up until confirmed otherwise. Measurements with
+/// neither data nor an error don't count towards any change.
+#[derive(Debug, Clone)]
+pub struct MonitorState {
+  confirmation_period: u32,
+  recovery_period: u32,
+  state: State,
+
+  /// Number of consecutive measurements disagreeing with the state so far.
+  streak: u32,
+
+  /// When the first of them was taken.
+  since: Option<OffsetDateTime>,
+}
+
+impl MonitorState {
+  /// Creates the state of a monitor, which goes down after
+  /// `confirmation_period` consecutive failed measurements and back up after
+  /// `recovery_period` consecutive successful ones. Periods below 1 are
+  /// treated as 1.
+  pub fn new(confirmation_period: i64, recovery_period: i64) -> Self {
+    let period = |period: i64| period.clamp(1, i64::from(u32::MAX)) as u32;
+
+    Self {
+      confirmation_period: period(confirmation_period),
+      recovery_period: period(recovery_period),
+      state: State::Up,
+      streak: 0,
+      since: None,
+    }
+  }
+
+  /// Creates the state of a monitor with the periods of its config.
+  pub fn from_config(config: &Config) -> Self {
+    match config {
+      Config::Ping(config) => Self::new(config.confirmation_period, config.recovery_period),
+      Config::Http(config) => Self::new(config.confirmation_period, config.recovery_period),
+    }
+  }
+
+  /// Sets the initial state, e.g. to resume from a persisted one.
+  pub fn with_state(mut self, state: State) -> Self {
+    self.state = state;
+    self
+  }
+
+  /// Returns the confirmed state.
+  pub fn state(&self) -> State {
+    self.state
+  }
+
+  /// Returns the number of consecutive measurements so far that disagree with
+  /// the confirmed state.
+  pub fn pending(&self) -> u32 {
+    self.streak
+  }
+
+  /// Records the next measurement of the monitor, returning the transition it
+  /// confirms, if any.
+  pub fn record(&mut self, measurement: &Measurement) -> Option<Transition> {
+    let observed = match measurement.status() {
+      Status::Up | Status::Degraded => State::Up,
+      Status::Down => State::Down,
+      Status::Unknown => return None,
+    };
+
+    if observed == self.state {
+      self.streak = 0;
+      self.since = None;
+
+      return None;
+    }
+
+    self.streak += 1;
+    let since = *self.since.get_or_insert(measurement.timestamp);
+
+    let period = match observed {
+      State::Down => self.confirmation_period,
+      State::Up => self.recovery_period,
+    };
+
+    if self.streak < period {
+      return None;
+    }
+
+    let from = std::mem::replace(&mut self.state, observed);
+    self.streak = 0;
+    self.since = None;
+
+    Some(Transition {
+      from,
+      to: observed,
+      since,
+      at: measurement.timestamp,
+    })
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::monitor::models::{Degradation, HttpConfig};
+
+  fn at(seconds: i64) -> OffsetDateTime {
+    OffsetDateTime::from_unix_timestamp(seconds).unwrap()
+  }
+
+  #[test]
+  fn confirmed_transitions() {
+    let mut state = MonitorState::from_config(&Config::Http(Box::new(HttpConfig {
+      confirmation_period: 3,
+      recovery_period: 2,
+      ..Default::default()
+    })));
+
+    assert_eq!(state.record(&Measurement::fixture(1).at(0).up(false)), None);
+    assert_eq!(
+      state.record(&Measurement::fixture(1).at(10).up(false)),
+      None
+    );
+    assert_eq!(
+      state.record(&Measurement::fixture(1).at(20)),
+      None,
+      "a blip"
+    );
+    assert_eq!(state.pending(), 0);
+
+    assert_eq!(
+      state.record(&Measurement::fixture(1).at(30).up(false)),
+      None
+    );
+    assert_eq!(
+      state.record(&Measurement {
+        data: None,
+        ..Measurement::fixture(1).at(40)
+      }),
+      None
+    );
+    assert_eq!(
+      state.record(&Measurement::fixture(1).at(50).up(false)),
+      None
+    );
+    assert_eq!(state.pending(), 2, "unknown results are skipped");
+    assert_eq!(
+      state.record(&Measurement::fixture(1).at(60).up(false)),
+      Some(Transition {
+        from: State::Up,
+        to: State::Down,
+        since: at(30),
+        at: at(60),
+      })
+    );
+    assert_eq!(state.state(), State::Down);
+
+    assert_eq!(state.record(&Measurement::fixture(1).at(70)), None);
+    assert_eq!(
+      state.record(&Measurement::fixture(1).at(80)),
+      Some(Transition {
+        from: State::Down,
+        to: State::Up,
+        since: at(70),
+        at: at(80),
+      })
+    );
+  }
+
+  #[test]
+  fn immediate_transitions() {
+    let mut state = MonitorState::new(0, 1).with_state(State::Down);
+
+    assert_eq!(state.record(&Measurement::fixture(1).at(0).up(false)), None);
+    assert!(
+      state
+        .record(&Measurement {
+          degradation: Some(Degradation::Warning),
+          ..Measurement::fixture(1).at(10)
+        })
+        .is_some()
+    );
+    assert!(
+      state
+        .record(&Measurement::fixture(1).at(20).up(false))
+        .is_some()
+    );
+    assert_eq!(state.state(), State::Down);
+  }
+}
@@ -10,6 +10,26 @@
 //! - A mapping of `interval` to sets of item `id`, allowing efficient
 //!   retrieval of all items that should be polled at a given interval.
 //!
+//! Besides recurring items, an item can be scheduled to run once, at a given
+//! moment or after a delay, e.g. to recheck a failing monitor sooner.
+//!
+//! A schedule can be configured at once with a
+//! [ScheduleBuilder], and a cheap read-only
+//! [view](ScheduleView) of it can be taken to be queried without locks.
+//!
+//! The state of a schedule can be saved with [Schedule::snapshot] and restored
+//! after a restart with [Schedule::restore], keeping the due times of the
+//! items.
+//!
+//! Items can be kept from being due during maintenance with blackout
+//! [windows](window::Window), for the whole schedule or for single items.
+//!
+//! Due items can be run periodically by a [Runner](runner::Runner), which
+//! takes the time from the schedule's [Clock], at a limited
+//! [rate](limiter::RateLimiter) per target. Schedules with thousands of
+//! distinct intervals can use a [WheelSchedule](wheel::WheelSchedule)
+//! instead.
+//!
 //! # Example
 //!
 //! ```rust
@@ -33,37 +53,117 @@
 //! let schedule: Schedule<Task> = Schedule::new();
 //!
 //! # tokio_test::block_on(async {
-//! schedule.insert(Task { id: 1, interval: 30 }).await;
-//! schedule.insert(Task { id: 2, interval: 60 }).await;
+//! schedule.insert(Task { id: 1, interval: 30 }).await.unwrap();
+//! schedule.insert(Task { id: 2, interval: 60 }).await.unwrap();
 //!
 //! assert_eq!(schedule.get_due(0, 90).await.len(), 2);
 //! # })
 //! ```
 
 use std::collections::{HashMap, HashSet};
-use std::hash::Hash;
+use std::hash::{BuildHasher, DefaultHasher, Hash, Hasher, RandomState};
+use std::mem;
 use std::sync::Arc;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use tokio::sync::{Mutex, RwLock, RwLockReadGuard, RwLockWriteGuard, broadcast};
+
+use crate::schedule::builder::ScheduleBuilder;
+use crate::schedule::clock::Clock;
+use crate::schedule::errors::ScheduleError;
+use crate::schedule::window::Window;
+
+pub mod breaker;
+pub mod builder;
+pub mod clock;
+pub mod errors;
+pub mod events;
+pub mod limiter;
+pub mod recheck;
+pub mod runner;
+pub mod wheel;
+pub mod window;
 
-use tokio::sync::RwLock;
+/// The default number of shards of a [Schedule].
+const DEFAULT_SHARDS: usize = 16;
+
+/// The number of [events](Event) kept for subscribers that lag behind.
+const EVENTS_CAPACITY: usize = 1024;
 
 /// A trait for items that can be scheduled.
 ///
 /// This trait defines the necessary requirements for an item to be
 /// stored and managed by a [Schedule]. Each item must have a unique
 /// identifier `id` and an associated `interval`. Both types must
-/// support hashing and equality checks, and be convertible to `i64`.
+/// support hashing and equality checks. The `id` can be of any such type,
+/// e.g. a number, a UUID or a string, while the `interval` must be
+/// measurable in [Seconds].
 pub trait Schedulable {
   /// The unique identifier for the item.
-  type Id: Eq + Hash + Into<i64> + Copy;
+  type Id: Eq + Hash + Clone;
 
   /// The interval associated with the item.
-  type Interval: Eq + Hash + Into<i64> + Copy;
+  type Interval: Eq + Hash + Seconds + Copy;
 
   /// Returns the unique identifier of the item.
   fn get_id(&self) -> Self::Id;
 
   /// Returns the interval of the item.
   fn get_interval(&self) -> Self::Interval;
+
+  /// Returns the maximum delay, in seconds, of the item's due times after the
+  /// multiples of its interval. If `None`, the jitter of the [Schedule] is
+  /// used.
+  fn get_jitter(&self) -> Option<i64> {
+    None
+  }
+}
+
+/// An interval of [Schedulable] items, measured in whole seconds.
+pub trait Seconds {
+  /// Returns the number of seconds.
+  fn seconds(&self) -> i64;
+
+  /// Returns the interval of a number of seconds, if it can be represented,
+  /// e.g. for a [circuit breaker](breaker) to stretch it. By default, it
+  /// can't.
+  fn from_seconds(seconds: i64) -> Option<Self>
+  where
+    Self: Sized,
+  {
+    let _ = seconds;
+    None
+  }
+}
+
+macro_rules! impl_seconds {
+  ($($type:ty),*) => {
+    $(
+      impl Seconds for $type {
+        fn seconds(&self) -> i64 {
+          i64::from(*self)
+        }
+
+        fn from_seconds(seconds: i64) -> Option<Self> {
+          <$type>::try_from(seconds).ok()
+        }
+      }
+    )*
+  };
+}
+
+impl_seconds!(i8, i16, i32, i64, u8, u16, u32);
+
+/// Durations are truncated to whole seconds.
+impl Seconds for Duration {
+  fn seconds(&self) -> i64 {
+    i64::try_from(self.as_secs()).unwrap_or(i64::MAX)
+  }
+
+  fn from_seconds(seconds: i64) -> Option<Self> {
+    Some(Duration::from_secs(u64::try_from(seconds).ok()?))
+  }
 }
 
 /// A schedule for managing [Schedulable] items.
@@ -81,340 +181,2350 @@ pub trait Schedulable {
 /// | Remove    | O(1)            |
 ///
 /// **m** - it's amount of unique intervals.
+///
+/// Getting due items takes O(n), where **n** is the amount of items, if any
+/// of them are delayed by a [jitter](Schedule::with_jitter) or
+/// [spread](Placement::Spread) within their interval, or restored from a
+/// [Snapshot], as they're checked one by one then.
+///
+/// Items are split between [shards](Schedule::shards) by `id`, each behind
+/// its own lock, so operations on items of different shards don't wait for
+/// each other.
+///
+/// Items with the same interval are all due at the same moments, unless
+/// a jitter is set. Then every item is delayed by a stable offset derived from
+/// its `id`, which spreads the items over the jitter. For an even load, items
+/// can be [spread](Placement::Spread) over their whole interval instead.
+///
+/// If [runs are tracked](Schedule::track_runs), an item is returned by
+/// [get_due](Schedule::get_due) once per due moment, even if the windows
+/// overlap.
 pub struct Schedule<Item: Schedulable> {
-  items: RwLock<HashMap<Item::Id, Arc<Item>>>,
-  intervals: RwLock<HashMap<Item::Interval, HashSet<Item::Id>>>,
+  shards: Box<[RwLock<Entries<Item>>]>,
+  hasher: RandomState,
+  jitter: i64,
+  track_runs: bool,
+  min_interval: i64,
+  clock: Arc<dyn Clock>,
+  events: broadcast::Sender<Event<Item::Id>>,
+
+  /// Windows during which no item is due.
+  blackouts: RwLock<Vec<Window>>,
+
+  placement: Placement,
+
+  /// The number of items placed with every interval, if they're
+  /// [spread](Placement::Spread).
+  placed: Mutex<HashMap<Item::Interval, u64>>,
+}
+
+/// How the due times of new items are placed within their interval.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Placement {
+  /// Items are delayed by an offset derived from their `id`, up to the
+  /// jitter of the schedule. Without a jitter, items with the same interval
+  /// are all due at the same moments.
+  #[default]
+  Jitter,
+
+  /// Items with the same interval are spread evenly over it, in the order
+  /// they're inserted, so they're due at different moments. The jitter is
+  /// ignored. Replaced items keep their offsets.
+  Spread,
+}
+
+/// A change of the items of a [Schedule], sent to its
+/// [subscribers](Schedule::subscribe).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Event<Id> {
+  /// An item with a new `id` was inserted.
+  Inserted(Id),
+
+  /// An item was replaced, or its interval was changed.
+  Updated(Id),
+
+  /// An item was removed.
+  Removed(Id),
+}
+
+/// A shard of a [Schedule]: the items with their grouping by interval,
+/// guarded by a single lock, so they're always updated together.
+struct Entries<Item: Schedulable> {
+  items: HashMap<Item::Id, Arc<Item>>,
+  intervals: HashMap<Item::Interval, HashSet<Item::Id>>,
+
+  /// Intervals set by [Schedule::update_interval], which differ from the
+  /// items' own ones.
+  overrides: HashMap<Item::Id, Item::Interval>,
+
+  /// Items run once, with the moments they're due at. They're kept apart from
+  /// the recurring items, so an item can be both.
+  once: HashMap<Item::Id, (i64, Arc<Item>)>,
+
+  /// Offsets restored from a [Snapshot], which are used instead of the ones
+  /// derived from the jitter, until the item is removed.
+  phases: HashMap<Item::Id, i64>,
+
+  /// The moments the items were last due at, when they were returned by
+  /// [Schedule::get_due] or completed after [Schedule::drain_due].
+  runs: HashMap<Item::Id, i64>,
+
+  /// Items returned by [Schedule::drain_due] and not completed yet, with the
+  /// moments they were due at.
+  claims: HashMap<Item::Id, i64>,
+
+  /// Windows during which the items aren't due, besides the ones of the
+  /// whole schedule.
+  blackouts: HashMap<Item::Id, Vec<Window>>,
+
+  /// The number of items with their own positive jitter.
+  jittered: usize,
+}
+
+/// A serializable state of a [Schedule], taken by [Schedule::snapshot].
+#[derive(Serialize, Deserialize)]
+#[serde(bound(
+  serialize = "Item: Serialize, Item::Interval: Serialize",
+  deserialize = "Item: Deserialize<'de>, Item::Interval: Deserialize<'de>"
+))]
+pub struct Snapshot<Item: Schedulable> {
+  jitter: i64,
+  #[serde(default)]
+  track_runs: bool,
+  items: Vec<SnapshotItem<Item>>,
+  once: Vec<(i64, Arc<Item>)>,
+  #[serde(default)]
+  blackouts: Vec<Window>,
+  #[serde(default)]
+  placement: Placement,
+}
+
+/// A read-only view of the recurring items of a [Schedule], taken by
+/// [Schedule::view]. It shares the items with the schedule and answers
+/// queries without locking, so it's cheap to take and to clone.
+pub struct ScheduleView<Item: Schedulable> {
+  items: Arc<HashMap<Item::Id, ViewItem<Item>>>,
+
+  /// Windows during which no item is due.
+  blackouts: Arc<[Window]>,
+}
+
+/// A recurring item of a [ScheduleView].
+struct ViewItem<Item> {
+  item: Arc<Item>,
+  interval: i64,
+  offset: i64,
+
+  /// Windows during which the item isn't due.
+  blackouts: Vec<Window>,
+}
+
+/// A recurring item of a [Snapshot].
+#[derive(Serialize, Deserialize)]
+#[serde(bound(
+  serialize = "Item: Serialize, Item::Interval: Serialize",
+  deserialize = "Item: Deserialize<'de>, Item::Interval: Deserialize<'de>"
+))]
+struct SnapshotItem<Item: Schedulable> {
+  item: Arc<Item>,
+
+  /// The interval set by [Schedule::update_interval], if any.
+  interval: Option<Item::Interval>,
+
+  /// The delay of the item's due times after the multiples of its interval.
+  offset: i64,
+
+  /// The moment the item was last due at, if runs are tracked.
+  last_run: Option<i64>,
+
+  /// The windows during which the item isn't due.
+  #[serde(default)]
+  blackouts: Vec<Window>,
+}
+
+impl<Item: Schedulable> Entries<Item> {
+  /// Returns the interval the item is scheduled with.
+  fn interval(&self, item: &Item) -> Item::Interval {
+    self
+      .overrides
+      .get(&item.get_id())
+      .copied()
+      .unwrap_or_else(|| item.get_interval())
+  }
+
+  fn schedule(&mut self, id: Item::Id, interval: Item::Interval) {
+    self.intervals.entry(interval).or_default().insert(id);
+  }
+
+  fn unschedule(&mut self, id: &Item::Id, interval: Item::Interval) {
+    if let Some(set) = self.intervals.get_mut(&interval)
+      && set.remove(id)
+      && set.is_empty()
+    {
+      self.intervals.remove(&interval);
+    }
+  }
+
+  /// Inserts the item, moving its `id` out of the interval of the replaced
+  /// item. The restored phase, the last run, the claim and the blackout
+  /// windows of the replaced item are kept.
+  fn insert(&mut self, item: Item) -> Option<Arc<Item>> {
+    let id = item.get_id();
+    let phase = self.phases.get(&id).copied();
+    let run = self.runs.get(&id).copied();
+    let claim = self.claims.get(&id).copied();
+    let blackouts = self.blackouts.remove(&id);
+    let replaced = self.remove(&id);
+
+    self.phases.extend(phase.map(|phase| (id.clone(), phase)));
+    self.runs.extend(run.map(|run| (id.clone(), run)));
+    self.claims.extend(claim.map(|claim| (id.clone(), claim)));
+    self
+      .blackouts
+      .extend(blackouts.map(|windows| (id.clone(), windows)));
+
+    self.schedule(id.clone(), item.get_interval());
+    self.put(id, Arc::new(item));
+
+    replaced
+  }
+
+  /// Adds the item, which must be scheduled already.
+  fn put(&mut self, id: Item::Id, item: Arc<Item>) {
+    self.jittered += usize::from(is_jittered(&*item));
+    self.items.insert(id, item);
+  }
+
+  fn remove(&mut self, id: &Item::Id) -> Option<Arc<Item>> {
+    let item = self.items.remove(id)?;
+    let interval = self.interval(&item);
+
+    self.jittered -= usize::from(is_jittered(&*item));
+
+    self.overrides.remove(id);
+    self.phases.remove(id);
+    self.runs.remove(id);
+    self.claims.remove(id);
+    self.blackouts.remove(id);
+    self.unschedule(id, interval);
+
+    Some(item)
+  }
+
+  /// Returns `true` if the item with `id` isn't due at the moment `at`,
+  /// because of its own windows or the `global` ones.
+  fn is_blacked_out(&self, id: &Item::Id, at: i64, global: &[Window]) -> bool {
+    global
+      .iter()
+      .chain(self.blackouts.get(id).into_iter().flatten())
+      .any(|window| window.contains(at))
+  }
+
+  /// Removes the one-shot items due until `to`, returning the ones not due
+  /// during a blackout window.
+  fn take_once(&mut self, to: i64, global: &[Window]) -> Vec<Arc<Item>> {
+    let taken = self
+      .once
+      .extract_if(|_, (at, _)| *at <= to)
+      .collect::<Vec<_>>();
+
+    taken
+      .into_iter()
+      .filter(|(id, (at, _))| !self.is_blacked_out(id, *at, global))
+      .map(|(_, (_, item))| item)
+      .collect()
+  }
 }
 
 impl<Item: Schedulable> Schedule<Item> {
   /// Create a new schedule.
   pub fn new() -> Self {
-    Self {
-      items: RwLock::new(HashMap::new()),
-      intervals: RwLock::new(HashMap::new()),
-    }
+    Self::with_jitter(0)
   }
 
-  /// Returns `true` if the [Schedule] doesn't contain elements.
-  pub async fn is_empty(&self) -> bool {
-    self.items.read().await.is_empty() && self.intervals.read().await.is_empty()
+  /// Create a new schedule delaying due times of the items by up to `jitter`
+  /// seconds (but less than their interval).
+  pub fn with_jitter(jitter: i64) -> Self {
+    ScheduleBuilder::new().jitter(jitter).build()
   }
 
-  /// Get an item by `id`.
-  pub async fn get(&self, id: Item::Id) -> Option<Arc<Item>> {
-    self.items.read().await.get(&id).cloned()
+  /// Sets the clock the current time is taken from, the
+  /// [SystemClock](clock::SystemClock) by default.
+  pub fn clock(self, clock: impl Clock) -> Self {
+    self.configure(|builder| builder.clock(clock))
   }
 
-  /// Get items that are included in the interval `from` and `to`.
-  ///
-  /// An element is included in the interval if there is at least
-  /// one value between `from` and `to` that is divisible by
-  /// the item's [interval](Schedulable::Interval) without a remainder.
-  ///
-  /// `from` and `to` should be > 0 and `from` should be <= `to`.
-  pub async fn get_due(&self, from: i64, to: i64) -> Vec<Arc<Item>> {
-    let mut result = Vec::new();
-    let intervals = self.intervals.read().await;
+  /// Sets how the due times of new items are placed within their interval,
+  /// [by the jitter](Placement::Jitter) by default.
+  pub fn placement(self, placement: Placement) -> Self {
+    self.configure(|builder| builder.placement(placement))
+  }
 
-    for (interval, ids) in intervals.iter() {
-      let interval = (*interval).into();
-      let next_check = ((from + interval - 1) / interval) * interval;
+  /// Sets the shortest interval, in seconds, items can be inserted with.
+  /// Intervals must be positive in any case.
+  pub fn min_interval(self, min_interval: i64) -> Self {
+    self.configure(|builder| builder.min_interval(min_interval))
+  }
 
-      if next_check <= to {
-        let guard = self.items.read().await;
+  /// Returns the clock of the schedule.
+  pub fn get_clock(&self) -> &Arc<dyn Clock> {
+    &self.clock
+  }
 
-        for id in ids {
-          if let Some(item) = guard.get(id) {
-            result.push(item.clone());
-          }
-        }
-      }
-    }
+  /// Sets whether the moment every item was last due at is recorded when it's
+  /// returned by [get_due](Schedule::get_due), so it isn't returned for the
+  /// same moment again. This guards against overlapping windows, e.g. when
+  /// several consumers take due items, at the cost of taking write locks.
+  pub fn track_runs(self, track: bool) -> Self {
+    self.configure(|builder| builder.track_runs(track))
+  }
 
-    result
+  /// Create a new schedule with space for at least `items` items with
+  /// `intervals` distinct intervals, so they're inserted without reallocation.
+  pub fn with_capacity(items: usize, intervals: usize) -> Self {
+    ScheduleBuilder::new().capacity(items, intervals).build()
   }
 
-  /// Insert an item into schedule.
-  ///
-  /// If an item with this `id` is already in the schedule, it will be replaced.
-  pub async fn insert(&self, item: Item) {
-    let id = item.get_id();
-    let interval = item.get_interval();
+  /// Sets the number of shards the items are split between. More shards let
+  /// more operations on different items proceed at the same time, while
+  /// operations on the whole schedule take more locks. Items inserted already
+  /// are moved to the new shards, and the capacity is kept.
+  pub fn shards(self, shards: usize) -> Self {
+    self.configure(|builder| builder.shards(shards))
+  }
 
-    {
-      let mut intervals = self.intervals.write().await;
+  /// Changes the configuration of the schedule as `configure` changes the
+  /// one of a [ScheduleBuilder], keeping the items inserted already.
+  fn configure(mut self, configure: impl FnOnce(ScheduleBuilder) -> ScheduleBuilder) -> Self {
+    let builder = configure(ScheduleBuilder {
+      items: 0,
+      intervals: 0,
+      shards: self.shards.len(),
+      jitter: self.jitter,
+      min_interval: self.min_interval,
+      track_runs: self.track_runs,
+      clock: Arc::clone(&self.clock),
+      blackouts: mem::take(self.blackouts.get_mut()),
+      placement: self.placement,
+    });
+
+    self.jitter = builder.jitter;
+    self.min_interval = builder.min_interval;
+    self.track_runs = builder.track_runs;
+    self.clock = builder.clock;
+    *self.blackouts.get_mut() = builder.blackouts;
+    self.placement = builder.placement;
+
+    if builder.shards != self.shards.len() {
+      self.reshard(builder.shards);
+    }
 
-      if let Some(ids_set) = intervals.get_mut(&interval) {
-        ids_set.insert(id);
-      } else {
-        let mut set = HashSet::new();
-        set.insert(id);
+    self
+  }
+
+  /// Moves the items to `shards` new shards, keeping the capacity.
+  fn reshard(&mut self, shards: usize) {
+    let (items, intervals) = self
+      .shards
+      .iter_mut()
+      .fold((0, 0), |(items, intervals), shard| {
+        let entries = shard.get_mut();
+        (
+          items + entries.items.capacity(),
+          intervals.max(entries.intervals.capacity()),
+        )
+      });
+
+    let old = mem::replace(
+      &mut self.shards,
+      Self::empty_shards(shards, items, intervals),
+    );
+
+    for entries in old {
+      let mut entries = entries.into_inner();
+
+      for (id, item) in mem::take(&mut entries.items) {
+        let overridden = entries.overrides.remove(&id);
+        let phase = entries.phases.remove(&id);
+        let run = entries.runs.remove(&id);
+        let claim = entries.claims.remove(&id);
+        let blackouts = entries.blackouts.remove(&id);
+        let target = self.entries_mut(&id);
+
+        target.schedule(
+          id.clone(),
+          overridden.unwrap_or_else(|| item.get_interval()),
+        );
+        target
+          .overrides
+          .extend(overridden.map(|interval| (id.clone(), interval)));
+        target.phases.extend(phase.map(|phase| (id.clone(), phase)));
+        target.runs.extend(run.map(|run| (id.clone(), run)));
+        target.claims.extend(claim.map(|claim| (id.clone(), claim)));
+        target
+          .blackouts
+          .extend(blackouts.map(|windows| (id.clone(), windows)));
+        target.put(id, item);
+      }
 
-        intervals.insert(interval, set);
+      for (id, once) in entries.once {
+        self.entries_mut(&id).once.insert(id, once);
       }
     }
+  }
 
-    {
-      let mut items = self.items.write().await;
+  /// Creates the shards with space for the items split between them. Every
+  /// shard may have items of every interval.
+  fn empty_shards(shards: usize, items: usize, intervals: usize) -> Box<[RwLock<Entries<Item>>]> {
+    (0..shards)
+      .map(|_| {
+        RwLock::new(Entries {
+          items: HashMap::with_capacity(items.div_ceil(shards)),
+          intervals: HashMap::with_capacity(intervals),
+          overrides: HashMap::new(),
+          once: HashMap::new(),
+          phases: HashMap::new(),
+          runs: HashMap::new(),
+          claims: HashMap::new(),
+          blackouts: HashMap::new(),
+          jittered: 0,
+        })
+      })
+      .collect()
+  }
 
-      items.insert(id, Arc::new(item));
-    }
+  /// Returns the index of the shard the item with `id` belongs to.
+  fn shard(&self, id: &Item::Id) -> usize {
+    (self.hasher.hash_one(id) % self.shards.len() as u64) as usize
   }
 
-  /// Remove an item by `id` from the schedule if it exists.
-  pub async fn remove(&self, id: Item::Id) {
-    if let Some(item) = self.items.write().await.remove(&id) {
-      let interval = item.get_interval();
-      let mut intervals = self.intervals.write().await;
+  /// Returns the entries of the item's shard without locking it.
+  fn entries_mut(&mut self, id: &Item::Id) -> &mut Entries<Item> {
+    let shard = self.shard(id);
+    self.shards[shard].get_mut()
+  }
 
-      if let Some(set) = intervals.get_mut(&interval) {
-        if set.remove(&id) && set.is_empty() {
-          intervals.remove(&interval);
-        }
-      }
+  /// Splits the ids between the shards they belong to, by index.
+  fn by_shard(&self, ids: impl IntoIterator<Item = Item::Id>) -> Vec<Vec<Item::Id>> {
+    let mut shards = self.shards.iter().map(|_| Vec::new()).collect::<Vec<_>>();
+
+    for id in ids {
+      shards[self.shard(&id)].push(id);
     }
-  }
 
-  /// Clears the schedule, removing all items. Keeps the allocated
-  /// memory for reuse.
-  pub async fn clear(&self) {
-    self.items.write().await.clear();
-    self.intervals.write().await.clear();
+    shards
   }
-}
 
-#[cfg(test)]
-mod tests {
-  use tokio::sync::RwLockReadGuard;
+  async fn read(&self, id: &Item::Id) -> RwLockReadGuard<'_, Entries<Item>> {
+    self.shards[self.shard(id)].read().await
+  }
 
-  use super::*;
+  async fn write(&self, id: &Item::Id) -> RwLockWriteGuard<'_, Entries<Item>> {
+    self.shards[self.shard(id)].write().await
+  }
 
-  #[derive(Debug, PartialEq)]
-  struct Task {
-    id: i64,
-    interval: i64,
-    updated: bool,
+  /// Returns a receiver of the changes of the items made from now on. Changes
+  /// of an item are received in the order they're made. A receiver lagging
+  /// behind by more than 1024 events misses the oldest ones.
+  ///
+  /// One-shot runs aren't reported.
+  pub fn subscribe(&self) -> broadcast::Receiver<Event<Item::Id>> {
+    self.events.subscribe()
   }
 
-  impl<Item: Schedulable> Schedule<Item> {
-    pub async fn items_ref(&self) -> RwLockReadGuard<'_, HashMap<Item::Id, Arc<Item>>> {
-      self.items.read().await
-    }
+  /// Sends the event to the subscribers, if there are any.
+  fn notify(&self, event: Event<Item::Id>) {
+    let _ = self.events.send(event);
+  }
 
-    pub async fn intervals_ref(
-      &self,
-    ) -> RwLockReadGuard<'_, HashMap<Item::Interval, HashSet<Item::Id>>> {
-      self.intervals.read().await
-    }
+  /// Sends the event of inserting an item with `id`.
+  fn notify_insert(&self, id: Item::Id, replaced: bool) {
+    self.notify(if replaced {
+      Event::Updated(id)
+    } else {
+      Event::Inserted(id)
+    });
   }
 
-  impl From<(i64, i64)> for Task {
-    fn from(args: (i64, i64)) -> Self {
-      Task {
-        id: args.0,
-        interval: args.1,
-        updated: false,
+  /// Returns `true` if the [Schedule] doesn't contain elements.
+  pub async fn is_empty(&self) -> bool {
+    for shard in &self.shards {
+      if !shard.read().await.items.is_empty() {
+        return false;
       }
     }
+
+    true
   }
 
-  impl Schedulable for Task {
-    type Id = i64;
-    type Interval = i64;
+  /// Returns the number of items in the [Schedule].
+  pub async fn len(&self) -> usize {
+    let mut len = 0;
 
-    fn get_id(&self) -> Self::Id {
-      self.id
+    for shard in &self.shards {
+      len += shard.read().await.items.len();
     }
 
-    fn get_interval(&self) -> Self::Interval {
-      self.interval
-    }
+    len
   }
 
-  #[tokio::test]
-  async fn empty_schedule() {
-    let schedule: Schedule<Task> = Schedule::new();
+  /// Returns the `id` of every item, in arbitrary order.
+  pub async fn ids(&self) -> Vec<Item::Id> {
+    let mut ids = Vec::new();
 
-    assert!(
-      schedule.items_ref().await.is_empty(),
-      "schedule items shouldn't be empty"
-    );
-    assert!(
-      schedule.intervals_ref().await.is_empty(),
-      "schedule intervals shouldn't be empty"
-    );
+    for shard in &self.shards {
+      ids.extend(shard.read().await.items.keys().cloned());
+    }
+
+    ids
   }
 
-  #[tokio::test]
-  async fn test_empty_schedule() {
-    let schedule: Schedule<Task> = Schedule::new();
+  /// Returns every distinct interval the items are scheduled with, along with
+  /// the number of items scheduled with it.
+  pub async fn interval_stats(&self) -> HashMap<Item::Interval, usize> {
+    let mut stats = HashMap::new();
 
-    assert!(
-      schedule.get_due(1, 100).await.is_empty(),
-      "empty schedule shouldn't return due items"
-    );
+    for shard in &self.shards {
+      for (interval, ids) in &shard.read().await.intervals {
+        *stats.entry(*interval).or_default() += ids.len();
+      }
+    }
+
+    stats
   }
 
-  #[tokio::test]
-  async fn get_due_on_boundary() {
-    let schedule: Schedule<Task> = Schedule::new();
+  /// Returns an iterator over a snapshot of the items, in arbitrary order.
+  /// Changes of the schedule made afterwards aren't reflected.
+  pub async fn iter(&self) -> impl Iterator<Item = Arc<Item>> + use<Item> {
+    let mut items = Vec::new();
 
-    schedule.insert(Task::from((1, 10))).await;
+    for shard in &self.shards {
+      items.extend(shard.read().await.items.values().cloned());
+    }
 
-    assert_eq!(
-      schedule.get_due(1, 10).await.len(),
-      1,
-      "schedule should return item on boundary"
-    );
-    assert_eq!(
-      schedule.get_due(10, 10).await.len(),
-      1,
-      "schedule should return item on boundary equals"
-    );
+    items.into_iter()
   }
 
-  #[tokio::test]
-  async fn get_due_before_boundary() {
-    let schedule: Schedule<Task> = Schedule::new();
+  /// Get an item by `id`.
+  pub async fn get(&self, id: Item::Id) -> Option<Arc<Item>> {
+    self.read(&id).await.items.get(&id).cloned()
+  }
 
-    schedule.insert(Task::from((1, 10))).await;
+  /// Returns the interval the item with `id` is scheduled with, in seconds,
+  /// which differs from its own if it was
+  /// [updated](Schedule::update_interval).
+  pub async fn interval(&self, id: Item::Id) -> Option<i64> {
+    let entries = self.read(&id).await;
+    let item = entries.items.get(&id)?;
 
-    assert!(
-      schedule.get_due(1, 9).await.is_empty(),
-      "schedule shouldn't return due items before boundary"
-    );
+    Some(entries.interval(item).seconds())
   }
 
-  #[tokio::test]
-  async fn test_multiple_intervals() {
-    let schedule: Schedule<Task> = Schedule::new();
+  /// Gets several items by `id` at once, taking the read lock of every shard
+  /// once. Ids without an item are left out.
+  pub async fn get_many(
+    &self,
+    ids: impl IntoIterator<Item = Item::Id>,
+  ) -> HashMap<Item::Id, Arc<Item>> {
+    let mut items = HashMap::new();
+
+    for (shard, ids) in self.shards.iter().zip(self.by_shard(ids)) {
+      if ids.is_empty() {
+        continue;
+      }
 
-    schedule.insert(Task::from((1, 5))).await;
-    schedule.insert(Task::from((2, 10))).await;
+      let entries = shard.read().await;
 
-    let ids: Vec<i64> = schedule.get_due(1, 10).await.iter().map(|t| t.id).collect();
+      items.extend(
+        ids
+          .into_iter()
+          .filter_map(|id| Some((id.clone(), Arc::clone(entries.items.get(&id)?)))),
+      );
+    }
 
-    assert!(
-      ids.contains(&1),
-      "schedule should return item with interval 5"
-    );
-    assert!(
-      ids.contains(&2),
-      "schedule should return item with interval 10"
-    );
+    items
   }
 
-  #[tokio::test]
-  async fn test_skip_multiple_intervals() {
-    let schedule: Schedule<Task> = Schedule::new();
+  /// Get items that are included in the interval `from` and `to`.
+  ///
+  /// An element is included in the interval if there is at least
+  /// one value between `from` and `to` that is divisible by
+  /// the item's [interval](Schedulable::Interval) without a remainder,
+  /// after subtracting the item's jitter offset.
+  ///
+  /// One-shot items due until `to` are included too, and removed from the
+  /// schedule.
+  ///
+  /// Items aren't included if the moment they're due at is in one of the
+  /// [blackout windows](Schedule::add_blackout).
+  ///
+  /// `from` and `to` should be > 0 and `from` should be <= `to`.
+  pub async fn get_due(&self, from: i64, to: i64) -> Vec<Arc<Item>> {
+    let blackouts = self.blackouts.read().await;
+    let mut result = Vec::new();
 
-    schedule.insert(Task::from((1, 10))).await;
+    for shard in &self.shards {
+      if self.track_runs {
+        let mut entries = shard.write().await;
+        let due = self
+          .due(&entries, from, to, &blackouts)
+          .map(|(id, at, item)| (id, at, Arc::clone(item)))
+          .collect::<Vec<_>>();
+
+        for (id, at, item) in due {
+          if entries.runs.get(&id).is_none_or(|run| *run < at) {
+            entries.runs.insert(id, at);
+            result.push(item);
+          }
+        }
 
-    assert_eq!(
-      schedule.get_due(1, 35).await.len(),
-      1,
-      "schedule should return due item even if multiple intervals were passed"
-    );
+        result.extend(entries.take_once(to, &blackouts));
+        continue;
+      }
+
+      let once_due = {
+        let entries = shard.read().await;
+        result.extend(
+          self
+            .due(&entries, from, to, &blackouts)
+            .map(|(_, _, item)| Arc::clone(item)),
+        );
+
+        entries.once.values().any(|(at, _)| *at <= to)
+      };
+
+      if once_due {
+        result.extend(shard.write().await.take_once(to, &blackouts));
+      }
+    }
+
+    result
   }
 
-  #[tokio::test]
-  async fn insert_single_item_into_schedule() {
-    let schedule: Schedule<Task> = Schedule::new();
+  /// Returns the moment the item with `id` was last due at, when it was
+  /// returned by [get_due](Schedule::get_due) or
+  /// [completed](Schedule::complete). It's recorded by `get_due` only if
+  /// [runs are tracked](Schedule::track_runs).
+  pub async fn last_run(&self, id: Item::Id) -> Option<i64> {
+    self.read(&id).await.runs.get(&id).copied()
+  }
 
-    schedule.insert(Task::from((1, 30))).await;
+  /// Returns the items due between `from` and `to`, as
+  /// [get_due](Schedule::get_due), claiming the recurring ones until they're
+  /// [completed](Schedule::complete) or [failed](Schedule::fail).
+  ///
+  /// Claimed items aren't returned again, nor are the ones completed at the
+  /// moment they're due at, so several workers can drain the schedule at once
+  /// without running an item twice. One-shot items are removed when they're
+  /// returned, so they aren't claimed.
+  pub async fn drain_due(&self, from: i64, to: i64) -> Vec<Arc<Item>> {
+    let blackouts = self.blackouts.read().await;
+    let mut result = Vec::new();
 
+    for shard in &self.shards {
+      let mut entries = shard.write().await;
+      let due = self
+        .due(&entries, from, to, &blackouts)
+        .filter(|(id, at, _)| {
+          !entries.claims.contains_key(id) && entries.runs.get(id).is_none_or(|run| run < at)
+        })
+        .map(|(id, at, item)| (id, at, Arc::clone(item)))
+        .collect::<Vec<_>>();
+
+      for (id, at, item) in due {
+        entries.claims.insert(id, at);
+        result.push(item);
+      }
+
+      result.extend(entries.take_once(to, &blackouts));
+    }
+
+    result
+  }
+
+  /// Releases the claim of the item with `id` taken by
+  /// [drain_due](Schedule::drain_due), recording the moment it was due at as
+  /// its [last run](Schedule::last_run). Returns `false` if the item isn't
+  /// claimed.
+  pub async fn complete(&self, id: Item::Id) -> bool {
+    let mut entries = self.write(&id).await;
+
+    let Some(at) = entries.claims.remove(&id) else {
+      return false;
+    };
+
+    entries.runs.insert(id, at);
+    true
+  }
+
+  /// Releases the claim of the item with `id` taken by
+  /// [drain_due](Schedule::drain_due) without recording a run, so it's
+  /// returned again by a drain including the moment it was due at. Returns
+  /// `false` if the item isn't claimed.
+  pub async fn fail(&self, id: Item::Id) -> bool {
+    self.write(&id).await.claims.remove(&id).is_some()
+  }
+
+  /// Returns the recurring items of the shard due between `from` and `to`,
+  /// with the last moments they're due at, unless they're blacked out then.
+  fn due<'a>(
+    &'a self,
+    entries: &'a Entries<Item>,
+    from: i64,
+    to: i64,
+    blackouts: &'a [Window],
+  ) -> impl Iterator<Item = (Item::Id, i64, &'a Arc<Item>)> {
+    // Without offsets, all items of an interval are due at the same moments,
+    // so they're checked once per interval.
+    let aligned = self.jitter == 0 && entries.jittered == 0 && entries.phases.is_empty();
+
+    entries.intervals.iter().flat_map(move |(interval, ids)| {
+      let interval = (*interval).seconds();
+      let last = to.div_euclid(interval) * interval;
+      let ids = (!aligned || last >= from)
+        .then_some(ids)
+        .into_iter()
+        .flatten();
+
+      ids.filter_map(move |id| {
+        let item = entries.items.get(id)?;
+        let at = if aligned {
+          last
+        } else {
+          let offset = self.offset(entries, item, interval);
+          (to - offset).div_euclid(interval) * interval + offset
+        };
+
+        (at >= from && !entries.is_blacked_out(id, at, blackouts)).then_some((id.clone(), at, item))
+      })
+    })
+  }
+
+  /// Returns the first moment at or after `from` the item with `id` is due,
+  /// or `None` if there is no such item. A pending one-shot run counts, if
+  /// it's earlier.
+  pub async fn next_due(&self, id: Item::Id, from: i64) -> Option<i64> {
+    let entries = self.read(&id).await;
+
+    let recurring = entries
+      .items
+      .get(&id)
+      .map(|item| self.next_check(&entries, item, entries.interval(item).seconds(), from));
+    let once = entries.once.get(&id).map(|(at, _)| (*at).max(from));
+
+    recurring.into_iter().chain(once).min()
+  }
+
+  /// Returns the first moment at or after `from` any item is due, or `None`
+  /// if the schedule is empty. It's the earliest `to` for which
+  /// [get_due](Schedule::get_due) returns items.
+  pub async fn next_tick(&self, from: i64) -> Option<i64> {
+    let mut next = None;
+
+    for shard in &self.shards {
+      let entries = &*shard.read().await;
+
+      let shard_next = entries
+        .intervals
+        .iter()
+        .flat_map(|(interval, ids)| {
+          let interval = (*interval).seconds();
+
+          ids
+            .iter()
+            .filter_map(|id| entries.items.get(id))
+            .map(move |item| self.next_check(entries, item, interval, from))
+        })
+        .chain(entries.once.values().map(|(at, _)| (*at).max(from)))
+        .min();
+
+      next = next.into_iter().chain(shard_next).min();
+    }
+
+    next
+  }
+
+  /// Returns the first moment at or after `from` the item is due with the
+  /// interval.
+  fn next_check(&self, entries: &Entries<Item>, item: &Item, interval: i64, from: i64) -> i64 {
+    next_moment(from, interval, self.offset(entries, item, interval))
+  }
+
+  /// Returns the delay of the item's due times after the multiples of its
+  /// interval.
+  fn offset(&self, entries: &Entries<Item>, item: &Item, interval: i64) -> i64 {
+    if let Some(phase) = entries.phases.get(&item.get_id()) {
+      return phase.rem_euclid(interval);
+    }
+
+    jitter_offset(item, interval, self.jitter)
+  }
+
+  /// Insert an item into schedule.
+  ///
+  /// If an item with this `id` is already in the schedule, it will be replaced
+  /// and returned. The item is scheduled with its own interval, even if the
+  /// replaced one had a different one.
+  ///
+  /// Fails if the interval of the item isn't positive or is shorter than the
+  /// [minimum](Schedule::min_interval).
+  pub async fn insert(&self, item: Item) -> Result<Option<Arc<Item>>, ScheduleError> {
+    validate_interval(item.get_interval().seconds(), self.min_interval)?;
+
+    let id = item.get_id();
+    let mut entries = self.write(&id).await;
+
+    self.place(&mut entries, &item).await;
+    let replaced = entries.insert(item);
+    drop(entries);
+
+    self.notify_insert(id, replaced.is_some());
+    Ok(replaced)
+  }
+
+  /// Inserts several items at once, taking the lock of every shard once.
+  /// Items with the same `id` as existing ones replace them, as with
+  /// [insert](Schedule::insert). If the interval of any item is invalid, none
+  /// are inserted.
+  pub async fn insert_many(
+    &self,
+    items: impl IntoIterator<Item = Item>,
+  ) -> Result<(), ScheduleError> {
+    let mut shards = self.shards.iter().map(|_| Vec::new()).collect::<Vec<_>>();
+
+    for item in items {
+      validate_interval(item.get_interval().seconds(), self.min_interval)?;
+      shards[self.shard(&item.get_id())].push(item);
+    }
+
+    for (shard, items) in self.shards.iter().zip(shards) {
+      if items.is_empty() {
+        continue;
+      }
+
+      let mut entries = shard.write().await;
+
+      for item in items {
+        let id = item.get_id();
+
+        self.place(&mut entries, &item).await;
+        let replaced = entries.insert(item);
+
+        self.notify_insert(id, replaced.is_some());
+      }
+    }
+
+    Ok(())
+  }
+
+  /// Sets the offset of the item if it's new and items are
+  /// [spread](Placement::Spread), before it's inserted.
+  async fn place(&self, entries: &mut Entries<Item>, item: &Item) {
+    let id = item.get_id();
+
+    if self.placement != Placement::Spread || entries.phases.contains_key(&id) {
+      return;
+    }
+
+    let interval = item.get_interval();
+    let mut placed = self.placed.lock().await;
+    let count = placed.entry(interval).or_default();
+
+    entries
+      .phases
+      .insert(id, spread_offset(*count, interval.seconds()));
+    *count += 1;
+  }
+
+  /// Schedules the item with `id` with another interval than its own, until
+  /// it's replaced. Returns `false` if there is no such item, and fails if the
+  /// interval is invalid, as with [insert](Schedule::insert).
+  pub async fn update_interval(
+    &self,
+    id: Item::Id,
+    interval: Item::Interval,
+  ) -> Result<bool, ScheduleError> {
+    validate_interval(interval.seconds(), self.min_interval)?;
+
+    let mut entries = self.write(&id).await;
+
+    let Some(item) = entries.items.get(&id).cloned() else {
+      return Ok(false);
+    };
+
+    let current = entries.interval(&item);
+    entries.unschedule(&id, current);
+    entries.schedule(id.clone(), interval);
+
+    if interval == item.get_interval() {
+      entries.overrides.remove(&id);
+    } else {
+      entries.overrides.insert(id.clone(), interval);
+    }
+
+    self.notify(Event::Updated(id));
+    Ok(true)
+  }
+
+  /// Schedules the item to run once, at `at`, after which it's removed. A run
+  /// at a moment that has already passed is due on the next
+  /// [get_due](Schedule::get_due) call.
+  ///
+  /// One-shot runs are independent of the recurring items: they aren't
+  /// counted by [len](Schedule::len), and the item with the same `id` keeps
+  /// its interval. A pending one-shot run of an item with the same `id` is
+  /// replaced and returned.
+  pub async fn insert_once(&self, item: Item, at: i64) -> Option<Arc<Item>> {
+    let mut entries = self.write(&item.get_id()).await;
+
+    entries
+      .once
+      .insert(item.get_id(), (at, Arc::new(item)))
+      .map(|(_, item)| item)
+  }
+
+  /// Schedules the item to run once, `delay` seconds after the current time
+  /// of the [clock](Schedule::clock), as [insert_once](Schedule::insert_once)
+  /// does.
+  pub async fn insert_delayed(&self, item: Item, delay: i64) -> Option<Arc<Item>> {
+    let now = self.clock.now();
+
+    self.insert_once(item, now + delay).await
+  }
+
+  /// Cancels a pending one-shot run of the item with `id`, returning the
+  /// item. Its recurring runs aren't affected.
+  pub async fn cancel_once(&self, id: Item::Id) -> Option<Arc<Item>> {
+    let mut entries = self.write(&id).await;
+
+    entries.once.remove(&id).map(|(_, item)| item)
+  }
+
+  /// Adds a window during which no item is due. Windows that are over by the
+  /// [clock](Schedule::clock) are dropped.
+  pub async fn add_blackout(&self, window: Window) {
+    let now = self.clock.now();
+    let mut blackouts = self.blackouts.write().await;
+
+    blackouts.retain(|window| !window.is_over(now));
+    blackouts.extend((!window.is_over(now)).then_some(window));
+  }
+
+  /// Removes the windows added by [add_blackout](Schedule::add_blackout).
+  pub async fn clear_blackouts(&self) {
+    self.blackouts.write().await.clear();
+  }
+
+  /// Adds a window during which the item with `id` isn't due, as
+  /// [add_blackout](Schedule::add_blackout). The windows are kept when the
+  /// item is replaced, and dropped when it's removed. Returns `false` if
+  /// there's no recurring item with `id`.
+  pub async fn add_item_blackout(&self, id: Item::Id, window: Window) -> bool {
+    let now = self.clock.now();
+    let mut entries = self.write(&id).await;
+
+    if !entries.items.contains_key(&id) {
+      return false;
+    }
+
+    let blackouts = entries.blackouts.entry(id).or_default();
+    blackouts.retain(|window| !window.is_over(now));
+    blackouts.extend((!window.is_over(now)).then_some(window));
+
+    true
+  }
+
+  /// Removes the windows added to the item with `id` by
+  /// [add_item_blackout](Schedule::add_item_blackout).
+  pub async fn clear_item_blackouts(&self, id: Item::Id) {
+    self.write(&id).await.blackouts.remove(&id);
+  }
+
+  /// Remove an item by `id` from the schedule if it exists.
+  pub async fn remove(&self, id: Item::Id) {
+    if self.write(&id).await.remove(&id).is_some() {
+      self.notify(Event::Removed(id));
+    }
+  }
+
+  /// Removes several items by `id` at once, taking the lock of every shard
+  /// once.
+  pub async fn remove_many(&self, ids: impl IntoIterator<Item = Item::Id>) {
+    for (shard, ids) in self.shards.iter().zip(self.by_shard(ids)) {
+      if ids.is_empty() {
+        continue;
+      }
+
+      let mut entries = shard.write().await;
+
+      for id in ids {
+        if entries.remove(&id).is_some() {
+          self.notify(Event::Removed(id));
+        }
+      }
+    }
+  }
+
+  /// Keeps only the items the predicate returns `true` for, removing the
+  /// others along with their pending one-shot runs. Every shard is locked
+  /// once.
+  pub async fn retain(&self, mut keep: impl FnMut(&Item) -> bool) {
+    for shard in &self.shards {
+      let mut entries = shard.write().await;
+
+      let removed = entries
+        .items
+        .iter()
+        .filter(|(_, item)| !keep(item))
+        .map(|(id, _)| id.clone())
+        .collect::<Vec<_>>();
+
+      for id in removed {
+        entries.remove(&id);
+        entries.once.remove(&id);
+        self.notify(Event::Removed(id));
+      }
+
+      entries.once.retain(|_, (_, item)| keep(item));
+    }
+  }
+
+  /// Removes every item scheduled with the interval, returning the removed
+  /// items.
+  pub async fn remove_by_interval(&self, interval: Item::Interval) -> Vec<Arc<Item>> {
+    let mut removed = Vec::new();
+
+    for shard in &self.shards {
+      let mut entries = shard.write().await;
+
+      let Some(ids) = entries.intervals.get(&interval).cloned() else {
+        continue;
+      };
+
+      for id in ids {
+        removed.extend(entries.remove(&id));
+        self.notify(Event::Removed(id));
+      }
+    }
+
+    removed
+  }
+
+  /// Takes a snapshot of the items, including one-shot runs, along with their
+  /// intervals and offsets. Shards are read one by one, so changes made
+  /// meanwhile may be partially included.
+  pub async fn snapshot(&self) -> Snapshot<Item> {
+    let mut items = Vec::new();
+    let mut once = Vec::new();
+
+    for shard in &self.shards {
+      let entries = shard.read().await;
+
+      items.extend(entries.items.iter().map(|(id, item)| SnapshotItem {
+        item: Arc::clone(item),
+        interval: entries.overrides.get(id).copied(),
+        offset: self.offset(&entries, item, entries.interval(item).seconds()),
+        last_run: entries.runs.get(id).copied(),
+        blackouts: entries.blackouts.get(id).cloned().unwrap_or_default(),
+      }));
+
+      once.extend(
+        entries
+          .once
+          .values()
+          .map(|(at, item)| (*at, Arc::clone(item))),
+      );
+    }
+
+    Snapshot {
+      jitter: self.jitter,
+      track_runs: self.track_runs,
+      items,
+      once,
+      blackouts: self.blackouts.read().await.clone(),
+      placement: self.placement,
+    }
+  }
+
+  /// Takes a read-only [view](ScheduleView) of the recurring items, with the
+  /// intervals, offsets and blackout windows they're scheduled with. Shards are read one by one,
+  /// as by [snapshot](Schedule::snapshot).
+  pub async fn view(&self) -> ScheduleView<Item> {
+    let mut items = HashMap::new();
+
+    for shard in &self.shards {
+      let entries = shard.read().await;
+
+      items.extend(entries.items.iter().map(|(id, item)| {
+        let interval = entries.interval(item).seconds();
+        let offset = self.offset(&entries, item, interval);
+
+        (id.clone(), ViewItem {
+          item: Arc::clone(item),
+          interval,
+          offset,
+          blackouts: entries.blackouts.get(id).cloned().unwrap_or_default(),
+        })
+      }));
+    }
+
+    ScheduleView {
+      items: Arc::new(items),
+      blackouts: self.blackouts.read().await.as_slice().into(),
+    }
+  }
+
+  /// Creates a schedule from a snapshot. Items are due at the same moments as
+  /// in the schedule the snapshot was taken of, even if their offsets would
+  /// be derived differently now, and keep them when they're replaced.
+  ///
+  /// Fails if the snapshot has an item with a non-positive interval.
+  pub fn restore(snapshot: Snapshot<Item>) -> Result<Self, ScheduleError> {
+    let mut schedule = ScheduleBuilder::new()
+      .jitter(snapshot.jitter)
+      .track_runs(snapshot.track_runs)
+      .blackouts(snapshot.blackouts)
+      .placement(snapshot.placement)
+      .build();
+
+    for SnapshotItem {
+      item,
+      interval,
+      offset,
+      last_run,
+      blackouts,
+    } in snapshot.items
+    {
+      let id = item.get_id();
+      let scheduled = interval.unwrap_or_else(|| item.get_interval());
+      validate_interval(scheduled.seconds(), 1)?;
+
+      if snapshot.placement == Placement::Spread {
+        *schedule
+          .placed
+          .get_mut()
+          .entry(item.get_interval())
+          .or_default() += 1;
+      }
+
+      let entries = schedule.entries_mut(&id);
+
+      entries.schedule(id.clone(), scheduled);
+      entries.phases.insert(id.clone(), offset);
+      entries.runs.extend(last_run.map(|run| (id.clone(), run)));
+
+      if !blackouts.is_empty() {
+        entries.blackouts.insert(id.clone(), blackouts);
+      }
+
+      if let Some(interval) = interval {
+        entries.overrides.insert(id.clone(), interval);
+      }
+
+      entries.put(id, item);
+    }
+
+    for (at, item) in snapshot.once {
+      let id = item.get_id();
+      schedule.entries_mut(&id).once.insert(id, (at, item));
+    }
+
+    Ok(schedule)
+  }
+
+  /// Shrinks the capacity of the schedule as much as possible, releasing the
+  /// memory kept after items are removed.
+  pub async fn shrink_to_fit(&self) {
+    for shard in &self.shards {
+      let mut entries = shard.write().await;
+
+      entries.items.shrink_to_fit();
+      entries.intervals.shrink_to_fit();
+      entries
+        .intervals
+        .values_mut()
+        .for_each(HashSet::shrink_to_fit);
+      entries.overrides.shrink_to_fit();
+      entries.once.shrink_to_fit();
+      entries.phases.shrink_to_fit();
+      entries.runs.shrink_to_fit();
+      entries.claims.shrink_to_fit();
+      entries.blackouts.shrink_to_fit();
+    }
+  }
+
+  /// Clears the schedule, removing all items and one-shot runs. Keeps the
+  /// allocated memory for reuse.
+  pub async fn clear(&self) {
+    for shard in &self.shards {
+      let mut entries = shard.write().await;
+
+      for id in entries.items.keys() {
+        self.notify(Event::Removed(id.clone()));
+      }
+
+      entries.items.clear();
+      entries.intervals.clear();
+      entries.overrides.clear();
+      entries.once.clear();
+      entries.phases.clear();
+      entries.runs.clear();
+      entries.claims.clear();
+      entries.blackouts.clear();
+      entries.jittered = 0;
+    }
+  }
+}
+
+impl<Item: Schedulable> Default for Schedule<Item> {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+impl<Item: Schedulable> ScheduleView<Item> {
+  /// Returns `true` if the view doesn't contain items.
+  pub fn is_empty(&self) -> bool {
+    self.items.is_empty()
+  }
+
+  /// Returns the number of items in the view.
+  pub fn len(&self) -> usize {
+    self.items.len()
+  }
+
+  /// Get an item by `id`.
+  pub fn get(&self, id: Item::Id) -> Option<&Arc<Item>> {
+    self.items.get(&id).map(|entry| &entry.item)
+  }
+
+  /// Returns the interval the item with `id` is scheduled with, in seconds.
+  pub fn interval(&self, id: Item::Id) -> Option<i64> {
+    self.items.get(&id).map(|entry| entry.interval)
+  }
+
+  /// Returns an iterator over the items, in arbitrary order.
+  pub fn iter(&self) -> impl Iterator<Item = &Arc<Item>> {
+    self.items.values().map(|entry| &entry.item)
+  }
+
+  /// Returns the items due between `from` and `to`, as
+  /// [Schedule::get_due] would when the view was taken, without one-shot
+  /// items.
+  pub fn get_due(&self, from: i64, to: i64) -> Vec<Arc<Item>> {
+    self
+      .items
+      .values()
+      .filter(|entry| {
+        let at = (to - entry.offset).div_euclid(entry.interval) * entry.interval + entry.offset;
+
+        at >= from
+          && !self
+            .blackouts
+            .iter()
+            .chain(&entry.blackouts)
+            .any(|window| window.contains(at))
+      })
+      .map(|entry| Arc::clone(&entry.item))
+      .collect()
+  }
+
+  /// Returns the first moment at or after `from` the item with `id` is due,
+  /// or `None` if there is no such item.
+  pub fn next_due(&self, id: Item::Id, from: i64) -> Option<i64> {
+    let entry = self.items.get(&id)?;
+
+    Some(next_moment(from, entry.interval, entry.offset))
+  }
+}
+
+impl<Item: Schedulable> Clone for ScheduleView<Item> {
+  fn clone(&self) -> Self {
+    Self {
+      items: Arc::clone(&self.items),
+      blackouts: Arc::clone(&self.blackouts),
+    }
+  }
+}
+
+/// Checks the interval is positive and isn't shorter than `minimum`.
+fn validate_interval(interval: i64, minimum: i64) -> Result<(), ScheduleError> {
+  if interval <= 0 {
+    return Err(ScheduleError::NonPositiveInterval(interval));
+  }
+
+  if interval < minimum {
+    return Err(ScheduleError::IntervalTooShort { interval, minimum });
+  }
+
+  Ok(())
+}
+
+/// Returns the delay of the item's due times after the multiples of its
+/// interval, derived from its `id`. The item's own jitter takes precedence
+/// over the `jitter` of the schedule.
+/// Returns `true` if the item has its own jitter, which may delay its due
+/// times.
+fn is_jittered<Item: Schedulable>(item: &Item) -> bool {
+  item.get_jitter().is_some_and(|jitter| jitter > 0)
+}
+
+fn jitter_offset<Item: Schedulable>(item: &Item, interval: i64, jitter: i64) -> i64 {
+  let jitter = item.get_jitter().unwrap_or(jitter).min(interval);
+
+  if jitter <= 0 {
+    return 0;
+  }
+
+  let mut hasher = DefaultHasher::new();
+  item.get_id().hash(&mut hasher);
+
+  (hasher.finish() % jitter as u64) as i64
+}
+
+/// Returns the offset of the item placed `index`-th within the interval. The
+/// bits of the index are reversed, so every next item halves the largest gap
+/// between the offsets of the previous ones.
+fn spread_offset(index: u64, interval: i64) -> i64 {
+  ((u128::from(index.reverse_bits()) * interval as u128) >> 64) as i64
+}
+
+/// Returns the first moment at or after `from` that is `offset` after
+/// a multiple of the interval.
+fn next_moment(from: i64, interval: i64, offset: i64) -> i64 {
+  (from - offset + interval - 1).div_euclid(interval) * interval + offset
+}
+
+#[cfg(test)]
+mod tests {
+  use std::time::Duration;
+
+  use super::*;
+  use crate::schedule::clock::MockClock;
+
+  #[derive(Debug, PartialEq, Serialize, Deserialize)]
+  struct Task {
+    id: i64,
+    interval: i64,
+    updated: bool,
+    jitter: Option<i64>,
+  }
+
+  impl<Item: Schedulable> Schedule<Item> {
+    pub async fn items_ref(&self) -> HashMap<Item::Id, Arc<Item>> {
+      let mut items = HashMap::new();
+
+      for shard in &self.shards {
+        items.extend(shard.read().await.items.clone());
+      }
+
+      items
+    }
+
+    pub async fn intervals_ref(&self) -> HashMap<Item::Interval, HashSet<Item::Id>> {
+      let mut intervals = HashMap::<_, HashSet<_>>::new();
+
+      for shard in &self.shards {
+        for (interval, ids) in &shard.read().await.intervals {
+          intervals
+            .entry(*interval)
+            .or_default()
+            .extend(ids.iter().cloned());
+        }
+      }
+
+      intervals
+    }
+  }
+
+  impl From<(i64, i64)> for Task {
+    fn from(args: (i64, i64)) -> Self {
+      Task {
+        id: args.0,
+        interval: args.1,
+        updated: false,
+        jitter: None,
+      }
+    }
+  }
+
+  impl Schedulable for Task {
+    type Id = i64;
+    type Interval = i64;
+
+    fn get_id(&self) -> Self::Id {
+      self.id
+    }
+
+    fn get_interval(&self) -> Self::Interval {
+      self.interval
+    }
+
+    fn get_jitter(&self) -> Option<i64> {
+      self.jitter
+    }
+  }
+
+  #[tokio::test]
+  async fn empty_schedule() {
+    let schedule: Schedule<Task> = Schedule::new();
+
+    assert!(
+      schedule.items_ref().await.is_empty(),
+      "schedule items shouldn't be empty"
+    );
+    assert!(
+      schedule.intervals_ref().await.is_empty(),
+      "schedule intervals shouldn't be empty"
+    );
+  }
+
+  #[tokio::test]
+  async fn test_empty_schedule() {
+    let schedule: Schedule<Task> = Schedule::new();
+
+    assert!(
+      schedule.get_due(1, 100).await.is_empty(),
+      "empty schedule shouldn't return due items"
+    );
+  }
+
+  #[tokio::test]
+  async fn get_due_on_boundary() {
+    let schedule: Schedule<Task> = Schedule::new();
+
+    schedule.insert(Task::from((1, 10))).await.unwrap();
+
+    assert_eq!(
+      schedule.get_due(1, 10).await.len(),
+      1,
+      "schedule should return item on boundary"
+    );
+    assert_eq!(
+      schedule.get_due(10, 10).await.len(),
+      1,
+      "schedule should return item on boundary equals"
+    );
+  }
+
+  #[tokio::test]
+  async fn get_due_before_boundary() {
+    let schedule: Schedule<Task> = Schedule::new();
+
+    schedule.insert(Task::from((1, 10))).await.unwrap();
+
+    assert!(
+      schedule.get_due(1, 9).await.is_empty(),
+      "schedule shouldn't return due items before boundary"
+    );
+  }
+
+  #[tokio::test]
+  async fn test_multiple_intervals() {
+    let schedule: Schedule<Task> = Schedule::new();
+
+    schedule.insert(Task::from((1, 5))).await.unwrap();
+    schedule.insert(Task::from((2, 10))).await.unwrap();
+
+    let ids: Vec<i64> = schedule.get_due(1, 10).await.iter().map(|t| t.id).collect();
+
+    assert!(
+      ids.contains(&1),
+      "schedule should return item with interval 5"
+    );
+    assert!(
+      ids.contains(&2),
+      "schedule should return item with interval 10"
+    );
+  }
+
+  #[tokio::test]
+  async fn test_skip_multiple_intervals() {
+    let schedule: Schedule<Task> = Schedule::new();
+
+    schedule.insert(Task::from((1, 10))).await.unwrap();
+
+    assert_eq!(
+      schedule.get_due(1, 35).await.len(),
+      1,
+      "schedule should return due item even if multiple intervals were passed"
+    );
+  }
+
+  #[tokio::test]
+  async fn insert_single_item_into_schedule() {
+    let schedule: Schedule<Task> = Schedule::new();
+
+    schedule.insert(Task::from((1, 30))).await.unwrap();
+
+    assert!(
+      schedule.items_ref().await.contains_key(&1),
+      "schedule items should contain entry"
+    );
+    assert!(
+      schedule.intervals_ref().await.contains_key(&30),
+      "schedule intervals should contain entry"
+    );
+    assert_eq!(
+      schedule.get(1).await,
+      Some(Arc::new(Task::from((1, 30)))),
+      "schedule should return entry by id"
+    );
+  }
+
+  #[tokio::test]
+  async fn insert_multiple_items_into_schedule() {
+    let schedule: Schedule<Task> = Schedule::new();
+
+    schedule.insert(Task::from((1, 30))).await.unwrap();
+    schedule.insert(Task::from((2, 30))).await.unwrap();
+
+    assert!(
+      schedule.items_ref().await.contains_key(&1),
+      "schedule items should contain entry"
+    );
+    assert!(
+      schedule.items_ref().await.contains_key(&2),
+      "schedule items should contain entry"
+    );
+    assert!(
+      schedule.intervals_ref().await.contains_key(&30),
+      "schedule intervals should contain entry"
+    );
+    assert_eq!(
+      schedule.get(1).await,
+      Some(Arc::new(Task::from((1, 30)))),
+      "schedule should return entry by id"
+    );
+    assert_eq!(
+      schedule.get(2).await,
+      Some(Arc::new(Task::from((2, 30)))),
+      "schedule should return entry by id"
+    );
+  }
+
+  #[tokio::test]
+  async fn insert_the_sane_item_twice() {
+    let schedule: Schedule<Task> = Schedule::new();
+
+    schedule.insert(Task::from((1, 30))).await.unwrap();
+    schedule.insert(Task::from((1, 30))).await.unwrap();
+
+    assert_eq!(
+      schedule.items_ref().await.len(),
+      1,
+      "schedule items shouldn't be empty"
+    );
+    assert_eq!(
+      schedule.intervals_ref().await.len(),
+      1,
+      "schedule intervals shouldn't be empty"
+    );
+  }
+
+  #[tokio::test]
+  async fn remove_item_from_schedule() {
+    let schedule: Schedule<Task> = Schedule::new();
+
+    schedule.insert(Task::from((1, 30))).await.unwrap();
+    schedule.remove(1).await;
+
+    assert!(
+      schedule.items_ref().await.is_empty(),
+      "schedule items should be empty"
+    );
+    assert!(
+      schedule.intervals_ref().await.is_empty(),
+      "schedule intervals should be empty"
+    );
+  }
+
+  #[tokio::test]
+  async fn clear() {
+    let schedule: Schedule<Task> = Schedule::new();
+
+    schedule.insert(Task::from((1, 10))).await.unwrap();
+    schedule.insert(Task::from((2, 20))).await.unwrap();
+
+    assert!(!schedule.is_empty().await, "schedule shouldn't be empty");
+
+    schedule.clear().await;
+    assert!(schedule.is_empty().await, "schedule should be empty");
+  }
+
+  #[tokio::test]
+  async fn jitter_spreads_due_items() {
+    let schedule: Schedule<Task> = Schedule::with_jitter(60);
+
+    for id in 0..100 {
+      schedule.insert(Task::from((id, 60))).await.unwrap();
+    }
+
+    assert!(
+      schedule.get_due(60, 60).await.len() < 100,
+      "items aren't due at the same moment"
+    );
+    assert_eq!(
+      schedule.get_due(60, 119).await.len(),
+      100,
+      "every item is due once per interval"
+    );
+    assert_eq!(
+      schedule.get_due(60, 119).await.len(),
+      schedule.get_due(120, 179).await.len(),
+      "offsets are stable"
+    );
+  }
+
+  #[tokio::test]
+  async fn item_jitter() {
+    let schedule: Schedule<Task> = Schedule::with_jitter(60);
+
+    schedule
+      .insert(Task {
+        jitter: Some(0),
+        ..Task::from((1, 60))
+      })
+      .await
+      .unwrap();
+
+    assert_eq!(
+      schedule.get_due(60, 60).await.len(),
+      1,
+      "item jitter overrides the schedule's"
+    );
+  }
+
+  #[tokio::test]
+  async fn item_jitter_without_schedule_jitter() {
+    let schedule: Schedule<Task> = Schedule::new();
+    let jittered = Task {
+      jitter: Some(59),
+      ..Task::from((1, 60))
+    };
+    let offset = jitter_offset(&jittered, 60, 0);
+
+    schedule.insert(jittered).await.unwrap();
+    schedule.insert(Task::from((2, 60))).await.unwrap();
+
+    let due = schedule.get_due(60 + offset, 60 + offset).await;
     assert!(
-      schedule.items_ref().await.contains_key(&1),
-      "schedule items should contain entry"
+      due.iter().any(|task| task.id == 1),
+      "item is delayed by its own jitter"
+    );
+    assert_eq!(schedule.get_due(60, 119).await.len(), 2);
+
+    schedule.remove(1).await;
+
+    assert_eq!(
+      schedule.get_due(60, 60).await,
+      [Arc::new(Task::from((2, 60)))],
+      "other items are due at the multiples of their interval"
     );
+  }
+
+  #[tokio::test]
+  async fn replace_item_with_another_interval() {
+    let schedule: Schedule<Task> = Schedule::new();
+
+    schedule.insert(Task::from((1, 30))).await.unwrap();
+    let replaced = schedule.insert(Task::from((1, 60))).await.unwrap();
+
+    assert_eq!(replaced, Some(Arc::new(Task::from((1, 30)))));
     assert!(
-      schedule.intervals_ref().await.contains_key(&30),
-      "schedule intervals should contain entry"
+      !schedule.intervals_ref().await.contains_key(&30),
+      "old interval is cleaned up"
+    );
+    assert!(
+      schedule.get_due(30, 30).await.is_empty(),
+      "item isn't due at the old interval"
+    );
+    assert_eq!(schedule.get_due(60, 60).await.len(), 1);
+  }
+
+  #[tokio::test]
+  async fn update_interval() {
+    let schedule: Schedule<Task> = Schedule::new();
+
+    schedule.insert(Task::from((1, 30))).await.unwrap();
+
+    assert!(schedule.update_interval(1, 20).await.unwrap());
+    assert!(
+      !schedule.update_interval(2, 20).await.unwrap(),
+      "item doesn't exist"
     );
     assert_eq!(
-      schedule.get(1).await,
-      Some(Arc::new(Task::from((1, 30)))),
-      "schedule should return entry by id"
+      schedule.intervals_ref().await.keys().collect::<Vec<_>>(),
+      vec![&20],
+      "id is moved to the new interval"
+    );
+    assert_eq!(schedule.get_due(20, 20).await.len(), 1);
+
+    schedule.remove(1).await;
+    assert!(
+      schedule.intervals_ref().await.is_empty(),
+      "item is removed from the updated interval"
     );
   }
 
   #[tokio::test]
-  async fn insert_multiple_items_into_schedule() {
+  async fn insert_and_remove_many() {
+    let schedule: Schedule<Task> = Schedule::new();
+
+    schedule
+      .insert_many((1..=4).map(|id| Task::from((id, id * 10))))
+      .await
+      .unwrap();
+
+    assert_eq!(schedule.items_ref().await.len(), 4);
+    assert_eq!(schedule.intervals_ref().await.len(), 4);
+
+    schedule.remove_many([1, 2, 5]).await;
+
+    assert_eq!(
+      schedule
+        .items_ref()
+        .await
+        .keys()
+        .copied()
+        .collect::<HashSet<_>>(),
+      HashSet::from([3, 4]),
+      "missing ids are ignored"
+    );
+    assert_eq!(schedule.intervals_ref().await.len(), 2);
+  }
+
+  #[tokio::test]
+  async fn introspection() {
+    let schedule: Schedule<Task> = Schedule::new();
+
+    assert_eq!(schedule.len().await, 0);
+    assert!(schedule.is_empty().await);
+
+    schedule
+      .insert_many([Task::from((1, 10)), Task::from((2, 20))])
+      .await
+      .unwrap();
+
+    assert_eq!(schedule.len().await, 2);
+    assert_eq!(
+      schedule.ids().await.into_iter().collect::<HashSet<_>>(),
+      HashSet::from([1, 2])
+    );
+
+    let items = schedule.iter().await;
+    schedule.clear().await;
+
+    assert_eq!(
+      items.map(|item| item.id).collect::<HashSet<_>>(),
+      HashSet::from([1, 2]),
+      "snapshot isn't affected by later changes"
+    );
+  }
+
+  #[tokio::test]
+  async fn next_due() {
+    let schedule: Schedule<Task> = Schedule::new();
+
+    schedule
+      .insert_many([Task::from((1, 30)), Task::from((2, 45))])
+      .await
+      .unwrap();
+
+    assert_eq!(schedule.next_due(1, 31).await, Some(60));
+    assert_eq!(schedule.next_due(1, 60).await, Some(60), "due at `from`");
+    assert_eq!(schedule.next_due(3, 31).await, None, "item doesn't exist");
+
+    assert_eq!(schedule.next_tick(31).await, Some(45));
+    assert_eq!(
+      schedule.get_due(31, 44).await.len(),
+      0,
+      "nothing is due before the next tick"
+    );
+
+    schedule.clear().await;
+    assert_eq!(schedule.next_tick(31).await, None);
+  }
+
+  #[tokio::test]
+  async fn one_shot_items() {
     let schedule: Schedule<Task> = Schedule::new();
 
-    schedule.insert(Task::from((1, 30))).await;
-    schedule.insert(Task::from((2, 30))).await;
+    schedule.insert(Task::from((1, 60))).await.unwrap();
+    schedule.insert_once(Task::from((1, 60)), 90).await;
+    schedule.insert_once(Task::from((2, 60)), 100).await;
+
+    assert_eq!(schedule.len().await, 1, "one-shot runs aren't counted");
+    assert_eq!(schedule.next_due(1, 61).await, Some(90));
+    assert_eq!(schedule.next_tick(61).await, Some(90));
+
+    let ids = |items: Vec<Arc<Task>>| items.iter().map(|item| item.id).collect::<Vec<_>>();
 
+    assert_eq!(ids(schedule.get_due(61, 95).await), vec![1]);
     assert!(
-      schedule.items_ref().await.contains_key(&1),
-      "schedule items should contain entry"
+      schedule.get_due(61, 95).await.is_empty(),
+      "one-shot item is removed once due"
+    );
+    assert_eq!(
+      schedule.next_due(1, 96).await,
+      Some(120),
+      "recurring item is kept"
+    );
+
+    assert!(schedule.cancel_once(2).await.is_some());
+    assert!(schedule.get_due(96, 110).await.is_empty());
+  }
+
+  #[tokio::test]
+  async fn delayed_item() {
+    let clock = MockClock::new(1000);
+    let schedule: Schedule<Task> = Schedule::new().clock(clock.clone());
+
+    schedule.insert_delayed(Task::from((1, 60)), 30).await;
+    assert_eq!(schedule.next_due(1, 1000).await, Some(1030));
+
+    clock.advance(Duration::from_secs(15));
+    schedule.insert_delayed(Task::from((1, 60)), 30).await;
+    assert_eq!(
+      schedule.next_due(1, 1000).await,
+      Some(1045),
+      "delay is counted from the current time"
     );
+  }
+
+  #[tokio::test]
+  async fn snapshot_and_restore() {
+    let schedule: Schedule<Task> = Schedule::with_jitter(60);
+
+    schedule
+      .insert_many((1..=20).map(|id| Task::from((id, 60))))
+      .await
+      .unwrap();
+    schedule.update_interval(1, 30).await.unwrap();
+    schedule.insert_once(Task::from((21, 60)), 90).await;
+
+    let json = serde_json::to_string(&schedule.snapshot().await).unwrap();
+    let restored =
+      Schedule::restore(serde_json::from_str::<Snapshot<Task>>(&json).unwrap()).unwrap();
+
+    assert_eq!(restored.len().await, 20);
+
+    for id in 1..=21 {
+      assert_eq!(
+        restored.next_due(id, 61).await,
+        schedule.next_due(id, 61).await,
+        "item is due at the same moment"
+      );
+    }
+
     assert!(
-      schedule.items_ref().await.contains_key(&2),
-      "schedule items should contain entry"
+      restored
+        .insert(Task::from((1, 30)))
+        .await
+        .unwrap()
+        .is_some()
+    );
+    assert_eq!(
+      restored.next_due(1, 61).await,
+      schedule.next_due(1, 61).await,
+      "replaced item keeps its phase"
+    );
+  }
+
+  #[tokio::test]
+  async fn restored_phase() {
+    let json = r#"{
+      "jitter": 0,
+      "items": [{
+        "item": { "id": 1, "interval": 60, "updated": false, "jitter": null },
+        "interval": null,
+        "offset": 7,
+        "last_run": null
+      }],
+      "once": []
+    }"#;
+    let schedule =
+      Schedule::restore(serde_json::from_str::<Snapshot<Task>>(json).unwrap()).unwrap();
+
+    assert_eq!(
+      schedule.next_due(1, 61).await,
+      Some(67),
+      "offset isn't derived again"
     );
+
+    schedule.remove(1).await;
+    schedule.insert(Task::from((1, 60))).await.unwrap();
+
+    assert_eq!(schedule.next_due(1, 61).await, Some(120));
+  }
+
+  #[tokio::test]
+  async fn reshard() {
+    let schedule: Schedule<Task> = Schedule::new();
+
+    schedule
+      .insert_many((1..=20).map(|id| Task::from((id, 60))))
+      .await
+      .unwrap();
+    schedule.update_interval(1, 30).await.unwrap();
+    schedule.insert_once(Task::from((21, 60)), 90).await;
+
+    let schedule = schedule.shards(3);
+
+    assert_eq!(schedule.len().await, 20, "items are moved to new shards");
+    assert_eq!(schedule.next_due(1, 31).await, Some(60));
+    assert_eq!(schedule.next_due(21, 31).await, Some(90));
+
+    schedule.remove_many(1..=10).await;
+    assert_eq!(schedule.len().await, 10);
+  }
+
+  #[tokio::test]
+  async fn track_runs() {
+    let schedule: Schedule<Task> = Schedule::new().track_runs(true);
+
+    schedule
+      .insert_many([Task::from((1, 60)), Task::from((2, 30))])
+      .await
+      .unwrap();
+
+    assert_eq!(schedule.get_due(1, 60).await.len(), 2);
+    assert_eq!(schedule.last_run(1).await, Some(60));
+    assert_eq!(schedule.last_run(2).await, Some(60), "last due moment");
+
     assert!(
-      schedule.intervals_ref().await.contains_key(&30),
-      "schedule intervals should contain entry"
+      schedule.get_due(30, 75).await.is_empty(),
+      "items aren't returned for the same moment twice"
     );
+    assert_eq!(schedule.get_due(61, 90).await.len(), 1);
+
+    schedule.insert(Task::from((1, 60))).await.unwrap();
     assert_eq!(
-      schedule.get(1).await,
-      Some(Arc::new(Task::from((1, 30)))),
-      "schedule should return entry by id"
+      schedule.last_run(1).await,
+      Some(60),
+      "replaced item keeps its last run"
     );
+
+    let restored = Schedule::restore(schedule.snapshot().await).unwrap();
+    assert!(restored.get_due(1, 90).await.is_empty());
+
+    let untracked: Schedule<Task> = Schedule::new();
+    untracked.insert(Task::from((1, 60))).await.unwrap();
+
+    assert_eq!(untracked.get_due(1, 60).await.len(), 1);
+    assert_eq!(untracked.get_due(30, 75).await.len(), 1);
+    assert_eq!(untracked.last_run(1).await, None);
+  }
+
+  #[tokio::test]
+  async fn remove_by_interval() {
+    let schedule: Schedule<Task> = Schedule::new();
+
+    schedule
+      .insert_many((1..=6).map(|id| Task::from((id, 10 * (id % 2 + 1)))))
+      .await
+      .unwrap();
+    schedule.update_interval(1, 30).await.unwrap();
+
+    let removed = schedule.remove_by_interval(20).await;
+
     assert_eq!(
-      schedule.get(2).await,
-      Some(Arc::new(Task::from((2, 30)))),
-      "schedule should return entry by id"
+      removed.iter().map(|item| item.id).collect::<HashSet<_>>(),
+      HashSet::from([3, 5]),
+      "items are removed by the interval they're scheduled with"
     );
+    assert_eq!(schedule.len().await, 4);
+    assert!(!schedule.intervals_ref().await.contains_key(&20));
+    assert!(schedule.remove_by_interval(20).await.is_empty());
   }
 
   #[tokio::test]
-  async fn insert_the_sane_item_twice() {
+  async fn capacity() {
+    let schedule: Schedule<Task> = Schedule::with_capacity(1000, 10);
+    let capacity = |schedule: &Schedule<Task>| {
+      schedule
+        .shards
+        .iter()
+        .map(|shard| shard.try_read().unwrap().items.capacity())
+        .sum::<usize>()
+    };
+
+    assert!(capacity(&schedule) >= 1000);
+
+    let schedule = schedule.shards(4);
+    assert!(capacity(&schedule) >= 1000, "capacity is kept");
+
+    schedule
+      .insert_many((1..=1000).map(|id| Task::from((id, 10))))
+      .await
+      .unwrap();
+    schedule.remove_many(1..=990).await;
+    schedule.shrink_to_fit().await;
+
+    assert!(capacity(&schedule) < 100);
+    assert_eq!(schedule.len().await, 10);
+  }
+
+  #[tokio::test]
+  async fn retain() {
     let schedule: Schedule<Task> = Schedule::new();
 
-    schedule.insert(Task::from((1, 30))).await;
-    schedule.insert(Task::from((1, 30))).await;
+    schedule
+      .insert_many((1..=10).map(|id| Task::from((id, id * 10))))
+      .await
+      .unwrap();
+    schedule.insert_once(Task::from((2, 20)), 100).await;
+    schedule.insert_once(Task::from((11, 20)), 100).await;
+
+    schedule.retain(|item| item.id % 2 == 1).await;
 
     assert_eq!(
-      schedule.items_ref().await.len(),
-      1,
-      "schedule items shouldn't be empty"
+      schedule.ids().await.into_iter().collect::<HashSet<_>>(),
+      HashSet::from([1, 3, 5, 7, 9])
     );
+    assert_eq!(schedule.intervals_ref().await.len(), 5);
     assert_eq!(
-      schedule.intervals_ref().await.len(),
-      1,
-      "schedule intervals shouldn't be empty"
+      schedule
+        .get_due(100, 100)
+        .await
+        .iter()
+        .map(|item| item.id)
+        .collect::<HashSet<_>>(),
+      HashSet::from([1, 5, 11]),
+      "one-shot runs of removed items are dropped"
     );
   }
 
   #[tokio::test]
-  async fn remove_item_from_schedule() {
+  async fn change_events() {
     let schedule: Schedule<Task> = Schedule::new();
+    let mut events = schedule.subscribe();
 
-    schedule.insert(Task::from((1, 30))).await;
+    schedule.insert(Task::from((1, 10))).await.unwrap();
+    schedule.insert(Task::from((1, 20))).await.unwrap();
+    schedule.update_interval(1, 30).await.unwrap();
+    schedule.insert_once(Task::from((2, 10)), 100).await;
+    schedule.remove(2).await;
     schedule.remove(1).await;
 
+    let mut received = Vec::new();
+    while let Ok(event) = events.try_recv() {
+      received.push(event);
+    }
+
+    assert_eq!(received, vec![
+      Event::Inserted(1),
+      Event::Updated(1),
+      Event::Updated(1),
+      Event::Removed(1),
+    ]);
+
+    schedule
+      .insert_many([Task::from((3, 10)), Task::from((4, 10))])
+      .await
+      .unwrap();
+    schedule.clear().await;
+
+    let mut removed = HashSet::new();
+    while let Ok(event) = events.try_recv() {
+      if let Event::Removed(id) = event {
+        removed.insert(id);
+      }
+    }
+
+    assert_eq!(removed, HashSet::from([3, 4]), "cleared items are reported");
+  }
+
+  #[tokio::test]
+  async fn read_only_view() {
+    let schedule: Schedule<Task> = Schedule::with_jitter(5);
+    schedule
+      .insert_many((1..=10).map(|id| Task::from((id, 30))))
+      .await
+      .unwrap();
+    schedule.update_interval(1, 60).await.unwrap();
+
+    let view = schedule.view().await;
+    schedule.remove(2).await;
+
+    assert_eq!(view.len(), 10, "later changes aren't reflected");
+    assert_eq!(view.clone().get(2).map(|task| task.id), Some(2));
+    assert_eq!(view.interval(1), Some(60));
+
+    for id in (1..=10).filter(|id| *id != 2) {
+      assert_eq!(view.next_due(id, 1), schedule.next_due(id, 1).await);
+    }
+
+    let mut due: Vec<i64> = view.get_due(1, 60).iter().map(|t| t.id).collect();
+    due.sort();
+    assert_eq!(due, (1..=10).collect::<Vec<_>>());
+  }
+
+  #[tokio::test]
+  async fn interval_stats() {
+    let schedule: Schedule<Task> = Schedule::new();
+    assert!(schedule.interval_stats().await.is_empty());
+
+    schedule
+      .insert_many((1..=10).map(|id| Task::from((id, if id <= 7 { 30 } else { 60 }))))
+      .await
+      .unwrap();
+    schedule.update_interval(1, 90).await.unwrap();
+    schedule.remove(10).await;
+
+    assert_eq!(
+      schedule.interval_stats().await,
+      HashMap::from([(30, 6), (60, 2), (90, 1)])
+    );
+  }
+
+  #[tokio::test]
+  async fn get_many() {
+    let schedule: Schedule<Task> = Schedule::new();
+    schedule
+      .insert_many((1..=100).map(|id| Task::from((id, 10))))
+      .await
+      .unwrap();
+
+    let items = schedule.get_many([1, 50, 100, 101]).await;
+
+    let mut ids: Vec<i64> = items.keys().cloned().collect();
+    ids.sort();
+    assert_eq!(ids, vec![1, 50, 100], "missing ids are left out");
+    assert!(items.iter().all(|(id, item)| item.id == *id));
+  }
+
+  #[tokio::test]
+  async fn blackout_windows() {
+    let schedule: Schedule<Task> = Schedule::new().clock(MockClock::new(0));
+    schedule
+      .insert_many([Task::from((1, 10)), Task::from((2, 10))])
+      .await
+      .unwrap();
+
+    schedule.add_blackout(Window::between(20, 40)).await;
+    assert!(schedule.add_item_blackout(1, Window::between(50, 60)).await);
+    assert!(!schedule.add_item_blackout(3, Window::between(50, 60)).await);
+
+    assert_eq!(schedule.get_due(1, 10).await.len(), 2);
     assert!(
-      schedule.items_ref().await.is_empty(),
-      "schedule items should be empty"
+      schedule.get_due(11, 30).await.is_empty(),
+      "no item is due during a global window"
+    );
+    assert_eq!(
+      schedule.get_due(31, 40).await.len(),
+      2,
+      "the end is excluded"
     );
+
+    let ids: Vec<i64> = schedule
+      .get_due(41, 50)
+      .await
+      .iter()
+      .map(|t| t.id)
+      .collect();
+    assert_eq!(ids, vec![2], "item's own window");
+
+    schedule.insert_once(Task::from((3, 10)), 25).await;
     assert!(
-      schedule.intervals_ref().await.is_empty(),
-      "schedule intervals should be empty"
+      schedule.get_due(41, 50).await.iter().all(|t| t.id != 3),
+      "one-shot runs during a window are skipped"
     );
+
+    schedule.insert(Task::from((1, 10))).await.unwrap();
+    let restored = Schedule::restore(schedule.snapshot().await).unwrap();
+    assert_eq!(
+      restored.get_due(41, 50).await.len(),
+      1,
+      "windows are kept by replaced and restored items"
+    );
+
+    let view = schedule.view().await;
+    assert!(view.get_due(11, 30).is_empty(), "view skips global windows");
+    assert_eq!(
+      view
+        .get_due(41, 50)
+        .iter()
+        .map(|t| t.id)
+        .collect::<Vec<_>>(),
+      vec![2],
+      "view skips item's own windows"
+    );
+
+    schedule.clear_blackouts().await;
+    schedule.clear_item_blackouts(1).await;
+    assert_eq!(schedule.get_due(11, 30).await.len(), 2);
+    assert_eq!(schedule.get_due(41, 50).await.len(), 2);
   }
 
   #[tokio::test]
-  async fn clear() {
+  async fn spread_placement() {
+    let schedule: Schedule<Task> = Schedule::with_jitter(10).placement(Placement::Spread);
+    schedule
+      .insert_many((1..=4).map(|id| Task::from((id, 60))))
+      .await
+      .unwrap();
+
+    let mut offsets = Vec::new();
+    for id in 1..=4 {
+      offsets.push(schedule.next_due(id, 1).await.unwrap() % 60);
+    }
+    offsets.sort();
+    assert_eq!(offsets, vec![0, 15, 30, 45], "items are spread evenly");
+
+    for moment in [15, 30, 45, 60] {
+      assert_eq!(schedule.get_due(moment, moment).await.len(), 1);
+    }
+
+    let next = schedule.next_due(2, 1).await;
+    schedule.insert(Task::from((2, 60))).await.unwrap();
+    assert_eq!(
+      schedule.next_due(2, 1).await,
+      next,
+      "replaced item keeps its offset"
+    );
+
+    let restored = Schedule::restore(schedule.snapshot().await).unwrap();
+    restored.insert(Task::from((5, 60))).await.unwrap();
+    assert_eq!(
+      restored.next_due(5, 1).await,
+      Some(7),
+      "restored schedule goes on spreading items"
+    );
+  }
+
+  #[test]
+  fn spread_offsets() {
+    let offsets: Vec<i64> = (0..8).map(|index| spread_offset(index, 80)).collect();
+
+    assert_eq!(offsets, vec![0, 40, 20, 60, 10, 50, 30, 70]);
+    assert_eq!(spread_offset(1, 1), 0);
+  }
+
+  #[tokio::test]
+  async fn string_keys() {
+    #[derive(Debug)]
+    struct Named {
+      name: String,
+      interval: Duration,
+    }
+
+    impl Schedulable for Named {
+      type Id = String;
+      type Interval = Duration;
+
+      fn get_id(&self) -> Self::Id {
+        self.name.clone()
+      }
+
+      fn get_interval(&self) -> Self::Interval {
+        self.interval
+      }
+    }
+
+    let schedule: Schedule<Named> = Schedule::with_jitter(5);
+    schedule
+      .insert_many([("api", 30), ("web", 60)].map(|(name, interval)| Named {
+        name: name.to_owned(),
+        interval: Duration::from_secs(interval),
+      }))
+      .await
+      .unwrap();
+
+    assert!(schedule.get("api".to_owned()).await.is_some());
+    assert_eq!(schedule.get_due(1, 60).await.len(), 2);
+
+    schedule.remove("web".to_owned()).await;
+    assert_eq!(schedule.ids().await, vec!["api".to_owned()]);
+
+    assert_eq!(
+      schedule
+        .insert(Named {
+          name: "db".to_owned(),
+          interval: Duration::from_millis(500),
+        })
+        .await
+        .unwrap_err(),
+      ScheduleError::NonPositiveInterval(0),
+      "intervals are truncated to whole seconds"
+    );
+  }
+
+  #[tokio::test]
+  async fn claimed_items() {
     let schedule: Schedule<Task> = Schedule::new();
+    schedule
+      .insert_many([Task::from((1, 10)), Task::from((2, 20))])
+      .await
+      .unwrap();
+
+    let mut ids: Vec<i64> = schedule
+      .drain_due(1, 20)
+      .await
+      .iter()
+      .map(|t| t.id)
+      .collect();
+    ids.sort();
+    assert_eq!(ids, vec![1, 2]);
 
-    schedule.insert(Task::from((1, 10))).await;
-    schedule.insert(Task::from((2, 20))).await;
+    assert!(
+      schedule.drain_due(1, 20).await.is_empty(),
+      "claimed items aren't returned twice"
+    );
+    assert!(
+      schedule.drain_due(21, 30).await.is_empty(),
+      "claimed items aren't returned at their next moments"
+    );
 
-    assert!(!schedule.is_empty().await, "schedule shouldn't be empty");
+    assert!(schedule.complete(1).await);
+    assert!(!schedule.complete(1).await, "the claim is released");
+    assert_eq!(schedule.last_run(1).await, Some(20));
+    assert!(
+      schedule.drain_due(15, 20).await.is_empty(),
+      "completed moments aren't returned again"
+    );
 
-    schedule.clear().await;
-    assert!(schedule.is_empty().await, "schedule should be empty");
+    assert!(schedule.fail(2).await);
+    assert_eq!(schedule.last_run(2).await, None);
+
+    let ids: Vec<i64> = schedule
+      .drain_due(15, 30)
+      .await
+      .iter()
+      .map(|t| t.id)
+      .collect();
+    assert_eq!(ids.len(), 2, "failed items are returned again");
+  }
+
+  #[tokio::test]
+  async fn interval_validation() {
+    let schedule: Schedule<Task> = Schedule::new().min_interval(30);
+
+    assert_eq!(
+      schedule.insert(Task::from((1, 0))).await,
+      Err(ScheduleError::NonPositiveInterval(0))
+    );
+    assert_eq!(
+      schedule.insert(Task::from((1, 10))).await,
+      Err(ScheduleError::IntervalTooShort {
+        interval: 10,
+        minimum: 30
+      })
+    );
+
+    assert!(
+      schedule
+        .insert_many([Task::from((1, 60)), Task::from((2, -60))])
+        .await
+        .is_err()
+    );
+    assert!(
+      schedule.is_empty().await,
+      "no item of the batch is inserted"
+    );
+
+    schedule.insert(Task::from((1, 60))).await.unwrap();
+    assert!(schedule.update_interval(1, 20).await.is_err());
+    assert_eq!(schedule.next_due(1, 1).await, Some(60), "interval is kept");
   }
 }
@@ -0,0 +1,199 @@
+//! Circuit breakers stretching the intervals of failing items.
+//!
+//! Once an item fails a number of consecutive runs, e.g. a monitor of a host
+//! in a long outage, its breaker opens: the item is scheduled with an
+//! interval growing exponentially with every further failure, up to a cap,
+//! so it doesn't take capacity from the others. Its first success closes the
+//! breaker and restores its own interval.
+//!
+//! Breakers are enabled on a [Runner](crate::schedule::runner::Runner) with
+//! [circuit_breaker](crate::schedule::runner::Runner::circuit_breaker), which
+//! tells failures apart with [is_failure](crate::schedule::runner::Runnable::is_failure).
+
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::sync::Mutex;
+
+/// Configuration of the circuit breakers of a runner.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct CircuitBreaker {
+  /// Number of consecutive failures opening the breaker.
+  pub threshold: u32,
+
+  /// Factor the interval is multiplied by on every failure of an open
+  /// breaker, starting with the one opening it.
+  pub multiplier: u32,
+
+  /// Maximum interval, in seconds, of an item with an open breaker. Items
+  /// with a longer interval of their own keep it.
+  pub max_interval: i64,
+}
+
+impl Default for CircuitBreaker {
+  fn default() -> Self {
+    Self {
+      threshold: 3,
+      multiplier: 2,
+      max_interval: 3600,
+    }
+  }
+}
+
+/// The state of the breaker of an item that failed its last run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BreakerState {
+  /// Number of consecutive failed runs.
+  pub failures: u32,
+
+  /// Whether the breaker is open, i.e. the item's interval is stretched.
+  pub open: bool,
+
+  /// Interval, in seconds, the item is scheduled with.
+  pub interval: i64,
+}
+
+/// The breakers of the items of a runner.
+#[derive(Debug)]
+pub(crate) struct Breakers<Id> {
+  config: CircuitBreaker,
+  states: Mutex<HashMap<Id, BreakerState>>,
+
+  /// Intervals the items should be scheduled with, from oldest to newest.
+  updates: Mutex<Vec<(Id, i64)>>,
+}
+
+impl<Id: Eq + Hash + Clone> Breakers<Id> {
+  pub(crate) fn new(config: CircuitBreaker) -> Self {
+    Self {
+      config,
+      states: Mutex::new(HashMap::new()),
+      updates: Mutex::new(Vec::new()),
+    }
+  }
+
+  /// Records whether a run of the item with `id` and its own `interval`
+  /// failed. If the interval it should be scheduled with changes, it's
+  /// queued for [updates](Breakers::updates).
+  pub(crate) fn record(&self, id: &Id, interval: i64, failed: bool) {
+    if let Some(interval) = self.transition(id, interval, failed) {
+      self
+        .updates
+        .lock()
+        .expect("breakers lock")
+        .push((id.clone(), interval));
+    }
+  }
+
+  /// Takes the queued interval updates.
+  pub(crate) fn updates(&self) -> Vec<(Id, i64)> {
+    std::mem::take(&mut *self.updates.lock().expect("breakers lock"))
+  }
+
+  /// Updates the state of the breaker, returning the new interval of the
+  /// item if it changes.
+  fn transition(&self, id: &Id, interval: i64, failed: bool) -> Option<i64> {
+    let mut states = self.states.lock().expect("breakers lock");
+
+    if !failed {
+      let state = states.remove(id)?;
+
+      return (state.interval != interval).then_some(interval);
+    }
+
+    let state = states.entry(id.clone()).or_insert(BreakerState {
+      failures: 0,
+      open: false,
+      interval,
+    });
+    state.failures = state.failures.saturating_add(1);
+
+    if state.failures < self.config.threshold.max(1) {
+      return None;
+    }
+
+    let exponent = state.failures - self.config.threshold.max(1) + 1;
+    let stretched = i64::from(self.config.multiplier.max(1))
+      .saturating_pow(exponent)
+      .saturating_mul(interval)
+      .min(self.config.max_interval.max(interval));
+
+    state.open = true;
+
+    if stretched == state.interval {
+      None
+    } else {
+      state.interval = stretched;
+      Some(stretched)
+    }
+  }
+
+  /// Returns the state of the breaker of the item with `id`, if its last run
+  /// failed.
+  pub(crate) fn get(&self, id: &Id) -> Option<BreakerState> {
+    self.states.lock().expect("breakers lock").get(id).copied()
+  }
+
+  /// Returns the items with an open breaker, with their states.
+  pub(crate) fn open(&self) -> Vec<(Id, BreakerState)> {
+    self
+      .states
+      .lock()
+      .expect("breakers lock")
+      .iter()
+      .filter(|(_, state)| state.open)
+      .map(|(id, state)| (id.clone(), *state))
+      .collect()
+  }
+
+  /// Forgets the breaker of the item with `id`, e.g. once it's removed.
+  pub(crate) fn forget(&self, id: &Id) {
+    self.states.lock().expect("breakers lock").remove(id);
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn stretched_intervals() {
+    let breakers = Breakers::new(CircuitBreaker {
+      threshold: 2,
+      multiplier: 3,
+      max_interval: 300,
+    });
+
+    breakers.record(&1, 10, true);
+    assert!(!breakers.get(&1).unwrap().open);
+    assert!(breakers.updates().is_empty());
+
+    for _ in 0..5 {
+      breakers.record(&1, 10, true);
+    }
+    assert_eq!(
+      breakers.updates(),
+      [(1, 30), (1, 90), (1, 270), (1, 300)],
+      "the interval is stretched up to the cap"
+    );
+    assert_eq!(
+      breakers.get(&1),
+      Some(BreakerState {
+        failures: 6,
+        open: true,
+        interval: 300,
+      })
+    );
+    assert_eq!(breakers.open().len(), 1);
+
+    breakers.record(&1, 10, false);
+    breakers.record(&2, 10, true);
+    breakers.record(&2, 10, false);
+    assert_eq!(
+      breakers.updates(),
+      [(1, 10)],
+      "a success restores the interval of an open breaker"
+    );
+    assert_eq!(breakers.get(&1), None);
+  }
+}
@@ -0,0 +1,204 @@
+//! A builder of [Schedule]s.
+//!
+//! The [ScheduleBuilder] holds the configuration of a schedule apart from its
+//! items, so it can be passed around, cloned and used to build schedules of
+//! any [Schedulable] type.
+//!
+//! # Example
+//!
+//! ```rust
+//! use limon_core::monitor::models::Monitor;
+//! use limon_core::schedule::Schedule;
+//! use limon_core::schedule::builder::ScheduleBuilder;
+//! use limon_core::schedule::clock::MockClock;
+//!
+//! let schedule: Schedule<Monitor> = ScheduleBuilder::new()
+//!   .capacity(10_000, 8)
+//!   .jitter(5)
+//!   .min_interval(10)
+//!   .clock(MockClock::new(0))
+//!   .build();
+//! ```
+
+use std::collections::HashMap;
+use std::hash::RandomState;
+use std::sync::Arc;
+
+use tokio::sync::{Mutex, RwLock, broadcast};
+
+use crate::schedule::clock::{Clock, SystemClock};
+use crate::schedule::window::Window;
+use crate::schedule::{DEFAULT_SHARDS, EVENTS_CAPACITY, Placement, Schedulable, Schedule};
+
+/// The configuration of a [Schedule], built by [build](ScheduleBuilder::build).
+#[derive(Clone)]
+pub struct ScheduleBuilder {
+  pub(super) items: usize,
+  pub(super) intervals: usize,
+  pub(super) shards: usize,
+  pub(super) jitter: i64,
+  pub(super) min_interval: i64,
+  pub(super) track_runs: bool,
+  pub(super) clock: Arc<dyn Clock>,
+  pub(super) blackouts: Vec<Window>,
+  pub(super) placement: Placement,
+}
+
+impl ScheduleBuilder {
+  /// Creates a builder with the defaults of [Schedule::new].
+  pub fn new() -> Self {
+    Self {
+      items: 0,
+      intervals: 0,
+      shards: DEFAULT_SHARDS,
+      jitter: 0,
+      min_interval: 1,
+      track_runs: false,
+      clock: Arc::new(SystemClock),
+      blackouts: Vec::new(),
+      placement: Placement::Jitter,
+    }
+  }
+
+  /// Reserves space for at least `items` items with `intervals` distinct
+  /// intervals, as [Schedule::with_capacity].
+  pub fn capacity(mut self, items: usize, intervals: usize) -> Self {
+    self.items = items;
+    self.intervals = intervals;
+    self
+  }
+
+  /// Sets the number of [shards](Schedule::shards).
+  pub fn shards(mut self, shards: usize) -> Self {
+    self.shards = shards.max(1);
+    self
+  }
+
+  /// Sets the maximum delay of the items' due times, as
+  /// [Schedule::with_jitter].
+  pub fn jitter(mut self, jitter: i64) -> Self {
+    self.jitter = jitter;
+    self
+  }
+
+  /// Sets the shortest interval items can be inserted with, as
+  /// [Schedule::min_interval].
+  pub fn min_interval(mut self, min_interval: i64) -> Self {
+    self.min_interval = min_interval.max(1);
+    self
+  }
+
+  /// Sets whether runs are tracked, as [Schedule::track_runs].
+  pub fn track_runs(mut self, track: bool) -> Self {
+    self.track_runs = track;
+    self
+  }
+
+  /// Sets the clock of the schedule, as [Schedule::clock].
+  pub fn clock(mut self, clock: impl Clock) -> Self {
+    self.clock = Arc::new(clock);
+    self
+  }
+
+  /// Sets how the due times of new items are placed, as
+  /// [Schedule::placement].
+  pub fn placement(mut self, placement: Placement) -> Self {
+    self.placement = placement;
+    self
+  }
+
+  /// Adds windows during which no item is due, as
+  /// [Schedule::add_blackout].
+  pub fn blackouts(mut self, windows: impl IntoIterator<Item = Window>) -> Self {
+    self.blackouts.extend(windows);
+    self
+  }
+
+  /// Builds an empty schedule. The builder can be cloned beforehand to build
+  /// several schedules alike.
+  pub fn build<Item: Schedulable>(self) -> Schedule<Item> {
+    Schedule {
+      shards: Schedule::empty_shards(self.shards, self.items, self.intervals),
+      hasher: RandomState::new(),
+      jitter: self.jitter,
+      track_runs: self.track_runs,
+      min_interval: self.min_interval,
+      clock: self.clock,
+      events: broadcast::Sender::new(EVENTS_CAPACITY),
+      blackouts: RwLock::new(self.blackouts),
+      placement: self.placement,
+      placed: Mutex::new(HashMap::new()),
+    }
+  }
+}
+
+impl Default for ScheduleBuilder {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::schedule::clock::MockClock;
+  use crate::schedule::errors::ScheduleError;
+
+  #[derive(Debug, PartialEq)]
+  struct Task {
+    id: i64,
+    interval: i64,
+  }
+
+  impl Schedulable for Task {
+    type Id = i64;
+    type Interval = i64;
+
+    fn get_id(&self) -> Self::Id {
+      self.id
+    }
+
+    fn get_interval(&self) -> Self::Interval {
+      self.interval
+    }
+  }
+
+  #[tokio::test]
+  async fn configured_schedule() {
+    let builder = ScheduleBuilder::default()
+      .shards(4)
+      .jitter(0)
+      .min_interval(20)
+      .track_runs(true)
+      .clock(MockClock::new(100));
+    let schedule: Schedule<Task> = builder.clone().build();
+
+    assert_eq!(schedule.shards.len(), 4);
+    assert_eq!(schedule.get_clock().now(), 100);
+    assert_eq!(
+      schedule
+        .insert(Task {
+          id: 1,
+          interval: 10
+        })
+        .await,
+      Err(ScheduleError::IntervalTooShort {
+        interval: 10,
+        minimum: 20
+      })
+    );
+
+    schedule
+      .insert(Task {
+        id: 1,
+        interval: 20,
+      })
+      .await
+      .unwrap();
+    assert_eq!(schedule.get_due(1, 20).await.len(), 1);
+    assert!(schedule.get_due(1, 20).await.is_empty(), "runs are tracked");
+
+    let other: Schedule<Task> = builder.build();
+    assert!(other.is_empty().await, "schedules built alike are apart");
+  }
+}
@@ -0,0 +1,151 @@
+//! Clocks the moments of a [Schedule](super::Schedule) are taken from.
+//!
+//! The [SystemClock] follows the real time. A [MockClock] only moves when it's
+//! told to, so scheduling can be tested deterministically without real
+//! sleeps.
+//!
+//! # Example
+//!
+//! ```rust
+//! use std::time::Duration;
+//!
+//! use limon_core::schedule::clock::{Clock, MockClock};
+//!
+//! # tokio_test::block_on(async {
+//! let clock = MockClock::new(100);
+//! let sleep = clock.sleep(Duration::from_secs(30));
+//!
+//! clock.advance(Duration::from_secs(30));
+//! sleep.await;
+//!
+//! assert_eq!(clock.now(), 130);
+//! # })
+//! ```
+
+use std::future::Future;
+use std::pin::Pin;
+use std::time::Duration;
+
+use time::OffsetDateTime;
+use tokio::sync::watch;
+
+/// A future completing once a [Clock] has slept for a while.
+pub type Sleep = Pin<Box<dyn Future<Output = ()> + Send>>;
+
+/// A source of the current time.
+pub trait Clock: Send + Sync + 'static {
+  /// Returns the current unix timestamp, in seconds.
+  fn now(&self) -> i64;
+
+  /// Returns the current unix timestamp, in milliseconds. By default, it's
+  /// the one in seconds, at whole seconds.
+  fn now_millis(&self) -> i64 {
+    self.now() * 1000
+  }
+
+  /// Returns a future completing once the clock has moved by `duration`.
+  fn sleep(&self, duration: Duration) -> Sleep;
+}
+
+/// The real time of the system.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+  fn now(&self) -> i64 {
+    OffsetDateTime::now_utc().unix_timestamp()
+  }
+
+  fn now_millis(&self) -> i64 {
+    (OffsetDateTime::now_utc().unix_timestamp_nanos() / 1_000_000) as i64
+  }
+
+  fn sleep(&self, duration: Duration) -> Sleep {
+    Box::pin(tokio::time::sleep(duration))
+  }
+}
+
+/// A clock moved by hand. Clones share the time, so a clone kept by a test
+/// moves the clock of a schedule.
+#[derive(Debug, Clone)]
+pub struct MockClock {
+  /// The current time, in milliseconds.
+  millis: watch::Sender<i64>,
+}
+
+impl MockClock {
+  /// Creates a clock showing the unix timestamp `now`, in seconds.
+  pub fn new(now: i64) -> Self {
+    Self {
+      millis: watch::Sender::new(now * 1000),
+    }
+  }
+
+  /// Sets the time to the unix timestamp `now`, in seconds. Sleeps ending
+  /// before it are completed.
+  pub fn set(&self, now: i64) {
+    self.millis.send_replace(now * 1000);
+  }
+
+  /// Moves the clock forward by `duration`, completing the sleeps ending
+  /// meanwhile.
+  pub fn advance(&self, duration: Duration) {
+    self
+      .millis
+      .send_modify(|millis| *millis += duration.as_millis() as i64);
+  }
+
+  /// Returns the number of sleeps that haven't completed yet.
+  pub fn sleeping(&self) -> usize {
+    self.millis.receiver_count()
+  }
+}
+
+impl Clock for MockClock {
+  fn now(&self) -> i64 {
+    self.millis.borrow().div_euclid(1000)
+  }
+
+  fn now_millis(&self) -> i64 {
+    *self.millis.borrow()
+  }
+
+  fn sleep(&self, duration: Duration) -> Sleep {
+    let deadline = *self.millis.borrow() + duration.as_millis() as i64;
+    let mut millis = self.millis.subscribe();
+
+    Box::pin(async move {
+      let _ = millis.wait_for(|millis| *millis >= deadline).await;
+    })
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[tokio::test]
+  async fn mock_sleep() {
+    let clock = MockClock::new(100);
+    let mut sleep = clock.sleep(Duration::from_millis(1500));
+
+    assert_eq!(clock.sleeping(), 1);
+
+    clock.advance(Duration::from_secs(1));
+    assert!(
+      tokio::time::timeout(Duration::from_millis(10), &mut sleep)
+        .await
+        .is_err(),
+      "sleep isn't over before the clock is moved by its duration"
+    );
+    assert_eq!(clock.now(), 101);
+
+    clock.clone().advance(Duration::from_millis(500));
+    sleep.await;
+    assert_eq!(clock.sleeping(), 0);
+
+    clock.set(200);
+    clock.sleep(Duration::ZERO).await;
+    assert_eq!(clock.now(), 200);
+  }
+}
@@ -0,0 +1,15 @@
+//! A module describing schedule errors.
+
+use thiserror::Error;
+
+/// Errors that can occur when scheduling an item.
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum ScheduleError {
+  /// The interval of the item isn't positive, so it would never be due.
+  #[error("Interval {0} isn't positive")]
+  NonPositiveInterval(i64),
+
+  /// The interval of the item is shorter than the minimum of the schedule.
+  #[error("Interval {interval} is shorter than the minimum of {minimum}")]
+  IntervalTooShort { interval: i64, minimum: i64 },
+}
@@ -0,0 +1,198 @@
+//! Lifecycle events of the runs of a runner.
+//!
+//! A [Runner](crate::schedule::runner::Runner) emits a [RunEvent] as runs
+//! start and finish, and as items start or stop failing, on a broadcast
+//! channel. Any number of subscribers, e.g. a UI or a logger, can
+//! [subscribe](crate::schedule::runner::Runner::subscribe) to it without
+//! wrapping the sink, much like the [changes](crate::schedule::Schedule::subscribe)
+//! of a schedule. A subscriber falling behind by more than 1024 events
+//! misses the oldest ones.
+//!
+//! # Example
+//!
+//! ```rust, no_run
+//! use std::sync::Arc;
+//!
+//! use limon_core::monitor::models::{Measurement, Monitor};
+//! use limon_core::schedule::Schedule;
+//! use limon_core::schedule::events::RunEvent;
+//! use limon_core::schedule::runner::Runner;
+//! use tokio::sync::broadcast::error::RecvError;
+//! use tokio::sync::mpsc;
+//!
+//! async fn run(schedule: Arc<Schedule<Monitor>>, sink: mpsc::Sender<Measurement>) {
+//!   let runner = Runner::new(schedule, sink);
+//!   let mut events = runner.subscribe();
+//!
+//!   tokio::spawn(runner.run());
+//!
+//!   loop {
+//!     match events.recv().await {
+//!       Ok(RunEvent::StateChanged { id, failing }) => println!("monitor {id} failing: {failing}"),
+//!       Ok(_) | Err(RecvError::Lagged(_)) => {}
+//!       Err(RecvError::Closed) => break,
+//!     }
+//!   }
+//! }
+//! ```
+
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use tokio::sync::broadcast;
+
+/// The number of [events](RunEvent) kept for subscribers that lag behind.
+const EVENTS_CAPACITY: usize = 1024;
+
+/// An event of the lifecycle of a run of the item with `id`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RunEvent<Id> {
+  /// The run took its permit and started.
+  CheckStarted { id: Id },
+
+  /// The run completed within its deadline, or its output was shared by the
+  /// run of another item.
+  CheckFinished {
+    id: Id,
+
+    /// Whether the output is of a [failed](crate::schedule::runner::Runnable::is_failure)
+    /// run.
+    failed: bool,
+
+    /// Time the run took.
+    elapsed: Duration,
+  },
+
+  /// The run exceeded its deadline.
+  CheckTimedOut { id: Id, deadline: Duration },
+
+  /// The item started or stopped failing, or finished its first run. Runs
+  /// exceeding their deadline count as failed.
+  StateChanged { id: Id, failing: bool },
+}
+
+/// The channel of the events of a runner, and the states of its items.
+#[derive(Debug)]
+pub(crate) struct Events<Id> {
+  sender: broadcast::Sender<RunEvent<Id>>,
+
+  /// Whether the last run of every item failed.
+  failing: Mutex<HashMap<Id, bool>>,
+}
+
+impl<Id: Eq + Hash + Clone> Events<Id> {
+  pub(crate) fn new() -> Self {
+    Self {
+      sender: broadcast::Sender::new(EVENTS_CAPACITY),
+      failing: Mutex::new(HashMap::new()),
+    }
+  }
+
+  pub(crate) fn subscribe(&self) -> broadcast::Receiver<RunEvent<Id>> {
+    self.sender.subscribe()
+  }
+
+  /// Sends an event to the subscribers, if there are any.
+  fn emit(&self, event: RunEvent<Id>) {
+    let _ = self.sender.send(event);
+  }
+
+  pub(crate) fn started(&self, id: &Id) {
+    self.emit(RunEvent::CheckStarted { id: id.clone() });
+  }
+
+  pub(crate) fn finished(&self, id: &Id, failed: bool, elapsed: Duration) {
+    self.emit(RunEvent::CheckFinished {
+      id: id.clone(),
+      failed,
+      elapsed,
+    });
+    self.record(id, failed);
+  }
+
+  pub(crate) fn timed_out(&self, id: &Id, deadline: Duration) {
+    self.emit(RunEvent::CheckTimedOut {
+      id: id.clone(),
+      deadline,
+    });
+    self.record(id, true);
+  }
+
+  /// Records whether the last run of the item failed, emitting a state
+  /// change if it differs from the previous one.
+  fn record(&self, id: &Id, failing: bool) {
+    let previous = self
+      .failing
+      .lock()
+      .expect("events lock")
+      .insert(id.clone(), failing);
+
+    if previous != Some(failing) {
+      self.emit(RunEvent::StateChanged {
+        id: id.clone(),
+        failing,
+      });
+    }
+  }
+
+  /// Forgets the state of the item with `id`, e.g. once it's removed.
+  pub(crate) fn forget(&self, id: &Id) {
+    self.failing.lock().expect("events lock").remove(id);
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn state_changes() {
+    let events = Events::new();
+    let mut receiver = events.subscribe();
+    let elapsed = Duration::from_millis(5);
+
+    events.started(&1);
+    events.finished(&1, false, elapsed);
+    events.finished(&1, false, elapsed);
+    events.timed_out(&1, Duration::from_secs(1));
+    events.finished(&1, true, elapsed);
+
+    let received: Vec<_> = std::iter::from_fn(|| receiver.try_recv().ok()).collect();
+    assert_eq!(
+      received,
+      [
+        RunEvent::CheckStarted { id: 1 },
+        RunEvent::CheckFinished {
+          id: 1,
+          failed: false,
+          elapsed
+        },
+        RunEvent::StateChanged {
+          id: 1,
+          failing: false
+        },
+        RunEvent::CheckFinished {
+          id: 1,
+          failed: false,
+          elapsed
+        },
+        RunEvent::CheckTimedOut {
+          id: 1,
+          deadline: Duration::from_secs(1)
+        },
+        RunEvent::StateChanged {
+          id: 1,
+          failing: true
+        },
+        RunEvent::CheckFinished {
+          id: 1,
+          failed: true,
+          elapsed
+        },
+      ],
+      "states are only emitted when they change"
+    );
+  }
+}
@@ -0,0 +1,97 @@
+//! Rate limiting of runs by their target.
+//!
+//! A [RateLimiter] spaces the runs of items with the same
+//! [target](crate::schedule::runner::Runnable::target), e.g. the monitors of
+//! several paths of an origin, so they don't hit it at the same moment. It's
+//! set on a [Runner](crate::schedule::runner::Runner), and can be shared by
+//! the runners of several schedules.
+//!
+//! # Example
+//!
+//! ```rust
+//! use std::time::Duration;
+//!
+//! use limon_core::schedule::limiter::RateLimiter;
+//!
+//! # tokio_test::block_on(async {
+//! let limiter = RateLimiter::new(2);
+//!
+//! assert_eq!(limiter.reserve("example.com"), Duration::ZERO);
+//! assert!(limiter.reserve("example.com") > Duration::from_millis(400));
+//! assert_eq!(limiter.reserve("example.org"), Duration::ZERO);
+//! # })
+//! ```
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use tokio::time::Instant;
+
+/// Limits the rate of runs per target.
+#[derive(Debug)]
+pub struct RateLimiter {
+  /// The time between two runs of a target.
+  interval: Duration,
+
+  /// The moment the next run of every target can start at.
+  slots: Mutex<HashMap<String, Instant>>,
+}
+
+impl RateLimiter {
+  /// Creates a limiter of `per_second` runs per target, at least one.
+  pub fn new(per_second: u32) -> Self {
+    Self {
+      interval: Duration::from_secs(1) / per_second.max(1),
+      slots: Mutex::new(HashMap::new()),
+    }
+  }
+
+  /// Reserves the next slot of the target, and returns how long to wait for
+  /// it. A run of a target without a reserved slot starts right away.
+  pub fn reserve(&self, target: &str) -> Duration {
+    let now = Instant::now();
+    let mut slots = self.slots.lock().expect("rate limiter lock");
+
+    slots.retain(|_, slot| *slot > now);
+
+    let slot = slots.get(target).map_or(now, |slot| (*slot).max(now));
+    slots.insert(target.to_owned(), slot + self.interval);
+
+    slot - now
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[tokio::test]
+  async fn spaced_runs() {
+    let limiter = RateLimiter::new(4);
+    let close = |wait: Duration, expected: u64| {
+      wait <= Duration::from_millis(expected) && wait > Duration::from_millis(expected - 10)
+    };
+
+    assert_eq!(limiter.reserve("origin"), Duration::ZERO);
+    assert!(close(limiter.reserve("origin"), 250));
+    assert!(close(limiter.reserve("origin"), 500));
+    assert_eq!(
+      limiter.reserve("other"),
+      Duration::ZERO,
+      "targets are independent"
+    );
+
+    let limiter = RateLimiter::new(1000);
+    limiter.reserve("origin");
+    limiter.reserve("other");
+
+    tokio::time::sleep(Duration::from_millis(5)).await;
+    assert_eq!(limiter.reserve("origin"), Duration::ZERO);
+    assert_eq!(
+      limiter.slots.lock().unwrap().len(),
+      1,
+      "expired slots are dropped"
+    );
+  }
+}
@@ -0,0 +1,99 @@
+//! Fast rechecks of failed items.
+//!
+//! A single failed run is often a blip, so rather than waiting a whole
+//! interval to find out, a [Runner](crate::schedule::runner::Runner) with
+//! [rechecks](crate::schedule::runner::Runner::recheck) runs a failed item
+//! again once, shortly after, and again while it keeps failing, up to a
+//! number of times. A recheck that succeeds clears a false alarm early,
+//! while failed rechecks count toward confirming the failure, e.g. the
+//! `confirmation_period` of a [MonitorState](crate::monitor::state::MonitorState),
+//! so it's confirmed within seconds. Rechecks stop once a failure is
+//! [confirmed](crate::schedule::runner::Runnable::confirmations).
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use crate::schedule::Schedulable;
+
+/// Configuration of the rechecks of a runner.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct Recheck {
+  /// Delay, in seconds, between a failed run and its recheck.
+  pub delay: i64,
+
+  /// Maximum number of consecutive rechecks after a failed run.
+  pub count: u32,
+}
+
+impl Default for Recheck {
+  fn default() -> Self {
+    Self { delay: 5, count: 2 }
+  }
+}
+
+/// The rechecks of the items of a runner.
+pub(crate) struct Rechecks<Item: Schedulable> {
+  config: Recheck,
+
+  /// Clones the items to recheck, as they're inserted as one-shot runs.
+  clone: fn(&Item) -> Item,
+
+  /// Number of rechecks of the items failing since their last success.
+  taken: Mutex<HashMap<Item::Id, u32>>,
+
+  /// Items to recheck, from oldest to newest.
+  queued: Mutex<Vec<Arc<Item>>>,
+}
+
+impl<Item: Schedulable> Rechecks<Item> {
+  pub(crate) fn new(config: Recheck, clone: fn(&Item) -> Item) -> Self {
+    Self {
+      config,
+      clone,
+      taken: Mutex::new(HashMap::new()),
+      queued: Mutex::new(Vec::new()),
+    }
+  }
+
+  pub(crate) fn delay(&self) -> i64 {
+    self.config.delay
+  }
+
+  /// Records whether a run of the item failed, and queues it for a recheck
+  /// if it's failing and has rechecks left, at most `confirmations - 1`.
+  pub(crate) fn record(&self, item: &Arc<Item>, failed: bool, confirmations: Option<u32>) {
+    let mut taken = self.taken.lock().expect("rechecks lock");
+
+    if !failed {
+      taken.remove(&item.get_id());
+      return;
+    }
+
+    let limit = confirmations.map_or(self.config.count, |confirmations| {
+      self.config.count.min(confirmations.saturating_sub(1))
+    });
+    let taken = taken.entry(item.get_id()).or_default();
+
+    if *taken < limit {
+      *taken += 1;
+      self
+        .queued
+        .lock()
+        .expect("rechecks lock")
+        .push(Arc::clone(item));
+    }
+  }
+
+  /// Takes copies of the items queued for a recheck.
+  pub(crate) fn queued(&self) -> Vec<Item> {
+    let queued = std::mem::take(&mut *self.queued.lock().expect("rechecks lock"));
+
+    queued.iter().map(|item| (self.clone)(item)).collect()
+  }
+
+  /// Forgets the rechecks of the item with `id`, e.g. once it's removed.
+  pub(crate) fn forget(&self, id: &Item::Id) {
+    self.taken.lock().expect("rechecks lock").remove(id);
+  }
+}
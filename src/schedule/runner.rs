@@ -0,0 +1,1764 @@
+//! A runner executing due items of a [Schedule].
+//!
+//! The [Runner] ticks on a fixed period, takes the items that became due
+//! since the previous tick and runs them concurrently, up to a limit. The
+//! output of every run is forwarded to a [Sink].
+//!
+//! The limit is enforced by a [Semaphore] the runs take a permit of, so a
+//! tick with thousands of due checks doesn't start them all at once. Runners
+//! of several schedules can share a [semaphore](Runner::semaphore), and with
+//! it a single limit. Items of a [class](Runnable::class), e.g. HTTP
+//! monitors, can be given a [queue](Runner::class_concurrency) with a limit
+//! of their own, so slow runs of one class don't delay the others.
+//!
+//! Outputs are sent to the sink one by one, unless it's wrapped in
+//! [Batched], which sends them in batches of a given size, or every given
+//! period, e.g. to export them to a remote backend at once. With
+//! [backpressure](Runner::backpressure), a sink that can't keep up, e.g. a
+//! full bounded channel, delays the next runs.
+//!
+//! A runner started with [run_until](Runner::run_until) shuts down once its
+//! [CancellationToken] is cancelled, e.g. on `SIGTERM`: it stops starting
+//! runs, waits for the ones in flight for a [grace period](Runner::grace),
+//! then [flushes](Sink::flush) the sink.
+//!
+//! Every run is bounded by a [deadline](Runner::deadline), so an item that
+//! never completes doesn't hold its permit forever.
+//!
+//! Ticks are aligned to the multiples of their period since the unix epoch,
+//! so they don't slide by the time dispatching takes. After a pause, e.g.
+//! of a suspended VM, the items due meanwhile are run on the next tick and
+//! counted as [late](Runner::late_runs), the ones that missed the most
+//! intervals first. With the `metrics` feature, late runs are also recorded
+//! as `limon_late_runs_total` and `limon_run_lateness_seconds`.
+//!
+//! A runner in [dry run](Runner::dry_run) mode only reports the items it
+//! would run on every tick, e.g. to validate a configuration or the spread
+//! of the due times, without running any.
+//!
+//! The [stats](Runner::stats) of a runner, e.g. its runs in flight and its
+//! error rate, tell how the agent itself is doing, and can be
+//! [exported](crate::export::prometheus::render_runner_stats) to Prometheus.
+//!
+//! Items failing repeatedly can have their intervals stretched by a
+//! [circuit breaker](Runner::circuit_breaker), and failed items can be
+//! [rechecked](Runner::recheck) shortly after, to confirm or clear their
+//! failure early.
+//!
+//! Runs starting and finishing, and items starting or stopping to fail, are
+//! emitted as [events](crate::schedule::events) to the
+//! [subscribers](Runner::subscribe) of the runner.
+//!
+//! The items of a running runner are reconciled with a new configuration by
+//! [apply](Runner::apply), without restarting it.
+//!
+//! Items of a tick doing the same work, e.g. monitors of several tenants
+//! checking one gateway, can be [deduplicated](Runner::dedup).
+//!
+//! Runs of items with the same [target](Runnable::target), e.g. monitors of
+//! one host, can be spaced by a [rate limit](Runner::rate_limit). Items
+//! waiting for their turn don't take a permit until it comes.
+//!
+//! # Example
+//!
+//! ```rust, no_run
+//! use std::sync::Arc;
+//!
+//! use limon_core::monitor::models::{Measurement, Monitor};
+//! use limon_core::schedule::Schedule;
+//! use limon_core::schedule::runner::Runner;
+//! use tokio::sync::mpsc;
+//!
+//! async fn run() {
+//!   let schedule = Arc::new(Schedule::<Monitor>::new());
+//!   let (sink, mut measurements) = mpsc::channel::<Measurement>(1024);
+//!
+//!   tokio::spawn(Runner::new(Arc::clone(&schedule), sink).concurrency(50).run());
+//!
+//!   while let Some(measurement) = measurements.recv().await {
+//!     println!("{:?}", measurement);
+//!   }
+//! }
+//! ```
+
+use std::collections::HashMap;
+use std::collections::hash_map::Entry;
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use std::{iter, mem};
+
+use tokio::sync::{Semaphore, broadcast, mpsc};
+use tokio::task::AbortHandle;
+use tokio_util::sync::CancellationToken;
+use tokio_util::task::TaskTracker;
+
+use crate::schedule::breaker::{BreakerState, Breakers, CircuitBreaker};
+use crate::schedule::errors::ScheduleError;
+use crate::schedule::events::{Events, RunEvent};
+use crate::schedule::limiter::RateLimiter;
+use crate::schedule::recheck::{Recheck, Rechecks};
+use crate::schedule::{Schedulable, Schedule, Seconds};
+
+/// The default period of the runner's ticks.
+const DEFAULT_TICK: Duration = Duration::from_secs(1);
+
+/// The default maximum number of items run at the same time.
+const DEFAULT_CONCURRENCY: usize = 100;
+
+/// The default time runs in flight are waited for on shutdown.
+const DEFAULT_GRACE: Duration = Duration::from_secs(30);
+
+/// The default maximum duration of a run.
+const DEFAULT_DEADLINE: Duration = Duration::from_secs(300);
+
+/// A [Schedulable] item that can be run when it's due. Its `id` is shared
+/// with the runs, so it's `Send` and `Sync` too.
+pub trait Runnable: Schedulable<Id: Send + Sync> + Send + Sync + 'static {
+  /// The result of a run.
+  type Output: Send + 'static;
+
+  /// Runs the item.
+  fn run(&self) -> impl Future<Output = Self::Output> + Send;
+
+  /// Returns the target the item's runs are [rate limited](Runner::rate_limit)
+  /// by, e.g. the host it sends requests to. By default, there's none, and
+  /// runs aren't limited.
+  fn target(&self) -> Option<&str> {
+    None
+  }
+
+  /// Returns whether the output is of a failed run, which counts toward the
+  /// [circuit breaker](Runner::circuit_breaker) of the item. By default, no
+  /// run fails.
+  fn is_failure(output: &Self::Output) -> bool {
+    let _ = output;
+    false
+  }
+
+  /// Returns the number of consecutive failed runs confirming a failure of
+  /// the item, e.g. the confirmation period of a monitor, past which
+  /// [rechecks](Runner::recheck) are pointless. By default, rechecks are
+  /// only limited by their count.
+  fn confirmations(&self) -> Option<u32> {
+    None
+  }
+
+  /// Returns the class of work of the item, e.g. the type of check it
+  /// performs. Classes can be given [queues](Runner::class_concurrency) of
+  /// their own. By default, there's none, and the item shares the queue of
+  /// the runner.
+  fn class(&self) -> Option<&str> {
+    None
+  }
+
+  /// Returns a key identifying the work a run does, e.g. a hash of the
+  /// request it sends. With [dedup](Runner::dedup), due items of a tick
+  /// with the same key are run once, and the output is [shared](Runnable::share)
+  /// with the others. By default, there's none, and the item always runs.
+  fn dedup_key(&self) -> Option<u64> {
+    None
+  }
+
+  /// Turns the output of an item run in place of this one, with the same
+  /// [dedup key](Runnable::dedup_key), into its own output, e.g. sets its
+  /// id. By default, the output is kept as is.
+  fn share(&self, output: Self::Output) -> Self::Output {
+    output
+  }
+
+  /// Releases the state kept outside of the runner for the item with `id`
+  /// once it's removed by [apply](Runner::apply), e.g. the sequence numbers
+  /// of a monitor. By default, there's none.
+  fn forget(id: &Self::Id) {
+    let _ = id;
+  }
+
+  /// Runs the item, like [run](Runnable::run), within `deadline`. By
+  /// default, a run that exceeds it is abandoned without an output.
+  fn run_with_deadline(
+    &self,
+    deadline: Duration,
+  ) -> impl Future<Output = Option<Self::Output>> + Send {
+    async move { tokio::time::timeout(deadline, self.run()).await.ok() }
+  }
+}
+
+/// A destination of the outputs of the runs.
+pub trait Sink<Output>: Send + Sync + 'static {
+  /// Consumes the output of a run.
+  fn send(&self, output: Output) -> impl Future<Output = ()> + Send;
+
+  /// Consumes the outputs of several runs, e.g. in a single request. By
+  /// default, they're sent one by one.
+  fn send_batch(&self, outputs: Vec<Output>) -> impl Future<Output = ()> + Send
+  where
+    Output: Send,
+  {
+    async move {
+      for output in outputs {
+        self.send(output).await;
+      }
+    }
+  }
+
+  /// Waits until the outputs sent so far are consumed, e.g. written out by a
+  /// batching sink. By default, there's nothing to wait for.
+  fn flush(&self) -> impl Future<Output = ()> + Send {
+    async {}
+  }
+}
+
+/// Outputs are sent to the channel. They're dropped if the receiver is
+/// closed.
+impl<Output: Send + 'static> Sink<Output> for mpsc::Sender<Output> {
+  async fn send(&self, output: Output) {
+    let _ = mpsc::Sender::send(self, output).await;
+  }
+}
+
+/// Collects outputs and sends them to a sink in [batches](Sink::send_batch),
+/// once `size` outputs are collected or every `period`, whichever comes
+/// first. [Flushing](Sink::flush) it sends the collected outputs right away.
+pub struct Batched<Output, S> {
+  batch: Arc<Batch<Output, S>>,
+  size: usize,
+
+  /// The task sending the outputs every period.
+  timer: AbortHandle,
+}
+
+/// The sink of a [Batched] one, with the outputs collected for it.
+struct Batch<Output, S> {
+  sink: S,
+  outputs: Mutex<Vec<Output>>,
+}
+
+impl<Output: Send + 'static, S: Sink<Output>> Batch<Output, S> {
+  /// Sends the collected outputs, if any.
+  async fn send(&self) {
+    let outputs = mem::take(&mut *self.outputs.lock().expect("batch lock"));
+
+    if !outputs.is_empty() {
+      self.sink.send_batch(outputs).await;
+    }
+  }
+}
+
+impl<Output: Send + 'static, S: Sink<Output>> Batched<Output, S> {
+  /// Spawns the task sending the outputs every `period` onto the current
+  /// Tokio runtime. It stops once the batched sink is dropped, and the
+  /// outputs collected by then are lost unless it's flushed.
+  ///
+  /// # Panics
+  ///
+  /// Panics if called outside of a Tokio runtime, or if `period` is zero.
+  pub fn new(sink: S, size: usize, period: Duration) -> Self {
+    let batch = Arc::new(Batch {
+      sink,
+      outputs: Mutex::new(Vec::new()),
+    });
+    let timer = tokio::spawn({
+      let batch = Arc::clone(&batch);
+      let mut interval = tokio::time::interval_at(tokio::time::Instant::now() + period, period);
+
+      async move {
+        loop {
+          interval.tick().await;
+          batch.send().await;
+        }
+      }
+    });
+
+    Self {
+      batch,
+      size: size.max(1),
+      timer: timer.abort_handle(),
+    }
+  }
+
+  /// Returns the number of outputs collected and not sent yet.
+  pub fn pending(&self) -> usize {
+    self.batch.outputs.lock().expect("batch lock").len()
+  }
+}
+
+impl<Output: Send + 'static, S: Sink<Output>> Sink<Output> for Batched<Output, S> {
+  async fn send(&self, output: Output) {
+    let full = {
+      let mut outputs = self.batch.outputs.lock().expect("batch lock");
+      outputs.push(output);
+
+      (outputs.len() >= self.size).then(|| mem::take(&mut *outputs))
+    };
+
+    if let Some(outputs) = full {
+      self.batch.sink.send_batch(outputs).await;
+    }
+  }
+
+  /// Sends the collected outputs, then flushes the sink.
+  async fn flush(&self) {
+    self.batch.send().await;
+    self.batch.sink.flush().await;
+  }
+}
+
+impl<Output, S> Drop for Batched<Output, S> {
+  fn drop(&mut self) {
+    self.timer.abort();
+  }
+}
+
+/// The changes made to a schedule by [Runner::apply], by item `id`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Applied<Id> {
+  /// Items that weren't in the schedule.
+  pub inserted: Vec<Id>,
+
+  /// Items that replaced different ones with the same `id`.
+  pub updated: Vec<Id>,
+
+  /// Items that are no longer in the schedule.
+  pub removed: Vec<Id>,
+}
+
+/// An item a runner in [dry run](Runner::dry_run) mode would have run.
+#[derive(Debug)]
+pub struct Planned<Item> {
+  /// The moment the item was due at, in seconds.
+  pub due: i64,
+
+  /// The interval the item is scheduled with, in seconds.
+  pub interval: i64,
+
+  /// The item, as it's scheduled.
+  pub item: Arc<Item>,
+}
+
+/// Reports the items a runner in dry run mode would have run.
+type DryRun<Item> = Box<dyn Fn(Planned<Item>) + Send + Sync>;
+
+/// Clones the output of a run shared by [deduplicated](Runner::dedup) items.
+type CloneOutput<Output> = fn(&Output) -> Output;
+
+/// Statistics of a [Runner], taken by [Runner::stats].
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct RunnerStats {
+  /// Number of runs in progress.
+  pub in_flight: usize,
+
+  /// Number of due items waiting for a permit or for their turn within the
+  /// rate limit.
+  pub queued: usize,
+
+  /// Number of completed runs, including the ones that exceeded their
+  /// deadline.
+  pub executed: u64,
+
+  /// Number of completed runs that failed or exceeded their deadline.
+  pub failed: u64,
+
+  /// Number of runs started [late](Runner::late_runs).
+  pub late: u64,
+
+  /// Number of outputs [shared](Runner::dedup) by a run of another item,
+  /// which aren't counted as runs.
+  pub deduplicated: u64,
+
+  /// Average number of runs completed per second since the runner was
+  /// created.
+  pub executed_per_second: f64,
+
+  /// Share of the completed runs that failed, from 0 to 1.
+  pub error_rate: f64,
+
+  /// Time the last tick took to start the due runs.
+  pub last_tick: Duration,
+}
+
+/// Counters of the runs of a [Runner], shared with the runs.
+#[derive(Debug, Default)]
+struct Counters {
+  queued: AtomicUsize,
+  executed: AtomicU64,
+  failed: AtomicU64,
+  deduplicated: AtomicU64,
+}
+
+/// A due run counted as [queued](RunnerStats::queued) until it takes its
+/// permit, or it's dropped without running, e.g. as the runner stops.
+struct Queued(Arc<Counters>);
+
+impl Queued {
+  fn new(counters: &Arc<Counters>) -> Self {
+    counters.queued.fetch_add(1, Ordering::Relaxed);
+    Self(Arc::clone(counters))
+  }
+}
+
+impl Drop for Queued {
+  fn drop(&mut self) {
+    self.0.queued.fetch_sub(1, Ordering::Relaxed);
+  }
+}
+
+/// Runs due items of a [Schedule] and forwards their outputs to a [Sink].
+pub struct Runner<Item: Runnable, S: Sink<Item::Output>> {
+  schedule: Arc<Schedule<Item>>,
+  sink: Arc<S>,
+  tick: Duration,
+  runs: Arc<Semaphore>,
+
+  /// Semaphores of the classes with a queue of their own.
+  classes: HashMap<String, Arc<Semaphore>>,
+
+  grace: Duration,
+  deadline: Duration,
+  backpressure: bool,
+
+  /// Clones the outputs shared by deduplicated runs, if enabled.
+  dedup: Option<CloneOutput<Item::Output>>,
+
+  limiter: Option<Arc<RateLimiter>>,
+  dry_run: Option<DryRun<Item>>,
+  breakers: Option<Arc<Breakers<Item::Id>>>,
+  rechecks: Option<Arc<Rechecks<Item>>>,
+  events: Arc<Events<Item::Id>>,
+
+  /// The number of runs started after the moment they were due at.
+  late: AtomicU64,
+
+  counters: Arc<Counters>,
+  created: Instant,
+
+  /// The time the last tick took, in nanoseconds.
+  last_tick: AtomicU64,
+
+  /// Runs in flight.
+  tasks: TaskTracker,
+
+  /// Cancels the runs still in flight once the grace period is over.
+  abandon: CancellationToken,
+}
+
+impl<Item: Runnable, S: Sink<Item::Output>> Runner<Item, S> {
+  /// Creates a runner for the schedule. Items can be inserted into and
+  /// removed from the schedule while it's running.
+  pub fn new(schedule: Arc<Schedule<Item>>, sink: S) -> Self {
+    Self {
+      schedule,
+      sink: Arc::new(sink),
+      tick: DEFAULT_TICK,
+      runs: Arc::new(Semaphore::new(DEFAULT_CONCURRENCY)),
+      classes: HashMap::new(),
+      grace: DEFAULT_GRACE,
+      deadline: DEFAULT_DEADLINE,
+      backpressure: false,
+      dedup: None,
+      limiter: None,
+      dry_run: None,
+      breakers: None,
+      rechecks: None,
+      events: Arc::new(Events::new()),
+      late: AtomicU64::new(0),
+      counters: Arc::default(),
+      created: Instant::now(),
+      last_tick: AtomicU64::new(0),
+      tasks: TaskTracker::new(),
+      abandon: CancellationToken::new(),
+    }
+  }
+
+  /// Sets the period of the ticks. Items are due at whole seconds, so it
+  /// shouldn't exceed a second for them to run on time. Ticks happen at the
+  /// multiples of the period, e.g. at every whole second by default.
+  pub fn tick(mut self, tick: Duration) -> Self {
+    self.tick = tick;
+    self
+  }
+
+  /// Sets the maximum number of items run at the same time. Due items wait
+  /// for a free slot, delaying the next ticks.
+  pub fn concurrency(mut self, concurrency: usize) -> Self {
+    self.runs = Arc::new(Semaphore::new(concurrency.max(1)));
+    self
+  }
+
+  /// Sets the semaphore every run takes a permit of, e.g. one shared with
+  /// the runners of other schedules, so their runs count toward the same
+  /// limit. It replaces the [concurrency](Runner::concurrency). Once it's
+  /// closed, the runner stops starting runs.
+  pub fn semaphore(mut self, semaphore: Arc<Semaphore>) -> Self {
+    self.runs = semaphore;
+    self
+  }
+
+  /// Gives the items of a [class](Runnable::class), e.g. HTTP monitors, a
+  /// queue of their own, running up to `concurrency` of them at the same
+  /// time regardless of the runs of other items. Once any class has a
+  /// queue, due items wait for their permits without holding up the next
+  /// ones, so a pile-up of slow checks doesn't starve the cheap ones.
+  pub fn class_concurrency(mut self, class: impl Into<String>, concurrency: usize) -> Self {
+    self
+      .classes
+      .insert(class.into(), Arc::new(Semaphore::new(concurrency.max(1))));
+    self
+  }
+
+  /// Sets how long runs in flight are waited for once the runner shuts down,
+  /// 30 seconds by default. The ones still running afterwards are cancelled,
+  /// and their outputs are lost.
+  pub fn grace(mut self, grace: Duration) -> Self {
+    self.grace = grace;
+    self
+  }
+
+  /// Sets the maximum duration of a run, 5 minutes by default. It should
+  /// exceed the time items normally take, as a run exceeding it is
+  /// [handled](Runnable::run_with_deadline) by the item, e.g. abandoned.
+  pub fn deadline(mut self, deadline: Duration) -> Self {
+    self.deadline = deadline;
+    self
+  }
+
+  /// Keeps the permit of every run until its output is accepted by the sink,
+  /// so a sink that can't keep up delays the next runs, rather than runs
+  /// waiting for it piling up in memory. Permits are released once outputs
+  /// are produced by default.
+  pub fn backpressure(mut self, backpressure: bool) -> Self {
+    self.backpressure = backpressure;
+    self
+  }
+
+  /// Limits the runs of items with the same [target](Runnable::target) to
+  /// `per_second`. Runs aren't limited by default.
+  pub fn rate_limit(self, per_second: u32) -> Self {
+    self.rate_limiter(Arc::new(RateLimiter::new(per_second)))
+  }
+
+  /// Sets the rate limiter of the runs, e.g. one shared with the runners of
+  /// other schedules, so their runs of a target count toward the same rate.
+  pub fn rate_limiter(mut self, limiter: Arc<RateLimiter>) -> Self {
+    self.limiter = Some(limiter);
+    self
+  }
+
+  /// Switches the runner to dry run mode: due items are passed to `report`,
+  /// e.g. to be logged, instead of being run, and nothing is sent to the
+  /// sink. The schedule still records them as run.
+  pub fn dry_run(mut self, report: impl Fn(Planned<Item>) + Send + Sync + 'static) -> Self {
+    self.dry_run = Some(Box::new(report));
+    self
+  }
+
+  /// Enables circuit breakers: an item whose runs keep failing is scheduled
+  /// with a stretched interval until it succeeds again. Intervals that can't
+  /// be represented [from seconds](Seconds::from_seconds) aren't stretched.
+  pub fn circuit_breaker(mut self, config: CircuitBreaker) -> Self {
+    self.breakers = Some(Arc::new(Breakers::new(config)));
+    self
+  }
+
+  /// Returns the state of the breaker of the item with `id`, if breakers are
+  /// enabled and its last run failed.
+  pub fn breaker(&self, id: &Item::Id) -> Option<BreakerState> {
+    self.breakers.as_ref()?.get(id)
+  }
+
+  /// Returns the items with an open breaker, with their states.
+  pub fn open_breakers(&self) -> Vec<(Item::Id, BreakerState)> {
+    self
+      .breakers
+      .as_ref()
+      .map_or_else(Vec::new, |breakers| breakers.open())
+  }
+
+  /// Returns a receiver of the [lifecycle events](RunEvent) of the runs
+  /// started from now on, e.g. to display them.
+  pub fn subscribe(&self) -> broadcast::Receiver<RunEvent<Item::Id>> {
+    self.events.subscribe()
+  }
+
+  /// Returns the number of runs that can start before due items wait.
+  pub fn available(&self) -> usize {
+    self.runs.available_permits()
+  }
+
+  /// Returns the number of runs started at a later second than the one they
+  /// were due at, e.g. after the process was paused, or ticks took longer
+  /// than their period.
+  pub fn late_runs(&self) -> u64 {
+    self.late.load(Ordering::Relaxed)
+  }
+
+  /// Waits until the outputs sent so far are consumed by the sink, e.g. the
+  /// ones collected by a [Batched] sink are sent.
+  pub async fn flush(&self) {
+    self.sink.flush().await;
+  }
+
+  /// Returns the statistics of the runner.
+  pub fn stats(&self) -> RunnerStats {
+    let queued = self.counters.queued.load(Ordering::Relaxed);
+    let executed = self.counters.executed.load(Ordering::Relaxed);
+    let failed = self.counters.failed.load(Ordering::Relaxed);
+
+    RunnerStats {
+      in_flight: self.tasks.len().saturating_sub(queued),
+      queued,
+      executed,
+      failed,
+      late: self.late_runs(),
+      deduplicated: self.counters.deduplicated.load(Ordering::Relaxed),
+      executed_per_second: executed as f64 / self.created.elapsed().as_secs_f64(),
+      error_rate: if executed == 0 {
+        0.0
+      } else {
+        failed as f64 / executed as f64
+      },
+      last_tick: Duration::from_nanos(self.last_tick.load(Ordering::Relaxed)),
+    }
+  }
+
+  /// Returns the schedule of the runner.
+  pub fn schedule(&self) -> &Arc<Schedule<Item>> {
+    &self.schedule
+  }
+
+  /// Runs due items on every tick, forever. Items due at the moment the runner
+  /// starts are run on the first tick. The time is taken from the
+  /// [clock](Schedule::clock) of the schedule.
+  pub async fn run(self) {
+    self.run_until(CancellationToken::new()).await
+  }
+
+  /// Runs due items on every tick, like [run](Runner::run), until `shutdown`
+  /// is cancelled. Then it stops starting runs, waits for the ones in flight
+  /// up to the [grace period](Runner::grace) and flushes the sink.
+  pub async fn run_until(self, shutdown: CancellationToken) {
+    let clock = Arc::clone(self.schedule.get_clock());
+    let mut last = None;
+
+    loop {
+      let now = clock.now();
+      let from = last.map_or(now, |last: i64| last + 1);
+
+      let tick = async {
+        if from <= now {
+          self.dispatch(from, now).await;
+          last = Some(now);
+        }
+
+        clock.sleep(self.until_tick(clock.now_millis())).await;
+      };
+
+      if shutdown.run_until_cancelled(tick).await.is_none() {
+        break;
+      }
+    }
+
+    self.shutdown().await;
+  }
+
+  /// Schedules the items with the intervals set by their breakers since the
+  /// previous tick.
+  async fn update_breakers(&self) {
+    let Some(breakers) = &self.breakers else {
+      return;
+    };
+
+    for (id, interval) in breakers.updates() {
+      if let Some(interval) = Item::Interval::from_seconds(interval) {
+        let _ = self.schedule.update_interval(id, interval).await;
+      }
+    }
+  }
+
+  /// Schedules one-shot runs of the items queued for a recheck since the
+  /// previous tick.
+  async fn schedule_rechecks(&self) {
+    let Some(rechecks) = &self.rechecks else {
+      return;
+    };
+
+    for item in rechecks.queued() {
+      self.schedule.insert_delayed(item, rechecks.delay()).await;
+    }
+  }
+
+  /// Returns the time from `now`, in milliseconds, until the next multiple
+  /// of the tick period.
+  fn until_tick(&self, now: i64) -> Duration {
+    let tick = (self.tick.as_millis() as i64).max(1);
+
+    Duration::from_millis((tick - now.rem_euclid(tick)) as u64)
+  }
+
+  /// Orders the items due between `from` and `to` by the number of their
+  /// intervals missed by `to`, most overdue first, so after a stall the
+  /// items catch up fairly, whatever their order in the schedule.
+  async fn prioritize(&self, due: Vec<Arc<Item>>, from: i64, to: i64) -> Vec<Arc<Item>> {
+    if from == to || due.len() < 2 {
+      return due;
+    }
+
+    let mut ranked = Vec::with_capacity(due.len());
+    for item in due {
+      let missed = match self.schedule.next_due(item.get_id(), from).await {
+        Some(at) => (to - at) as f64 / item.get_interval().seconds().max(1) as f64,
+        None => 0.0,
+      };
+
+      ranked.push((missed, item));
+    }
+    ranked.sort_by(|(first, _), (second, _)| second.total_cmp(first));
+
+    ranked.into_iter().map(|(_, item)| item).collect()
+  }
+
+  /// Counts a run of an item due between `from` and `to` as late if it was
+  /// due before `to`.
+  async fn track_lateness(&self, item: &Item, from: i64, to: i64) {
+    if from == to {
+      return;
+    }
+
+    let Some(due) = self.schedule.next_due(item.get_id(), from).await else {
+      return;
+    };
+
+    if due < to {
+      self.late.fetch_add(1, Ordering::Relaxed);
+
+      #[cfg(feature = "tracing")]
+      tracing::debug!(due, lateness = to - due, "late run");
+
+      #[cfg(feature = "metrics")]
+      {
+        metrics::counter!("limon_late_runs_total").increment(1);
+        metrics::histogram!("limon_run_lateness_seconds").record((to - due) as f64);
+      }
+    }
+  }
+
+  /// Waits for the runs in flight up to the grace period, cancels the
+  /// remaining ones and flushes the sink.
+  async fn shutdown(&self) {
+    self.tasks.close();
+
+    if tokio::time::timeout(self.grace, self.tasks.wait())
+      .await
+      .is_err()
+    {
+      #[cfg(feature = "tracing")]
+      tracing::warn!(
+        abandoned = self.tasks.len(),
+        "runs still in flight after the grace period are cancelled"
+      );
+
+      self.abandon.cancel();
+      self.tasks.wait().await;
+    }
+
+    self.sink.flush().await;
+  }
+
+  /// Starts runs of the items due between `from` and `to` (in seconds, see
+  /// [get_due](Schedule::get_due)), without waiting for them to finish.
+  /// Runs delayed by the rate limit take their permit once it's their turn,
+  /// as do all runs once classes have queues of their own.
+  #[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(name = "tick", level = "debug", skip(self), fields(due))
+  )]
+  pub async fn dispatch(&self, from: i64, to: i64) {
+    let start = Instant::now();
+    self.update_breakers().await;
+    self.schedule_rechecks().await;
+
+    let due = self
+      .prioritize(self.schedule.get_due(from, to).await, from, to)
+      .await;
+
+    #[cfg(feature = "tracing")]
+    tracing::Span::current().record("due", due.len());
+
+    if let Some(report) = &self.dry_run {
+      for item in due {
+        let id = item.get_id();
+
+        report(Planned {
+          due: self.schedule.next_due(id.clone(), from).await.unwrap_or(to),
+          interval: self
+            .schedule
+            .interval(id)
+            .await
+            .unwrap_or_else(|| item.get_interval().seconds()),
+          item,
+        });
+      }
+
+      return;
+    }
+
+    let due = match self.dedup {
+      Some(_) => dedup(due),
+      None => due.into_iter().map(|item| (item, Vec::new())).collect(),
+    };
+
+    let counters = &self.counters;
+    // The runs are counted as queued at once, and the ones left once the
+    // dispatch stops early are dropped along with their guards.
+    let due: Vec<_> = due
+      .into_iter()
+      .map(|group| (group, Queued::new(counters)))
+      .collect();
+
+    for ((item, shared), queued) in due {
+      for item in iter::once(&item).chain(&shared) {
+        self.track_lateness(item, from, to).await;
+      }
+
+      let delay = match (&self.limiter, item.target()) {
+        (Some(limiter), Some(target)) => limiter.reserve(target),
+        _ => Duration::ZERO,
+      };
+      let runs = match item.class().and_then(|class| self.classes.get(class)) {
+        Some(runs) => Arc::clone(runs),
+        None => Arc::clone(&self.runs),
+      };
+      let (permit, queued) = if delay.is_zero() && self.classes.is_empty() {
+        let Ok(permit) = Arc::clone(&self.runs).acquire_owned().await else {
+          return;
+        };
+        drop(queued);
+
+        (Some(permit), None)
+      } else {
+        (None, Some(queued))
+      };
+      let sink = Arc::clone(&self.sink);
+      let breakers = self.breakers.clone();
+      let rechecks = self.rechecks.clone();
+      let events = Arc::clone(&self.events);
+      let counters = Arc::clone(counters);
+      let abandon = self.abandon.clone();
+      let deadline = self.deadline;
+      let backpressure = self.backpressure;
+      let clone = self.dedup;
+
+      #[cfg(feature = "tracing")]
+      let span = tracing::debug_span!(
+        "run",
+        target = item.target(),
+        delay_ms = delay.as_millis() as u64,
+        failed = tracing::field::Empty
+      );
+
+      let run = async move {
+        abandon
+          .run_until_cancelled(async move {
+            let permit = match permit {
+              Some(permit) => permit,
+              None => {
+                if !delay.is_zero() {
+                  tokio::time::sleep(delay).await;
+                }
+
+                let Ok(permit) = runs.acquire_owned().await else {
+                  return;
+                };
+                drop(queued);
+
+                permit
+              }
+            };
+            events.started(&item.get_id());
+
+            let started = Instant::now();
+            let output = item.run_with_deadline(deadline).await;
+            let elapsed = started.elapsed();
+            // With backpressure, the permit is released once the output is
+            // sent.
+            let _permit = backpressure.then_some(permit);
+
+            counters.executed.fetch_add(1, Ordering::Relaxed);
+            if output.as_ref().is_none_or(Item::is_failure) {
+              counters.failed.fetch_add(1, Ordering::Relaxed);
+            }
+
+            #[cfg(feature = "tracing")]
+            match &output {
+              Some(output) => {
+                tracing::Span::current().record("failed", Item::is_failure(output));
+              }
+              None => tracing::warn!(?deadline, "run exceeded its deadline"),
+            }
+
+            if let Some(rechecks) = &rechecks {
+              let failed = output.as_ref().is_none_or(Item::is_failure);
+              rechecks.record(&item, failed, item.confirmations());
+            }
+
+            let Some(output) = output else {
+              events.timed_out(&item.get_id(), deadline);
+              return;
+            };
+            let shared: Vec<_> = match clone {
+              Some(clone) => shared
+                .into_iter()
+                .map(|other| {
+                  let output = other.share(clone(&output));
+                  (other, output)
+                })
+                .collect(),
+              None => Vec::new(),
+            };
+            counters
+              .deduplicated
+              .fetch_add(shared.len() as u64, Ordering::Relaxed);
+
+            for (item, output) in iter::once((item, output)).chain(shared) {
+              let failed = Item::is_failure(&output);
+              events.finished(&item.get_id(), failed, elapsed);
+
+              if let Some(breakers) = &breakers {
+                breakers.record(&item.get_id(), item.get_interval().seconds(), failed);
+              }
+
+              sink.send(output).await;
+            }
+          })
+          .await;
+      };
+
+      #[cfg(feature = "tracing")]
+      let run = tracing::Instrument::instrument(run, span);
+
+      self.tasks.spawn(run);
+    }
+
+    self.last_tick.store(
+      u64::try_from(start.elapsed().as_nanos()).unwrap_or(u64::MAX),
+      Ordering::Relaxed,
+    );
+  }
+}
+
+/// Groups the due items by their [dedup key](Runnable::dedup_key), keeping
+/// their order: the first item of a group is run, and its output is shared
+/// with the others.
+fn dedup<Item: Runnable>(due: Vec<Arc<Item>>) -> Vec<(Arc<Item>, Vec<Arc<Item>>)> {
+  let mut groups: Vec<(Arc<Item>, Vec<Arc<Item>>)> = Vec::new();
+  let mut keys: HashMap<u64, usize> = HashMap::new();
+
+  for item in due {
+    let Some(key) = item.dedup_key() else {
+      groups.push((item, Vec::new()));
+      continue;
+    };
+
+    match keys.entry(key) {
+      Entry::Occupied(group) => groups[*group.get()].1.push(item),
+      Entry::Vacant(group) => {
+        group.insert(groups.len());
+        groups.push((item, Vec::new()));
+      }
+    }
+  }
+
+  groups
+}
+
+impl<Item: Runnable, S: Sink<Item::Output>> Runner<Item, S>
+where
+  Item::Output: Clone,
+{
+  /// Runs the due items of a tick with the same [dedup key](Runnable::dedup_key)
+  /// once, e.g. monitors of many tenants checking a shared gateway, and
+  /// [shares](Runnable::share) the output with all of them. If the run
+  /// exceeds its deadline, none of them has an output.
+  pub fn dedup(mut self) -> Self {
+    self.dedup = Some(Clone::clone);
+    self
+  }
+}
+
+impl<Item: Runnable + Clone, S: Sink<Item::Output>> Runner<Item, S> {
+  /// Enables rechecks: a failed item is run again once, `delay` seconds
+  /// after, and again while it keeps failing, up to `count` times or until
+  /// its failure is [confirmed](Runnable::confirmations).
+  pub fn recheck(mut self, config: Recheck) -> Self {
+    self.rechecks = Some(Arc::new(Rechecks::new(config, Clone::clone)));
+    self
+  }
+}
+
+impl<Item: Runnable + PartialEq, S: Sink<Item::Output>> Runner<Item, S> {
+  /// Reconciles the schedule with `items`, e.g. a reloaded configuration:
+  /// new items are inserted, changed ones replace the scheduled ones, and
+  /// the items missing from `items` are removed. Unchanged items are left
+  /// alone, and replaced ones keep their due times and last runs, so none
+  /// is run twice. Runs in flight complete with the items they started
+  /// with. If several items have the same `id`, the last one is kept.
+  ///
+  /// Fails without changing the schedule if the interval of any item is
+  /// invalid, as with [insert](Schedule::insert).
+  pub async fn apply(&self, items: Vec<Item>) -> Result<Applied<Item::Id>, ScheduleError> {
+    let items: HashMap<Item::Id, Item> = items
+      .into_iter()
+      .map(|item| (item.get_id(), item))
+      .collect();
+    let mut current: HashMap<Item::Id, Arc<Item>> = self
+      .schedule
+      .iter()
+      .await
+      .map(|item| (item.get_id(), item))
+      .collect();
+
+    let mut applied = Applied {
+      inserted: Vec::new(),
+      updated: Vec::new(),
+      removed: Vec::new(),
+    };
+    let mut changed = Vec::new();
+
+    for (id, item) in items {
+      match current.remove(&id) {
+        Some(scheduled) if *scheduled == item => continue,
+        Some(_) => applied.updated.push(id),
+        None => applied.inserted.push(id),
+      }
+
+      changed.push(item);
+    }
+
+    applied.removed = current.into_keys().collect();
+
+    // The items are all validated before any is inserted, so the state of
+    // the runner is only changed once they're known to be valid.
+    self.schedule.insert_many(changed).await?;
+
+    if let Some(breakers) = &self.breakers {
+      for id in applied.updated.iter().chain(&applied.removed) {
+        breakers.forget(id);
+      }
+    }
+    if let Some(rechecks) = &self.rechecks {
+      for id in applied.updated.iter().chain(&applied.removed) {
+        rechecks.forget(id);
+      }
+    }
+    for id in &applied.removed {
+      self.events.forget(id);
+      Item::forget(id);
+    }
+
+    self
+      .schedule
+      .remove_many(applied.removed.iter().cloned())
+      .await;
+
+    Ok(applied)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::collections::HashSet;
+  use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+  use super::*;
+  use crate::schedule::clock::{Clock, MockClock, SystemClock};
+
+  #[derive(Clone)]
+  struct Check {
+    id: i64,
+    interval: i64,
+    running: Arc<AtomicUsize>,
+    peak: Arc<AtomicUsize>,
+  }
+
+  impl Schedulable for Check {
+    type Id = i64;
+    type Interval = i64;
+
+    fn get_id(&self) -> Self::Id {
+      self.id
+    }
+
+    fn get_interval(&self) -> Self::Interval {
+      self.interval
+    }
+  }
+
+  impl PartialEq for Check {
+    fn eq(&self, other: &Self) -> bool {
+      self.id == other.id && self.interval == other.interval
+    }
+  }
+
+  impl Runnable for Check {
+    type Output = i64;
+
+    async fn run(&self) -> i64 {
+      let running = self.running.fetch_add(1, Ordering::SeqCst) + 1;
+      self.peak.fetch_max(running, Ordering::SeqCst);
+
+      tokio::time::sleep(Duration::from_millis(20)).await;
+      self.running.fetch_sub(1, Ordering::SeqCst);
+
+      self.id
+    }
+
+    fn target(&self) -> Option<&str> {
+      Some("origin")
+    }
+
+    fn is_failure(output: &i64) -> bool {
+      *output == 0
+    }
+
+    fn class(&self) -> Option<&str> {
+      Some(if self.id % 2 == 0 { "even" } else { "odd" })
+    }
+
+    fn dedup_key(&self) -> Option<u64> {
+      Some(self.id as u64 % 2)
+    }
+
+    fn share(&self, _output: i64) -> i64 {
+      self.id
+    }
+  }
+
+  async fn schedule(intervals: &[i64], peak: &Arc<AtomicUsize>) -> Arc<Schedule<Check>> {
+    schedule_with_clock(intervals, peak, SystemClock).await
+  }
+
+  async fn schedule_with_clock(
+    intervals: &[i64],
+    peak: &Arc<AtomicUsize>,
+    clock: impl Clock,
+  ) -> Arc<Schedule<Check>> {
+    let schedule = Arc::new(Schedule::new().clock(clock));
+    let running = Arc::new(AtomicUsize::new(0));
+
+    for (id, interval) in intervals.iter().enumerate() {
+      schedule
+        .insert(Check {
+          id: id as i64,
+          interval: *interval,
+          running: Arc::clone(&running),
+          peak: Arc::clone(peak),
+        })
+        .await
+        .unwrap();
+    }
+
+    schedule
+  }
+
+  #[tokio::test]
+  async fn dispatch_due_items() {
+    let peak = Arc::new(AtomicUsize::new(0));
+    let (sink, mut outputs) = mpsc::channel(16);
+    let runner = Runner::new(schedule(&[10, 20, 30], &peak).await, sink);
+
+    runner.dispatch(1, 20).await;
+    drop(runner);
+
+    let mut ids = Vec::new();
+    while let Some(id) = outputs.recv().await {
+      ids.push(id);
+    }
+    ids.sort();
+
+    assert_eq!(ids, vec![0, 1], "outputs of due items are sent");
+  }
+
+  #[tokio::test]
+  async fn bounded_concurrency() {
+    let peak = Arc::new(AtomicUsize::new(0));
+    let (sink, mut outputs) = mpsc::channel(16);
+    let runner = Runner::new(schedule(&[10; 6], &peak).await, sink).concurrency(2);
+
+    runner.dispatch(1, 10).await;
+    drop(runner);
+
+    let mut count = 0;
+    while outputs.recv().await.is_some() {
+      count += 1;
+    }
+
+    assert_eq!(count, 6);
+    assert_eq!(
+      peak.load(Ordering::SeqCst),
+      2,
+      "at most two items run at once"
+    );
+  }
+
+  #[tokio::test]
+  async fn class_queues() {
+    let peak = Arc::new(AtomicUsize::new(0));
+    let (sink, mut outputs) = mpsc::channel(16);
+    let runner = Runner::new(schedule(&[10; 6], &peak).await, sink)
+      .concurrency(1)
+      .class_concurrency("odd", 3);
+
+    let start = Instant::now();
+    runner.dispatch(1, 10).await;
+    assert!(
+      start.elapsed() < Duration::from_millis(20),
+      "due items wait for their permits in their queues"
+    );
+    drop(runner);
+
+    let mut count = 0;
+    while outputs.recv().await.is_some() {
+      count += 1;
+    }
+
+    assert_eq!(count, 6);
+    assert_eq!(
+      peak.load(Ordering::SeqCst),
+      4,
+      "a class with a queue runs apart from the others"
+    );
+  }
+
+  #[tokio::test]
+  async fn shared_semaphore() {
+    let peak = Arc::new(AtomicUsize::new(0));
+    let semaphore = Arc::new(Semaphore::new(2));
+    let (sink, mut outputs) = mpsc::channel(16);
+
+    let first =
+      Runner::new(schedule(&[10; 3], &peak).await, sink.clone()).semaphore(Arc::clone(&semaphore));
+    let second = Runner::new(schedule(&[10; 3], &peak).await, sink).semaphore(semaphore);
+
+    tokio::join!(first.dispatch(1, 10), second.dispatch(1, 10));
+    assert_eq!(first.available(), 0, "runs are in flight");
+    drop((first, second));
+
+    let mut count = 0;
+    while outputs.recv().await.is_some() {
+      count += 1;
+    }
+
+    assert_eq!(count, 6);
+    assert_eq!(
+      peak.load(Ordering::SeqCst),
+      2,
+      "at most two items of both runners run at once"
+    );
+  }
+
+  #[tokio::test]
+  async fn closed_semaphore() {
+    let peak = Arc::new(AtomicUsize::new(0));
+    let semaphore = Arc::new(Semaphore::new(2));
+    semaphore.close();
+
+    let (sink, _outputs) = mpsc::channel(16);
+    let runner = Runner::new(schedule(&[10; 3], &peak).await, sink).semaphore(semaphore);
+
+    runner.dispatch(1, 10).await;
+    assert_eq!(
+      runner.stats().queued,
+      0,
+      "runs that can't take a permit aren't left queued"
+    );
+  }
+
+  #[tokio::test]
+  async fn events() {
+    let peak = Arc::new(AtomicUsize::new(0));
+    let (sink, _outputs) = mpsc::channel(16);
+    let runner = Runner::new(schedule(&[10; 2], &peak).await, sink);
+    let mut events = runner.subscribe();
+
+    runner.dispatch(1, 10).await;
+    runner.tasks.close();
+    runner.tasks.wait().await;
+
+    let mut started = 0;
+    let mut states = Vec::new();
+    while let Ok(event) = events.try_recv() {
+      match event {
+        RunEvent::CheckStarted { .. } => started += 1,
+        RunEvent::StateChanged { id, failing } => states.push((id, failing)),
+        _ => {}
+      }
+    }
+    states.sort();
+
+    assert_eq!(started, 2);
+    assert_eq!(
+      states,
+      [(0, true), (1, false)],
+      "the first runs set the states"
+    );
+  }
+
+  #[tokio::test]
+  async fn deadline() {
+    let peak = Arc::new(AtomicUsize::new(0));
+    let (sink, mut outputs) = mpsc::channel(16);
+    let runner =
+      Runner::new(schedule(&[10; 3], &peak).await, sink).deadline(Duration::from_millis(1));
+
+    runner.dispatch(1, 10).await;
+    runner.tasks.close();
+    runner.tasks.wait().await;
+
+    assert_eq!(
+      runner.available(),
+      DEFAULT_CONCURRENCY,
+      "permits are released"
+    );
+    drop(runner);
+    assert_eq!(
+      outputs.recv().await,
+      None,
+      "runs past the deadline are abandoned"
+    );
+  }
+
+  #[tokio::test]
+  async fn dedup() {
+    let peak = Arc::new(AtomicUsize::new(0));
+    let (sink, mut outputs) = mpsc::channel(16);
+    let runner = Runner::new(schedule(&[10; 5], &peak).await, sink).dedup();
+
+    runner.dispatch(1, 10).await;
+    runner.tasks.close();
+    runner.tasks.wait().await;
+
+    let stats = runner.stats();
+    assert_eq!(stats.executed, 2, "items with the same key are run once");
+    assert_eq!(stats.deduplicated, 3);
+    drop(runner);
+
+    let mut ids = Vec::new();
+    while let Some(id) = outputs.recv().await {
+      ids.push(id);
+    }
+    ids.sort();
+
+    assert_eq!(ids, [0, 1, 2, 3, 4], "the output is shared with every item");
+  }
+
+  #[tokio::test]
+  async fn backpressure() {
+    let peak = Arc::new(AtomicUsize::new(0));
+    let (sink, mut outputs) = mpsc::channel(1);
+    let runner = Arc::new(
+      Runner::new(schedule(&[10; 3], &peak).await, sink)
+        .concurrency(1)
+        .backpressure(true),
+    );
+
+    let dispatch = tokio::spawn({
+      let runner = Arc::clone(&runner);
+      async move { runner.dispatch(1, 10).await }
+    });
+
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    assert!(
+      !dispatch.is_finished(),
+      "the run waiting for the full sink holds its permit"
+    );
+    assert_eq!(runner.available(), 0);
+
+    for _ in 0..3 {
+      outputs.recv().await.unwrap();
+    }
+    dispatch.await.unwrap();
+  }
+
+  #[tokio::test]
+  async fn rate_limit() {
+    let peak = Arc::new(AtomicUsize::new(0));
+    let (sink, mut outputs) = mpsc::channel(16);
+    let runner = Runner::new(schedule(&[10; 3], &peak).await, sink).rate_limit(10);
+
+    let start = tokio::time::Instant::now();
+    runner.dispatch(1, 10).await;
+    assert_eq!(
+      runner.available(),
+      DEFAULT_CONCURRENCY - 1,
+      "delayed runs don't take a permit"
+    );
+    drop(runner);
+
+    let mut count = 0;
+    while outputs.recv().await.is_some() {
+      count += 1;
+    }
+
+    assert_eq!(count, 3);
+    assert!(start.elapsed() >= Duration::from_millis(200));
+    assert_eq!(
+      peak.load(Ordering::SeqCst),
+      1,
+      "runs of a target are spaced"
+    );
+  }
+
+  #[tokio::test]
+  async fn apply() {
+    let peak = Arc::new(AtomicUsize::new(0));
+    let (sink, _outputs) = mpsc::channel(16);
+    let runner = Runner::new(schedule(&[10, 20, 30], &peak).await, sink);
+    let check = |id: i64, interval: i64| Check {
+      id,
+      interval,
+      running: Arc::new(AtomicUsize::new(0)),
+      peak: Arc::clone(&peak),
+    };
+
+    let unchanged = runner.schedule().get(0).await.unwrap();
+    let mut applied = runner
+      .apply(vec![check(0, 10), check(1, 60), check(5, 10)])
+      .await
+      .unwrap();
+    applied.removed.sort();
+
+    assert_eq!(applied, Applied {
+      inserted: vec![5],
+      updated: vec![1],
+      removed: vec![2],
+    });
+    assert!(
+      Arc::ptr_eq(&unchanged, &runner.schedule().get(0).await.unwrap()),
+      "unchanged items aren't replaced"
+    );
+    assert_eq!(runner.schedule().get(1).await.unwrap().interval, 60);
+    assert_eq!(runner.schedule().len().await, 3);
+
+    assert_eq!(
+      runner.apply(vec![check(7, 0)]).await,
+      Err(ScheduleError::NonPositiveInterval(0))
+    );
+    assert_eq!(
+      runner.schedule().len().await,
+      3,
+      "the schedule is unchanged"
+    );
+  }
+
+  #[tokio::test]
+  async fn failed_apply() {
+    let peak = Arc::new(AtomicUsize::new(0));
+    let (sink, _outputs) = mpsc::channel(16);
+    let runner = Runner::new(schedule(&[10, 20], &peak).await, sink)
+      .circuit_breaker(CircuitBreaker::default());
+    let check = |id: i64, interval: i64| Check {
+      id,
+      interval,
+      running: Arc::new(AtomicUsize::new(0)),
+      peak: Arc::clone(&peak),
+    };
+
+    runner.breakers.as_ref().unwrap().record(&1, 20, true);
+    assert_eq!(
+      runner.apply(vec![check(0, 10), check(7, 0)]).await,
+      Err(ScheduleError::NonPositiveInterval(0))
+    );
+    assert_eq!(
+      runner.breaker(&1).map(|state| state.failures),
+      Some(1),
+      "the breakers of the items left out are kept"
+    );
+    assert_eq!(runner.schedule().len().await, 2);
+  }
+
+  #[tokio::test]
+  async fn dry_run() {
+    let peak = Arc::new(AtomicUsize::new(0));
+    let (sink, mut outputs) = mpsc::channel(16);
+    let (report, mut planned) = mpsc::unbounded_channel();
+    let runner = Runner::new(schedule(&[10, 20, 30], &peak).await, sink).dry_run(
+      move |planned: Planned<Check>| {
+        let _ = report.send((planned.item.id, planned.due, planned.interval));
+      },
+    );
+
+    runner.schedule().update_interval(1, 5).await.unwrap();
+    runner.dispatch(1, 20).await;
+    drop(runner);
+
+    let mut reported = Vec::new();
+    while let Some(planned) = planned.recv().await {
+      reported.push(planned);
+    }
+    reported.sort();
+
+    assert_eq!(reported, [(0, 10, 10), (1, 5, 5)]);
+    assert_eq!(outputs.recv().await, None, "items aren't run");
+    assert_eq!(peak.load(Ordering::SeqCst), 0);
+  }
+
+  #[tokio::test]
+  async fn late_runs() {
+    let peak = Arc::new(AtomicUsize::new(0));
+    let (sink, _outputs) = mpsc::channel(16);
+    let runner = Runner::new(schedule(&[10, 30], &peak).await, sink);
+
+    runner.dispatch(110, 110).await;
+    runner.dispatch(111, 120).await;
+    assert_eq!(runner.late_runs(), 0, "items due at the tick are on time");
+
+    runner.dispatch(121, 135).await;
+    assert_eq!(runner.late_runs(), 1, "the item due at 130 is late");
+  }
+
+  #[tokio::test]
+  async fn overdue_first() {
+    let peak = Arc::new(AtomicUsize::new(0));
+    let (sink, mut outputs) = mpsc::channel(16);
+    let runner = Runner::new(schedule(&[100, 100, 10, 100, 10], &peak).await, sink).concurrency(1);
+
+    runner.dispatch(1, 120).await;
+    drop(runner);
+
+    let mut ids = Vec::new();
+    while let Some(id) = outputs.recv().await {
+      ids.push(id);
+    }
+
+    assert_eq!(ids.len(), 5);
+    assert_eq!(
+      ids[..2].iter().copied().collect::<HashSet<_>>(),
+      HashSet::from([2, 4]),
+      "the items that missed the most intervals run first"
+    );
+  }
+
+  #[tokio::test]
+  async fn circuit_breaker() {
+    let peak = Arc::new(AtomicUsize::new(0));
+    let (sink, _outputs) = mpsc::channel(16);
+    let runner =
+      Runner::new(schedule(&[10, 10], &peak).await, sink).circuit_breaker(CircuitBreaker {
+        threshold: 1,
+        ..Default::default()
+      });
+
+    runner.dispatch(1, 10).await;
+    runner.tasks.close();
+    runner.tasks.wait().await;
+
+    assert_eq!(runner.open_breakers(), [(0, BreakerState {
+      failures: 1,
+      open: true,
+      interval: 20,
+    })]);
+    assert_eq!(runner.breaker(&1), None, "the item succeeded");
+
+    runner.dispatch(11, 11).await;
+    assert_eq!(runner.schedule().interval(0).await, Some(20));
+    assert_eq!(runner.schedule().interval(1).await, Some(10));
+  }
+
+  #[tokio::test]
+  async fn recheck() {
+    let peak = Arc::new(AtomicUsize::new(0));
+    let (sink, mut outputs) = mpsc::channel(16);
+    let clock = MockClock::new(5);
+    let runner = Runner::new(schedule_with_clock(&[10, 10], &peak, clock).await, sink)
+      .recheck(Recheck { delay: 1, count: 2 });
+
+    for (from, to) in [(1, 10), (11, 19), (21, 29), (31, 39)] {
+      runner.dispatch(from, to).await;
+      runner.tasks.close();
+      runner.tasks.wait().await;
+      runner.tasks.reopen();
+    }
+    drop(runner);
+
+    let mut ids = Vec::new();
+    while let Some(id) = outputs.recv().await {
+      ids.push(id);
+    }
+    ids.sort();
+
+    assert_eq!(
+      ids,
+      [0, 0, 0, 1],
+      "the failed item is rechecked up to twice"
+    );
+  }
+
+  #[tokio::test]
+  async fn stats() {
+    let peak = Arc::new(AtomicUsize::new(0));
+    let (sink, _outputs) = mpsc::channel(16);
+    let runner = Runner::new(schedule(&[10; 4], &peak).await, sink).concurrency(2);
+
+    runner.dispatch(1, 10).await;
+
+    let stats = runner.stats();
+    assert_eq!(stats.in_flight, 2);
+    assert_eq!(stats.queued, 0, "every due item took a permit");
+    assert!(stats.last_tick > Duration::ZERO);
+
+    runner.tasks.close();
+    runner.tasks.wait().await;
+
+    let stats = runner.stats();
+    assert_eq!((stats.in_flight, stats.executed, stats.failed), (0, 4, 1));
+    assert_eq!(stats.error_rate, 0.25);
+    assert!(stats.executed_per_second > 0.0);
+  }
+
+  /// Records the sizes of the batches sent to it.
+  #[derive(Clone, Default)]
+  struct Batches(Arc<Mutex<Vec<usize>>>);
+
+  impl Sink<i64> for Batches {
+    async fn send(&self, _output: i64) {
+      self.0.lock().unwrap().push(1);
+    }
+
+    async fn send_batch(&self, outputs: Vec<i64>) {
+      self.0.lock().unwrap().push(outputs.len());
+    }
+  }
+
+  #[tokio::test]
+  async fn batched() {
+    let batches = Batches::default();
+    let sink = Batched::new(batches.clone(), 3, Duration::from_millis(100));
+
+    for output in 0..4 {
+      sink.send(output).await;
+    }
+    assert_eq!(*batches.0.lock().unwrap(), [3], "a full batch is sent");
+    assert_eq!(sink.pending(), 1);
+
+    tokio::time::sleep(Duration::from_millis(150)).await;
+    assert_eq!(
+      *batches.0.lock().unwrap(),
+      [3, 1],
+      "a batch is sent every period"
+    );
+
+    sink.send(4).await;
+    sink.send(5).await;
+    let runner = Runner::new(Arc::new(Schedule::<Check>::new()), sink);
+    runner.flush().await;
+    assert_eq!(
+      *batches.0.lock().unwrap(),
+      [3, 1, 2],
+      "flushed outputs are sent"
+    );
+  }
+
+  /// Forwards outputs to a channel and records whether it was flushed.
+  struct Flushed {
+    outputs: mpsc::Sender<i64>,
+    flushed: Arc<AtomicBool>,
+  }
+
+  impl Sink<i64> for Flushed {
+    async fn send(&self, output: i64) {
+      let _ = self.outputs.send(output).await;
+    }
+
+    async fn flush(&self) {
+      self.flushed.store(true, Ordering::SeqCst);
+    }
+  }
+
+  #[tokio::test]
+  async fn graceful_shutdown() {
+    for (grace, expected) in [(Duration::from_secs(5), 3), (Duration::ZERO, 0)] {
+      let peak = Arc::new(AtomicUsize::new(0));
+      let flushed = Arc::new(AtomicBool::new(false));
+      let (outputs, mut received) = mpsc::channel(16);
+
+      let runner = Runner::new(schedule(&[10; 3], &peak).await, Flushed {
+        outputs,
+        flushed: Arc::clone(&flushed),
+      })
+      .grace(grace);
+
+      runner.dispatch(1, 10).await;
+
+      let shutdown = CancellationToken::new();
+      shutdown.cancel();
+      runner.run_until(shutdown).await;
+
+      let mut count = 0;
+      while received.recv().await.is_some() {
+        count += 1;
+      }
+
+      assert_eq!(count, expected, "runs in flight within the grace period");
+      assert!(flushed.load(Ordering::SeqCst), "the sink is flushed");
+    }
+  }
+
+  /// Moves the clock once the runner is waiting for the next tick.
+  async fn tick(clock: &MockClock, duration: Duration) {
+    while clock.sleeping() == 0 {
+      tokio::task::yield_now().await;
+    }
+
+    clock.advance(duration);
+  }
+
+  #[tokio::test]
+  async fn ticks_by_clock() {
+    let clock = MockClock::new(110);
+    let peak = Arc::new(AtomicUsize::new(0));
+    let (sink, mut outputs) = mpsc::channel(16);
+
+    let schedule = schedule_with_clock(&[10, 25], &peak, clock.clone()).await;
+    let runner = tokio::spawn(
+      Runner::new(schedule, sink)
+        .tick(Duration::from_secs(10))
+        .run(),
+    );
+
+    assert_eq!(outputs.recv().await, Some(0), "item due at the start");
+
+    tick(&clock, Duration::from_secs(10)).await;
+    assert_eq!(outputs.recv().await, Some(0));
+
+    tick(&clock, Duration::from_secs(20)).await;
+    let mut ids = vec![outputs.recv().await, outputs.recv().await];
+    ids.sort();
+    assert_eq!(ids, vec![Some(0), Some(1)], "items due meanwhile are run");
+
+    runner.abort();
+  }
+
+  #[tokio::test]
+  async fn aligned_ticks() {
+    let clock = MockClock::new(110);
+    clock.advance(Duration::from_millis(300));
+
+    let peak = Arc::new(AtomicUsize::new(0));
+    let (sink, mut outputs) = mpsc::channel(16);
+
+    let schedule = schedule_with_clock(&[1], &peak, clock.clone()).await;
+    let runner = tokio::spawn(Runner::new(schedule, sink).run());
+
+    assert_eq!(outputs.recv().await, Some(0));
+
+    tick(&clock, Duration::from_millis(700)).await;
+    assert_eq!(
+      outputs.recv().await,
+      Some(0),
+      "the next tick is at the next whole second"
+    );
+
+    runner.abort();
+  }
+}
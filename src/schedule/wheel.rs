@@ -0,0 +1,446 @@
+//! A schedule backed by a hierarchical timing wheel.
+//!
+//! Every item of a [WheelSchedule] is kept in a slot by the moment it's next
+//! due at. Slots of the lowest level span a second, and a slot of every next
+//! level spans all slots of the previous one. Taking due items visits only the
+//! occupied slots up to the end of the window, so
+//! [get_due](WheelSchedule::get_due) takes time proportional to the number of
+//! due items, rather than to the number of items or distinct intervals as in
+//! a [Schedule](super::Schedule). It pays off for schedules with thousands of
+//! distinct intervals.
+//!
+//! Unlike a [Schedule](super::Schedule), the wheel only moves forward: every
+//! due moment of an item is returned once, and windows are expected to follow
+//! each other, as the [Runner](super::runner::Runner) requests them.
+//!
+//! # Example
+//!
+//! ```rust
+//! use limon_core::schedule::Schedulable;
+//! use limon_core::schedule::wheel::WheelSchedule;
+//!
+//! struct Task {
+//!     id: i64,
+//!     interval: i64,
+//! }
+//!
+//! impl Schedulable for Task {
+//!     type Id = i64;
+//!     type Interval = i64;
+//!
+//!     fn get_id(&self) -> Self::Id { self.id }
+//!     fn get_interval(&self) -> Self::Interval { self.interval }
+//! }
+//!
+//! let schedule: WheelSchedule<Task> = WheelSchedule::new();
+//!
+//! # tokio_test::block_on(async {
+//! schedule.insert(Task { id: 1, interval: 30 }).await.unwrap();
+//! schedule.insert(Task { id: 2, interval: 45 }).await.unwrap();
+//!
+//! assert_eq!(schedule.get_due(1, 30).await.len(), 1);
+//! assert_eq!(schedule.get_due(31, 45).await.len(), 1);
+//! # })
+//! ```
+
+use std::array;
+use std::collections::HashMap;
+use std::mem;
+use std::sync::Arc;
+
+use tokio::sync::Mutex;
+
+use crate::schedule::errors::ScheduleError;
+use crate::schedule::{Schedulable, Seconds, jitter_offset, next_moment, validate_interval};
+
+/// The number of bits of a moment the slots of a level are indexed by.
+const LEVEL_BITS: u32 = 6;
+
+/// The number of slots of every level.
+const SLOTS: usize = 1 << LEVEL_BITS;
+
+/// The number of levels. The wheel spans 2^36 seconds; items due later are
+/// kept in the farthest slot and placed again once it's reached. Slots of the
+/// highest level are reached in turns, so it may hold items due in the next
+/// turn too.
+const LEVELS: usize = 6;
+
+/// A schedule for [Schedulable] items backed by a timing wheel.
+///
+/// | Operation | Time complexity |
+/// |-----------|-----------------|
+/// | Get       | O(1)            |
+/// | Get due   | O(m)            |
+/// | Insert    | O(1)            |
+/// | Remove    | O(1)            |
+///
+/// **m** - it's amount of due items.
+///
+/// Items are due at the same moments as in a [Schedule](super::Schedule)
+/// with the same jitter.
+pub struct WheelSchedule<Item: Schedulable> {
+  wheel: Mutex<Wheel<Item>>,
+  jitter: i64,
+}
+
+struct Wheel<Item: Schedulable> {
+  /// The moment items due before have already been taken.
+  now: i64,
+  items: HashMap<Item::Id, Entry<Item>>,
+  levels: [Level<Item::Id>; LEVELS],
+
+  /// A counter of insertions, which tells slot entries of the current items
+  /// from the ones left by replaced and removed items.
+  generation: u64,
+}
+
+struct Entry<Item> {
+  item: Arc<Item>,
+  due: i64,
+  interval: i64,
+  offset: i64,
+  generation: u64,
+}
+
+struct Level<Id> {
+  /// A bit per slot, set if the slot isn't empty.
+  occupied: u64,
+  slots: [Vec<(Id, u64)>; SLOTS],
+}
+
+impl<Item: Schedulable> Wheel<Item> {
+  fn new() -> Self {
+    Self {
+      now: 0,
+      items: HashMap::new(),
+      levels: array::from_fn(|_| Level {
+        occupied: 0,
+        slots: array::from_fn(|_| Vec::new()),
+      }),
+      generation: 0,
+    }
+  }
+
+  fn insert(&mut self, item: Arc<Item>, interval: i64, offset: i64) -> Option<Arc<Item>> {
+    let id = item.get_id();
+    let due = next_moment(self.now, interval, offset);
+
+    self.generation += 1;
+    self.place(id.clone(), due, self.generation);
+
+    let entry = Entry {
+      item,
+      due,
+      interval,
+      offset,
+      generation: self.generation,
+    };
+
+    self.items.insert(id, entry).map(|entry| entry.item)
+  }
+
+  /// Puts the `id` into the slot of the moment it's due at, on the level of
+  /// the highest bit the moment differs from `now` in.
+  fn place(&mut self, id: Item::Id, due: i64, generation: u64) {
+    let now = self.now as u64;
+    let due = due.max(self.now) as u64;
+    let mask = SLOTS as u64 - 1;
+
+    let level = ((((due ^ now) | mask).ilog2() / LEVEL_BITS) as usize).min(LEVELS - 1);
+    let shift = level as u32 * LEVEL_BITS;
+
+    let slot = if due - now < 1 << (LEVELS as u32 * LEVEL_BITS) {
+      (due >> shift) & mask
+    } else {
+      ((now >> shift) + mask) & mask
+    };
+
+    let level = &mut self.levels[level];
+    level.occupied |= 1 << slot;
+    level.slots[slot as usize].push((id, generation));
+  }
+
+  /// Returns the level and the index of the occupied slot reached first,
+  /// along with the moment it starts at. Slots of lower levels are always
+  /// reached before the ones of higher levels.
+  fn next_slot(&self) -> Option<(usize, usize, i64)> {
+    let now = self.now as u64;
+
+    self.levels.iter().enumerate().find_map(|(index, level)| {
+      if level.occupied == 0 {
+        return None;
+      }
+
+      let shift = index as u32 * LEVEL_BITS;
+      let current = ((now >> shift) as usize) & (SLOTS - 1);
+      let slot =
+        (level.occupied.rotate_right(current as u32).trailing_zeros() as usize + current) % SLOTS;
+
+      let range = 1u64 << shift;
+      let level_range = range << LEVEL_BITS;
+      let mut start = (now & !(level_range - 1)) + slot as u64 * range;
+
+      if start < now {
+        start += level_range;
+      }
+
+      Some((index, slot, start as i64))
+    })
+  }
+
+  /// Moves the wheel to `to`, adding items due until then to `due`. Every item
+  /// is placed again by the first moment it's due at after `to`.
+  fn advance(&mut self, to: i64, due: &mut Vec<Arc<Item>>) {
+    while let Some((level, slot, start)) = self.next_slot()
+      && start <= to
+    {
+      self.now = start;
+
+      let level = &mut self.levels[level];
+      level.occupied &= !(1 << slot);
+
+      for (id, generation) in mem::take(&mut level.slots[slot]) {
+        let Some(entry) = self.items.get_mut(&id) else {
+          continue;
+        };
+
+        if entry.generation != generation {
+          continue;
+        }
+
+        if entry.due <= start {
+          due.push(Arc::clone(&entry.item));
+          entry.due = next_moment(to + 1, entry.interval, entry.offset);
+        }
+
+        let at = entry.due;
+        self.place(id, at, generation);
+      }
+    }
+
+    self.now = self.now.max(to + 1);
+  }
+}
+
+impl<Item: Schedulable> WheelSchedule<Item> {
+  /// Create a new schedule.
+  pub fn new() -> Self {
+    Self::with_jitter(0)
+  }
+
+  /// Create a new schedule delaying due times of the items by up to `jitter`
+  /// seconds (but less than their interval).
+  pub fn with_jitter(jitter: i64) -> Self {
+    Self {
+      wheel: Mutex::new(Wheel::new()),
+      jitter,
+    }
+  }
+
+  /// Returns `true` if the schedule doesn't contain elements.
+  pub async fn is_empty(&self) -> bool {
+    self.wheel.lock().await.items.is_empty()
+  }
+
+  /// Returns the number of items in the schedule.
+  pub async fn len(&self) -> usize {
+    self.wheel.lock().await.items.len()
+  }
+
+  /// Get an item by `id`.
+  pub async fn get(&self, id: Item::Id) -> Option<Arc<Item>> {
+    let wheel = self.wheel.lock().await;
+
+    wheel.items.get(&id).map(|entry| Arc::clone(&entry.item))
+  }
+
+  /// Returns the moment the item with `id` is next returned as due at, or
+  /// `None` if there is no such item.
+  pub async fn next_due(&self, id: Item::Id) -> Option<i64> {
+    self
+      .wheel
+      .lock()
+      .await
+      .items
+      .get(&id)
+      .map(|entry| entry.due)
+  }
+
+  /// Get items due between `from` and `to`, moving the wheel to `to`.
+  ///
+  /// Due moments before `from` that haven't been taken yet are skipped, and
+  /// the ones already taken by a previous call aren't returned again.
+  ///
+  /// `from` and `to` should be > 0 and `from` should be <= `to`.
+  pub async fn get_due(&self, from: i64, to: i64) -> Vec<Arc<Item>> {
+    let mut wheel = self.wheel.lock().await;
+    let mut result = Vec::new();
+
+    if from > wheel.now {
+      wheel.advance(from - 1, &mut Vec::new());
+    }
+
+    wheel.advance(to, &mut result);
+
+    result
+  }
+
+  /// Insert an item into schedule. It's first due at the next multiple of its
+  /// interval (delayed by the jitter offset) the wheel hasn't reached yet.
+  ///
+  /// If an item with this `id` is already in the schedule, it will be replaced
+  /// and returned. Fails if the interval of the item isn't positive.
+  pub async fn insert(&self, item: Item) -> Result<Option<Arc<Item>>, ScheduleError> {
+    let interval = item.get_interval().seconds();
+    validate_interval(interval, 1)?;
+
+    let offset = jitter_offset(&item, interval, self.jitter);
+
+    let mut wheel = self.wheel.lock().await;
+    Ok(wheel.insert(Arc::new(item), interval, offset))
+  }
+
+  /// Remove an item by `id` from the schedule if it exists.
+  pub async fn remove(&self, id: Item::Id) -> Option<Arc<Item>> {
+    let mut wheel = self.wheel.lock().await;
+
+    wheel.items.remove(&id).map(|entry| entry.item)
+  }
+}
+
+impl<Item: Schedulable> Default for WheelSchedule<Item> {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::collections::HashSet;
+
+  use super::*;
+  use crate::schedule::Schedule;
+
+  struct Task {
+    id: i64,
+    interval: i64,
+  }
+
+  impl Schedulable for Task {
+    type Id = i64;
+    type Interval = i64;
+
+    fn get_id(&self) -> Self::Id {
+      self.id
+    }
+
+    fn get_interval(&self) -> Self::Interval {
+      self.interval
+    }
+  }
+
+  fn ids(items: Vec<Arc<Task>>) -> HashSet<i64> {
+    items.iter().map(|item| item.id).collect()
+  }
+
+  #[tokio::test]
+  async fn same_due_items_as_schedule() {
+    let wheel = WheelSchedule::with_jitter(30);
+    let schedule = Schedule::with_jitter(30);
+
+    for id in 1..=300 {
+      let interval = 5 + id * id * 37 % 86_400;
+
+      wheel.insert(Task { id, interval }).await.unwrap();
+      schedule.insert(Task { id, interval }).await.unwrap();
+    }
+
+    let mut from = 1_700_000_000;
+
+    for window in [1, 7, 60, 600].iter().cycle().take(1000) {
+      let to = from + window - 1;
+
+      assert_eq!(
+        ids(wheel.get_due(from, to).await),
+        ids(schedule.get_due(from, to).await),
+        "due items between {} and {}",
+        from,
+        to
+      );
+
+      from = to + 1;
+    }
+  }
+
+  #[tokio::test]
+  async fn skipped_moments() {
+    let wheel = WheelSchedule::new();
+
+    wheel
+      .insert(Task {
+        id: 1,
+        interval: 10,
+      })
+      .await
+      .unwrap();
+
+    assert_eq!(wheel.get_due(1, 10).await.len(), 1);
+    assert_eq!(
+      wheel.get_due(5, 10).await.len(),
+      0,
+      "due moments are taken once"
+    );
+    assert_eq!(wheel.get_due(100, 100).await.len(), 1);
+    assert_eq!(wheel.next_due(1).await, Some(110));
+  }
+
+  #[tokio::test]
+  async fn replace_and_remove() {
+    let wheel = WheelSchedule::new();
+
+    wheel
+      .insert(Task {
+        id: 1,
+        interval: 10,
+      })
+      .await
+      .unwrap();
+    assert!(
+      wheel
+        .insert(Task {
+          id: 1,
+          interval: 25
+        })
+        .await
+        .unwrap()
+        .is_some()
+    );
+
+    assert_eq!(wheel.len().await, 1);
+    assert!(
+      wheel.get_due(1, 24).await.is_empty(),
+      "item isn't due at the old interval"
+    );
+    assert_eq!(wheel.get_due(25, 25).await.len(), 1);
+
+    assert!(wheel.remove(1).await.is_some());
+    assert!(wheel.get_due(26, 100).await.is_empty());
+    assert!(wheel.is_empty().await);
+  }
+
+  #[tokio::test]
+  async fn distant_moments() {
+    let wheel = WheelSchedule::new();
+    let interval = 1 << 40;
+
+    wheel.insert(Task { id: 1, interval }).await.unwrap();
+    wheel.get_due(1, 1).await;
+
+    assert_eq!(wheel.next_due(1).await, Some(interval));
+    assert!(
+      wheel.get_due(2, interval - 1).await.is_empty(),
+      "item placed beyond the wheel isn't due earlier"
+    );
+    assert_eq!(wheel.get_due(interval, interval).await.len(), 1);
+  }
+}
@@ -0,0 +1,129 @@
+//! Blackout windows, during which items of a [Schedule](super::Schedule)
+//! aren't due.
+//!
+//! A [Window] is either a range between two moments, e.g. a planned
+//! maintenance, or a range recurring every week, e.g. a nightly deploy.
+//!
+//! # Example
+//!
+//! ```rust
+//! use std::time::Duration;
+//!
+//! use limon_core::schedule::window::Window;
+//! use time::{Time, Weekday};
+//!
+//! // Every Sunday, from 02:00 to 04:00 UTC.
+//! let window = Window::weekly(Weekday::Sunday, Time::from_hms(2, 0, 0).unwrap(), Duration::from_secs(7200));
+//!
+//! // Sunday, 4 January 1970, 03:00 UTC.
+//! assert!(window.contains(270_000));
+//! assert!(!window.contains(277_200));
+//! ```
+
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use time::{Time, Weekday};
+
+/// The number of seconds in a week.
+const WEEK: i64 = 7 * 86_400;
+
+/// The unix timestamp of the first Monday midnight, 5 January 1970, UTC.
+const FIRST_MONDAY: i64 = 4 * 86_400;
+
+/// A range of time items aren't due in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Window {
+  /// Between two unix timestamps, from `from` until `to`, exclusive.
+  Between { from: i64, to: i64 },
+
+  /// Every week, from `start` seconds after Monday midnight, UTC, for
+  /// `duration` seconds.
+  Weekly { start: i64, duration: i64 },
+}
+
+impl Window {
+  /// Creates a window between two unix timestamps, `to` excluded.
+  pub fn between(from: i64, to: i64) -> Self {
+    Self::Between { from, to }
+  }
+
+  /// Creates a window recurring every week on `day`, from `at`, UTC, for
+  /// `duration`. It may end on one of the next days.
+  pub fn weekly(day: Weekday, at: Time, duration: Duration) -> Self {
+    let (hours, minutes, seconds) = at.as_hms();
+
+    Self::Weekly {
+      start: i64::from(day.number_days_from_monday()) * 86_400
+        + i64::from(hours) * 3600
+        + i64::from(minutes) * 60
+        + i64::from(seconds),
+      duration: duration.as_secs() as i64,
+    }
+  }
+
+  /// Returns `true` if the unix timestamp `at` is in the window.
+  pub fn contains(&self, at: i64) -> bool {
+    match *self {
+      Self::Between { from, to } => from <= at && at < to,
+      Self::Weekly { start, duration } => {
+        duration >= WEEK || (at - FIRST_MONDAY - start).rem_euclid(WEEK) < duration
+      }
+    }
+  }
+
+  /// Returns `true` if the window doesn't contain `now` or any later moment.
+  pub fn is_over(&self, now: i64) -> bool {
+    match *self {
+      Self::Between { to, .. } => to <= now,
+      Self::Weekly { duration, .. } => duration <= 0,
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn between() {
+    let window = Window::between(100, 200);
+
+    assert!(!window.contains(99));
+    assert!(window.contains(100));
+    assert!(window.contains(199));
+    assert!(!window.contains(200), "the end is excluded");
+
+    assert!(!window.is_over(199));
+    assert!(window.is_over(200));
+  }
+
+  #[test]
+  fn weekly() {
+    let midnight = Time::MIDNIGHT;
+    let monday = Window::weekly(Weekday::Monday, midnight, Duration::from_secs(3600));
+
+    assert!(monday.contains(FIRST_MONDAY));
+    assert!(monday.contains(FIRST_MONDAY + 3599));
+    assert!(!monday.contains(FIRST_MONDAY + 3600));
+    assert!(
+      monday.contains(FIRST_MONDAY + 10 * WEEK + 60),
+      "recurs weekly"
+    );
+    assert!(monday.contains(FIRST_MONDAY - WEEK), "recurs before too");
+
+    let sunday = Window::weekly(
+      Weekday::Sunday,
+      Time::from_hms(23, 0, 0).unwrap(),
+      Duration::from_secs(7200),
+    );
+
+    assert!(sunday.contains(FIRST_MONDAY - 1800));
+    assert!(
+      sunday.contains(FIRST_MONDAY + 1800),
+      "spans the end of the week"
+    );
+    assert!(!sunday.contains(FIRST_MONDAY + 3600));
+    assert!(!sunday.is_over(i64::MAX - 1));
+  }
+}
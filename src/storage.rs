@@ -0,0 +1,14 @@
+//! A module persisting measurements.
+//!
+//! - **sqlite** - Stores measurements in an embedded SQLite database, for
+//!   deployments without an external one.
+//! - **maintenance** - Downsamples stored measurements into hourly aggregates
+//!   and drops the old ones, as a task of a [Schedule](crate::schedule::Schedule).
+//!
+//! Both require the `sqlite` feature.
+
+pub mod errors;
+#[cfg(feature = "sqlite")]
+pub mod maintenance;
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
@@ -0,0 +1,20 @@
+//! A module describing storage errors.
+
+use thiserror::Error;
+
+/// Errors that can occur when storing or querying measurements.
+#[derive(Error, Debug)]
+pub enum StorageError {
+  /// The database failed.
+  #[cfg(feature = "sqlite")]
+  #[error("SQLite error: {0}")]
+  Sqlite(#[from] rusqlite::Error),
+
+  /// A measurement couldn't be serialized or deserialized.
+  #[error("Serialization error: {0}")]
+  Serialize(#[from] serde_json::Error),
+
+  /// The task accessing the database failed.
+  #[error("Task error: {0}")]
+  Task(#[from] tokio::task::JoinError),
+}
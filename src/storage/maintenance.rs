@@ -0,0 +1,274 @@
+//! Periodic maintenance of stored measurements.
+//!
+//! A [Maintenance] task downsamples the measurements of a [SqliteStorage]
+//! into hourly aggregates, then drops the measurements older than the
+//! retention period. It's [Runnable], so it's run on a [Schedule] like any
+//! other item, and can share the schedule of the monitors as a [Job].
+//!
+//! # Example
+//!
+//! ```rust, no_run
+//! use std::sync::Arc;
+//! use std::time::Duration;
+//!
+//! use limon_core::monitor::models::Monitor;
+//! use limon_core::schedule::Schedule;
+//! use limon_core::schedule::runner::Runner;
+//! use limon_core::storage::errors::StorageError;
+//! use limon_core::storage::maintenance::{Job, Maintenance, Outcome};
+//! use limon_core::storage::sqlite::SqliteStorage;
+//! use tokio::sync::mpsc;
+//!
+//! async fn run(monitors: Vec<Monitor>) -> Result<(), StorageError> {
+//!   let storage = SqliteStorage::open("limon.db").await?;
+//!   let schedule = Arc::new(Schedule::<Job>::new());
+//!
+//!   let maintenance = Maintenance::new(-1, storage.clone()).retention(Duration::from_secs(30 * 86_400));
+//!   schedule.insert(maintenance.into()).await.unwrap();
+//!
+//!   for monitor in monitors {
+//!     schedule.insert(monitor.into()).await.unwrap();
+//!   }
+//!
+//!   let (sink, mut outcomes) = mpsc::channel(1024);
+//!   tokio::spawn(Runner::new(schedule, sink).run());
+//!
+//!   while let Some(outcome) = outcomes.recv().await {
+//!     match outcome {
+//!       Outcome::Measurement(measurement) => storage.insert(&measurement).await?,
+//!       Outcome::Maintenance(report) => println!("{:?}", report),
+//!     }
+//!   }
+//!
+//!   Ok(())
+//! }
+//! ```
+
+use std::time::Duration;
+
+use time::OffsetDateTime;
+
+use super::errors::StorageError;
+use super::sqlite::SqliteStorage;
+use crate::monitor::models::{Measurement, Monitor};
+use crate::schedule::Schedulable;
+#[cfg(doc)]
+use crate::schedule::Schedule;
+use crate::schedule::runner::Runnable;
+
+/// The default interval of the maintenance, in seconds.
+const DEFAULT_INTERVAL: i64 = 3600;
+
+/// A schedulable task downsampling and dropping old measurements.
+pub struct Maintenance {
+  id: i64,
+  interval: i64,
+  storage: SqliteStorage,
+  retention: Option<Duration>,
+  downsampling: bool,
+}
+
+/// The changes of a maintenance run.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Report {
+  /// Number of hourly aggregates written.
+  pub aggregated: usize,
+
+  /// Number of measurements dropped.
+  pub deleted: usize,
+}
+
+impl Maintenance {
+  /// Creates a task maintaining `storage` hourly, which downsamples the
+  /// measurements and keeps them forever. Within a shared schedule, the `id`
+  /// must not be used by a monitor.
+  pub fn new(id: i64, storage: SqliteStorage) -> Self {
+    Self {
+      id,
+      interval: DEFAULT_INTERVAL,
+      storage,
+      retention: None,
+      downsampling: true,
+    }
+  }
+
+  /// Sets how often the task runs, in seconds.
+  pub fn interval(mut self, interval: i64) -> Self {
+    self.interval = interval;
+    self
+  }
+
+  /// Drops measurements once they're older than `retention`. It's rounded
+  /// up to whole hours.
+  pub fn retention(mut self, retention: Duration) -> Self {
+    self.retention = Some(retention);
+    self
+  }
+
+  /// Sets whether measurements are downsampled before they're dropped.
+  pub fn downsampling(mut self, downsampling: bool) -> Self {
+    self.downsampling = downsampling;
+    self
+  }
+
+  /// Maintains the storage as of `now`.
+  pub async fn run_at(&self, now: OffsetDateTime) -> Result<Report, StorageError> {
+    let mut report = Report::default();
+
+    if self.downsampling {
+      report.aggregated = self.storage.downsample(now).await?;
+    }
+
+    if let Some(retention) = self.retention {
+      report.deleted = self.storage.delete_before(now - retention).await?;
+    }
+
+    Ok(report)
+  }
+}
+
+impl Schedulable for Maintenance {
+  type Id = i64;
+  type Interval = i64;
+
+  fn get_id(&self) -> Self::Id {
+    self.id
+  }
+
+  fn get_interval(&self) -> Self::Interval {
+    self.interval
+  }
+}
+
+impl Runnable for Maintenance {
+  type Output = Result<Report, StorageError>;
+
+  async fn run(&self) -> Self::Output {
+    self.run_at(OffsetDateTime::now_utc()).await
+  }
+}
+
+/// An item of a schedule shared by monitors and the maintenance.
+pub enum Job {
+  /// A monitor, measured when it's due.
+  Monitor(Monitor),
+
+  /// The maintenance of the storage.
+  Maintenance(Maintenance),
+}
+
+/// The output of a [Job].
+#[derive(Debug)]
+pub enum Outcome {
+  /// The measurement of a monitor.
+  Measurement(Box<Measurement>),
+
+  /// The report of a maintenance run.
+  Maintenance(Result<Report, StorageError>),
+}
+
+impl From<Monitor> for Job {
+  fn from(monitor: Monitor) -> Self {
+    Job::Monitor(monitor)
+  }
+}
+
+impl From<Maintenance> for Job {
+  fn from(maintenance: Maintenance) -> Self {
+    Job::Maintenance(maintenance)
+  }
+}
+
+impl Schedulable for Job {
+  type Id = i64;
+  type Interval = i64;
+
+  fn get_id(&self) -> Self::Id {
+    match self {
+      Job::Monitor(monitor) => monitor.get_id(),
+      Job::Maintenance(maintenance) => maintenance.get_id(),
+    }
+  }
+
+  fn get_interval(&self) -> Self::Interval {
+    match self {
+      Job::Monitor(monitor) => monitor.get_interval(),
+      Job::Maintenance(maintenance) => maintenance.get_interval(),
+    }
+  }
+}
+
+impl Runnable for Job {
+  type Output = Outcome;
+
+  async fn run(&self) -> Outcome {
+    match self {
+      Job::Monitor(monitor) => Outcome::Measurement(Box::new(monitor.run().await)),
+      Job::Maintenance(maintenance) => Outcome::Maintenance(maintenance.run().await),
+    }
+  }
+
+  fn forget(id: &i64) {
+    Monitor::forget(id);
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::sync::Arc;
+
+  use tokio::sync::mpsc;
+
+  use super::*;
+  use crate::schedule::Schedule;
+  use crate::schedule::runner::Runner;
+
+  #[tokio::test]
+  async fn scheduled() {
+    let storage = SqliteStorage::in_memory().await.unwrap();
+    let now = OffsetDateTime::now_utc();
+    let day = Duration::from_secs(86_400);
+
+    storage
+      .insert_many(&[
+        Measurement {
+          timestamp: now - day * 3,
+          ..Measurement::fixture(1)
+        },
+        Measurement {
+          timestamp: now - day * 2,
+          ..Measurement::fixture(1)
+        },
+        Measurement {
+          timestamp: now,
+          ..Measurement::fixture(1)
+        },
+      ])
+      .await
+      .unwrap();
+
+    let schedule = Arc::new(Schedule::<Job>::new());
+    let maintenance = Maintenance::new(-1, storage.clone())
+      .interval(60)
+      .retention(day);
+    schedule.insert(maintenance.into()).await.unwrap();
+
+    let (sink, mut outcomes) = mpsc::channel(1);
+    Runner::new(schedule, sink).dispatch(0, 60).await;
+
+    let Some(Outcome::Maintenance(report)) = outcomes.recv().await else {
+      panic!("the maintenance should run");
+    };
+
+    assert_eq!(report.unwrap(), Report {
+      aggregated: 2,
+      deleted: 2
+    });
+    assert_eq!(storage.count(1).await.unwrap(), 1);
+    assert_eq!(
+      storage.hourly(1, now - day * 4, now).await.unwrap().len(),
+      2,
+      "the aggregates are kept"
+    );
+  }
+}
@@ -0,0 +1,469 @@
+//! Measurements stored in an embedded SQLite database.
+//!
+//! A [SqliteStorage] keeps every measurement in a single table, indexed by
+//! monitor and time, and queries the measurements of a monitor over a range
+//! of time. Queries run on Tokio's blocking threads.
+//!
+//! Measurements can be downsampled into hourly [Aggregate]s, which outlive
+//! the measurements dropped by a retention policy. Both are applied
+//! periodically by a [Maintenance](super::maintenance::Maintenance) task.
+//!
+//! # Example
+//!
+//! ```rust, no_run
+//! use limon_core::monitor::models::Measurement;
+//! use limon_core::storage::errors::StorageError;
+//! use limon_core::storage::sqlite::SqliteStorage;
+//! use time::{Duration, OffsetDateTime};
+//!
+//! async fn last_day(measurement: &Measurement) -> Result<Vec<Measurement>, StorageError> {
+//!   let storage = SqliteStorage::open("limon.db").await?;
+//!   storage.insert(measurement).await?;
+//!
+//!   let now = OffsetDateTime::now_utc();
+//!   storage.range(measurement.monitor_id, now - Duration::days(1), now).await
+//! }
+//! ```
+
+use std::path::Path;
+use std::sync::{Arc, Mutex, PoisonError};
+
+use rusqlite::{Connection, OptionalExtension, params};
+use time::OffsetDateTime;
+use tokio::task;
+
+use crate::monitor::models::Measurement;
+use crate::storage::errors::StorageError;
+
+/// The schema of the database. The measurement is stored serialized, along
+/// with the columns it's queried and aggregated by.
+const SCHEMA: &str = "
+  CREATE TABLE IF NOT EXISTS measurements (
+    monitor_id INTEGER NOT NULL,
+    timestamp INTEGER NOT NULL,
+    status TEXT NOT NULL,
+    latency REAL,
+    measurement TEXT NOT NULL
+  );
+
+  CREATE INDEX IF NOT EXISTS measurements_by_monitor
+    ON measurements (monitor_id, timestamp);
+
+  CREATE TABLE IF NOT EXISTS hourly (
+    monitor_id INTEGER NOT NULL,
+    hour INTEGER NOT NULL,
+    count INTEGER NOT NULL,
+    failures INTEGER NOT NULL,
+    latency_min REAL,
+    latency_avg REAL,
+    latency_max REAL,
+    PRIMARY KEY (monitor_id, hour)
+  );
+";
+
+/// The length of an hour, in milliseconds.
+const HOUR: i64 = 3_600_000;
+
+/// The measurements of a monitor during an hour.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Aggregate {
+  /// Start of the hour.
+  pub start: OffsetDateTime,
+
+  /// Number of measurements.
+  pub count: u64,
+
+  /// Number of failed measurements.
+  pub failures: u64,
+
+  /// Lowest latency of the successful measurements, in milliseconds.
+  pub latency_min: Option<f32>,
+
+  /// Average latency of the successful measurements, in milliseconds.
+  pub latency_avg: Option<f32>,
+
+  /// Highest latency of the successful measurements, in milliseconds.
+  pub latency_max: Option<f32>,
+}
+
+/// Measurements stored in a SQLite database.
+///
+/// Clones share the connection.
+#[derive(Clone)]
+pub struct SqliteStorage {
+  connection: Arc<Mutex<Connection>>,
+}
+
+impl SqliteStorage {
+  /// Opens the database at `path`, creating it and its schema if needed.
+  pub async fn open(path: impl AsRef<Path>) -> Result<Self, StorageError> {
+    let path = path.as_ref().to_path_buf();
+
+    Self::init(move || Connection::open(path)).await
+  }
+
+  /// Opens a database kept in memory, lost once the storage is dropped.
+  pub async fn in_memory() -> Result<Self, StorageError> {
+    Self::init(Connection::open_in_memory).await
+  }
+
+  async fn init(
+    open: impl FnOnce() -> rusqlite::Result<Connection> + Send + 'static,
+  ) -> Result<Self, StorageError> {
+    let connection = task::spawn_blocking(move || {
+      let connection = open()?;
+      connection.execute_batch(SCHEMA)?;
+
+      Ok::<_, StorageError>(connection)
+    })
+    .await??;
+
+    Ok(Self {
+      connection: Arc::new(Mutex::new(connection)),
+    })
+  }
+
+  /// Runs `query` with the connection on a blocking thread.
+  async fn with<T: Send + 'static>(
+    &self,
+    query: impl FnOnce(&mut Connection) -> Result<T, StorageError> + Send + 'static,
+  ) -> Result<T, StorageError> {
+    let connection = Arc::clone(&self.connection);
+
+    task::spawn_blocking(move || {
+      let mut connection = connection.lock().unwrap_or_else(PoisonError::into_inner);
+
+      query(&mut connection)
+    })
+    .await?
+  }
+
+  /// Stores a measurement.
+  pub async fn insert(&self, measurement: &Measurement) -> Result<(), StorageError> {
+    self.insert_many(std::slice::from_ref(measurement)).await
+  }
+
+  /// Stores measurements in a single transaction.
+  pub async fn insert_many(&self, measurements: &[Measurement]) -> Result<(), StorageError> {
+    let rows = measurements
+      .iter()
+      .map(|measurement| {
+        Ok((
+          measurement.monitor_id,
+          unix_millis(measurement.timestamp),
+          measurement.status().as_str(),
+          measurement.data.as_ref().map(|data| data.latency()),
+          serde_json::to_string(measurement)?,
+        ))
+      })
+      .collect::<Result<Vec<_>, StorageError>>()?;
+
+    self
+      .with(move |connection| {
+        let transaction = connection.transaction()?;
+
+        {
+          let mut insert = transaction.prepare_cached(
+            "INSERT INTO measurements (monitor_id, timestamp, status, latency, measurement)
+             VALUES (?1, ?2, ?3, ?4, ?5)",
+          )?;
+
+          for (monitor_id, timestamp, status, latency, measurement) in rows {
+            insert.execute(params![monitor_id, timestamp, status, latency, measurement])?;
+          }
+        }
+
+        Ok(transaction.commit()?)
+      })
+      .await
+  }
+
+  /// Returns the measurements of a monitor taken from `from` until `to`,
+  /// exclusive, oldest first. Errors are restored as their reports.
+  pub async fn range(
+    &self,
+    monitor_id: i64,
+    from: OffsetDateTime,
+    to: OffsetDateTime,
+  ) -> Result<Vec<Measurement>, StorageError> {
+    let (from, to) = (unix_millis(from), unix_millis(to));
+
+    self
+      .with(move |connection| {
+        let mut select = connection.prepare_cached(
+          "SELECT measurement FROM measurements
+           WHERE monitor_id = ?1 AND timestamp >= ?2 AND timestamp < ?3
+           ORDER BY timestamp",
+        )?;
+
+        let rows =
+          select.query_map(params![monitor_id, from, to], |row| row.get::<_, String>(0))?;
+
+        rows.map(|row| Ok(serde_json::from_str(&row?)?)).collect()
+      })
+      .await
+  }
+
+  /// Returns the latest measurement of a monitor, if any.
+  pub async fn latest(&self, monitor_id: i64) -> Result<Option<Measurement>, StorageError> {
+    let measurement = self
+      .with(move |connection| {
+        Ok(
+          connection
+            .prepare_cached(
+              "SELECT measurement FROM measurements
+               WHERE monitor_id = ?1
+               ORDER BY timestamp DESC LIMIT 1",
+            )?
+            .query_row(params![monitor_id], |row| row.get::<_, String>(0))
+            .optional()?,
+        )
+      })
+      .await?;
+
+    Ok(
+      measurement
+        .map(|measurement| serde_json::from_str(&measurement))
+        .transpose()?,
+    )
+  }
+
+  /// Returns the hourly aggregates of a monitor for the hours starting from
+  /// `from` until `to`, exclusive, oldest first.
+  pub async fn hourly(
+    &self,
+    monitor_id: i64,
+    from: OffsetDateTime,
+    to: OffsetDateTime,
+  ) -> Result<Vec<Aggregate>, StorageError> {
+    let (from, to) = (unix_millis(from), unix_millis(to));
+
+    self
+      .with(move |connection| {
+        let mut select = connection.prepare_cached(
+          "SELECT hour, count, failures, latency_min, latency_avg, latency_max FROM hourly
+           WHERE monitor_id = ?1 AND hour >= ?2 AND hour < ?3
+           ORDER BY hour",
+        )?;
+
+        let rows = select.query_map(params![monitor_id, from, to], |row| {
+          Ok(Aggregate {
+            start: from_unix_millis(row.get(0)?),
+            count: row.get(1)?,
+            failures: row.get(2)?,
+            latency_min: row.get(3)?,
+            latency_avg: row.get(4)?,
+            latency_max: row.get(5)?,
+          })
+        })?;
+
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
+      })
+      .await
+  }
+
+  /// Aggregates the measurements of the hours that ended by `until` into
+  /// hourly aggregates, and returns the number of aggregates written.
+  ///
+  /// Hours since the latest aggregated one are aggregated again, so the
+  /// measurements of an hour should be stored before it's aggregated.
+  pub async fn downsample(&self, until: OffsetDateTime) -> Result<usize, StorageError> {
+    let until = floor_hour(unix_millis(until));
+
+    self
+      .with(move |connection| {
+        Ok(connection.execute(
+          "INSERT INTO hourly (monitor_id, hour, count, failures, latency_min, latency_avg, latency_max)
+           SELECT monitor_id, timestamp / ?2 * ?2 AS start, COUNT(*), SUM(status = 'down'),
+             MIN(latency), AVG(latency), MAX(latency)
+           FROM measurements
+           WHERE timestamp >= IFNULL((SELECT MAX(hour) FROM hourly), timestamp) AND timestamp < ?1
+           GROUP BY monitor_id, start
+           ON CONFLICT (monitor_id, hour) DO UPDATE SET
+             count = excluded.count,
+             failures = excluded.failures,
+             latency_min = excluded.latency_min,
+             latency_avg = excluded.latency_avg,
+             latency_max = excluded.latency_max",
+          params![until, HOUR],
+        )?)
+      })
+      .await
+  }
+
+  /// Deletes the measurements taken before the hour `before` falls in, and
+  /// returns their number. Their hourly aggregates are kept, and no hour is
+  /// left partially deleted.
+  pub async fn delete_before(&self, before: OffsetDateTime) -> Result<usize, StorageError> {
+    let before = floor_hour(unix_millis(before));
+
+    self
+      .with(move |connection| {
+        Ok(
+          connection.execute("DELETE FROM measurements WHERE timestamp < ?1", params![
+            before
+          ])?,
+        )
+      })
+      .await
+  }
+
+  /// Returns the number of stored measurements of a monitor.
+  pub async fn count(&self, monitor_id: i64) -> Result<usize, StorageError> {
+    self
+      .with(move |connection| {
+        Ok(connection.query_row(
+          "SELECT COUNT(*) FROM measurements WHERE monitor_id = ?1",
+          params![monitor_id],
+          |row| row.get(0),
+        )?)
+      })
+      .await
+  }
+}
+
+/// Returns the unix timestamp of the moment, in milliseconds.
+fn unix_millis(moment: OffsetDateTime) -> i64 {
+  (moment.unix_timestamp_nanos() / 1_000_000) as i64
+}
+
+/// Returns the moment of a unix timestamp in milliseconds.
+fn from_unix_millis(millis: i64) -> OffsetDateTime {
+  OffsetDateTime::from_unix_timestamp_nanos(i128::from(millis) * 1_000_000)
+    .unwrap_or(OffsetDateTime::UNIX_EPOCH)
+}
+
+/// Returns the start of the hour of a unix timestamp in milliseconds.
+fn floor_hour(millis: i64) -> i64 {
+  millis - millis.rem_euclid(HOUR)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::monitor::models::Status;
+
+  fn at(seconds: i64) -> OffsetDateTime {
+    OffsetDateTime::from_unix_timestamp(seconds).unwrap()
+  }
+
+  #[tokio::test]
+  async fn range_queries() {
+    let storage = SqliteStorage::in_memory().await.unwrap();
+
+    storage
+      .insert_many(&[
+        Measurement::fixture(1).at(30).latency(30.0),
+        Measurement::fixture(1).at(10).latency(10.0),
+        Measurement::fixture(1).at(20).up(false),
+        Measurement::fixture(2).at(15).latency(15.0),
+      ])
+      .await
+      .unwrap();
+    storage
+      .insert(&Measurement::fixture(1).at(40).latency(40.0))
+      .await
+      .unwrap();
+
+    let measurements = storage.range(1, at(10), at(40)).await.unwrap();
+    let timestamps: Vec<i64> = measurements
+      .iter()
+      .map(|measurement| measurement.timestamp.unix_timestamp())
+      .collect();
+
+    assert_eq!(timestamps, [10, 20, 30], "the end is excluded");
+    assert_eq!(measurements[1].status(), Status::Down);
+    assert_eq!(
+      measurements[1].error.as_ref().unwrap().to_string(),
+      Measurement::fixture(1)
+        .at(20)
+        .up(false)
+        .error
+        .unwrap()
+        .to_string(),
+      "the error is kept as its report"
+    );
+
+    assert_eq!(storage.count(1).await.unwrap(), 4);
+    assert_eq!(storage.latest(1).await.unwrap().unwrap().timestamp, at(40));
+    assert!(storage.latest(3).await.unwrap().is_none());
+  }
+
+  #[tokio::test]
+  async fn downsampling() {
+    let storage = SqliteStorage::in_memory().await.unwrap();
+
+    storage
+      .insert_many(&[
+        Measurement::fixture(1).at(100).latency(100.0),
+        Measurement::fixture(1).at(300).latency(300.0),
+        Measurement::fixture(1).at(200).up(false),
+        Measurement::fixture(1).at(3_700).latency(3_700.0),
+        Measurement::fixture(2).at(500).latency(500.0),
+      ])
+      .await
+      .unwrap();
+
+    assert_eq!(
+      storage.downsample(at(3_599)).await.unwrap(),
+      0,
+      "the first hour isn't over"
+    );
+    assert_eq!(storage.downsample(at(3_600)).await.unwrap(), 2);
+
+    let hourly = storage.hourly(1, at(0), at(7_200)).await.unwrap();
+    assert_eq!(hourly, [Aggregate {
+      start: at(0),
+      count: 3,
+      failures: 1,
+      latency_min: Some(100.0),
+      latency_avg: Some(200.0),
+      latency_max: Some(300.0),
+    }]);
+
+    storage
+      .insert(&Measurement::fixture(1).at(7_000).up(false))
+      .await
+      .unwrap();
+    storage.downsample(at(7_300)).await.unwrap();
+
+    let hourly = storage.hourly(1, at(0), at(7_200)).await.unwrap();
+    assert_eq!(hourly.len(), 2);
+    assert_eq!((hourly[1].count, hourly[1].failures), (2, 1));
+    assert_eq!(hourly[1].latency_avg, Some(3_700.0));
+
+    assert_eq!(
+      storage.delete_before(at(3_700)).await.unwrap(),
+      4,
+      "the whole hour is kept"
+    );
+    assert_eq!(storage.count(1).await.unwrap(), 2);
+    assert_eq!(
+      storage.hourly(1, at(0), at(3_600)).await.unwrap(),
+      hourly[..1]
+    );
+  }
+
+  #[tokio::test]
+  async fn persisted() {
+    let path = std::env::temp_dir().join(format!("limon-{}.db", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+
+    let storage = SqliteStorage::open(&path).await.unwrap();
+    storage
+      .insert(&Measurement::fixture(1).at(10).latency(10.0))
+      .await
+      .unwrap();
+    drop(storage);
+
+    let storage = SqliteStorage::open(&path).await.unwrap();
+    assert_eq!(
+      storage.count(1).await.unwrap(),
+      1,
+      "kept across connections"
+    );
+
+    drop(storage);
+    std::fs::remove_file(&path).unwrap();
+  }
+}
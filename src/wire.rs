@@ -0,0 +1,163 @@
+//! A compact binary wire format for measurements.
+//!
+//! Measurements are encoded in batches as Protocol Buffers messages, so
+//! agents taking many measurements can ship them to a collector at a fraction
+//! of the size and cost of JSON. Every batch carries the [VERSION] of the
+//! format it was encoded with. Fields are only ever added within a version,
+//! so collectors decode the batches of older and newer agents alike, and the
+//! version is raised when an incompatible change is made.
+//!
+//! It requires the `protobuf` feature.
+//!
+//! # Example
+//!
+//! ```rust
+//! use limon_core::monitor::models::Measurement;
+//! use limon_core::wire::{self, errors::WireError};
+//!
+//! fn relay(measurements: &[Measurement]) -> Result<Vec<Measurement>, WireError> {
+//!   let bytes = wire::encode(measurements);
+//!
+//!   wire::decode(&bytes)
+//! }
+//! ```
+
+use prost::Message;
+
+use crate::monitor::models::Measurement;
+use crate::wire::errors::WireError;
+
+pub mod errors;
+mod proto;
+
+/// The version of the format batches are encoded with.
+pub const VERSION: u32 = 1;
+
+/// Encodes a batch of measurements.
+pub fn encode(measurements: &[Measurement]) -> Vec<u8> {
+  proto::Batch {
+    version: VERSION,
+    measurements: measurements.iter().map(Into::into).collect(),
+  }
+  .encode_to_vec()
+}
+
+/// Decodes a batch of measurements. Their errors are decoded as
+/// [reports](crate::monitor::errors::CollectorError::Reported).
+pub fn decode(bytes: &[u8]) -> Result<Vec<Measurement>, WireError> {
+  let batch = proto::Batch::decode(bytes)?;
+
+  if batch.version == 0 || batch.version > VERSION {
+    return Err(WireError::UnsupportedVersion(batch.version));
+  }
+
+  batch
+    .measurements
+    .into_iter()
+    .map(TryInto::try_into)
+    .collect()
+}
+
+#[cfg(test)]
+mod tests {
+  use std::collections::HashMap;
+
+  use time::OffsetDateTime;
+  use uuid::Uuid;
+
+  use super::*;
+  use crate::monitor::errors::{CollectorError, ErrorCode, HttpError};
+  use crate::monitor::models::{
+    Agent, Certificate, Data, Degradation, HttpData, HttpVersion, IpFamily, Partial, Phase,
+    PingData, PingProtocol,
+  };
+
+  #[test]
+  fn round_trip() {
+    let measurement = Measurement {
+      id: Uuid::now_v7(),
+      timestamp: OffsetDateTime::from_unix_timestamp_nanos(1_700_000_000_123_000_000).unwrap(),
+      sequence: 12,
+      labels: HashMap::from([(String::from("env"), String::from("prod"))]),
+      agent: Some(Agent::new("probe-1").region("eu-west")),
+      ..Measurement::fixture(7)
+    };
+    let http = Measurement {
+      degradation: Some(Degradation::Critical),
+      ..measurement.clone().data(Data::Http(HttpData {
+        total: 183.5,
+        connect: 20.0,
+        ip_family: Some(IpFamily::V6),
+        resolved_ip: Some("2001:db8::1".parse().unwrap()),
+        remote_port: Some(443),
+        status_code: Some(200),
+        http_version: Some(HttpVersion::Http2),
+        certificate: Some(Certificate {
+          subject: String::from("CN=example.com"),
+          issuer: String::from("CN=CA"),
+          not_after: OffsetDateTime::from_unix_timestamp(1_800_000_000).unwrap(),
+          subject_alt_names: vec![String::from("example.com")],
+        }),
+        ..Default::default()
+      }))
+    };
+    let ping = measurement.clone().data(Data::Ping(PingData {
+      ping: 12.5,
+      packet_size: 64,
+      protocol: PingProtocol::Tcp,
+      resolved_ip: Some("10.0.0.1".parse().unwrap()),
+      ..Default::default()
+    }));
+    let failed = Measurement {
+      agent: None,
+      trace: Some(String::from("> GET / HTTP/1.1")),
+      partial: Some(Partial {
+        completed: Some(Phase::Transfer),
+        truncated: true,
+        resolved_ip: Some("10.0.0.2".parse().unwrap()),
+      }),
+      ..measurement.error(CollectorError::Http(HttpError::StatusMismatch {
+        expected: 200,
+        actual: 503,
+        snippet: None,
+      }))
+    };
+
+    let bytes = encode(&[http.clone(), ping.clone(), failed.clone()]);
+    assert!(bytes.len() < serde_json::to_vec(&[&http, &ping, &failed]).unwrap().len() / 2);
+
+    let decoded = decode(&bytes).unwrap();
+    assert_eq!(decoded.len(), 3);
+
+    for (decoded, original) in decoded.iter().zip([&http, &ping, &failed]) {
+      assert_eq!(decoded.id, original.id);
+      assert_eq!(decoded.sequence, original.sequence);
+      assert_eq!(decoded.timestamp, original.timestamp);
+      assert_eq!(decoded.labels, original.labels);
+      assert_eq!(decoded.agent, original.agent);
+      assert_eq!(decoded.data, original.data);
+      assert_eq!(decoded.degradation, original.degradation);
+      assert_eq!(decoded.trace, original.trace);
+      assert_eq!(decoded.partial, original.partial);
+    }
+
+    let report = decoded[2].error.as_ref().unwrap().report();
+    assert_eq!(report, failed.error.unwrap().report());
+    assert_eq!(report.code, ErrorCode::StatusMismatch);
+  }
+
+  #[test]
+  fn versions() {
+    assert!(decode(&encode(&[])).unwrap().is_empty());
+
+    let newer = proto::Batch {
+      version: VERSION + 1,
+      measurements: vec![],
+    };
+    assert!(matches!(
+      decode(&newer.encode_to_vec()),
+      Err(WireError::UnsupportedVersion(version)) if version == VERSION + 1
+    ));
+    assert!(matches!(decode(&[0xff, 0xff]), Err(WireError::Decode(_))));
+  }
+}
@@ -0,0 +1,19 @@
+//! A module describing wire format errors.
+
+use thiserror::Error;
+
+/// Errors that can occur when decoding measurements.
+#[derive(Error, Debug)]
+pub enum WireError {
+  /// The bytes aren't a valid batch.
+  #[error("Decode error: {0}")]
+  Decode(#[from] prost::DecodeError),
+
+  /// The batch was encoded by a newer, incompatible version of the format.
+  #[error("Unsupported wire format version {0}")]
+  UnsupportedVersion(u32),
+
+  /// A field of a measurement has a value out of its range.
+  #[error("Invalid {0}")]
+  InvalidField(&'static str),
+}
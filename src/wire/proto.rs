@@ -0,0 +1,489 @@
+//! Protocol Buffers messages of the wire format, and their conversions from
+//! and into the models.
+//!
+//! Tags must never be reused: a removed field keeps its tag reserved.
+
+use std::collections::HashMap;
+use std::net::IpAddr;
+
+use serde::Deserialize;
+use serde::de::IntoDeserializer;
+use time::OffsetDateTime;
+use uuid::Uuid;
+
+use crate::monitor::errors::{CollectorError, ErrorCode, ErrorReport};
+use crate::monitor::models;
+use crate::wire::errors::WireError;
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct Batch {
+  #[prost(uint32, tag = "1")]
+  pub version: u32,
+
+  #[prost(message, repeated, tag = "2")]
+  pub measurements: Vec<Measurement>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct Measurement {
+  /// Unix timestamp, in milliseconds.
+  #[prost(int64, tag = "1")]
+  pub timestamp: i64,
+
+  #[prost(int64, tag = "2")]
+  pub monitor_id: i64,
+
+  #[prost(map = "string, string", tag = "3")]
+  pub labels: HashMap<String, String>,
+
+  #[prost(message, optional, tag = "4")]
+  pub agent: Option<Agent>,
+
+  #[prost(oneof = "Data", tags = "5, 6")]
+  pub data: Option<Data>,
+
+  #[prost(enumeration = "Degradation", tag = "7")]
+  pub degradation: i32,
+
+  #[prost(message, optional, tag = "8")]
+  pub error: Option<Report>,
+
+  #[prost(string, optional, tag = "9")]
+  pub trace: Option<String>,
+
+  #[prost(message, optional, tag = "10")]
+  pub partial: Option<Partial>,
+
+  /// The 16 bytes of the UUID.
+  #[prost(bytes = "vec", tag = "11")]
+  pub id: Vec<u8>,
+
+  #[prost(uint64, tag = "12")]
+  pub sequence: u64,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct Agent {
+  #[prost(string, tag = "1")]
+  pub id: String,
+
+  #[prost(string, optional, tag = "2")]
+  pub region: Option<String>,
+}
+
+#[derive(Clone, PartialEq, prost::Oneof)]
+pub enum Data {
+  #[prost(message, tag = "5")]
+  Ping(PingData),
+
+  #[prost(message, tag = "6")]
+  Http(HttpData),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, prost::Enumeration)]
+#[repr(i32)]
+pub enum Degradation {
+  None = 0,
+  Warning = 1,
+  Critical = 2,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct Partial {
+  #[prost(enumeration = "Phase", optional, tag = "1")]
+  pub completed: Option<i32>,
+
+  #[prost(bool, tag = "2")]
+  pub truncated: bool,
+
+  /// Octets of the address, 4 or 16.
+  #[prost(bytes = "vec", optional, tag = "3")]
+  pub resolved_ip: Option<Vec<u8>>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, prost::Enumeration)]
+#[repr(i32)]
+pub enum Phase {
+  Dns = 0,
+  Connect = 1,
+  Tls = 2,
+  Transfer = 3,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct PingData {
+  #[prost(float, tag = "1")]
+  pub dns_lookup: f32,
+
+  #[prost(float, tag = "2")]
+  pub ping: f32,
+
+  #[prost(float, tag = "3")]
+  pub ping_min: f32,
+
+  #[prost(float, tag = "4")]
+  pub ping_max: f32,
+
+  #[prost(float, tag = "5")]
+  pub ping_stddev: f32,
+
+  #[prost(float, tag = "6")]
+  pub packet_loss: f32,
+
+  #[prost(uint64, tag = "7")]
+  pub packet_size: u64,
+
+  #[prost(enumeration = "PingProtocol", tag = "8")]
+  pub protocol: i32,
+
+  #[prost(string, optional, tag = "9")]
+  pub ptr: Option<String>,
+
+  /// Octets of the address, 4 or 16.
+  #[prost(bytes = "vec", optional, tag = "10")]
+  pub resolved_ip: Option<Vec<u8>>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, prost::Enumeration)]
+#[repr(i32)]
+pub enum PingProtocol {
+  Icmp = 0,
+  Tcp = 1,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct HttpData {
+  #[prost(float, tag = "1")]
+  pub dns_lookup: f32,
+
+  #[prost(float, tag = "2")]
+  pub connect: f32,
+
+  #[prost(float, tag = "3")]
+  pub tls_handshake: f32,
+
+  #[prost(float, tag = "4")]
+  pub data_transfer: f32,
+
+  #[prost(float, tag = "5")]
+  pub total: f32,
+
+  #[prost(enumeration = "IpFamily", tag = "6")]
+  pub ip_family: i32,
+
+  /// Octets of the address, 4 or 16.
+  #[prost(bytes = "vec", optional, tag = "7")]
+  pub resolved_ip: Option<Vec<u8>>,
+
+  #[prost(message, optional, tag = "8")]
+  pub certificate: Option<Certificate>,
+
+  #[prost(uint32, optional, tag = "9")]
+  pub remote_port: Option<u32>,
+
+  #[prost(uint32, optional, tag = "10")]
+  pub status_code: Option<u32>,
+
+  #[prost(enumeration = "HttpVersion", optional, tag = "11")]
+  pub http_version: Option<i32>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, prost::Enumeration)]
+#[repr(i32)]
+pub enum HttpVersion {
+  Http10 = 0,
+  Http11 = 1,
+  Http2 = 2,
+  Http3 = 3,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, prost::Enumeration)]
+#[repr(i32)]
+pub enum IpFamily {
+  Unknown = 0,
+  V4 = 1,
+  V6 = 2,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct Certificate {
+  #[prost(string, tag = "1")]
+  pub subject: String,
+
+  #[prost(string, tag = "2")]
+  pub issuer: String,
+
+  /// Unix timestamp, in seconds.
+  #[prost(int64, tag = "3")]
+  pub not_after: i64,
+
+  #[prost(string, repeated, tag = "4")]
+  pub subject_alt_names: Vec<String>,
+}
+
+/// An [ErrorReport], with its kind and code as they're serialized.
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct Report {
+  #[prost(string, tag = "1")]
+  pub kind: String,
+
+  #[prost(string, tag = "2")]
+  pub code: String,
+
+  #[prost(string, tag = "3")]
+  pub message: String,
+}
+
+impl From<&models::Measurement> for Measurement {
+  fn from(measurement: &models::Measurement) -> Self {
+    Self {
+      id: measurement.id.as_bytes().to_vec(),
+      sequence: measurement.sequence,
+      timestamp: (measurement.timestamp.unix_timestamp_nanos() / 1_000_000) as i64,
+      monitor_id: measurement.monitor_id,
+      labels: measurement.labels.clone(),
+      agent: measurement.agent.as_ref().map(|agent| Agent {
+        id: agent.id.clone(),
+        region: agent.region.clone(),
+      }),
+      data: measurement.data.as_ref().map(Into::into),
+      degradation: match measurement.degradation {
+        None => Degradation::None,
+        Some(models::Degradation::Warning) => Degradation::Warning,
+        Some(models::Degradation::Critical) => Degradation::Critical,
+      } as i32,
+      error: measurement.error.as_ref().map(|error| {
+        let report = error.report();
+
+        Report {
+          kind: report.kind.as_str().to_owned(),
+          code: report.code.as_str().to_owned(),
+          message: report.message,
+        }
+      }),
+      trace: measurement.trace.clone(),
+      partial: measurement.partial.map(|partial| Partial {
+        completed: partial.completed.map(|phase| {
+          (match phase {
+            models::Phase::Dns => Phase::Dns,
+            models::Phase::Connect => Phase::Connect,
+            models::Phase::Tls => Phase::Tls,
+            models::Phase::Transfer => Phase::Transfer,
+          }) as i32
+        }),
+        truncated: partial.truncated,
+        resolved_ip: partial.resolved_ip.map(octets),
+      }),
+    }
+  }
+}
+
+impl From<&models::Data> for Data {
+  fn from(data: &models::Data) -> Self {
+    match data {
+      models::Data::Ping(data) => Data::Ping(PingData {
+        dns_lookup: data.dns_lookup,
+        ping: data.ping,
+        ping_min: data.ping_min,
+        ping_max: data.ping_max,
+        ping_stddev: data.ping_stddev,
+        packet_loss: data.packet_loss,
+        packet_size: data.packet_size as u64,
+        protocol: match data.protocol {
+          models::PingProtocol::Icmp => PingProtocol::Icmp,
+          models::PingProtocol::Tcp => PingProtocol::Tcp,
+        } as i32,
+        ptr: data.ptr.clone(),
+        resolved_ip: data.resolved_ip.map(octets),
+      }),
+      models::Data::Http(data) => Data::Http(HttpData {
+        dns_lookup: data.dns_lookup,
+        connect: data.connect,
+        tls_handshake: data.tls_handshake,
+        data_transfer: data.data_transfer,
+        total: data.total,
+        ip_family: match data.ip_family {
+          None => IpFamily::Unknown,
+          Some(models::IpFamily::V4) => IpFamily::V4,
+          Some(models::IpFamily::V6) => IpFamily::V6,
+        } as i32,
+        resolved_ip: data.resolved_ip.map(octets),
+        remote_port: data.remote_port.map(u32::from),
+        status_code: data.status_code.map(u32::from),
+        http_version: data.http_version.map(|version| {
+          (match version {
+            models::HttpVersion::Http10 => HttpVersion::Http10,
+            models::HttpVersion::Http11 => HttpVersion::Http11,
+            models::HttpVersion::Http2 => HttpVersion::Http2,
+            models::HttpVersion::Http3 => HttpVersion::Http3,
+          }) as i32
+        }),
+        certificate: data.certificate.as_ref().map(|certificate| Certificate {
+          subject: certificate.subject.clone(),
+          issuer: certificate.issuer.clone(),
+          not_after: certificate.not_after.unix_timestamp(),
+          subject_alt_names: certificate.subject_alt_names.clone(),
+        }),
+      }),
+    }
+  }
+}
+
+impl TryFrom<Measurement> for models::Measurement {
+  type Error = WireError;
+
+  fn try_from(measurement: Measurement) -> Result<Self, Self::Error> {
+    let timestamp =
+      OffsetDateTime::from_unix_timestamp_nanos(i128::from(measurement.timestamp) * 1_000_000)
+        .map_err(|_| WireError::InvalidField("timestamp"))?;
+
+    let degradation = match Degradation::try_from(measurement.degradation) {
+      Ok(Degradation::None) => None,
+      Ok(Degradation::Warning) => Some(models::Degradation::Warning),
+      Ok(Degradation::Critical) => Some(models::Degradation::Critical),
+      Err(_) => return Err(WireError::InvalidField("degradation")),
+    };
+
+    let error = match measurement.error {
+      Some(report) => Some(CollectorError::Reported(ErrorReport {
+        kind: parse(&report.kind).map_err(|_| WireError::InvalidField("error kind"))?,
+        code: parse::<ErrorCode>(&report.code).unwrap_or(ErrorCode::Unknown),
+        message: report.message,
+      })),
+      None => None,
+    };
+
+    Ok(models::Measurement {
+      id: Uuid::from_slice(&measurement.id).map_err(|_| WireError::InvalidField("id"))?,
+      timestamp,
+      monitor_id: measurement.monitor_id,
+      sequence: measurement.sequence,
+      labels: measurement.labels,
+      agent: measurement.agent.map(|agent| models::Agent {
+        id: agent.id,
+        region: agent.region,
+      }),
+      data: measurement.data.map(TryInto::try_into).transpose()?,
+      degradation,
+      error,
+      trace: measurement.trace,
+      partial: measurement.partial.map(TryInto::try_into).transpose()?,
+    })
+  }
+}
+
+impl TryFrom<Data> for models::Data {
+  type Error = WireError;
+
+  fn try_from(data: Data) -> Result<Self, Self::Error> {
+    Ok(match data {
+      Data::Ping(data) => models::Data::Ping(models::PingData {
+        dns_lookup: data.dns_lookup,
+        ping: data.ping,
+        ping_min: data.ping_min,
+        ping_max: data.ping_max,
+        ping_stddev: data.ping_stddev,
+        packet_loss: data.packet_loss,
+        packet_size: usize::try_from(data.packet_size)
+          .map_err(|_| WireError::InvalidField("packet size"))?,
+        protocol: match PingProtocol::try_from(data.protocol) {
+          Ok(PingProtocol::Icmp) => models::PingProtocol::Icmp,
+          Ok(PingProtocol::Tcp) => models::PingProtocol::Tcp,
+          Err(_) => return Err(WireError::InvalidField("ping protocol")),
+        },
+        ptr: data.ptr,
+        resolved_ip: data.resolved_ip.map(address).transpose()?,
+      }),
+      Data::Http(data) => models::Data::Http(models::HttpData {
+        dns_lookup: data.dns_lookup,
+        connect: data.connect,
+        tls_handshake: data.tls_handshake,
+        data_transfer: data.data_transfer,
+        total: data.total,
+        ip_family: match IpFamily::try_from(data.ip_family) {
+          Ok(IpFamily::Unknown) => None,
+          Ok(IpFamily::V4) => Some(models::IpFamily::V4),
+          Ok(IpFamily::V6) => Some(models::IpFamily::V6),
+          Err(_) => return Err(WireError::InvalidField("IP family")),
+        },
+        resolved_ip: data.resolved_ip.map(address).transpose()?,
+        remote_port: data
+          .remote_port
+          .map(|port| u16::try_from(port).map_err(|_| WireError::InvalidField("port")))
+          .transpose()?,
+        status_code: data
+          .status_code
+          .map(|code| u16::try_from(code).map_err(|_| WireError::InvalidField("status code")))
+          .transpose()?,
+        http_version: data
+          .http_version
+          .map(|version| match HttpVersion::try_from(version) {
+            Ok(HttpVersion::Http10) => Ok(models::HttpVersion::Http10),
+            Ok(HttpVersion::Http11) => Ok(models::HttpVersion::Http11),
+            Ok(HttpVersion::Http2) => Ok(models::HttpVersion::Http2),
+            Ok(HttpVersion::Http3) => Ok(models::HttpVersion::Http3),
+            Err(_) => Err(WireError::InvalidField("HTTP version")),
+          })
+          .transpose()?,
+        certificate: data
+          .certificate
+          .map(|certificate| {
+            Ok::<_, WireError>(models::Certificate {
+              subject: certificate.subject,
+              issuer: certificate.issuer,
+              not_after: OffsetDateTime::from_unix_timestamp(certificate.not_after)
+                .map_err(|_| WireError::InvalidField("certificate expiration"))?,
+              subject_alt_names: certificate.subject_alt_names,
+            })
+          })
+          .transpose()?,
+      }),
+    })
+  }
+}
+
+impl TryFrom<Partial> for models::Partial {
+  type Error = WireError;
+
+  fn try_from(partial: Partial) -> Result<Self, Self::Error> {
+    let completed = partial
+      .completed
+      .map(|phase| match Phase::try_from(phase) {
+        Ok(Phase::Dns) => Ok(models::Phase::Dns),
+        Ok(Phase::Connect) => Ok(models::Phase::Connect),
+        Ok(Phase::Tls) => Ok(models::Phase::Tls),
+        Ok(Phase::Transfer) => Ok(models::Phase::Transfer),
+        Err(_) => Err(WireError::InvalidField("phase")),
+      })
+      .transpose()?;
+
+    Ok(models::Partial {
+      completed,
+      truncated: partial.truncated,
+      resolved_ip: partial.resolved_ip.map(address).transpose()?,
+    })
+  }
+}
+
+fn octets(ip: IpAddr) -> Vec<u8> {
+  match ip {
+    IpAddr::V4(ip) => ip.octets().to_vec(),
+    IpAddr::V6(ip) => ip.octets().to_vec(),
+  }
+}
+
+fn address(octets: Vec<u8>) -> Result<IpAddr, WireError> {
+  if let Ok(octets) = <[u8; 4]>::try_from(octets.as_slice()) {
+    Ok(IpAddr::from(octets))
+  } else if let Ok(octets) = <[u8; 16]>::try_from(octets.as_slice()) {
+    Ok(IpAddr::from(octets))
+  } else {
+    Err(WireError::InvalidField("IP address"))
+  }
+}
+
+/// Parses a kind or code by its serialized name. Codes added by newer agents
+/// fail to parse, and are decoded as unknown.
+fn parse<'a, T: Deserialize<'a>>(name: &'a str) -> Result<T, serde::de::value::Error> {
+  T::deserialize(name.into_deserializer())
+}